@@ -0,0 +1,41 @@
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+PRAGMA busy_timeout = 5000;
+
+BEGIN;
+
+-- Append-only record of what actually aired. Rows outlive the queue entries
+-- they came from, so nothing here references playout_queue.
+CREATE TABLE IF NOT EXISTS as_run_log (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue_id INTEGER,
+    plan_id TEXT NOT NULL,
+    asset_path TEXT NOT NULL,
+    asset_checksum TEXT,
+    content_kind TEXT,
+    node_origin TEXT,
+    destination TEXT,
+    transition TEXT,
+    outcome TEXT NOT NULL,
+    started_at DATETIME NOT NULL,
+    finished_at DATETIME,
+    note TEXT,
+    recorded_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_as_run_started ON as_run_log(started_at);
+CREATE INDEX IF NOT EXISTS idx_as_run_plan ON as_run_log(plan_id, started_at);
+
+CREATE TRIGGER IF NOT EXISTS trg_as_run_no_update
+BEFORE UPDATE ON as_run_log
+BEGIN
+    SELECT RAISE(ABORT, 'as_run_log is append-only');
+END;
+
+CREATE TRIGGER IF NOT EXISTS trg_as_run_no_delete
+BEFORE DELETE ON as_run_log
+BEGIN
+    SELECT RAISE(ABORT, 'as_run_log is append-only');
+END;
+
+COMMIT;
@@ -0,0 +1,21 @@
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+PRAGMA busy_timeout = 5000;
+
+BEGIN;
+
+-- Requests to take the air from the entry playing. An unclaimed row holds its
+-- entry out of normal selection until the broadcaster airs it.
+CREATE TABLE IF NOT EXISTS break_ins (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    queue_id INTEGER NOT NULL,
+    policy TEXT NOT NULL,
+    reason TEXT,
+    requested_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    claimed_at DATETIME,
+    interrupted_id INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_break_ins_pending ON break_ins(claimed_at, id);
+
+COMMIT;
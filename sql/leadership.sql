@@ -0,0 +1,41 @@
+PRAGMA journal_mode = WAL;
+PRAGMA synchronous = NORMAL;
+PRAGMA busy_timeout = 5000;
+
+BEGIN;
+
+-- Lease deciding which broadcaster node airs. A single row, renewed by its
+-- holder on every heartbeat and taken over once it expires.
+CREATE TABLE IF NOT EXISTS leader_lease (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    holder TEXT NOT NULL,
+    epoch INTEGER NOT NULL,
+    acquired_at TEXT NOT NULL,
+    renewed_at TEXT NOT NULL,
+    expires_at TEXT NOT NULL,
+    released INTEGER NOT NULL DEFAULT 0
+);
+
+-- Every change of holder.
+CREATE TABLE IF NOT EXISTS leader_handovers (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    at TEXT NOT NULL,
+    from_node TEXT,
+    to_node TEXT NOT NULL,
+    epoch INTEGER NOT NULL,
+    reason TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_leader_handovers_at ON leader_handovers(at DESC);
+
+-- Last state each node reported.
+CREATE TABLE IF NOT EXISTS leader_nodes (
+    node_id TEXT PRIMARY KEY,
+    role TEXT NOT NULL,
+    state TEXT NOT NULL,
+    epoch INTEGER,
+    detail TEXT,
+    updated_at TEXT NOT NULL
+);
+
+COMMIT;
@@ -117,6 +117,38 @@ CREATE TABLE IF NOT EXISTS edge_latency (
 
 CREATE INDEX IF NOT EXISTS idx_edge_latency_ts ON edge_latency(ts DESC);
 
+CREATE TABLE IF NOT EXISTS encoder_samples (
+    ts DATETIME DEFAULT CURRENT_TIMESTAMP,
+    queue_id INTEGER,
+    frame INTEGER,
+    fps REAL,
+    bitrate_kbps REAL,
+    speed REAL,
+    drop_frames INTEGER,
+    dup_frames INTEGER,
+    out_time_s REAL
+);
+
+CREATE INDEX IF NOT EXISTS idx_encoder_samples_ts ON encoder_samples(ts DESC);
+
+CREATE TABLE IF NOT EXISTS output_samples (
+    ts DATETIME DEFAULT CURRENT_TIMESTAMP,
+    capture_s REAL,
+    black_s REAL,
+    frozen_s REAL,
+    silent_s REAL,
+    black_for_s REAL,
+    frozen_for_s REAL,
+    silent_for_s REAL,
+    freeze_events INTEGER,
+    vmaf_estimate REAL,
+    stream_bitrate_mbps REAL,
+    audio_peak_db REAL,
+    signature_deviation REAL
+);
+
+CREATE INDEX IF NOT EXISTS idx_output_samples_ts ON output_samples(ts DESC);
+
 CREATE TABLE IF NOT EXISTS cdn_tokens (
     ts DATETIME DEFAULT CURRENT_TIMESTAMP,
     path TEXT,
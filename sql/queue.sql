@@ -22,6 +22,14 @@ CREATE TABLE IF NOT EXISTS playout_queue (
     play_finished_at DATETIME,
     failure_reason TEXT,
     content_kind TEXT,
+    scheduled_start DATETIME,
+    anchor TEXT,
+    tags TEXT,
+    source_domain TEXT,
+    retry_count INTEGER DEFAULT 0,
+    next_attempt_at DATETIME,
+    queue_position INTEGER,
+    resume_offset_s REAL,
     FOREIGN KEY(plan_id) REFERENCES plans(plan_id)
 );
 
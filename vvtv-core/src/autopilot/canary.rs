@@ -451,7 +451,18 @@ pub struct ProgressionRecommendation {
     pub estimated_time_minutes: u32,
 }
 
-/// Types of progression recommendations (full definition below at line 553)
+/// Types of progression recommendations
+#[derive(Debug, Clone, Serialize)]
+pub enum RecommendationType {
+    WaitForSamples,
+    ReadyForAnalysis,
+    ExtendMonitoring,
+    ConsiderRollback,
+    TimeoutWarning,
+    ParameterAdjustment,
+    QualityReview,
+    ExtendDuration,
+}
 
 /// Priority levels for recommendations
 #[derive(Debug, Clone, Serialize)]
@@ -0,0 +1,338 @@
+//! Incidents opened from the watchdog.
+//!
+//! An evaluation that escalates (a rule with `escalate`, a spent budget or a
+//! failing selfcheck) opens an incident, as do restarts repeated over
+//! `restarts_before_incident` consecutive evaluations. While it is open,
+//! later evaluations append what they newly observed to its timeline and
+//! raise its severity when a more severe rule fires. Once the rules that
+//! escalated or restarted while it was open have not fired for
+//! `resolve_after_seconds` it resolves itself, whatever else keeps firing.
+//! Every change is written to the incident history; opening, raising and
+//! resolving are also routed to the channels configured for the incident's
+//! severity.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::WatchdogIncidentSection;
+use crate::{
+    IncidentDispatch, IncidentError, IncidentHistoryRecord, IncidentHistoryWriter,
+    IncidentNotifier, IncidentReport, IncidentSeverity, IncidentTimelineEntry,
+};
+
+use super::watchdog::{FiringState, WatchdogAction, WatchdogReport};
+
+const INCIDENT_CATEGORY: &str = "Watchdog";
+const INCIDENT_AUTHOR: &str = "watchdog";
+/// Tracks escalations raised outside the rules, such as a failing selfcheck.
+const UNRULED_ESCALATION: &str = "";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IncidentChange {
+    Opened,
+    /// New observations were added to the timeline.
+    Updated,
+    /// A more severe rule fired.
+    Raised,
+    Resolved,
+}
+
+impl fmt::Display for IncidentChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            IncidentChange::Opened => "opened",
+            IncidentChange::Updated => "updated",
+            IncidentChange::Raised => "raised",
+            IncidentChange::Resolved => "resolved",
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct IncidentUpdate {
+    pub change: IncidentChange,
+    pub incident: IncidentReport,
+    pub record: IncidentHistoryRecord,
+    /// Set when the change was routed to the notifier.
+    pub dispatch: Option<IncidentDispatch>,
+}
+
+#[derive(Debug, Default)]
+struct TrackerState {
+    open: Option<IncidentReport>,
+    restart_streak: u32,
+    /// When each rule last fired, in any state.
+    last_fired: HashMap<String, DateTime<Utc>>,
+    /// Rules that escalated or restarted while the incident was open.
+    incident_rules: Vec<String>,
+    last_entries: Vec<String>,
+}
+
+/// Keeps the watchdog's open incident.
+#[derive(Debug)]
+pub struct WatchdogIncidents {
+    section: WatchdogIncidentSection,
+    notifier: IncidentNotifier,
+    history: IncidentHistoryWriter,
+    state: Mutex<TrackerState>,
+}
+
+impl WatchdogIncidents {
+    pub fn new(
+        section: WatchdogIncidentSection,
+        notifier: IncidentNotifier,
+        history: IncidentHistoryWriter,
+    ) -> Self {
+        Self {
+            section,
+            notifier,
+            history,
+            state: Mutex::new(TrackerState::default()),
+        }
+    }
+
+    pub fn open_incident(&self) -> Option<IncidentReport> {
+        self.state.lock().unwrap().open.clone()
+    }
+
+    /// Records `report`, evaluated at `now`, against the open incident.
+    pub fn observe(
+        &self,
+        report: &WatchdogReport,
+        now: DateTime<Utc>,
+    ) -> Result<Option<IncidentUpdate>, IncidentError> {
+        if !self.section.enabled {
+            return Ok(None);
+        }
+        let mut state = self.state.lock().unwrap();
+        let escalations: Vec<&str> = report
+            .actions
+            .iter()
+            .filter_map(|action| match action {
+                WatchdogAction::Escalate(reason) => Some(reason.as_str()),
+                _ => None,
+            })
+            .collect();
+        if report.firings.iter().any(|firing| firing.restarted) {
+            state.restart_streak += 1;
+        } else {
+            state.restart_streak = 0;
+        }
+        let mut triggers: Vec<&str> = Vec::new();
+        for firing in &report.firings {
+            state.last_fired.insert(firing.rule.clone(), now);
+            if firing.escalated || firing.restarted {
+                triggers.push(&firing.rule);
+            }
+        }
+        if escalations.len()
+            > report
+                .firings
+                .iter()
+                .filter(|firing| firing.escalated)
+                .count()
+        {
+            state.last_fired.insert(UNRULED_ESCALATION.to_string(), now);
+            triggers.push(UNRULED_ESCALATION);
+        }
+        let mut entries: Vec<String> = report.observations.clone();
+        for reason in &escalations {
+            if !entries.iter().any(|entry| entry == reason) {
+                entries.push(reason.to_string());
+            }
+        }
+        let new_entries: Vec<String> = entries
+            .iter()
+            .filter(|entry| !state.last_entries.contains(&entry_key(entry)))
+            .cloned()
+            .collect();
+        state.last_entries = entries.iter().map(|entry| entry_key(entry)).collect();
+
+        let severity = report_severity(report);
+        let repeated_restarts = self.section.restarts_before_incident > 0
+            && state.restart_streak >= self.section.restarts_before_incident;
+        let resolve_after = Duration::seconds(self.section.resolve_after_seconds as i64);
+        let restart_streak = state.restart_streak;
+        if state.open.is_some() || !escalations.is_empty() || repeated_restarts {
+            for rule in triggers {
+                if !state.incident_rules.iter().any(|known| known == rule) {
+                    state.incident_rules.push(rule.to_string());
+                }
+            }
+        }
+        let quiet = state.incident_rules.iter().all(|rule| {
+            state
+                .last_fired
+                .get(rule)
+                .is_none_or(|at| now - *at >= resolve_after)
+        });
+
+        let change = match state.open.as_mut() {
+            None => {
+                if escalations.is_empty() && !repeated_restarts {
+                    return Ok(None);
+                }
+                let reason = escalations.first().map_or_else(
+                    || format!("{restart_streak} avaliações seguidas com reinícios"),
+                    |reason| reason.to_string(),
+                );
+                state.open = Some(new_incident(report, reason, severity, now));
+                IncidentChange::Opened
+            }
+            Some(incident) => {
+                if quiet {
+                    incident.resolved_at = Some(now);
+                    incident.timeline.push(IncidentTimelineEntry::new(
+                        now,
+                        format!(
+                            "as regras do incidente não dispararam em {}s; incidente resolvido automaticamente",
+                            resolve_after.num_seconds()
+                        ),
+                    ));
+                    IncidentChange::Resolved
+                } else {
+                    let raised = rank(severity) > rank(incident.severity);
+                    if raised {
+                        incident.severity = severity;
+                        incident.timeline.push(IncidentTimelineEntry::new(
+                            now,
+                            format!("severidade elevada para {}", severity.badge()),
+                        ));
+                    }
+                    for entry in &new_entries {
+                        incident
+                            .timeline
+                            .push(IncidentTimelineEntry::new(now, entry.clone()));
+                    }
+                    let acted = record_actions(incident, &report.actions);
+                    if raised {
+                        IncidentChange::Raised
+                    } else if acted || !new_entries.is_empty() {
+                        IncidentChange::Updated
+                    } else {
+                        return Ok(None);
+                    }
+                }
+            }
+        };
+
+        let incident = if change == IncidentChange::Resolved {
+            state.restart_streak = 0;
+            state.incident_rules.clear();
+            state.open.take()
+        } else {
+            state.open.clone()
+        }
+        .expect("incident is open");
+        drop(state);
+
+        let record = self.history.write(&incident, self.section.include_json)?;
+        let dispatch = if change == IncidentChange::Updated {
+            None
+        } else {
+            let mut notification = incident.notification();
+            notification.link = Some(record.markdown_path.display().to_string());
+            Some(self.notifier.notify(&notification)?)
+        };
+        Ok(Some(IncidentUpdate {
+            change,
+            incident,
+            record,
+            dispatch,
+        }))
+    }
+}
+
+fn new_incident(
+    report: &WatchdogReport,
+    reason: String,
+    severity: IncidentSeverity,
+    now: DateTime<Utc>,
+) -> IncidentReport {
+    let mut timeline: Vec<IncidentTimelineEntry> = report
+        .observations
+        .iter()
+        .map(|observation| IncidentTimelineEntry::new(now, observation.clone()))
+        .collect();
+    if !report.observations.contains(&reason) {
+        timeline.push(IncidentTimelineEntry::new(now, reason.clone()));
+    }
+    let mut incident = IncidentReport {
+        incident_id: format!("WD-{}", now.format("%Y%m%d-%H%M%S")),
+        title: format!("Watchdog: {reason}"),
+        severity,
+        category: INCIDENT_CATEGORY.to_string(),
+        detected_at: now,
+        resolved_at: None,
+        summary: reason,
+        impact: format!(
+            "buffer de {:.2}h, {} falhas na última hora",
+            report.metrics.buffer_duration_hours, report.metrics.failures_last_hour
+        ),
+        root_cause: "Em investigação".to_string(),
+        lessons_learned: Vec::new(),
+        actions_taken: Vec::new(),
+        preventive_actions: Vec::new(),
+        timeline,
+        author: Some(INCIDENT_AUTHOR.to_string()),
+    };
+    record_actions(&mut incident, &report.actions);
+    incident
+}
+
+/// Entries are compared without their figures, so a buffer that keeps
+/// shrinking is not logged again on every evaluation.
+fn entry_key(entry: &str) -> String {
+    entry.chars().filter(|ch| !ch.is_ascii_digit()).collect()
+}
+
+/// Adds actions not taken before; returns whether there were any.
+fn record_actions(incident: &mut IncidentReport, actions: &[WatchdogAction]) -> bool {
+    let taken = incident.actions_taken.len();
+    for action in actions {
+        if matches!(action, WatchdogAction::Escalate(_)) {
+            continue;
+        }
+        let name = action.as_str();
+        if !incident.actions_taken.iter().any(|taken| taken == name) {
+            incident.actions_taken.push(name.to_string());
+        }
+    }
+    incident.actions_taken.len() > taken
+}
+
+/// Most severe rule that fired, one level higher when a budget is spent.
+/// Escalations without a rule, such as a failing selfcheck, are medium.
+fn report_severity(report: &WatchdogReport) -> IncidentSeverity {
+    let severity = report
+        .firings
+        .iter()
+        .map(|firing| firing.severity)
+        .max_by_key(|severity| rank(*severity))
+        .unwrap_or(IncidentSeverity::Medium);
+    if report
+        .firings
+        .iter()
+        .any(|firing| firing.state == FiringState::BudgetExhausted)
+    {
+        match severity {
+            IncidentSeverity::Low => IncidentSeverity::Medium,
+            IncidentSeverity::Medium => IncidentSeverity::High,
+            IncidentSeverity::High | IncidentSeverity::Critical => IncidentSeverity::Critical,
+        }
+    } else {
+        severity
+    }
+}
+
+fn rank(severity: IncidentSeverity) -> u8 {
+    match severity {
+        IncidentSeverity::Low => 0,
+        IncidentSeverity::Medium => 1,
+        IncidentSeverity::High => 2,
+        IncidentSeverity::Critical => 3,
+    }
+}
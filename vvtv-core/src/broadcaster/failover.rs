@@ -122,6 +122,20 @@ impl FailoverManager {
     }
 
     pub async fn promote_failover(&self, script: &Path) -> Result<(), FailoverError> {
+        self.run_script(script).await?;
+        info!(script = %script.display(), "failover promotion executed");
+        Ok(())
+    }
+
+    /// Runs `script` to stop this node publishing after it lost the air to
+    /// another.
+    pub async fn fence(&self, script: &Path) -> Result<(), FailoverError> {
+        self.run_script(script).await?;
+        info!(script = %script.display(), "failover fence executed");
+        Ok(())
+    }
+
+    async fn run_script(&self, script: &Path) -> Result<(), FailoverError> {
         let mut command = Command::new(script);
         let output = self
             .executor
@@ -135,7 +149,6 @@ impl FailoverManager {
                 stderr: String::from_utf8_lossy(&output.stderr).to_string(),
             });
         }
-        info!(script = %script.display(), "failover promotion executed");
         Ok(())
     }
 
@@ -0,0 +1,691 @@
+//! Active/standby coordination between broadcaster nodes.
+//!
+//! Both nodes heartbeat a single lease in a store they share. The holder
+//! airs; everyone else stands by. The primary takes a free or expired lease
+//! at once, while a standby waits `standby_grace_seconds` past its expiry so
+//! a primary that is merely slow is not displaced. A node that finds its
+//! lease taken, or cannot renew it before it runs out, fences itself: it
+//! stops airing and runs `fence_script`, so two nodes never publish at
+//! once. The expiry is watched by a timer of its own, and store calls run
+//! off the runtime under a timeout, so a store that hangs cannot keep a
+//! node airing past its lease. Taking the lease runs `promote_script`. Each change of holder is
+//! kept as a handover, and each node records the state it last saw, for
+//! `vvtvctl failover status`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row, TransactionBehavior};
+use thiserror::Error;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::config::LeadershipSection;
+use crate::sqlite::configure_connection;
+
+use super::failover::{FailoverError, FailoverManager};
+
+const LEADERSHIP_SCHEMA: &str = include_str!("../../../sql/leadership.sql");
+
+#[derive(Debug, Error)]
+pub enum LeadershipError {
+    #[error("sqlite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("io error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("invalid role: {0}")]
+    InvalidRole(String),
+    #[error("failover error: {0}")]
+    Failover(#[from] FailoverError),
+    #[error("lease store did not answer within {0:?}")]
+    Timeout(StdDuration),
+    #[error("lease store task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NodeRole {
+    Primary,
+    Standby,
+}
+
+impl NodeRole {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NodeRole::Primary => "primary",
+            NodeRole::Standby => "standby",
+        }
+    }
+}
+
+impl fmt::Display for NodeRole {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for NodeRole {
+    type Err = LeadershipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "primary" => Ok(NodeRole::Primary),
+            "standby" => Ok(NodeRole::Standby),
+            other => Err(LeadershipError::InvalidRole(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lease {
+    pub holder: String,
+    /// Incremented on every change of holder.
+    pub epoch: i64,
+    pub acquired_at: DateTime<Utc>,
+    pub renewed_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Given up by its holder before expiring.
+    pub released: bool,
+}
+
+impl Lease {
+    /// Whether another node may take the lease at `now`.
+    pub fn is_free(&self, now: DateTime<Utc>) -> bool {
+        self.released || self.expires_at <= now
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Handover {
+    pub at: DateTime<Utc>,
+    pub from_node: Option<String>,
+    pub to_node: String,
+    pub epoch: i64,
+    /// initial | expired | released
+    pub reason: String,
+}
+
+/// State a node last reported.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeHeartbeat {
+    pub node_id: String,
+    pub role: NodeRole,
+    pub state: String,
+    pub epoch: Option<i64>,
+    pub detail: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LeadershipState {
+    Standby,
+    /// Holding the lease for `epoch`; this node airs.
+    Active {
+        epoch: i64,
+    },
+    /// Lost the lease of `epoch` and stopped airing.
+    Fenced {
+        epoch: i64,
+        reason: String,
+    },
+}
+
+impl LeadershipState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LeadershipState::Standby => "standby",
+            LeadershipState::Active { .. } => "active",
+            LeadershipState::Fenced { .. } => "fenced",
+        }
+    }
+
+    pub fn epoch(&self) -> Option<i64> {
+        match self {
+            LeadershipState::Standby => None,
+            LeadershipState::Active { epoch } | LeadershipState::Fenced { epoch, .. } => {
+                Some(*epoch)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LeadershipState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LeadershipState::Fenced { reason, .. } => write!(f, "fenced: {reason}"),
+            other => f.write_str(other.as_str()),
+        }
+    }
+}
+
+/// Store both nodes reach. Operations are atomic, so two nodes contending
+/// for the lease at once cannot both get it.
+pub trait LeaseStore: Send + Sync {
+    /// Takes or renews the lease for `node` until `now + ttl` when it is
+    /// free or already `node`'s, and returns the lease as it stands
+    /// afterwards, whoever holds it.
+    fn acquire(
+        &self,
+        node: &str,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Lease, LeadershipError>;
+
+    /// Gives up the lease if `node` holds it.
+    fn release(&self, node: &str, now: DateTime<Utc>) -> Result<(), LeadershipError>;
+
+    fn lease(&self) -> Result<Option<Lease>, LeadershipError>;
+
+    fn record_node(&self, status: &NodeHeartbeat) -> Result<(), LeadershipError>;
+
+    fn nodes(&self) -> Result<Vec<NodeHeartbeat>, LeadershipError>;
+
+    /// Most recent first.
+    fn handovers(&self, limit: usize) -> Result<Vec<Handover>, LeadershipError>;
+}
+
+/// [`LeaseStore`] in a SQLite file, on storage both nodes mount or, in
+/// tests, a local path.
+#[derive(Debug, Clone)]
+pub struct SqliteLeaseStore {
+    path: PathBuf,
+}
+
+impl SqliteLeaseStore {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, LeadershipError> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let store = Self { path };
+        store.open()?.execute_batch(LEADERSHIP_SCHEMA)?;
+        Ok(store)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn open(&self) -> Result<Connection, LeadershipError> {
+        let conn = Connection::open(&self.path)?;
+        configure_connection(&conn)?;
+        Ok(conn)
+    }
+}
+
+impl LeaseStore for SqliteLeaseStore {
+    fn acquire(
+        &self,
+        node: &str,
+        ttl: Duration,
+        now: DateTime<Utc>,
+    ) -> Result<Lease, LeadershipError> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let current = tx
+            .query_row("SELECT * FROM leader_lease WHERE id=1", [], lease_from_row)
+            .optional()?;
+        let lease = match current {
+            Some(lease) if lease.holder == node => Lease {
+                renewed_at: now,
+                expires_at: now + ttl,
+                released: false,
+                ..lease
+            },
+            Some(lease) if !lease.is_free(now) => {
+                tx.commit()?;
+                return Ok(lease);
+            }
+            previous => {
+                let reason = match &previous {
+                    None => "initial",
+                    Some(lease) if lease.released => "released",
+                    Some(_) => "expired",
+                };
+                let lease = Lease {
+                    holder: node.to_string(),
+                    epoch: previous.as_ref().map_or(1, |lease| lease.epoch + 1),
+                    acquired_at: now,
+                    renewed_at: now,
+                    expires_at: now + ttl,
+                    released: false,
+                };
+                tx.execute(
+                    "INSERT INTO leader_handovers (at, from_node, to_node, epoch, reason)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        now.to_rfc3339(),
+                        previous.map(|lease| lease.holder),
+                        node,
+                        lease.epoch,
+                        reason
+                    ],
+                )?;
+                lease
+            }
+        };
+        tx.execute(
+            "INSERT INTO leader_lease (id, holder, epoch, acquired_at, renewed_at, expires_at, released)
+             VALUES (1, ?1, ?2, ?3, ?4, ?5, 0)
+             ON CONFLICT(id) DO UPDATE SET holder=excluded.holder, epoch=excluded.epoch,
+                 acquired_at=excluded.acquired_at, renewed_at=excluded.renewed_at,
+                 expires_at=excluded.expires_at, released=0",
+            params![
+                lease.holder,
+                lease.epoch,
+                lease.acquired_at.to_rfc3339(),
+                lease.renewed_at.to_rfc3339(),
+                lease.expires_at.to_rfc3339()
+            ],
+        )?;
+        tx.commit()?;
+        Ok(lease)
+    }
+
+    fn release(&self, node: &str, now: DateTime<Utc>) -> Result<(), LeadershipError> {
+        let conn = self.open()?;
+        conn.execute(
+            "UPDATE leader_lease SET released=1, expires_at=?2 WHERE id=1 AND holder=?1",
+            params![node, now.to_rfc3339()],
+        )?;
+        Ok(())
+    }
+
+    fn lease(&self) -> Result<Option<Lease>, LeadershipError> {
+        let conn = self.open()?;
+        Ok(conn
+            .query_row("SELECT * FROM leader_lease WHERE id=1", [], lease_from_row)
+            .optional()?)
+    }
+
+    fn record_node(&self, status: &NodeHeartbeat) -> Result<(), LeadershipError> {
+        let conn = self.open()?;
+        conn.execute(
+            "INSERT INTO leader_nodes (node_id, role, state, epoch, detail, updated_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(node_id) DO UPDATE SET role=excluded.role, state=excluded.state,
+                 epoch=excluded.epoch, detail=excluded.detail, updated_at=excluded.updated_at",
+            params![
+                status.node_id,
+                status.role.as_str(),
+                status.state,
+                status.epoch,
+                status.detail,
+                status.updated_at.to_rfc3339()
+            ],
+        )?;
+        Ok(())
+    }
+
+    fn nodes(&self) -> Result<Vec<NodeHeartbeat>, LeadershipError> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT node_id, role, state, epoch, detail, updated_at FROM leader_nodes
+             ORDER BY node_id",
+        )?;
+        let nodes = stmt
+            .query_map([], |row| {
+                let role: String = row.get(1)?;
+                Ok(NodeHeartbeat {
+                    node_id: row.get(0)?,
+                    role: role.parse().unwrap_or(NodeRole::Standby),
+                    state: row.get(2)?,
+                    epoch: row.get(3)?,
+                    detail: row.get(4)?,
+                    updated_at: parse_timestamp(row, 5)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(nodes)
+    }
+
+    fn handovers(&self, limit: usize) -> Result<Vec<Handover>, LeadershipError> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT at, from_node, to_node, epoch, reason FROM leader_handovers
+             ORDER BY id DESC LIMIT ?1",
+        )?;
+        let handovers = stmt
+            .query_map([limit as i64], |row| {
+                Ok(Handover {
+                    at: parse_timestamp(row, 0)?,
+                    from_node: row.get(1)?,
+                    to_node: row.get(2)?,
+                    epoch: row.get(3)?,
+                    reason: row.get(4)?,
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(handovers)
+    }
+}
+
+fn lease_from_row(row: &Row<'_>) -> rusqlite::Result<Lease> {
+    Ok(Lease {
+        holder: row.get("holder")?,
+        epoch: row.get("epoch")?,
+        acquired_at: parse_timestamp(row, "acquired_at")?,
+        renewed_at: parse_timestamp(row, "renewed_at")?,
+        expires_at: parse_timestamp(row, "expires_at")?,
+        released: row.get::<_, i64>("released")? != 0,
+    })
+}
+
+fn parse_timestamp<I: rusqlite::RowIndex>(
+    row: &Row<'_>,
+    index: I,
+) -> rusqlite::Result<DateTime<Utc>> {
+    let value: String = row.get(index)?;
+    DateTime::parse_from_rfc3339(&value)
+        .map(|at| at.with_timezone(&Utc))
+        .map_err(|error| {
+            rusqlite::Error::FromSqlConversionFailure(
+                0,
+                rusqlite::types::Type::Text,
+                Box::new(error),
+            )
+        })
+}
+
+/// This node's side of the lease.
+pub struct LeaderElection {
+    section: LeadershipSection,
+    role: NodeRole,
+    store: Arc<dyn LeaseStore>,
+    failover: Option<FailoverManager>,
+    state: watch::Sender<LeadershipState>,
+    /// Expiry of the lease as last renewed, while this node holds it.
+    held_until: watch::Sender<Option<DateTime<Utc>>>,
+    /// First heartbeat, from which a standby waits for a primary that never
+    /// took the lease.
+    started_at: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl fmt::Debug for LeaderElection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("LeaderElection")
+            .field("section", &self.section)
+            .field("state", &*self.state.borrow())
+            .finish()
+    }
+}
+
+impl LeaderElection {
+    pub fn new(
+        section: LeadershipSection,
+        store: Arc<dyn LeaseStore>,
+    ) -> Result<Self, LeadershipError> {
+        let role = section.role.parse()?;
+        let (state, _) = watch::channel(LeadershipState::Standby);
+        let (held_until, _) = watch::channel(None);
+        Ok(Self {
+            section,
+            role,
+            store,
+            failover: None,
+            state,
+            held_until,
+            started_at: Mutex::new(None),
+        })
+    }
+
+    /// Runs the promote and fence scripts through `failover`.
+    pub fn with_failover(mut self, failover: FailoverManager) -> Self {
+        self.failover = Some(failover);
+        self
+    }
+
+    pub fn node_id(&self) -> &str {
+        &self.section.node_id
+    }
+
+    pub fn role(&self) -> NodeRole {
+        self.role
+    }
+
+    pub fn state(&self) -> LeadershipState {
+        self.state.borrow().clone()
+    }
+
+    pub fn is_active(&self) -> bool {
+        matches!(*self.state.borrow(), LeadershipState::Active { .. })
+    }
+
+    /// Notified on every change of state.
+    pub fn subscribe(&self) -> watch::Receiver<LeadershipState> {
+        self.state.subscribe()
+    }
+
+    /// Heartbeats every `heartbeat_seconds`, and fences this node when its
+    /// lease is about to run out unrenewed, until the task is dropped.
+    pub async fn run(&self) {
+        tokio::join!(self.heartbeat(), self.fence_on_expiry());
+    }
+
+    async fn heartbeat(&self) {
+        let interval = StdDuration::from_secs(self.section.heartbeat_seconds.max(1));
+        loop {
+            if let Err(error) = self.tick().await {
+                warn!(node = %self.section.node_id, %error, "leadership heartbeat failed");
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+
+    /// Fences this node `store_timeout` before the lease it holds expires,
+    /// unless a renewal moved the expiry first, however long the heartbeat
+    /// is held up.
+    async fn fence_on_expiry(&self) {
+        let mut held_until = self.held_until.subscribe();
+        loop {
+            let Some(until) = *held_until.borrow_and_update() else {
+                if held_until.changed().await.is_err() {
+                    return;
+                }
+                continue;
+            };
+            let fence_at = until - self.store_margin();
+            let wait = (fence_at - Utc::now()).to_std().unwrap_or_default();
+            tokio::select! {
+                changed = held_until.changed() => {
+                    if changed.is_err() {
+                        return;
+                    }
+                }
+                _ = tokio::time::sleep(wait) => {
+                    if *held_until.borrow() == Some(until) {
+                        self.fence_expiring("lease not renewed before it ran out".to_string())
+                            .await;
+                        let state = self.state();
+                        self.record(&state, Utc::now()).await;
+                    }
+                }
+            }
+        }
+    }
+
+    pub async fn tick(&self) -> Result<LeadershipState, LeadershipError> {
+        self.tick_at(Utc::now()).await
+    }
+
+    /// Renews or contends for the lease at `now`, promoting or fencing this
+    /// node when the holder changed.
+    pub async fn tick_at(&self, now: DateTime<Utc>) -> Result<LeadershipState, LeadershipError> {
+        self.started_at.lock().unwrap().get_or_insert(now);
+        let lease = match self.contend(now).await {
+            Ok(lease) => lease,
+            Err(error) => {
+                // Without the store this node cannot tell whether another
+                // took over, so it only airs while its last renewal lasts.
+                let held_until = *self.held_until.borrow();
+                if held_until.is_some_and(|until| until - self.store_margin() <= now) {
+                    self.fence_expiring(format!("lease store unreachable: {error}"))
+                        .await;
+                }
+                return Err(error);
+            }
+        };
+        let current = self.state();
+        match lease {
+            Some(lease) if lease.holder == self.section.node_id => {
+                self.held_until.send_replace(Some(lease.expires_at));
+                if current != (LeadershipState::Active { epoch: lease.epoch }) {
+                    self.promote(lease.epoch).await;
+                }
+            }
+            lease => {
+                self.held_until.send_replace(None);
+                if let LeadershipState::Active { epoch } = current {
+                    let reason = match lease {
+                        Some(lease) => {
+                            format!("lease taken by {} (epoch {})", lease.holder, lease.epoch)
+                        }
+                        None => "lease disappeared from the store".to_string(),
+                    };
+                    self.fence(epoch, reason).await;
+                }
+            }
+        }
+        let state = self.state();
+        self.record(&state, now).await;
+        Ok(state)
+    }
+
+    /// Gives up the lease, as on shutdown, so the other node takes over
+    /// without waiting for it to expire.
+    pub async fn step_down(&self, now: DateTime<Utc>) -> Result<(), LeadershipError> {
+        if !self.is_active() {
+            return Ok(());
+        }
+        let node = self.section.node_id.clone();
+        self.with_store(move |store| store.release(&node, now))
+            .await?;
+        self.held_until.send_replace(None);
+        self.state.send_replace(LeadershipState::Standby);
+        info!(node = %self.section.node_id, "lease released");
+        self.record(&LeadershipState::Standby, now).await;
+        Ok(())
+    }
+
+    /// Resolves with the reason once this node stops being active.
+    pub(super) async fn lost(&self) -> String {
+        let mut state = self.state.subscribe();
+        loop {
+            match &*state.borrow_and_update() {
+                LeadershipState::Active { .. } => {}
+                LeadershipState::Fenced { reason, .. } => return reason.clone(),
+                LeadershipState::Standby => return "lease released".to_string(),
+            }
+            if state.changed().await.is_err() {
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+
+    /// Longest a store call may take, and how long before its lease runs
+    /// out a node that could not renew fences itself: a third of the time
+    /// between a renewal falling due and the lease expiring, so a renewal
+    /// that times out still leaves time to fence.
+    fn store_timeout(&self) -> StdDuration {
+        let slack = self
+            .section
+            .lease_seconds
+            .saturating_sub(self.section.heartbeat_seconds);
+        StdDuration::from_millis(slack * 1000 / 3).max(StdDuration::from_secs(1))
+    }
+
+    fn store_margin(&self) -> Duration {
+        Duration::from_std(self.store_timeout()).unwrap_or_else(|_| Duration::seconds(1))
+    }
+
+    /// Runs `call` against the store on the blocking pool, giving up after
+    /// `store_timeout`.
+    async fn with_store<T, F>(&self, call: F) -> Result<T, LeadershipError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&dyn LeaseStore) -> Result<T, LeadershipError> + Send + 'static,
+    {
+        let store = self.store.clone();
+        let timeout = self.store_timeout();
+        let task = tokio::task::spawn_blocking(move || call(store.as_ref()));
+        match tokio::time::timeout(timeout, task).await {
+            Ok(result) => result?,
+            Err(_) => Err(LeadershipError::Timeout(timeout)),
+        }
+    }
+
+    /// Takes the lease when this node may, or returns it as it stands.
+    async fn contend(&self, now: DateTime<Utc>) -> Result<Option<Lease>, LeadershipError> {
+        let ttl = Duration::seconds(self.section.lease_seconds as i64);
+        if self.role == NodeRole::Standby && !self.is_active() {
+            let grace = Duration::seconds(self.section.standby_grace_seconds as i64);
+            let lease = self.with_store(|store| store.lease()).await?;
+            let wait_until = match &lease {
+                Some(lease) if lease.holder == self.section.node_id || lease.released => now,
+                Some(lease) => lease.expires_at + grace,
+                None => self.started_at.lock().unwrap().unwrap_or(now) + ttl + grace,
+            };
+            if now < wait_until {
+                return Ok(lease);
+            }
+        }
+        let node = self.section.node_id.clone();
+        self.with_store(move |store| store.acquire(&node, ttl, now))
+            .await
+            .map(Some)
+    }
+
+    async fn promote(&self, epoch: i64) {
+        self.state.send_replace(LeadershipState::Active { epoch });
+        info!(node = %self.section.node_id, epoch, "lease acquired, node is active");
+        if let (Some(failover), Some(script)) = (&self.failover, &self.section.promote_script) {
+            if let Err(error) = failover.promote_failover(script).await {
+                warn!(node = %self.section.node_id, %error, "promote script failed");
+            }
+        }
+    }
+
+    /// Fences this node, still airing on a lease it could not renew.
+    async fn fence_expiring(&self, reason: String) {
+        self.held_until.send_replace(None);
+        if let LeadershipState::Active { epoch } = self.state() {
+            self.fence(epoch, reason).await;
+        }
+    }
+
+    async fn fence(&self, epoch: i64, reason: String) {
+        warn!(node = %self.section.node_id, epoch, %reason, "lease lost, fencing node");
+        self.state
+            .send_replace(LeadershipState::Fenced { epoch, reason });
+        if let (Some(failover), Some(script)) = (&self.failover, &self.section.fence_script) {
+            if let Err(error) = failover.fence(script).await {
+                warn!(node = %self.section.node_id, %error, "fence script failed");
+            }
+        }
+    }
+
+    async fn record(&self, state: &LeadershipState, now: DateTime<Utc>) {
+        let detail = match state {
+            LeadershipState::Fenced { reason, .. } => Some(reason.clone()),
+            _ => None,
+        };
+        let status = NodeHeartbeat {
+            node_id: self.section.node_id.clone(),
+            role: self.role,
+            state: state.as_str().to_string(),
+            epoch: state.epoch(),
+            detail,
+            updated_at: now,
+        };
+        if let Err(error) = self
+            .with_store(move |store| store.record_node(&status))
+            .await
+        {
+            warn!(node = %self.section.node_id, %error, "failed to record node state");
+        }
+    }
+}
@@ -1,25 +1,56 @@
+pub mod escalation;
 pub mod failover;
+pub mod leadership;
+pub mod output_monitor;
+pub mod overlay;
+pub mod preflight;
+pub mod session;
+pub mod slate;
+pub mod telemetry;
+pub mod transitions;
 pub mod watchdog;
 
+use std::collections::HashMap;
 use std::fmt;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration as StdDuration;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, Utc};
+use sha2::{Digest, Sha256};
 use tokio::fs as async_fs;
-use tokio::process::Command;
+use tokio::process::{Child, Command};
 use tracing::{debug, info, warn};
 use uuid::Uuid;
 
+use crate::config::BroadcasterHlsSection;
 use crate::{
-    BroadcasterConfig, PlayoutQueueStore, QueueEntry, QueueFilter, QueueItem, QueueMetrics,
-    QueueSelectionPolicy, QueueStatus,
+    AsRunOutcome, AsRunRecord, BreakIn, BroadcasterConfig, EmergencyRefill, Interruption,
+    MetricsStore, OverlayConfig, PlayoutQueueStore, QueueEntry, QueueFilter, QueueItem,
+    QueueMetrics, QueueSelectionPolicy, QueueStatus, RecoveryAction, RecoveryDecision,
+    RetryOutcome, RetryPolicy, SqlitePlanStore,
 };
 
 use self::failover::FailoverError;
+use self::leadership::LeaderElection;
+use self::overlay::{OverlayEntry, OverlayGraph, OverlayLayer};
+use self::preflight::Preflight;
+use self::session::{DestinationStatus, OutputDestination, OutputFormat, OutputSession};
+use self::slate::EmergencySlate;
+use self::telemetry::{read_progress, EncoderTelemetry};
+use self::transitions::{TransitionEnd, TransitionLibrary, TransitionPreparer};
 use thiserror::Error;
 
+/// `node_origin` of entries injected by the emergency loop.
+pub const EMERGENCY_LOOP_ORIGIN: &str = "emergency-loop";
+/// `node_origin` of the generated slate queued when the archive is empty.
+pub const EMERGENCY_SLATE_ORIGIN: &str = "emergency-slate";
+const EMERGENCY_LOOP_ASSETS: usize = 5;
+const EMERGENCY_BUFFER_HOURS: i64 = 1;
+
 #[derive(Debug, Error)]
 pub enum BroadcasterError {
     #[error("queue error: {0}")]
@@ -34,11 +65,26 @@ pub enum BroadcasterError {
     },
     #[error("failover error: {0}")]
     Failover(#[from] FailoverError),
+    #[error("output session lost: {0}")]
+    SessionLost(String),
+    #[error("invalid transition: {0}")]
+    InvalidTransition(String),
+    #[error("invalid destination: {0}")]
+    InvalidDestination(String),
+    #[error("invalid emergency slate: {0}")]
+    InvalidSlate(String),
+    #[error("fenced: {0}")]
+    Fenced(String),
 }
 
 #[async_trait::async_trait]
 pub trait CommandExecutor: Send + Sync {
     async fn run(&self, command: &mut Command) -> std::io::Result<std::process::Output>;
+
+    /// Starts a process without waiting for it, as used by the output session.
+    async fn spawn(&self, command: &mut Command) -> std::io::Result<Child> {
+        command.spawn()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -63,9 +109,22 @@ pub struct Broadcaster {
     queue: PlayoutQueueStore,
     config: BroadcasterConfig,
     policy: QueueSelectionPolicy,
+    retry: RetryPolicy,
     paths: BroadcasterPaths,
     executor: Arc<dyn CommandExecutor>,
+    plans: Option<SqlitePlanStore>,
+    destinations: Vec<OutputDestination>,
+    session: Option<OutputSession>,
+    transitions: TransitionPreparer,
+    overlays: OverlayLayer,
+    preflight: Preflight,
+    slate: Option<EmergencySlate>,
+    telemetry: EncoderTelemetry,
+    leadership: Option<Arc<LeaderElection>>,
+    /// Destinations the entry on air, or last aired, was published to.
+    aired_to: Mutex<Vec<String>>,
     last_entry: Mutex<Option<QueueEntry>>,
+    recovered: AtomicBool,
 }
 
 impl fmt::Debug for Broadcaster {
@@ -73,78 +132,462 @@ impl fmt::Debug for Broadcaster {
         f.debug_struct("Broadcaster")
             .field("config", &self.config)
             .field("policy", &self.policy)
+            .field("retry", &self.retry)
             .field("paths", &self.paths)
             .finish()
     }
 }
 
 impl Broadcaster {
+    /// Broadcaster airing `queue` as `config` describes; fails when the
+    /// emergency slate in `[failover.slate]` is invalid.
     pub fn new(
         queue: PlayoutQueueStore,
         config: BroadcasterConfig,
         paths: BroadcasterPaths,
         executor: Option<Arc<dyn CommandExecutor>>,
-    ) -> Self {
+    ) -> Result<Self, BroadcasterError> {
         let executor = executor.unwrap_or_else(|| Arc::new(SystemCommandExecutor));
         if let Some(parent) = paths.temp_dir.parent() {
             let _ = fs::create_dir_all(parent);
         }
+        if config.hls.native {
+            let _ = fs::create_dir_all(&config.hls.output_path);
+        }
         let policy = QueueSelectionPolicy::from_queue_config(&config.queue);
-        Self {
+        let retry = RetryPolicy::from_config(&config.queue.retry);
+        let library = TransitionLibrary::from_config(&config.transitions).unwrap_or_else(|error| {
+            warn!(%error, "ignoring invalid transition catalog");
+            TransitionLibrary::default()
+        });
+        let transitions = TransitionPreparer::new(
+            paths.ffmpeg.clone(),
+            paths.temp_dir.clone(),
+            Arc::clone(&executor),
+            library,
+        );
+        let destinations = output_destinations(&config);
+        let preflight = Preflight::new(
+            config.preflight.clone(),
+            paths.ffprobe.clone(),
+            Arc::clone(&executor),
+        );
+        let slate =
+            EmergencySlate::from_config(&config.failover.slate, &paths.temp_dir.join("slate"))?;
+        let mut broadcaster = Self {
             queue,
             config,
             policy,
+            retry,
             paths,
             executor,
+            plans: None,
+            destinations,
+            session: None,
+            transitions,
+            overlays: OverlayLayer::default(),
+            preflight,
+            slate,
+            telemetry: EncoderTelemetry::new(),
+            leadership: None,
+            aired_to: Mutex::new(Vec::new()),
             last_entry: Mutex::new(None),
+            recovered: AtomicBool::new(false),
+        };
+        if broadcaster.config.rtmp.persistent_session {
+            broadcaster.session = Some(
+                OutputSession::new(
+                    broadcaster.paths.ffmpeg.clone(),
+                    broadcaster.base_args(),
+                    broadcaster.destinations.clone(),
+                    Arc::clone(&broadcaster.executor),
+                )
+                .with_telemetry(broadcaster.telemetry.clone())
+                .with_stop_grace(StdDuration::from_millis(
+                    broadcaster.config.queue.break_in.stop_grace_ms,
+                )),
+            );
+        }
+        Ok(broadcaster)
+    }
+
+    /// Plan store used to flag plans whose queue entries went `dead`.
+    pub fn with_plan_store(mut self, plans: SqlitePlanStore) -> Self {
+        self.plans = Some(plans);
+        self
+    }
+
+    /// On-air graphics burned into entries, from `overlays.toml`.
+    pub fn with_overlays(mut self, config: OverlayConfig) -> Self {
+        self.overlays = OverlayLayer::new(config);
+        self
+    }
+
+    /// Records the encoder's progress samples in `metrics` while entries
+    /// air.
+    pub fn with_metrics_store(self, metrics: MetricsStore) -> Self {
+        self.telemetry.record_to(metrics);
+        self
+    }
+
+    /// Airs only while `election` holds the lease, and stops the entry on
+    /// air when it loses it.
+    pub fn with_leadership(mut self, election: Arc<LeaderElection>) -> Self {
+        self.leadership = Some(election);
+        self
+    }
+
+    /// Reconciles entries a previous process left `playing`. Runs once per
+    /// broadcaster, before the first selection, and may be called earlier to
+    /// inspect the decisions.
+    pub fn recover_orphaned(&self) -> Result<Vec<RecoveryDecision>, BroadcasterError> {
+        if self.recovered.swap(true, Ordering::SeqCst) {
+            return Ok(Vec::new());
         }
+        let decisions = self.queue.recover_orphaned()?;
+        for decision in &decisions {
+            match decision.action {
+                RecoveryAction::MarkedPlayed => info!(
+                    queue_id = decision.id,
+                    plan_id = %decision.plan_id,
+                    note = %decision.note,
+                    "orphaned entry marked played"
+                ),
+                RecoveryAction::Requeued => warn!(
+                    queue_id = decision.id,
+                    plan_id = %decision.plan_id,
+                    note = %decision.note,
+                    "orphaned entry requeued"
+                ),
+            }
+        }
+        Ok(decisions)
+    }
+
+    /// Output session shared by all entries, when `rtmp.persistent_session`
+    /// is enabled.
+    pub fn session(&self) -> Option<&OutputSession> {
+        self.session.as_ref()
+    }
+
+    /// Live progress of the encoder feeding the outputs.
+    pub fn telemetry(&self) -> &EncoderTelemetry {
+        &self.telemetry
+    }
+
+    /// Checks assets pass before they go live.
+    pub fn preflight(&self) -> &Preflight {
+        &self.preflight
+    }
+
+    /// Slate queued while the archive has no emergency assets, unless
+    /// disabled in `[failover.slate]`.
+    pub fn slate(&self) -> Option<&EmergencySlate> {
+        self.slate.as_ref()
+    }
+
+    /// Catalog and rules choosing the transition between entries.
+    pub fn transition_library(&self) -> &TransitionLibrary {
+        self.transitions.library()
+    }
+
+    /// Health of each publish destination. Without a persistent session
+    /// outputs only exist while an entry airs, so none report connected.
+    pub async fn destination_status(&self) -> Vec<DestinationStatus> {
+        match &self.session {
+            Some(session) => session.status().await,
+            None => self
+                .destinations
+                .iter()
+                .map(|destination| DestinationStatus {
+                    name: destination.name.clone(),
+                    url: destination.url.clone(),
+                    connected: false,
+                    started: 0,
+                    failures: 0,
+                    last_error: None,
+                    last_error_at: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// Closes the output session, if any, once fed data is flushed, and
+    /// waits for the encoder samples still being recorded.
+    pub async fn shutdown(&self) -> Result<(), BroadcasterError> {
+        self.transitions.clear();
+        let closed = match &self.session {
+            Some(session) => session.shutdown().await,
+            None => Ok(()),
+        };
+        self.telemetry.flush().await;
+        closed
+    }
+
+    /// Airs `plan` until it ends or a break-in is requested, returning the
+    /// break-in that stopped it. Losing the lease stops it with
+    /// [`BroadcasterError::Fenced`].
+    async fn play(&self, plan: &StreamingPlan) -> Result<Option<BreakIn>, BroadcasterError> {
+        let mut break_in = None;
+        let mut fenced = None;
+        let watch = async {
+            tokio::select! {
+                pending = self.wait_for_break_in() => break_in = Some(pending),
+                reason = self.wait_for_fence() => fenced = Some(reason),
+            }
+        };
+        if let Some(session) = &self.session {
+            let fed = session.play_until(&plan.inputs, &plan.output, watch).await;
+            *self.aired_to.lock().unwrap() = match &fed {
+                Ok(feed) => feed.destinations.clone(),
+                Err(_) => session.live_destinations().await,
+            };
+            fed?;
+            return match fenced {
+                Some(reason) => Err(BroadcasterError::Fenced(reason)),
+                None => Ok(break_in),
+            };
+        }
+        if self.destinations.is_empty() {
+            return Err(BroadcasterError::InvalidDestination(
+                "no output destination configured".to_string(),
+            ));
+        }
+        // A one-off run does not report which tee outputs failed.
+        *self.aired_to.lock().unwrap() = self
+            .destinations
+            .iter()
+            .map(|destination| destination.name.clone())
+            .collect();
+        let args = plan.standalone_args(self);
+        let mut command = Command::new(&self.paths.ffmpeg);
+        // A one-off run has no control channel, so a break-in cuts it.
+        command.args(&args).kill_on_drop(true);
+        let output = tokio::select! {
+            output = self.executor.run(&mut command) => output?,
+            _ = watch => {
+                return match fenced {
+                    Some(reason) => Err(BroadcasterError::Fenced(reason)),
+                    None => Ok(break_in),
+                };
+            }
+        };
+        // A one-off run only hands over its progress once it exits.
+        let feeder = read_progress(output.stderr.as_slice(), &self.telemetry).await?;
+        if !output.status.success() {
+            return Err(BroadcasterError::CommandFailure {
+                command: format!("{} {}", self.paths.ffmpeg.display(), args.join(" ")),
+                status: output.status.code(),
+                stderr: feeder.log,
+            });
+        }
+        Ok(None)
+    }
+
+    /// Resolves once a break-in is pending. Lookup errors are logged and
+    /// retried on the next poll.
+    async fn wait_for_break_in(&self) -> BreakIn {
+        let poll = StdDuration::from_millis(self.config.queue.break_in.poll_ms.max(10));
+        loop {
+            tokio::time::sleep(poll).await;
+            match self.queue.pending_break_in() {
+                Ok(Some(break_in)) => return break_in,
+                Ok(None) => {}
+                Err(err) => warn!(error = %err, "failed to check for break-ins"),
+            }
+        }
+    }
+
+    /// Resolves once this node loses the lease; never without an election.
+    async fn wait_for_fence(&self) -> String {
+        match &self.leadership {
+            Some(election) => election.lost().await,
+            None => std::future::pending().await,
+        }
+    }
+
+    /// Names of the destinations the entry went out to, comma-separated.
+    fn aired_to(&self) -> Option<String> {
+        let aired_to = self.aired_to.lock().unwrap();
+        (!aired_to.is_empty()).then(|| aired_to.join(","))
+    }
+
+    fn append_as_run(&self, record: &AsRunRecord) {
+        if let Err(err) = self.queue.record_as_run(record) {
+            warn!(plan_id = %record.plan_id, error = %err, "failed to append as-run record");
+        }
+    }
+
+    async fn as_run_record(
+        &self,
+        entry: &QueueEntry,
+        plan: &StreamingPlan,
+        outcome: AsRunOutcome,
+        started_at: DateTime<Utc>,
+        finished_at: DateTime<Utc>,
+    ) -> AsRunRecord {
+        let asset = PathBuf::from(&entry.asset_path);
+        let checksum = tokio::task::spawn_blocking(move || asset_checksum(&asset))
+            .await
+            .ok()
+            .flatten();
+        let mut record = AsRunRecord::from_entry(entry, outcome, started_at);
+        record.asset_checksum = checksum;
+        record.destination = self.aired_to();
+        record.transition = Some(plan.transition.clone());
+        record.finished_at = Some(finished_at);
+        record
     }
 
     pub async fn run_once(&self) -> Result<Option<BroadcasterEvent>, BroadcasterError> {
+        if let Some(election) = &self.leadership {
+            if !election.is_active() {
+                debug!(node = %election.node_id(), state = %election.state(), "standing by");
+                return Ok(None);
+            }
+        }
+        self.recover_orphaned()?;
         let metrics = self.queue.metrics()?;
         self.ensure_emergency_buffer(&metrics).await?;
 
-        let previous = { self.last_entry.lock().unwrap().clone() };
-        let Some(mut current) = self.queue.begin_playback(&self.policy)? else {
+        let Some(mut current) = self.next_entry()? else {
             debug!("playout queue empty");
             return Ok(None);
         };
+        // A break-in stops the entry on air and puts the urgent one in its
+        // place, which may itself be broken into. An entry failing pre-flight
+        // gives way to the next candidate before anything airs.
+        loop {
+            let urgent = if self.passes_preflight(&current).await? {
+                match self.air(current).await? {
+                    Aired::Finished(event) => return Ok(Some(event)),
+                    Aired::BrokenInto(urgent) => urgent.map(|entry| *entry),
+                }
+            } else {
+                None
+            };
+            current = match urgent {
+                Some(entry) => entry,
+                None => match self.next_entry()? {
+                    Some(entry) => entry,
+                    None => return Ok(None),
+                },
+            };
+        }
+    }
 
-        let plan = self.compose_plan(previous.as_ref(), &current).await?;
-        let started_at = current.play_started_at.unwrap_or_else(Utc::now);
+    /// Checks `entry`'s asset before it airs. A failing entry counts as a
+    /// failed playout, so it is retried after the backoff or ends up dead.
+    async fn passes_preflight(&self, entry: &QueueEntry) -> Result<bool, BroadcasterError> {
+        if !self.preflight.enabled() {
+            return Ok(true);
+        }
+        let Err(failure) = self.preflight.verify(Path::new(&entry.asset_path)).await else {
+            return Ok(true);
+        };
+        warn!(
+            queue_id = entry.id,
+            plan_id = %entry.plan_id,
+            asset = %entry.asset_path,
+            %failure,
+            "asset failed pre-flight, entry skipped"
+        );
+        let reason = format!("pre-flight: {failure}");
+        let outcome = self.queue.record_failure(entry.id, &reason, &self.retry)?;
+        self.handle_failure_outcome(entry, outcome);
+        Ok(false)
+    }
 
-        let mut command = Command::new(&self.paths.ffmpeg);
-        for arg in &plan.args {
-            command.arg(arg);
+    /// A pending break-in first, then normal selection.
+    fn next_entry(&self) -> Result<Option<QueueEntry>, BroadcasterError> {
+        while let Some(break_in) = self.queue.pending_break_in()? {
+            if let Some(entry) = self.queue.begin_break_in(&break_in, None)? {
+                info!(
+                    break_in = break_in.id,
+                    queue_id = entry.id,
+                    plan_id = %entry.plan_id,
+                    "break-in on air"
+                );
+                return Ok(Some(entry));
+            }
+            warn!(
+                break_in = break_in.id,
+                "break-in entry left the queue, skipped"
+            );
         }
-        let output = self
-            .executor
-            .run(&mut command)
-            .await
-            .map_err(BroadcasterError::Io)?;
-        if !output.status.success() {
-            let stderr = String::from_utf8_lossy(&output.stderr).to_string();
-            self.queue.mark_playback_result(
-                current.id,
-                QueueStatus::Failed,
-                None,
-                Some(&stderr),
-            )?;
-            plan.cleanup().await;
-            return Err(BroadcasterError::CommandFailure {
-                command: format!("{} {}", self.paths.ffmpeg.display(), plan.args.join(" ")),
-                status: output.status.code(),
-                stderr,
-            });
+        Ok(self.queue.begin_playback(&self.policy)?)
+    }
+
+    async fn air(&self, mut current: QueueEntry) -> Result<Aired, BroadcasterError> {
+        let previous = { self.last_entry.lock().unwrap().clone() };
+        let next = self.peek_next(&current);
+        let mut plan = self.compose_plan(previous.as_ref(), &current).await;
+        self.apply_overlays(&mut plan, &current, next.as_ref())
+            .await;
+        if let Some(next) = &next {
+            self.preflight.warm(PathBuf::from(&next.asset_path));
+            self.prepare_next_transition(&current, next);
         }
+        let started_at = current.play_started_at.unwrap_or_else(Utc::now);
+
+        self.telemetry.set_entry(Some(current.id));
+        let played = self.play(&plan).await;
+        self.telemetry.set_entry(None);
+        let break_in = match played {
+            Ok(break_in) => break_in,
+            Err(BroadcasterError::Fenced(reason)) => {
+                self.fence(&current, &plan, &reason, started_at, Utc::now())
+                    .await?;
+                return Err(BroadcasterError::Fenced(reason));
+            }
+            Err(err) => {
+                let reason = match &err {
+                    BroadcasterError::CommandFailure { stderr, .. } => stderr.clone(),
+                    other => other.to_string(),
+                };
+                let mut record = self
+                    .as_run_record(
+                        &current,
+                        &plan,
+                        AsRunOutcome::Failed,
+                        started_at,
+                        Utc::now(),
+                    )
+                    .await;
+                record.note = reason.lines().last().map(str::to_string);
+                self.append_as_run(&record);
+                let outcome = self
+                    .queue
+                    .record_failure(current.id, &reason, &self.retry)?;
+                self.handle_failure_outcome(&current, outcome);
+                plan.cleanup().await;
+                return Err(err);
+            }
+        };
 
         let finished_at = Utc::now();
+        if let Some(break_in) = break_in {
+            let urgent = self
+                .break_in(&current, &plan, &break_in, started_at, finished_at)
+                .await?;
+            return Ok(Aired::BrokenInto(urgent.map(Box::new)));
+        }
         self.queue.mark_playback_result(
             current.id,
             QueueStatus::Played,
             current.duration_s,
             None,
         )?;
+        let record = self
+            .as_run_record(
+                &current,
+                &plan,
+                AsRunOutcome::Played,
+                started_at,
+                finished_at,
+            )
+            .await;
+        self.append_as_run(&record);
         plan.cleanup().await;
 
         let buffer = self.queue.metrics()?.buffer_duration_hours;
@@ -156,65 +599,272 @@ impl Broadcaster {
         }
 
         info!(plan_id = %current.plan_id, buffer_hours = buffer, "playout completed");
-        Ok(Some(BroadcasterEvent {
+        Ok(Aired::Finished(BroadcasterEvent {
             queue_id: current.id,
             plan_id: current.plan_id,
             status: QueueStatus::Played,
             started_at,
             finished_at,
             buffer_hours_after: buffer,
-            destination: self.config.rtmp.origin.clone(),
+            destination: self.aired_to().unwrap_or_default(),
         }))
     }
 
+    /// Settles `current` after `break_in` stopped it and takes the urgent
+    /// entry to air. Returns `None` when the break-in was withdrawn or its
+    /// entry left the queue meanwhile.
+    async fn break_in(
+        &self,
+        current: &QueueEntry,
+        plan: &StreamingPlan,
+        break_in: &BreakIn,
+        started_at: DateTime<Utc>,
+        stopped_at: DateTime<Utc>,
+    ) -> Result<Option<QueueEntry>, BroadcasterError> {
+        let aired = (stopped_at - started_at).num_milliseconds().max(0) as f64 / 1000.0;
+        let offset_s = current.resume_offset_s.unwrap_or(0.0) + aired;
+        let interruption = Interruption {
+            queue_id: current.id,
+            offset_s,
+        };
+        let urgent = self.queue.begin_break_in(break_in, Some(interruption))?;
+        let mut record = self
+            .as_run_record(
+                current,
+                plan,
+                AsRunOutcome::Interrupted,
+                started_at,
+                stopped_at,
+            )
+            .await;
+        record.note = Some(format!(
+            "break-in #{} at {offset_s:.1}s ({}){}",
+            break_in.id,
+            break_in.policy,
+            break_in
+                .reason
+                .as_deref()
+                .map(|reason| format!(": {reason}"))
+                .unwrap_or_default()
+        ));
+        self.append_as_run(&record);
+        plan.cleanup().await;
+        // The transition prepared out of `current` no longer applies.
+        self.transitions.clear();
+        *self.last_entry.lock().unwrap() = None;
+        warn!(
+            break_in = break_in.id,
+            queue_id = current.id,
+            plan_id = %current.plan_id,
+            offset_s,
+            policy = %break_in.policy,
+            "entry stopped for break-in"
+        );
+        Ok(urgent)
+    }
+
+    /// Settles `current` after this node lost the lease while it aired: it
+    /// goes back to the head of the queue to resume where it stopped, and
+    /// the outputs are closed.
+    async fn fence(
+        &self,
+        current: &QueueEntry,
+        plan: &StreamingPlan,
+        reason: &str,
+        started_at: DateTime<Utc>,
+        stopped_at: DateTime<Utc>,
+    ) -> Result<(), BroadcasterError> {
+        let aired = (stopped_at - started_at).num_milliseconds().max(0) as f64 / 1000.0;
+        let offset_s = current.resume_offset_s.unwrap_or(0.0) + aired;
+        self.queue.suspend_playback(Interruption {
+            queue_id: current.id,
+            offset_s,
+        })?;
+        let mut record = self
+            .as_run_record(
+                current,
+                plan,
+                AsRunOutcome::Interrupted,
+                started_at,
+                stopped_at,
+            )
+            .await;
+        record.note = Some(format!("fenced at {offset_s:.1}s: {reason}"));
+        self.append_as_run(&record);
+        plan.cleanup().await;
+        *self.last_entry.lock().unwrap() = None;
+        warn!(
+            queue_id = current.id,
+            plan_id = %current.plan_id,
+            offset_s,
+            %reason,
+            "entry stopped, node fenced"
+        );
+        self.shutdown().await
+    }
+
+    fn handle_failure_outcome(&self, entry: &QueueEntry, outcome: RetryOutcome) {
+        match outcome {
+            RetryOutcome::Requeued {
+                retry_count,
+                next_attempt_at,
+            } => {
+                warn!(
+                    plan_id = %entry.plan_id,
+                    retry_count,
+                    next_attempt_at = %next_attempt_at,
+                    "playout failed, entry requeued"
+                );
+            }
+            RetryOutcome::Dead { attempts } => {
+                warn!(plan_id = %entry.plan_id, attempts, "playout failed, entry is dead");
+                let Some(plans) = &self.plans else {
+                    return;
+                };
+                let note = format!(
+                    "playout failed {attempts} times; queue entry {} dead",
+                    entry.id
+                );
+                if let Err(err) = plans.fail_plan(&entry.plan_id, note) {
+                    warn!(plan_id = %entry.plan_id, error = %err, "failed to flag dead plan");
+                }
+            }
+        }
+    }
+
     async fn compose_plan(
         &self,
         previous: Option<&QueueEntry>,
         current: &QueueEntry,
-    ) -> Result<StreamingPlan, BroadcasterError> {
-        let planner = TransitionPlanner { broadcaster: self };
-        planner.compose(previous, current).await
+    ) -> StreamingPlan {
+        // Prepared transitions start the entry from the top.
+        if let (Some(previous), None) = (previous, current.resume_offset_s) {
+            if let Some(plan) = self.transitions.take(previous.id, current.id).await {
+                return plan;
+            }
+        }
+        StreamingPlan::direct(current)
+    }
+
+    /// Queue entry the selection would air once `current` ends.
+    fn peek_next(&self, current: &QueueEntry) -> Option<QueueEntry> {
+        let ends_at = current.play_started_at.unwrap_or_else(Utc::now)
+            + Duration::seconds(current.remaining_duration_s().unwrap_or(0));
+        match self.queue.peek_next_at(&self.policy, current.id, ends_at) {
+            Ok(next) => next,
+            Err(err) => {
+                warn!(plan_id = %current.plan_id, error = %err, "failed to look up next entry");
+                None
+            }
+        }
+    }
+
+    /// Starts rendering the transition out of `current` into `next` while
+    /// `current` airs.
+    fn prepare_next_transition(&self, current: &QueueEntry, next: &QueueEntry) {
+        // Entries of unknown length have no tail to fade from.
+        let (Some(duration_s), Some(next_duration_s)) = (
+            current.duration_s,
+            next.duration_s.filter(|seconds| *seconds > 0),
+        ) else {
+            return;
+        };
+        self.transitions.prepare(
+            TransitionEnd {
+                queue_id: current.id,
+                asset_path: current.asset_path.clone(),
+                content_kind: current.content_kind.clone(),
+                duration_s,
+            },
+            TransitionEnd {
+                queue_id: next.id,
+                asset_path: next.asset_path.clone(),
+                content_kind: next.content_kind.clone(),
+                duration_s: next_duration_s,
+            },
+        );
+    }
+
+    /// Burns the configured graphics into `plan`. Entries air without them
+    /// when the text files cannot be written.
+    async fn apply_overlays(
+        &self,
+        plan: &mut StreamingPlan,
+        current: &QueueEntry,
+        next: Option<&QueueEntry>,
+    ) {
+        let title = self.plan_title(&current.plan_id);
+        let up_next = next.and_then(|entry| self.plan_title(&entry.plan_id));
+        let entry = OverlayEntry {
+            content_kind: current.content_kind.as_deref(),
+            title: title.as_deref(),
+            duration_s: current.remaining_duration_s(),
+            up_next: up_next.as_deref(),
+        };
+        let key = format!("{}_overlay", current.id);
+        // Entries without graphics still go through the encoder so the
+        // output does not switch between encoded and copied video.
+        let graph = match self.overlays.graph(&entry, &self.paths.temp_dir, &key) {
+            Some(graph) => match write_text_files(&graph.text_files).await {
+                Ok(()) => graph,
+                Err(err) => {
+                    warn!(plan_id = %current.plan_id, error = %err, "failed to write overlay text, airing without graphics");
+                    OverlayGraph::passthrough()
+                }
+            },
+            None if self.overlays.is_enabled() => OverlayGraph::passthrough(),
+            None => return,
+        };
+        plan.apply_overlay(graph);
+    }
+
+    fn plan_title(&self, plan_id: &str) -> Option<String> {
+        let plans = self.plans.as_ref()?;
+        match plans.fetch_by_id(plan_id) {
+            Ok(plan) => plan?.title,
+            Err(err) => {
+                debug!(plan_id, error = %err, "failed to look up plan title");
+                None
+            }
+        }
     }
 
     async fn ensure_emergency_buffer(
         &self,
         metrics: &QueueMetrics,
     ) -> Result<(), BroadcasterError> {
-        if metrics.buffer_duration_hours >= 1.0 {
+        if metrics.buffer_duration_hours >= EMERGENCY_BUFFER_HOURS as f64 {
             return Ok(());
         }
         let queued = self.queue.list(&QueueFilter {
             status: Some(QueueStatus::Queued),
             limit: Some(50),
         })?;
-        if queued
-            .iter()
-            .any(|item| item.node_origin.as_deref() == Some("emergency-loop"))
-        {
+        if queued.iter().any(|item| {
+            matches!(
+                item.node_origin.as_deref(),
+                Some(EMERGENCY_LOOP_ORIGIN | EMERGENCY_SLATE_ORIGIN)
+            )
+        }) {
             return Ok(());
         }
 
-        let assets = self.collect_emergency_assets()?;
+        let assets = collect_emergency_assets(&self.paths.archive_dir)?;
         if assets.is_empty() {
             warn!(
                 "no emergency assets found in {}",
                 self.paths.archive_dir.display()
             );
+            // The slate only fills dead air; it never pads a short buffer.
+            if metrics.queue_length == 0 {
+                self.queue_slate().await;
+            }
             return Ok(());
         }
         let mut injected = 0;
-        for asset in assets.into_iter().take(5) {
+        for asset in assets.into_iter().take(EMERGENCY_LOOP_ASSETS) {
             let duration = self.probe_duration(&asset).await.ok();
-            let plan_id = format!("emergency-{}", Uuid::new_v4());
-            let item = QueueItem {
-                plan_id,
-                asset_path: asset.to_string_lossy().to_string(),
-                duration_s: duration,
-                curation_score: Some(0.2),
-                priority: 1,
-                node_origin: Some("emergency-loop".into()),
-                content_kind: Some("music".into()),
-            };
+            let item = emergency_loop_item(&asset, duration);
             if self.queue.enqueue(&item).is_ok() {
                 injected += 1;
             }
@@ -225,20 +875,27 @@ impl Broadcaster {
         Ok(())
     }
 
-    fn collect_emergency_assets(&self) -> std::io::Result<Vec<PathBuf>> {
-        let mut entries = Vec::new();
-        if self.paths.archive_dir.exists() {
-            for entry in fs::read_dir(&self.paths.archive_dir)? {
-                let entry = entry?;
-                let path = entry.path();
-                if path.extension().map(|ext| ext == "mp4").unwrap_or(false) {
-                    entries.push(path);
-                }
+    async fn queue_slate(&self) {
+        let Some(slate) = &self.slate else {
+            return;
+        };
+        let path = match slate
+            .ensure(&self.paths.ffmpeg, self.executor.as_ref())
+            .await
+        {
+            Ok(path) => path,
+            Err(err) => {
+                warn!(error = %err, "failed to render emergency slate");
+                return;
             }
+        };
+        let item = emergency_slate_item(&path, slate.duration_s as i64);
+        match self.queue.enqueue(&item) {
+            Ok(id) => {
+                info!(queue_id = id, style = %slate.style, "emergency slate injected to queue")
+            }
+            Err(err) => warn!(error = %err, "failed to queue emergency slate"),
         }
-        entries.sort();
-        entries.reverse();
-        Ok(entries)
     }
 
     async fn probe_duration(&self, asset: &Path) -> Result<i64, BroadcasterError> {
@@ -301,6 +958,13 @@ impl Broadcaster {
     }
 }
 
+/// How an entry left the air.
+enum Aired {
+    Finished(BroadcasterEvent),
+    /// Stopped by a break-in; holds the urgent entry now playing.
+    BrokenInto(Option<Box<QueueEntry>>),
+}
+
 #[derive(Debug, Clone)]
 pub struct BroadcasterEvent {
     pub queue_id: i64,
@@ -309,178 +973,406 @@ pub struct BroadcasterEvent {
     pub started_at: DateTime<Utc>,
     pub finished_at: DateTime<Utc>,
     pub buffer_hours_after: f64,
+    /// Destinations the entry went out to, comma-separated.
     pub destination: String,
 }
 
-struct TransitionPlanner<'a> {
-    broadcaster: &'a Broadcaster,
+struct StreamingPlan {
+    /// ffmpeg input arguments for the entry; the output side depends on
+    /// whether it airs through the persistent session.
+    inputs: Vec<String>,
+    /// Codec arguments after the inputs; stream copy unless overlays are
+    /// enabled.
+    output: Vec<String>,
+    cleanup: Vec<PathBuf>,
+    /// Transition into the entry, as recorded in the as-run log.
+    transition: String,
 }
 
-impl<'a> TransitionPlanner<'a> {
-    async fn compose(
-        &self,
-        previous: Option<&QueueEntry>,
-        current: &QueueEntry,
-    ) -> Result<StreamingPlan, BroadcasterError> {
-        if let Some(prev) = previous {
-            if let Some(plan) = self.try_crossfade(prev, current).await? {
-                return Ok(plan);
-            }
+impl StreamingPlan {
+    fn new(inputs: Vec<String>, cleanup: Vec<PathBuf>, transition: String) -> Self {
+        Self {
+            inputs,
+            output: vec!["-c".to_string(), "copy".to_string()],
+            cleanup,
+            transition,
         }
-        Ok(self.direct_plan(current))
     }
 
-    fn direct_plan(&self, current: &QueueEntry) -> StreamingPlan {
-        let mut args = self.broadcaster.base_args();
-        args.push("-re".to_string());
-        args.push("-i".to_string());
-        args.push(current.asset_path.clone());
-        args.push("-c".to_string());
-        args.push("copy".to_string());
-        args.push("-f".to_string());
-        args.push("flv".to_string());
-        args.push(self.broadcaster.config.rtmp.origin.clone());
-        StreamingPlan::new(args, vec![])
+    fn apply_overlay(&mut self, graph: OverlayGraph) {
+        self.inputs.extend(graph.inputs.iter().cloned());
+        self.output = graph.output_args();
+        self.cleanup
+            .extend(graph.text_files.into_iter().map(|(path, _)| path));
     }
 
-    async fn ensure_temp_dir(&self) -> Result<PathBuf, BroadcasterError> {
-        let dir = &self.broadcaster.paths.temp_dir;
-        async_fs::create_dir_all(dir).await?;
-        Ok(dir.clone())
+    /// Plays `current` on its own, cutting from whatever aired before. An
+    /// entry stopped by a break-in picks up where it left off.
+    fn direct(current: &QueueEntry) -> Self {
+        let mut inputs = Vec::new();
+        if let Some(offset) = current.resume_offset_s.filter(|offset| *offset > 0.0) {
+            inputs.push("-ss".to_string());
+            inputs.push(format!("{offset:.3}"));
+        }
+        inputs.extend([
+            "-re".to_string(),
+            "-i".to_string(),
+            current.asset_path.clone(),
+        ]);
+        Self::new(inputs, vec![], "cut".to_string())
     }
 
-    async fn try_crossfade(
-        &self,
-        previous: &QueueEntry,
-        current: &QueueEntry,
-    ) -> Result<Option<StreamingPlan>, BroadcasterError> {
-        let prev_duration = previous.duration_s.unwrap_or(0);
-        let current_duration = current.duration_s.unwrap_or(0);
-        if prev_duration < 2 || current_duration < 2 {
-            return Ok(None);
+    /// Arguments for a one-off ffmpeg that publishes the entry by itself.
+    fn standalone_args(&self, broadcaster: &Broadcaster) -> Vec<String> {
+        let mut args = broadcaster.base_args();
+        for arg in ["-progress", "pipe:2", "-nostats"] {
+            args.push(arg.to_string());
         }
-        let prev_offset = (prev_duration as f64 - 0.4).max(0.0);
-        if prev_offset <= 0.0 {
-            return Ok(None);
+        args.extend(self.inputs.iter().cloned());
+        args.extend(self.output.iter().cloned());
+        match broadcaster.destinations.as_slice() {
+            [single] => {
+                args.push("-f".to_string());
+                args.push(single.format.as_str().to_string());
+                args.extend(single.muxer_args.iter().cloned());
+                args.push(single.url.clone());
+            }
+            destinations => {
+                // The tee muxer needs explicit maps; `onfail=ignore` keeps the
+                // other destinations up when one fails.
+                if !self.output.iter().any(|arg| arg == "-map") {
+                    for arg in ["-map", "0:v?", "-map", "0:a?"] {
+                        args.push(arg.to_string());
+                    }
+                }
+                let slaves: Vec<String> = destinations
+                    .iter()
+                    .map(|destination| {
+                        format!(
+                            "[f={}:onfail=ignore]{}",
+                            destination.format.as_str(),
+                            destination.url
+                        )
+                    })
+                    .collect();
+                args.push("-f".to_string());
+                args.push("tee".to_string());
+                args.push(slaves.join("|"));
+            }
         }
-        let temp_dir = self.ensure_temp_dir().await?;
-        let transition_path =
-            temp_dir.join(format!("{}_to_{}_transition.mp4", previous.id, current.id));
-        let trimmed_path = temp_dir.join(format!("{}_trimmed.mp4", current.id));
-        let playlist_path = temp_dir.join(format!("{}_playlist.txt", current.id));
-
-        let filter = format!(
-            "[0:v]trim=start={prev_offset},setpts=PTS-STARTPTS[v0];\
-             [0:a]atrim=start={prev_offset},asetpts=PTS-STARTPTS[a0];\
-             [1:v]trim=end=0.4,setpts=PTS-STARTPTS[v1];\
-             [1:a]atrim=end=0.4,asetpts=PTS-STARTPTS[a1];\
-             [v0][v1]xfade=transition=fade:duration=0.4:offset=0[vout];\
-             [a0][a1]acrossfade=d=0.4:c1=sin:c2=sin[aout]"
-        );
+        args
+    }
 
-        let transition_args = vec![
-            "-y".to_string(),
-            "-i".to_string(),
-            previous.asset_path.clone(),
-            "-i".to_string(),
-            current.asset_path.clone(),
-            "-filter_complex".to_string(),
-            filter,
-            "-map".to_string(),
-            "[vout]".to_string(),
-            "-map".to_string(),
-            "[aout]".to_string(),
-            "-c:v".to_string(),
-            "libx264".to_string(),
-            "-preset".to_string(),
-            "veryfast".to_string(),
-            "-crf".to_string(),
-            "20".to_string(),
-            "-c:a".to_string(),
-            "aac".to_string(),
-            "-ar".to_string(),
-            "48000".to_string(),
-            "-ac".to_string(),
-            "2".to_string(),
-            "-movflags".to_string(),
-            "+faststart".to_string(),
-            transition_path.to_string_lossy().to_string(),
-        ];
-        self.broadcaster
-            .run_external(&self.broadcaster.paths.ffmpeg, &transition_args)
-            .await?;
-
-        let trim_args = vec![
-            "-y".to_string(),
-            "-ss".to_string(),
-            "0.4".to_string(),
-            "-i".to_string(),
-            current.asset_path.clone(),
-            "-c:v".to_string(),
-            "libx264".to_string(),
-            "-preset".to_string(),
-            "veryfast".to_string(),
-            "-profile:v".to_string(),
-            "high".to_string(),
-            "-pix_fmt".to_string(),
-            "yuv420p".to_string(),
-            "-c:a".to_string(),
-            "aac".to_string(),
-            "-ar".to_string(),
-            "48000".to_string(),
-            "-ac".to_string(),
-            "2".to_string(),
-            "-movflags".to_string(),
-            "+faststart".to_string(),
-            trimmed_path.to_string_lossy().to_string(),
-        ];
-        self.broadcaster
-            .run_external(&self.broadcaster.paths.ffmpeg, &trim_args)
-            .await?;
-
-        let playlist_content = format!(
-            "file '{}'\nfile '{}'\n",
-            transition_path.display(),
-            trimmed_path.display()
+    async fn cleanup(&self) {
+        for path in &self.cleanup {
+            if let Err(error) = async_fs::remove_file(path).await {
+                debug!(path = %path.display(), %error, "failed to remove temp artifact");
+            }
+        }
+    }
+}
+
+/// The RTMP origin followed by the enabled simulcast destinations.
+fn output_destinations(config: &BroadcasterConfig) -> Vec<OutputDestination> {
+    let rtmp = &config.rtmp;
+    let mut destinations = Vec::new();
+    if rtmp.enabled {
+        destinations.push(
+            OutputDestination::new("origin", rtmp.origin.clone()).with_reconnect(
+                rtmp.reconnect_attempts,
+                StdDuration::from_millis(rtmp.reconnect_delay_ms as u64),
+            ),
         );
-        async_fs::write(&playlist_path, playlist_content).await?;
-
-        let mut args = self.broadcaster.base_args();
-        args.push("-re".to_string());
-        args.push("-f".to_string());
-        args.push("concat".to_string());
-        args.push("-safe".to_string());
-        args.push("0".to_string());
-        args.push("-i".to_string());
-        args.push(playlist_path.to_string_lossy().to_string());
-        args.push("-c".to_string());
-        args.push("copy".to_string());
-        args.push("-f".to_string());
-        args.push("flv".to_string());
-        args.push(self.broadcaster.config.rtmp.origin.clone());
-
-        Ok(Some(StreamingPlan::new(
-            args,
-            vec![transition_path, trimmed_path, playlist_path],
-        )))
     }
+    if config.hls.native {
+        match hls_destination(&config.hls) {
+            Ok(destination) => destinations.push(destination.with_reconnect(
+                rtmp.reconnect_attempts,
+                StdDuration::from_millis(rtmp.reconnect_delay_ms as u64),
+            )),
+            Err(error) => warn!(%error, "native HLS output disabled"),
+        }
+    }
+    for section in rtmp.destinations.iter().filter(|section| section.enabled) {
+        let mut destination = OutputDestination::new(section.name.clone(), section.url.clone())
+            .with_reconnect(
+                section
+                    .reconnect_attempts
+                    .unwrap_or(rtmp.reconnect_attempts),
+                StdDuration::from_millis(
+                    section
+                        .reconnect_delay_ms
+                        .unwrap_or(rtmp.reconnect_delay_ms) as u64,
+                ),
+            );
+        if let Some(format) = &section.format {
+            match format.parse::<OutputFormat>() {
+                Ok(format) => destination = destination.with_format(format),
+                Err(error) => {
+                    warn!(destination = %section.name, %error, "skipping simulcast destination");
+                    continue;
+                }
+            }
+        }
+        destinations.push(destination);
+    }
+    destinations
 }
 
-struct StreamingPlan {
-    args: Vec<String>,
-    cleanup: Vec<PathBuf>,
+/// Rolling live playlist written straight from the session, as configured
+/// in `[hls]`.
+pub fn hls_destination(hls: &BroadcasterHlsSection) -> Result<OutputDestination, BroadcasterError> {
+    let segment_duration = hls.segment_duration.max(1);
+    let window = parse_window_seconds(&hls.playlist_length).ok_or_else(|| {
+        BroadcasterError::InvalidDestination(format!(
+            "invalid hls playlist_length {}",
+            hls.playlist_length
+        ))
+    })?;
+    let (segment_type, extension) = match hls.segment_type.as_str() {
+        "fmp4" => ("fmp4", "m4s"),
+        "ts" | "mpegts" => ("mpegts", "ts"),
+        other => {
+            return Err(BroadcasterError::InvalidDestination(format!(
+                "unknown hls segment_type {other}"
+            )))
+        }
+    };
+    let dir = Path::new(&hls.output_path);
+    // The window rolls, so old segments are removed as they leave it. A
+    // restarted output appends to the playlist it left, continuing the media
+    // sequence behind a discontinuity.
+    let mut flags = hls.flags.clone();
+    for required in ["delete_segments", "append_list", "discont_start"] {
+        if !flags.iter().any(|flag| flag == required) {
+            flags.push(required.to_string());
+        }
+    }
+    let mut args = vec![
+        "-hls_time".to_string(),
+        segment_duration.to_string(),
+        "-hls_list_size".to_string(),
+        (window / segment_duration as u64).max(1).to_string(),
+        "-hls_segment_type".to_string(),
+        segment_type.to_string(),
+        "-hls_flags".to_string(),
+        flags.join("+"),
+        // Without a playlist to append to, numbering from the clock still
+        // keeps the sequence ahead of every earlier run.
+        "-hls_start_number_source".to_string(),
+        "epoch".to_string(),
+        "-hls_segment_filename".to_string(),
+        dir.join(format!("segment_%06d.{extension}"))
+            .to_string_lossy()
+            .to_string(),
+    ];
+    if segment_type == "fmp4" {
+        args.push("-hls_fmp4_init_filename".to_string());
+        args.push("init.mp4".to_string());
+    }
+    let playlist = dir.join(&hls.playlist_name);
+    Ok(
+        OutputDestination::new("hls", playlist.to_string_lossy().to_string())
+            .with_format(OutputFormat::Hls)
+            .with_muxer_args(args),
+    )
 }
 
-impl StreamingPlan {
-    fn new(args: Vec<String>, cleanup: Vec<PathBuf>) -> Self {
-        Self { args, cleanup }
+/// Seconds in a span such as `"48m"`, `"2h"`, `"90s"` or `"2880"`.
+fn parse_window_seconds(span: &str) -> Option<u64> {
+    let span = span.trim();
+    let (number, unit) = match span.char_indices().last()? {
+        (index, unit) if unit.is_ascii_alphabetic() => (&span[..index], unit),
+        _ => (span, 's'),
+    };
+    let value: u64 = number.trim().parse().ok()?;
+    let multiplier = match unit.to_ascii_lowercase() {
+        's' => 1,
+        'm' => 60,
+        'h' => 3_600,
+        _ => return None,
+    };
+    Some(value * multiplier).filter(|seconds| *seconds > 0)
+}
+
+async fn write_text_files(files: &[(PathBuf, String)]) -> std::io::Result<()> {
+    for (path, text) in files {
+        if let Some(dir) = path.parent() {
+            async_fs::create_dir_all(dir).await?;
+        }
+        async_fs::write(path, text).await?;
     }
+    Ok(())
+}
 
-    async fn cleanup(&self) {
-        for path in &self.cleanup {
-            if let Err(error) = async_fs::remove_file(path).await {
-                debug!(path = %path.display(), %error, "failed to remove temp artifact");
+/// SHA-256 of `asset`, taken from the processor's `checksums.json` next to
+/// it when listed there and computed from the file otherwise.
+fn asset_checksum(asset: &Path) -> Option<String> {
+    let name = asset.file_name()?.to_str()?;
+    let listed = asset
+        .parent()
+        .map(|dir| dir.join("checksums.json"))
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice::<HashMap<String, String>>(&bytes).ok())
+        .and_then(|mut checksums| checksums.remove(name));
+    if listed.is_some() {
+        return listed;
+    }
+    let mut file = fs::File::open(asset).ok()?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer).ok()?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Some(hex::encode(hasher.finalize()))
+}
+
+/// Archived renditions eligible for the emergency loop, best QC first.
+///
+/// Renditions sit in `archive_dir` or in a processor ready directory one
+/// level below it; ties, such as assets without QC metadata, go newest name
+/// first.
+pub fn collect_emergency_assets(archive_dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    if archive_dir.exists() {
+        for entry in fs::read_dir(archive_dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                for nested in fs::read_dir(&path)? {
+                    let nested = nested?.path();
+                    if is_rendition(&nested) {
+                        entries.push(nested);
+                    }
+                }
+            } else if is_rendition(&path) {
+                entries.push(path);
             }
         }
     }
+    let mut weighted: Vec<(f64, PathBuf)> = entries
+        .into_iter()
+        .map(|path| (qc_weight(&path), path))
+        .collect();
+    weighted.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| b.1.cmp(&a.1)));
+    Ok(weighted.into_iter().map(|(_, path)| path).collect())
+}
+
+fn is_rendition(path: &Path) -> bool {
+    path.extension().map(|ext| ext == "mp4").unwrap_or(false)
+}
+
+/// Weight given to assets without QC metadata.
+const NEUTRAL_QC_WEIGHT: f64 = 0.5;
+
+/// QC summary in the processor's `manifest.json`.
+#[derive(Debug, Default, serde::Deserialize)]
+struct ManifestQc {
+    #[serde(default)]
+    quality: Option<ManifestQuality>,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ManifestQuality {
+    mid: Option<ManifestMid>,
+    qc_warning: bool,
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ManifestMid {
+    vmaf_score: Option<f64>,
+    ssim_score: Option<f64>,
+    black_ratio: f64,
+    freeze_score: f64,
+}
+
+/// Emergency weight of `asset` in `0..=1`, from the `manifest.json` the
+/// processor writes next to it: the perceptual scores, discounted for black
+/// or frozen picture and QC warnings.
+fn qc_weight(asset: &Path) -> f64 {
+    let quality = asset
+        .parent()
+        .map(|dir| dir.join("manifest.json"))
+        .and_then(|path| fs::read(path).ok())
+        .and_then(|bytes| serde_json::from_slice::<ManifestQc>(&bytes).ok())
+        .and_then(|manifest| manifest.quality);
+    let Some(quality) = quality else {
+        return NEUTRAL_QC_WEIGHT;
+    };
+    let mid = quality.mid.unwrap_or_default();
+    let scores: Vec<f64> = [mid.vmaf_score.map(|vmaf| vmaf / 100.0), mid.ssim_score]
+        .into_iter()
+        .flatten()
+        .map(|score| score.clamp(0.0, 1.0))
+        .collect();
+    let mut weight = if scores.is_empty() {
+        NEUTRAL_QC_WEIGHT
+    } else {
+        scores.iter().sum::<f64>() / scores.len() as f64
+    };
+    weight *= 1.0 - mid.black_ratio.clamp(0.0, 1.0);
+    weight *= 1.0 - 0.5 * mid.freeze_score.clamp(0.0, 1.0);
+    if quality.qc_warning {
+        weight *= 0.7;
+    }
+    weight
+}
+
+/// Queue item the emergency loop enqueues for `asset`.
+pub fn emergency_loop_item(asset: &Path, duration_s: Option<i64>) -> QueueItem {
+    QueueItem {
+        plan_id: format!("emergency-{}", Uuid::new_v4()),
+        asset_path: asset.to_string_lossy().to_string(),
+        duration_s,
+        curation_score: Some(0.2),
+        priority: 1,
+        node_origin: Some(EMERGENCY_LOOP_ORIGIN.into()),
+        content_kind: Some("music".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
+    }
+}
+
+/// Queue item for the generated slate at `path`.
+pub fn emergency_slate_item(path: &Path, duration_s: i64) -> QueueItem {
+    QueueItem {
+        plan_id: format!("emergency-slate-{}", Uuid::new_v4()),
+        asset_path: path.to_string_lossy().to_string(),
+        duration_s: Some(duration_s),
+        curation_score: Some(0.0),
+        priority: 1,
+        node_origin: Some(EMERGENCY_SLATE_ORIGIN.into()),
+        content_kind: Some("slate".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
+    }
+}
+
+/// Emergency loop refill as the broadcaster would perform it, for
+/// `PlayoutQueueStore::forecast`. Durations are not probed.
+pub fn emergency_refill(archive_dir: &Path) -> std::io::Result<EmergencyRefill> {
+    let items = collect_emergency_assets(archive_dir)?
+        .into_iter()
+        .take(EMERGENCY_LOOP_ASSETS)
+        .map(|asset| {
+            let mut item = emergency_loop_item(&asset, None);
+            let stem = asset
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            item.plan_id = format!("emergency-{stem}");
+            item
+        })
+        .collect();
+    Ok(EmergencyRefill {
+        threshold: Duration::hours(EMERGENCY_BUFFER_HOURS),
+        items,
+    })
 }
@@ -0,0 +1,356 @@
+//! Content monitoring of the live output.
+//!
+//! ffprobe only tells the watchdog that the stream exists. On each
+//! evaluation the monitor also captures a few seconds of the output through
+//! ffmpeg's blackdetect, freezedetect and silencedetect filters and reads
+//! the stretches they report from stderr. A stretch still running when the
+//! capture ends carries over to the next capture, so the watchdog's rules
+//! see how long the picture has been black or frozen, or the audio silent,
+//! across evaluations. Samples are recorded in the metrics store when one
+//! is attached, together with the frame-based [`LiveQualityCollector`]
+//! sample when a collector is.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Utc};
+use tokio::process::Command;
+use tracing::warn;
+
+use crate::config::OutputMonitorSection;
+use crate::monitor::{LiveQcSample, LiveQualityCollector, MetricsStore};
+
+use super::watchdog::WatchdogError;
+use super::CommandExecutor;
+
+/// How close to the edges of a capture a stretch may start or end and
+/// still count as covering them.
+const EDGE_TOLERANCE_S: f64 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectionKind {
+    Black,
+    Freeze,
+    Silence,
+}
+
+/// A stretch reported by one of the filters, in seconds from the start of
+/// the capture. `end_s` is `None` when it was still running at the end.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Detection {
+    pub kind: DetectionKind,
+    pub start_s: f64,
+    pub end_s: Option<f64>,
+}
+
+/// Reads the stretches reported by blackdetect, freezedetect and
+/// silencedetect from ffmpeg's stderr.
+pub fn parse_detections(stderr: &str) -> Vec<Detection> {
+    let mut detections: Vec<Detection> = Vec::new();
+    for line in stderr.lines() {
+        if let Some(start_s) = field(line, "black_start:") {
+            detections.push(Detection {
+                kind: DetectionKind::Black,
+                start_s,
+                end_s: field(line, "black_end:"),
+            });
+            continue;
+        }
+        for (kind, start, end) in [
+            (
+                DetectionKind::Freeze,
+                "freezedetect.freeze_start:",
+                "freezedetect.freeze_end:",
+            ),
+            (DetectionKind::Silence, "silence_start:", "silence_end:"),
+        ] {
+            if let Some(start_s) = field(line, start) {
+                detections.push(Detection {
+                    kind,
+                    start_s,
+                    end_s: None,
+                });
+            } else if let Some(end_s) = field(line, end) {
+                if let Some(open) = detections
+                    .iter_mut()
+                    .rev()
+                    .find(|detection| detection.kind == kind && detection.end_s.is_none())
+                {
+                    open.end_s = Some(end_s);
+                }
+            }
+        }
+    }
+    detections
+}
+
+fn field(line: &str, key: &str) -> Option<f64> {
+    let (_, rest) = line.split_once(key)?;
+    rest.trim_start()
+        .split(|ch: char| ch.is_whitespace() || ch == '|')
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// What one capture of the output showed.
+#[derive(Debug, Clone)]
+pub struct OutputSample {
+    pub at: DateTime<Utc>,
+    pub capture_s: f64,
+    /// Seconds of the capture that were black, frozen or silent.
+    pub black_s: f64,
+    pub frozen_s: f64,
+    pub silent_s: f64,
+    /// How long each has lasted up to the end of the capture, including
+    /// earlier captures it covered entirely.
+    pub black_for_s: f64,
+    pub frozen_for_s: f64,
+    pub silent_for_s: f64,
+    pub freeze_events: u32,
+    /// From the attached [`LiveQualityCollector`]'s frame sample.
+    pub vmaf_estimate: Option<f64>,
+    pub stream_bitrate_mbps: Option<f64>,
+    pub audio_peak_db: Option<f64>,
+    pub signature_deviation: Option<f64>,
+}
+
+impl OutputSample {
+    fn apply_live_sample(&mut self, live: &LiveQcSample) {
+        self.vmaf_estimate = Some(live.vmaf_estimate);
+        self.stream_bitrate_mbps = Some(live.stream_bitrate_mbps);
+        self.audio_peak_db = Some(live.audio_peak_db);
+        self.signature_deviation = Some(live.signature_deviation);
+    }
+
+    pub fn black_ratio(&self) -> f64 {
+        if self.capture_s > 0.0 {
+            (self.black_s / self.capture_s).min(1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Streaks {
+    black: f64,
+    frozen: f64,
+    silent: f64,
+    /// When the previous capture was sampled.
+    at: Option<DateTime<Utc>>,
+}
+
+/// Time `kind` covered within the capture, and how long it had lasted at
+/// its end given the length `carried` it had reached in the previous one,
+/// sampled `since_previous` seconds earlier.
+fn measure(
+    detections: &[Detection],
+    kind: DetectionKind,
+    capture_s: f64,
+    carried: f64,
+    since_previous: Option<f64>,
+) -> (f64, f64) {
+    let stretches: Vec<(f64, f64)> = detections
+        .iter()
+        .filter(|detection| detection.kind == kind)
+        .map(|detection| {
+            let start = detection.start_s.clamp(0.0, capture_s);
+            let end = detection.end_s.unwrap_or(capture_s).clamp(start, capture_s);
+            (start, end)
+        })
+        .collect();
+    let total = stretches.iter().map(|(start, end)| end - start).sum();
+    let lasting = match stretches.last() {
+        Some((start, end)) if *end >= capture_s - EDGE_TOLERANCE_S => {
+            if *start <= EDGE_TOLERANCE_S {
+                // A stretch carried over also ran between the captures.
+                match since_previous {
+                    Some(elapsed) if carried > 0.0 => carried + elapsed.max(capture_s),
+                    _ => carried + capture_s,
+                }
+            } else {
+                capture_s - start
+            }
+        }
+        _ => 0.0,
+    };
+    (total, lasting)
+}
+
+pub struct OutputMonitor {
+    section: OutputMonitorSection,
+    ffmpeg: PathBuf,
+    url: String,
+    executor: Arc<dyn CommandExecutor>,
+    streaks: Mutex<Streaks>,
+    latest: Mutex<Option<OutputSample>>,
+    store: Option<MetricsStore>,
+    live_quality: Option<LiveQualityCollector>,
+}
+
+impl fmt::Debug for OutputMonitor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutputMonitor")
+            .field("section", &self.section)
+            .field("url", &self.url)
+            .finish()
+    }
+}
+
+impl OutputMonitor {
+    pub fn new(
+        section: OutputMonitorSection,
+        ffmpeg: PathBuf,
+        origin_url: &str,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Self {
+        let url = section
+            .url
+            .clone()
+            .unwrap_or_else(|| origin_url.to_string());
+        Self {
+            section,
+            ffmpeg,
+            url,
+            executor,
+            streaks: Mutex::new(Streaks::default()),
+            latest: Mutex::new(None),
+            store: None,
+            live_quality: None,
+        }
+    }
+
+    /// Records every sample in `store`.
+    pub fn with_metrics_store(mut self, store: MetricsStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Takes a frame sample with `collector` after each capture.
+    pub fn with_live_quality(mut self, collector: LiveQualityCollector) -> Self {
+        self.live_quality = Some(collector);
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn latest(&self) -> Option<OutputSample> {
+        self.latest.lock().unwrap().clone()
+    }
+
+    /// ffmpeg arguments capturing the output through the detection filters.
+    pub fn capture_args(&self) -> Vec<String> {
+        let section = &self.section;
+        vec![
+            "-hide_banner".to_string(),
+            "-nostats".to_string(),
+            "-loglevel".to_string(),
+            "info".to_string(),
+            "-t".to_string(),
+            section.capture_seconds.to_string(),
+            "-i".to_string(),
+            self.url.clone(),
+            "-vf".to_string(),
+            format!(
+                "blackdetect=d={min}:pix_th={pix:.2},freezedetect=n={freeze}dB:d={min}",
+                min = section.min_detection_seconds,
+                pix = section.black_pixel_threshold,
+                freeze = section.freeze_noise_db
+            ),
+            "-af".to_string(),
+            format!(
+                "silencedetect=n={}dB:d={}",
+                section.silence_noise_db, section.min_detection_seconds
+            ),
+            "-f".to_string(),
+            "null".to_string(),
+            "-".to_string(),
+        ]
+    }
+
+    /// Captures the output and measures it against the previous captures.
+    pub async fn sample(&self, now: DateTime<Utc>) -> Result<OutputSample, WatchdogError> {
+        let args = self.capture_args();
+        let mut command = Command::new(&self.ffmpeg);
+        command.args(&args).kill_on_drop(true);
+        let output = self.executor.run(&mut command).await?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        if !output.status.success() {
+            return Err(WatchdogError::CommandFailure {
+                command: format!("{} {}", self.ffmpeg.display(), args.join(" ")),
+                status: output.status.code(),
+                stderr: stderr.lines().last().unwrap_or_default().to_string(),
+            });
+        }
+        let detections = parse_detections(&stderr);
+        let capture_s = self.section.capture_seconds;
+        let mut sample = {
+            let mut streaks = self.streaks.lock().unwrap();
+            let since_previous = streaks
+                .at
+                .map(|at| (now - at).num_milliseconds() as f64 / 1000.0);
+            let (black_s, black_for_s) = measure(
+                &detections,
+                DetectionKind::Black,
+                capture_s,
+                streaks.black,
+                since_previous,
+            );
+            let (frozen_s, frozen_for_s) = measure(
+                &detections,
+                DetectionKind::Freeze,
+                capture_s,
+                streaks.frozen,
+                since_previous,
+            );
+            let (silent_s, silent_for_s) = measure(
+                &detections,
+                DetectionKind::Silence,
+                capture_s,
+                streaks.silent,
+                since_previous,
+            );
+            *streaks = Streaks {
+                black: black_for_s,
+                frozen: frozen_for_s,
+                silent: silent_for_s,
+                at: Some(now),
+            };
+            OutputSample {
+                at: now,
+                capture_s,
+                black_s,
+                frozen_s,
+                silent_s,
+                black_for_s,
+                frozen_for_s,
+                silent_for_s,
+                freeze_events: detections
+                    .iter()
+                    .filter(|detection| detection.kind == DetectionKind::Freeze)
+                    .count() as u32,
+                vmaf_estimate: None,
+                stream_bitrate_mbps: None,
+                audio_peak_db: None,
+                signature_deviation: None,
+            }
+        };
+        if let Some(collector) = &self.live_quality {
+            match collector.collect().await {
+                Ok(live) => sample.apply_live_sample(&live),
+                Err(error) => warn!(%error, "live quality sample failed"),
+            }
+        }
+        if let Some(store) = &self.store {
+            if let Err(error) = store.record_output_sample(&sample) {
+                warn!(%error, "failed to record output sample");
+            }
+        }
+        *self.latest.lock().unwrap() = Some(sample.clone());
+        Ok(sample)
+    }
+}
@@ -0,0 +1,239 @@
+//! On-air graphics burned into the picture.
+//!
+//! The layer adds a channel logo, a lower third with the plan title and an
+//! "up next" card for the forecast next entry, each limited to the
+//! `content_kind`s configured in `overlays.toml`. While any element is
+//! enabled every entry is re-encoded, those without graphics through a
+//! passthrough graph, so one session never mixes encoded and stream-copied
+//! video.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::{LogoOverlaySection, LowerThirdSection, OverlayConfig, UpNextSection};
+use crate::queue::selection::kind_key;
+
+/// Distance of the text elements from the frame edges, in pixels.
+const TEXT_MARGIN: u32 = 48;
+
+/// What the layer needs to know about the entry going to air.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OverlayEntry<'a> {
+    pub content_kind: Option<&'a str>,
+    pub title: Option<&'a str>,
+    pub duration_s: Option<i64>,
+    /// Title of the entry expected to follow.
+    pub up_next: Option<&'a str>,
+}
+
+/// ffmpeg arguments that burn the active elements into an entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OverlayGraph {
+    /// Inputs appended after the entry's own, which stays input 0.
+    pub inputs: Vec<String>,
+    pub filter: String,
+    /// Files `drawtext` reads its text from; they must exist before ffmpeg
+    /// starts.
+    pub text_files: Vec<(PathBuf, String)>,
+}
+
+impl OverlayGraph {
+    /// Graph that re-encodes the entry without drawing anything.
+    pub fn passthrough() -> Self {
+        Self {
+            inputs: Vec::new(),
+            filter: "[0:v]null[vout]".to_string(),
+            text_files: Vec::new(),
+        }
+    }
+
+    /// Filter, mapping and encoder arguments that follow the inputs.
+    pub fn output_args(&self) -> Vec<String> {
+        [
+            "-filter_complex",
+            &self.filter,
+            "-map",
+            "[vout]",
+            "-map",
+            "0:a?",
+            "-c:v",
+            "libx264",
+            "-preset",
+            "veryfast",
+            "-crf",
+            "20",
+            "-pix_fmt",
+            "yuv420p",
+            "-c:a",
+            "copy",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OverlayLayer {
+    config: OverlayConfig,
+}
+
+impl OverlayLayer {
+    pub fn new(config: OverlayConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether any element is enabled, for any kind.
+    pub fn is_enabled(&self) -> bool {
+        self.config.logo.as_ref().is_some_and(|s| s.enabled)
+            || self.config.lower_third.as_ref().is_some_and(|s| s.enabled)
+            || self.config.up_next.as_ref().is_some_and(|s| s.enabled)
+    }
+
+    /// Graph for `entry`, or `None` when no element applies to it. Text
+    /// files are named after `key` inside `text_dir`.
+    pub fn graph(
+        &self,
+        entry: &OverlayEntry<'_>,
+        text_dir: &Path,
+        key: &str,
+    ) -> Option<OverlayGraph> {
+        let mut texts = Vec::new();
+        let mut text_files = Vec::new();
+
+        if let (Some(section), Some(title)) = (self.lower_third(entry), entry.title) {
+            let path = text_dir.join(format!("{key}_lower_third.txt"));
+            let start = section.show_after_seconds;
+            texts.push(drawtext(
+                &section.font_file,
+                section.font_size,
+                &path,
+                &format!("x={TEXT_MARGIN}:y=h-th-{}", TEXT_MARGIN * 2),
+                start as f64,
+                (start + section.duration_seconds) as f64,
+            ));
+            text_files.push((path, title.to_string()));
+        }
+
+        if let Some((section, next, duration)) = self.up_next(entry) {
+            let path = text_dir.join(format!("{key}_up_next.txt"));
+            let start = (duration - section.lead_seconds as i64) as f64;
+            texts.push(drawtext(
+                &section.font_file,
+                section.font_size,
+                &path,
+                &format!("x=w-tw-{TEXT_MARGIN}:y=h-th-{}", TEXT_MARGIN * 2),
+                start,
+                start + section.duration_seconds as f64,
+            ));
+            text_files.push((path, format!("{}: {next}", section.label)));
+        }
+
+        let logo = self.logo(entry);
+        if texts.is_empty() && logo.is_none() {
+            return None;
+        }
+
+        let base = if texts.is_empty() {
+            "[0:v]null".to_string()
+        } else {
+            format!("[0:v]{}", texts.join(","))
+        };
+        let mut inputs = Vec::new();
+        let filter = match logo {
+            Some(section) => {
+                inputs.push("-i".to_string());
+                inputs.push(section.image.clone());
+                format!(
+                    "{base}[base];[1:v]format=rgba,colorchannelmixer=aa={}[logo];\
+                     [base][logo]overlay={}[vout]",
+                    section.opacity.clamp(0.0, 1.0),
+                    logo_position(&section.position, section.margin)
+                )
+            }
+            None => format!("{base}[vout]"),
+        };
+        Some(OverlayGraph {
+            inputs,
+            filter,
+            text_files,
+        })
+    }
+
+    fn logo(&self, entry: &OverlayEntry<'_>) -> Option<&LogoOverlaySection> {
+        self.config
+            .logo
+            .as_ref()
+            .filter(|section| section.enabled && shown_for(&section.content_kinds, entry))
+    }
+
+    fn lower_third(&self, entry: &OverlayEntry<'_>) -> Option<&LowerThirdSection> {
+        self.config
+            .lower_third
+            .as_ref()
+            .filter(|section| section.enabled && shown_for(&section.content_kinds, entry))
+    }
+
+    /// The up-next card needs a known duration long enough to lead into.
+    fn up_next<'a>(
+        &'a self,
+        entry: &OverlayEntry<'a>,
+    ) -> Option<(&'a UpNextSection, &'a str, i64)> {
+        let section = self
+            .config
+            .up_next
+            .as_ref()
+            .filter(|section| section.enabled && shown_for(&section.content_kinds, entry))?;
+        let duration = entry
+            .duration_s
+            .filter(|duration| *duration > section.lead_seconds as i64)?;
+        Some((section, entry.up_next?, duration))
+    }
+}
+
+fn shown_for(kinds: &[String], entry: &OverlayEntry<'_>) -> bool {
+    let kind = kind_key(entry.content_kind);
+    kinds.is_empty() || kinds.iter().any(|allowed| kind_key(Some(allowed)) == kind)
+}
+
+fn drawtext(
+    font: &str,
+    size: u32,
+    text_file: &Path,
+    position: &str,
+    start: f64,
+    end: f64,
+) -> String {
+    // The text is read verbatim; titles must not be taken for `%{...}`.
+    format!(
+        "drawtext=fontfile={}:textfile={}:expansion=none:fontsize={size}:fontcolor=white:\
+         box=1:boxcolor=black@0.6:boxborderw=16:{position}:enable='between(t,{start},{end})'",
+        escape_filter_value(font),
+        escape_filter_value(&text_file.to_string_lossy())
+    )
+}
+
+/// Escapes an option value inside a filter graph: once for the filter's
+/// option parser and once more for the graph parser.
+fn escape_filter_value(value: &str) -> String {
+    escape(&escape(value, "\\':"), "\\'[],;")
+}
+
+fn escape(value: &str, special: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if special.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn logo_position(position: &str, margin: u32) -> String {
+    match position {
+        "top_left" => format!("{margin}:{margin}"),
+        "bottom_left" => format!("{margin}:H-h-{margin}"),
+        "bottom_right" => format!("W-w-{margin}:H-h-{margin}"),
+        _ => format!("W-w-{margin}:{margin}"),
+    }
+}
@@ -0,0 +1,271 @@
+//! Pre-flight verification of assets before they air.
+//!
+//! The processor leaves `checksums.json` and `manifest.json` next to the
+//! renditions it writes to `ready/`. Before an entry goes live the
+//! broadcaster checks its asset against them: the file exists, its size and
+//! SHA-256 match what the processor recorded, and ffprobe reads a duration
+//! that does not fall short of the manifest's. Assets without those files
+//! only get the existence and ffprobe checks.
+//!
+//! Hashing a long rendition takes a while, so the entry expected to air
+//! next is verified in the background while the current one plays. A
+//! verified asset is remembered by size and modification time and is not
+//! checked again until either changes.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+use sha2::{Digest, Sha256};
+use tokio::process::Command;
+use tracing::debug;
+
+use crate::config::PreflightSection;
+
+use super::CommandExecutor;
+
+/// Why an asset may not air.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PreflightFailure {
+    Missing,
+    Empty,
+    SizeMismatch { expected: u64, actual: u64 },
+    ChecksumMismatch { expected: String, actual: String },
+    Unreadable(String),
+    Probe(String),
+    Truncated { expected_s: f64, probed_s: f64 },
+}
+
+impl fmt::Display for PreflightFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreflightFailure::Missing => f.write_str("asset missing"),
+            PreflightFailure::Empty => f.write_str("asset is empty"),
+            PreflightFailure::SizeMismatch { expected, actual } => {
+                write!(f, "size {actual} bytes, manifest lists {expected}")
+            }
+            PreflightFailure::ChecksumMismatch { expected, actual } => {
+                write!(f, "checksum {actual} does not match {expected}")
+            }
+            PreflightFailure::Unreadable(error) => write!(f, "asset unreadable: {error}"),
+            PreflightFailure::Probe(error) => write!(f, "ffprobe failed: {error}"),
+            PreflightFailure::Truncated {
+                expected_s,
+                probed_s,
+            } => write!(
+                f,
+                "probed duration {probed_s:.1}s short of manifest's {expected_s:.1}s"
+            ),
+        }
+    }
+}
+
+/// What the processor recorded about an asset.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AssetExpectations {
+    pub size: Option<u64>,
+    pub checksum: Option<String>,
+    pub duration_s: Option<f64>,
+}
+
+impl AssetExpectations {
+    /// Reads the `checksums.json` and `manifest.json` next to `asset`.
+    pub fn load(asset: &Path) -> Self {
+        let (Some(dir), Some(name)) = (asset.parent(), asset.file_name().and_then(|n| n.to_str()))
+        else {
+            return Self::default();
+        };
+        let checksum = read_json::<HashMap<String, String>>(&dir.join("checksums.json"))
+            .and_then(|mut checksums| checksums.remove(name));
+        let manifest = read_json::<ManifestSummary>(&dir.join("manifest.json")).unwrap_or_default();
+        Self {
+            size: manifest.sizes.get(name).copied(),
+            checksum,
+            duration_s: manifest.duration,
+        }
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default)]
+struct ManifestSummary {
+    duration: Option<f64>,
+    sizes: HashMap<String, u64>,
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let bytes = fs::read(path).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Size and modification time of an asset that passed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Fingerprint {
+    size: u64,
+    modified: Option<SystemTime>,
+}
+
+#[derive(Clone)]
+pub struct Preflight {
+    section: PreflightSection,
+    ffprobe: PathBuf,
+    executor: Arc<dyn CommandExecutor>,
+    verified: Arc<Mutex<HashMap<PathBuf, Fingerprint>>>,
+}
+
+impl fmt::Debug for Preflight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Preflight")
+            .field("section", &self.section)
+            .field("ffprobe", &self.ffprobe)
+            .finish()
+    }
+}
+
+impl Preflight {
+    pub(super) fn new(
+        section: PreflightSection,
+        ffprobe: PathBuf,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Self {
+        Self {
+            section,
+            ffprobe,
+            executor,
+            verified: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.section.enabled
+    }
+
+    /// Verifies `asset`, or returns at once when it passed before unchanged.
+    pub async fn verify(&self, asset: &Path) -> Result<(), PreflightFailure> {
+        let metadata = match tokio::fs::metadata(asset).await {
+            Ok(metadata) if metadata.is_file() => metadata,
+            Ok(_) => return Err(PreflightFailure::Missing),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(PreflightFailure::Missing)
+            }
+            Err(error) => return Err(PreflightFailure::Unreadable(error.to_string())),
+        };
+        if metadata.len() == 0 {
+            return Err(PreflightFailure::Empty);
+        }
+        let fingerprint = Fingerprint {
+            size: metadata.len(),
+            modified: metadata.modified().ok(),
+        };
+        if self.verified.lock().unwrap().get(asset) == Some(&fingerprint) {
+            return Ok(());
+        }
+
+        let path = asset.to_path_buf();
+        let expected = tokio::task::spawn_blocking(move || AssetExpectations::load(&path))
+            .await
+            .unwrap_or_default();
+        if let Some(size) = expected.size {
+            if size != fingerprint.size {
+                return Err(PreflightFailure::SizeMismatch {
+                    expected: size,
+                    actual: fingerprint.size,
+                });
+            }
+        }
+        if let (true, Some(checksum)) = (self.section.verify_checksum, &expected.checksum) {
+            let path = asset.to_path_buf();
+            let actual = tokio::task::spawn_blocking(move || sha256_file(&path))
+                .await
+                .map_err(|error| PreflightFailure::Unreadable(error.to_string()))?
+                .map_err(|error| PreflightFailure::Unreadable(error.to_string()))?;
+            if !actual.eq_ignore_ascii_case(checksum) {
+                return Err(PreflightFailure::ChecksumMismatch {
+                    expected: checksum.clone(),
+                    actual,
+                });
+            }
+        }
+        if self.section.probe {
+            let probed_s = self.probe(asset).await?;
+            if let Some(expected_s) = expected.duration_s {
+                if probed_s + self.section.duration_tolerance_seconds < expected_s {
+                    return Err(PreflightFailure::Truncated {
+                        expected_s,
+                        probed_s,
+                    });
+                }
+            }
+        }
+
+        self.verified
+            .lock()
+            .unwrap()
+            .insert(asset.to_path_buf(), fingerprint);
+        Ok(())
+    }
+
+    /// Verifies `asset` in the background so the check is cached by the
+    /// time it airs. Failures are reported when it does.
+    pub(super) fn warm(&self, asset: PathBuf) {
+        if !self.section.enabled {
+            return;
+        }
+        let preflight = self.clone();
+        tokio::spawn(async move {
+            if let Err(failure) = preflight.verify(&asset).await {
+                debug!(asset = %asset.display(), %failure, "upcoming asset failed pre-flight");
+            }
+        });
+    }
+
+    async fn probe(&self, asset: &Path) -> Result<f64, PreflightFailure> {
+        let mut command = Command::new(&self.ffprobe);
+        command
+            .args([
+                "-v",
+                "error",
+                "-show_entries",
+                "format=duration",
+                "-of",
+                "default=noprint_wrappers=1:nokey=1",
+            ])
+            .arg(asset)
+            .kill_on_drop(true);
+        let output = self
+            .executor
+            .run(&mut command)
+            .await
+            .map_err(|error| PreflightFailure::Probe(error.to_string()))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(PreflightFailure::Probe(
+                stderr.lines().last().unwrap_or("no output").to_string(),
+            ));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|duration| duration.is_finite() && *duration > 0.0)
+            .ok_or_else(|| PreflightFailure::Probe("no duration".to_string()))
+    }
+}
+
+fn sha256_file(path: &Path) -> std::io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = [0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
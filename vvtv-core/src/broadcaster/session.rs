@@ -0,0 +1,747 @@
+//! Long-lived publish session fanned out to every destination.
+//!
+//! Each destination (the RTMP origin plus any simulcast outputs) gets its own
+//! ffmpeg that reads MPEG-TS on stdin and keeps its connection open for as
+//! long as the broadcaster runs. Each queue entry is played by a short-lived
+//! feeder that remuxes (or, with graphics, encodes) it to MPEG-TS in real time
+//! and writes it to stdout; the session copies that single stream into every
+//! destination. Feeder timestamps are shifted by the media time published so
+//! far so the output stays monotonic across entries. Each destination is
+//! written by its own task through a bounded queue, so one that stalls falls
+//! behind and is dropped instead of holding up the feeder. A destination that
+//! fails, before or during an entry, is restarted under its own reconnect
+//! policy without disturbing the rest; the tail of its stderr is kept as the
+//! error.
+//! A feeder can be stopped early: it is asked to quit so the stream ends on a
+//! complete packet, and killed if it does not within the stop grace.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{DateTime, Utc};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, Command};
+use tokio::sync::mpsc;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use super::telemetry::{read_progress, EncoderTelemetry, FeederLog};
+use super::{BroadcasterError, CommandExecutor};
+
+const COPY_BUFFER_BYTES: usize = 64 * 1024;
+/// Chunks queued for a destination before the feeder waits on it.
+const OUTPUT_QUEUE_CHUNKS: usize = 32;
+/// How long the feeder waits on full queues before dropping those
+/// destinations as fallen behind.
+const OUTPUT_LAG_GRACE: StdDuration = StdDuration::from_secs(1);
+const DEFAULT_STOP_GRACE: StdDuration = StdDuration::from_secs(3);
+/// stderr lines of an output process kept for its error.
+const OUTPUT_LOG_LINES: usize = 20;
+
+/// Result of feeding one entry into the session.
+#[derive(Debug, Clone)]
+pub struct SessionFeed {
+    /// Wall-clock time the feeder ran for.
+    pub elapsed: StdDuration,
+    /// At least one destination had to be (re)started for this entry.
+    pub reconnected: bool,
+    /// Destinations that could not be started or dropped out mid-entry.
+    pub failed: Vec<String>,
+    /// Destinations that carried the entry to its end.
+    pub destinations: Vec<String>,
+    /// The feeder was stopped before the end of the entry.
+    pub stopped: bool,
+}
+
+/// Muxer used to publish to a destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// RTMP and RTMPS.
+    Flv,
+    /// SRT, UDP and other transport-stream outputs.
+    MpegTs,
+    /// Local HLS playlist and segments.
+    Hls,
+}
+
+impl OutputFormat {
+    /// Format implied by a destination URL or path.
+    pub fn infer(url: &str) -> Self {
+        let lower = url.to_ascii_lowercase();
+        if lower.starts_with("srt://") || lower.starts_with("udp://") {
+            OutputFormat::MpegTs
+        } else if lower.ends_with(".m3u8") {
+            OutputFormat::Hls
+        } else {
+            OutputFormat::Flv
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutputFormat::Flv => "flv",
+            OutputFormat::MpegTs => "mpegts",
+            OutputFormat::Hls => "hls",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = BroadcasterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "flv" => Ok(Self::Flv),
+            "mpegts" => Ok(Self::MpegTs),
+            "hls" => Ok(Self::Hls),
+            other => Err(BroadcasterError::InvalidDestination(format!(
+                "unknown output format {other}"
+            ))),
+        }
+    }
+}
+
+/// One place the session publishes to.
+#[derive(Debug, Clone)]
+pub struct OutputDestination {
+    pub name: String,
+    pub url: String,
+    pub format: OutputFormat,
+    /// Extra muxer options placed before the URL.
+    pub muxer_args: Vec<String>,
+    pub reconnect_attempts: u32,
+    pub reconnect_delay: StdDuration,
+}
+
+impl OutputDestination {
+    pub fn new(name: impl Into<String>, url: impl Into<String>) -> Self {
+        let url = url.into();
+        Self {
+            name: name.into(),
+            format: OutputFormat::infer(&url),
+            url,
+            muxer_args: Vec::new(),
+            reconnect_attempts: 0,
+            reconnect_delay: StdDuration::from_millis(0),
+        }
+    }
+
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn with_muxer_args(mut self, args: Vec<String>) -> Self {
+        self.muxer_args = args;
+        self
+    }
+
+    /// Restarts allowed, per entry, when the destination's output process
+    /// cannot be started or is lost mid-entry, and the delay between them.
+    pub fn with_reconnect(mut self, attempts: u32, delay: StdDuration) -> Self {
+        self.reconnect_attempts = attempts;
+        self.reconnect_delay = delay;
+        self
+    }
+}
+
+/// Health of one destination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DestinationStatus {
+    pub name: String,
+    pub url: String,
+    pub connected: bool,
+    /// Output processes started for this destination so far.
+    pub started: u64,
+    /// Start failures and mid-entry drops so far.
+    pub failures: u64,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<DateTime<Utc>>,
+}
+
+pub struct OutputSession {
+    ffmpeg: PathBuf,
+    base_args: Vec<String>,
+    destinations: Vec<OutputDestination>,
+    executor: Arc<dyn CommandExecutor>,
+    telemetry: EncoderTelemetry,
+    stop_grace: StdDuration,
+    state: Mutex<SessionState>,
+}
+
+struct SessionState {
+    outputs: Vec<OutputState>,
+    /// Seconds of output published so far; the next feeder starts here.
+    timeline: f64,
+}
+
+#[derive(Default)]
+struct OutputState {
+    running: Option<RunningOutput>,
+    started: u64,
+    failures: u64,
+    last_error: Option<(String, DateTime<Utc>)>,
+    /// Mid-entry restarts left for the entry being fed.
+    reconnects_left: u32,
+    /// Earliest time of the next mid-entry restart.
+    retry_at: Option<Instant>,
+}
+
+impl OutputState {
+    fn fail(&mut self, error: String) {
+        self.failures += 1;
+        self.last_error = Some((error, Utc::now()));
+    }
+}
+
+struct RunningOutput {
+    child: Child,
+    /// Chunks for the task that writes the output's stdin.
+    queue: mpsc::Sender<Arc<[u8]>>,
+    writer: JoinHandle<std::io::Result<()>>,
+    log: OutputLog,
+}
+
+impl RunningOutput {
+    /// Writes everything sent to `queue` into `stdin` until the queue closes
+    /// or the pipe breaks.
+    fn new(child: Child, mut stdin: ChildStdin, log: OutputLog) -> Self {
+        let (queue, mut chunks) = mpsc::channel::<Arc<[u8]>>(OUTPUT_QUEUE_CHUNKS);
+        let writer = tokio::spawn(async move {
+            while let Some(chunk) = chunks.recv().await {
+                stdin.write_all(&chunk).await?;
+            }
+            stdin.flush().await
+        });
+        Self {
+            child,
+            queue,
+            writer,
+            log,
+        }
+    }
+
+    /// Stops the output without waiting for the data still queued.
+    async fn kill(mut self) {
+        self.writer.abort();
+        let _ = self.child.kill().await;
+    }
+}
+
+/// Last lines an output process wrote to stderr.
+#[derive(Clone, Default)]
+struct OutputLog(Arc<StdMutex<VecDeque<String>>>);
+
+impl OutputLog {
+    /// Drains `stderr` in the background so the output never blocks on it.
+    fn capture(stderr: impl AsyncRead + Unpin + Send + 'static) -> Self {
+        let log = Self::default();
+        let lines = log.0.clone();
+        tokio::spawn(async move {
+            let mut reader = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = reader.next_line().await {
+                let mut lines = lines.lock().unwrap();
+                if lines.len() == OUTPUT_LOG_LINES {
+                    lines.pop_front();
+                }
+                lines.push_back(line);
+            }
+        });
+        log
+    }
+
+    /// `error` followed by the captured stderr, if any.
+    fn describe(&self, error: impl Into<String>) -> String {
+        let mut error = error.into();
+        let lines = self.0.lock().unwrap();
+        if !lines.is_empty() {
+            error.push_str(": ");
+            error.push_str(&lines.iter().cloned().collect::<Vec<_>>().join("\n"));
+        }
+        error
+    }
+}
+
+impl OutputSession {
+    pub fn new(
+        ffmpeg: PathBuf,
+        base_args: Vec<String>,
+        destinations: Vec<OutputDestination>,
+        executor: Arc<dyn CommandExecutor>,
+    ) -> Self {
+        let outputs = destinations
+            .iter()
+            .map(|_| OutputState::default())
+            .collect();
+        Self {
+            ffmpeg,
+            base_args,
+            destinations,
+            executor,
+            telemetry: EncoderTelemetry::new(),
+            stop_grace: DEFAULT_STOP_GRACE,
+            state: Mutex::new(SessionState {
+                outputs,
+                timeline: 0.0,
+            }),
+        }
+    }
+
+    /// Publishes the feeders' progress to `telemetry` instead of a private
+    /// handle.
+    pub fn with_telemetry(mut self, telemetry: EncoderTelemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// Time a stopped feeder gets to quit on its own before it is killed.
+    pub fn with_stop_grace(mut self, grace: StdDuration) -> Self {
+        self.stop_grace = grace;
+        self
+    }
+
+    pub fn telemetry(&self) -> &EncoderTelemetry {
+        &self.telemetry
+    }
+
+    pub fn destinations(&self) -> &[OutputDestination] {
+        &self.destinations
+    }
+
+    /// Arguments of the long-lived output process for `destination`.
+    pub fn output_args(&self, destination: &OutputDestination) -> Vec<String> {
+        let mut args = self.base_args.clone();
+        for arg in [
+            "-fflags", "+genpts", "-f", "mpegts", "-i", "pipe:0", "-c", "copy", "-f",
+        ] {
+            args.push(arg.to_string());
+        }
+        args.push(destination.format.as_str().to_string());
+        args.extend(destination.muxer_args.iter().cloned());
+        args.push(destination.url.clone());
+        args
+    }
+
+    /// Arguments of the feeder for an entry whose ffmpeg inputs are `inputs`
+    /// and whose codec arguments are `codec`.
+    pub fn feeder_args(
+        &self,
+        inputs: &[String],
+        codec: &[String],
+        offset_seconds: f64,
+    ) -> Vec<String> {
+        let mut args = self.base_args.clone();
+        for arg in ["-progress", "pipe:2", "-nostats"] {
+            args.push(arg.to_string());
+        }
+        args.extend(inputs.iter().cloned());
+        args.extend(codec.iter().cloned());
+        for arg in ["-f", "mpegts", "-output_ts_offset"] {
+            args.push(arg.to_string());
+        }
+        args.push(format!("{offset_seconds:.3}"));
+        args.push("pipe:1".to_string());
+        args
+    }
+
+    /// Number of output processes started so far, across destinations.
+    pub async fn sessions_started(&self) -> u64 {
+        let state = self.state.lock().await;
+        state.outputs.iter().map(|output| output.started).sum()
+    }
+
+    /// Names of the destinations whose output is running.
+    pub async fn live_destinations(&self) -> Vec<String> {
+        let mut state = self.state.lock().await;
+        self.destinations
+            .iter()
+            .zip(state.outputs.iter_mut())
+            .filter_map(|(destination, output)| {
+                let running = output.running.as_mut()?;
+                matches!(running.child.try_wait(), Ok(None)).then(|| destination.name.clone())
+            })
+            .collect()
+    }
+
+    /// Health of every destination, in configuration order.
+    pub async fn status(&self) -> Vec<DestinationStatus> {
+        let mut state = self.state.lock().await;
+        let mut statuses = Vec::with_capacity(self.destinations.len());
+        for (destination, output) in self.destinations.iter().zip(state.outputs.iter_mut()) {
+            let connected = match output.running.as_mut() {
+                Some(running) => matches!(running.child.try_wait(), Ok(None)),
+                None => false,
+            };
+            statuses.push(DestinationStatus {
+                name: destination.name.clone(),
+                url: destination.url.clone(),
+                connected,
+                started: output.started,
+                failures: output.failures,
+                last_error: output.last_error.as_ref().map(|(error, _)| error.clone()),
+                last_error_at: output.last_error.as_ref().map(|(_, at)| *at),
+            });
+        }
+        statuses
+    }
+
+    /// Plays one entry through the session, starting any destination that
+    /// is not running. Fails only when no destination could take the entry.
+    pub async fn play(
+        &self,
+        inputs: &[String],
+        codec: &[String],
+    ) -> Result<SessionFeed, BroadcasterError> {
+        self.play_until(inputs, codec, std::future::pending()).await
+    }
+
+    /// Like [`play`](Self::play), but stops the feeder as soon as `stop`
+    /// completes. The destinations stay up for the next entry.
+    pub async fn play_until(
+        &self,
+        inputs: &[String],
+        codec: &[String],
+        stop: impl Future<Output = ()>,
+    ) -> Result<SessionFeed, BroadcasterError> {
+        let mut state = self.state.lock().await;
+        let mut failed = Vec::new();
+        let mut reconnected = self.ensure_running(&mut state, &mut failed).await;
+        if state.outputs.iter().all(|output| output.running.is_none()) {
+            return Err(BroadcasterError::SessionLost(format!(
+                "no destination available ({})",
+                failed.join(", ")
+            )));
+        }
+        let args = self.feeder_args(inputs, codec, state.timeline);
+        let mut command = Command::new(&self.ffmpeg);
+        command
+            .args(&args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        let started = Instant::now();
+        let mut feeder = self.executor.spawn(&mut command).await?;
+        let (Some(mut stdout), Some(stderr)) = (feeder.stdout.take(), feeder.stderr.take()) else {
+            let _ = feeder.kill().await;
+            return Err(BroadcasterError::Io(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "feeder output not captured",
+            )));
+        };
+        let mut control = feeder.stdin.take();
+        // stderr carries the progress blocks and is read alongside, which
+        // also keeps a chatty feeder from blocking.
+        let (stopped, (copied, progress)) = {
+            let pump = async {
+                tokio::join!(
+                    self.fan_out(
+                        &mut stdout,
+                        &mut state.outputs,
+                        &mut failed,
+                        &mut reconnected
+                    ),
+                    read_progress(stderr, &self.telemetry)
+                )
+            };
+            tokio::pin!(pump);
+            tokio::pin!(stop);
+            tokio::select! {
+                pumped = &mut pump => (false, pumped),
+                _ = &mut stop => {
+                    // `q` makes ffmpeg finish the packet in flight and exit.
+                    if let Some(stdin) = control.as_mut() {
+                        let _ = stdin.write_all(b"q").await;
+                        let _ = stdin.flush().await;
+                    }
+                    let pumped = match tokio::time::timeout(self.stop_grace, &mut pump).await {
+                        Ok(pumped) => pumped,
+                        Err(_) => {
+                            warn!("feeder ignored the stop request, killing it");
+                            let _ = feeder.start_kill();
+                            // Children of the feeder may still hold its pipes.
+                            tokio::time::timeout(self.stop_grace, &mut pump)
+                                .await
+                                .unwrap_or((Ok(()), Ok(FeederLog::default())))
+                        }
+                    };
+                    (true, pumped)
+                }
+            }
+        };
+        drop(control);
+        let progress = progress.unwrap_or_default();
+        if let Err(err) = copied {
+            let _ = feeder.kill().await;
+            return Err(BroadcasterError::Io(err));
+        }
+        if state.outputs.iter().all(|output| output.running.is_none()) {
+            let _ = feeder.kill().await;
+            return Err(BroadcasterError::SessionLost(format!(
+                "every destination dropped mid-entry ({})",
+                failed.join(", ")
+            )));
+        }
+        let status = feeder.wait().await?;
+        let elapsed = started.elapsed();
+        // Media time, not wall time: a feeder running slower or faster than
+        // real time must not open gaps or overlaps in the output timestamps.
+        state.timeline += progress.out_time_s.unwrap_or(elapsed.as_secs_f64());
+        if !status.success() && !stopped {
+            return Err(BroadcasterError::CommandFailure {
+                command: format!("{} {}", self.ffmpeg.display(), args.join(" ")),
+                status: status.code(),
+                stderr: progress.log,
+            });
+        }
+        let destinations = self
+            .destinations
+            .iter()
+            .zip(state.outputs.iter())
+            .filter(|(_, output)| output.running.is_some())
+            .map(|(destination, _)| destination.name.clone())
+            .collect();
+        Ok(SessionFeed {
+            elapsed,
+            reconnected,
+            failed,
+            destinations,
+            stopped,
+        })
+    }
+
+    /// Closes every output process after the data already fed is flushed.
+    pub async fn shutdown(&self) -> Result<(), BroadcasterError> {
+        let mut state = self.state.lock().await;
+        for output in &mut state.outputs {
+            if let Some(RunningOutput {
+                mut child,
+                queue,
+                writer,
+                ..
+            }) = output.running.take()
+            {
+                // The writer drains the queue and closes stdin once it ends.
+                drop(queue);
+                let _ = writer.await;
+                child.wait().await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Copies the feeder's stream into every running destination. A
+    /// destination whose queue stays full for the lag grace has fallen
+    /// behind and is dropped rather than holding up the others; lost
+    /// destinations are restarted while their reconnect attempts for the
+    /// entry last.
+    async fn fan_out(
+        &self,
+        source: &mut (impl AsyncReadExt + Unpin),
+        outputs: &mut [OutputState],
+        failed: &mut Vec<String>,
+        reconnected: &mut bool,
+    ) -> std::io::Result<()> {
+        let mut buffer = vec![0u8; COPY_BUFFER_BYTES];
+        loop {
+            let read = source.read(&mut buffer).await?;
+            if read == 0 {
+                break;
+            }
+            if self.reconnect_lost(outputs, failed).await {
+                *reconnected = true;
+            }
+            let chunk: Arc<[u8]> = Arc::from(&buffer[..read]);
+            // One grace per chunk, however many destinations are behind.
+            let deadline = tokio::time::Instant::now() + OUTPUT_LAG_GRACE;
+            for (destination, output) in self.destinations.iter().zip(outputs.iter_mut()) {
+                let Some(running) = output.running.as_mut() else {
+                    continue;
+                };
+                let sent = running.queue.send(Arc::clone(&chunk));
+                let error = match tokio::time::timeout_at(deadline, sent).await {
+                    Ok(Ok(())) => continue,
+                    Err(_) => "fell behind the feed".to_string(),
+                    // The writer only stops early when the pipe breaks.
+                    Ok(Err(_)) => match (&mut running.writer).await {
+                        Ok(Err(err)) => err.to_string(),
+                        _ => "output writer stopped".to_string(),
+                    },
+                };
+                self.drop_output(destination, output, error, failed).await;
+            }
+            if outputs
+                .iter()
+                .all(|output| output.running.is_none() && output.reconnects_left == 0)
+            {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Restarts the destinations lost mid-entry whose retry delay has
+    /// passed. Returns whether any came back.
+    async fn reconnect_lost(&self, outputs: &mut [OutputState], failed: &mut Vec<String>) -> bool {
+        let now = Instant::now();
+        let mut reconnected = false;
+        for (destination, output) in self.destinations.iter().zip(outputs.iter_mut()) {
+            if output.running.is_some()
+                || output.reconnects_left == 0
+                || output.retry_at.is_some_and(|at| at > now)
+            {
+                continue;
+            }
+            output.reconnects_left -= 1;
+            match self.spawn_output(destination).await {
+                Ok(running) => {
+                    output.started += 1;
+                    output.running = Some(running);
+                    reconnected = true;
+                    info!(destination = %destination.name, "destination output restarted mid-entry");
+                }
+                Err(err) => {
+                    warn!(destination = %destination.name, error = %err, "failed to restart destination output");
+                    output.fail(err.to_string());
+                    output.retry_at = Some(now + destination.reconnect_delay);
+                    if !failed.contains(&destination.name) {
+                        failed.push(destination.name.clone());
+                    }
+                }
+            }
+        }
+        reconnected
+    }
+
+    async fn drop_output(
+        &self,
+        destination: &OutputDestination,
+        output: &mut OutputState,
+        error: String,
+        failed: &mut Vec<String>,
+    ) {
+        let mut error = error;
+        if let Some(lost) = output.running.take() {
+            error = lost.log.describe(error);
+            lost.kill().await;
+        }
+        warn!(destination = %destination.name, %error, "destination lost mid-entry");
+        output.fail(error);
+        output.retry_at = Some(Instant::now() + destination.reconnect_delay);
+        if !failed.contains(&destination.name) {
+            failed.push(destination.name.clone());
+        }
+    }
+
+    /// Starts every destination that is not running and resets the
+    /// mid-entry restarts of those that are. Returns whether any was
+    /// (re)started.
+    async fn ensure_running(&self, state: &mut SessionState, failed: &mut Vec<String>) -> bool {
+        let was_live = state
+            .outputs
+            .iter_mut()
+            .any(|output| match &mut output.running {
+                Some(running) => matches!(running.child.try_wait(), Ok(None)),
+                None => false,
+            });
+        let mut reconnected = false;
+        for (destination, output) in self.destinations.iter().zip(state.outputs.iter_mut()) {
+            output.reconnects_left = destination.reconnect_attempts;
+            output.retry_at = None;
+            if let Some(running) = output.running.as_mut() {
+                match running.child.try_wait() {
+                    Ok(None) => continue,
+                    Ok(Some(status)) => {
+                        let error = running
+                            .log
+                            .describe(format!("output exited with {:?}", status.code()));
+                        warn!(
+                            destination = %destination.name,
+                            %error,
+                            "destination output exited, reconnecting"
+                        );
+                        output.fail(error);
+                    }
+                    Err(err) => output.fail(err.to_string()),
+                }
+                if let Some(lost) = output.running.take() {
+                    lost.kill().await;
+                }
+            }
+            match self.start_output(destination).await {
+                Ok(running) => {
+                    output.started += 1;
+                    output.running = Some(running);
+                    reconnected = true;
+                    info!(destination = %destination.name, url = %destination.url, "destination output started");
+                }
+                Err(err) => {
+                    warn!(destination = %destination.name, error = %err, "destination unavailable");
+                    output.fail(err.to_string());
+                    // Its attempts are spent; it is retried on the next entry.
+                    output.reconnects_left = 0;
+                    failed.push(destination.name.clone());
+                }
+            }
+        }
+        // A publish that starts from nothing starts a new timeline.
+        if !was_live {
+            state.timeline = 0.0;
+        }
+        reconnected
+    }
+
+    /// Starts the output for `destination`, retrying under its reconnect
+    /// policy.
+    async fn start_output(
+        &self,
+        destination: &OutputDestination,
+    ) -> Result<RunningOutput, BroadcasterError> {
+        let mut attempt = 0;
+        loop {
+            match self.spawn_output(destination).await {
+                Ok(running) => return Ok(running),
+                Err(err) if attempt < destination.reconnect_attempts => {
+                    attempt += 1;
+                    warn!(
+                        destination = %destination.name,
+                        attempt,
+                        error = %err,
+                        "failed to start destination output, retrying"
+                    );
+                    tokio::time::sleep(destination.reconnect_delay).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    async fn spawn_output(
+        &self,
+        destination: &OutputDestination,
+    ) -> Result<RunningOutput, BroadcasterError> {
+        let mut command = Command::new(&self.ffmpeg);
+        command
+            .args(self.output_args(destination))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped());
+        let mut child = self.executor.spawn(&mut command).await?;
+        let Some(stdin) = child.stdin.take() else {
+            let _ = child.kill().await;
+            return Err(BroadcasterError::SessionLost(
+                "output stdin not captured".to_string(),
+            ));
+        };
+        let log = child
+            .stderr
+            .take()
+            .map(OutputLog::capture)
+            .unwrap_or_default();
+        Ok(RunningOutput::new(child, stdin, log))
+    }
+}
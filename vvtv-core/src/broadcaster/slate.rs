@@ -0,0 +1,270 @@
+//! Generated fallback slate.
+//!
+//! When the archive holds no emergency assets, the broadcaster renders a
+//! card ("we'll be right back" over a solid background) or SMPTE bars with a
+//! tone through ffmpeg's lavfi sources and queues it whenever nothing else
+//! is queued. The clip is cached under the temp directory, keyed by its
+//! settings, so it is only rendered again when `[failover.slate]` changes.
+//! On-air graphics such as the logo are burned in when it airs, like any
+//! other entry.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use sha2::{Digest, Sha256};
+use tokio::fs as async_fs;
+use tokio::process::Command;
+
+use crate::config::SlateSection;
+
+use super::{BroadcasterError, CommandExecutor};
+
+/// Tone aired under bars when `tone_hz` is not set.
+const DEFAULT_BARS_TONE_HZ: u32 = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlateStyle {
+    /// Text centred on a solid background.
+    Card,
+    /// SMPTE HD colour bars with the text along the bottom.
+    Bars,
+}
+
+impl SlateStyle {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlateStyle::Card => "card",
+            SlateStyle::Bars => "bars",
+        }
+    }
+}
+
+impl fmt::Display for SlateStyle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for SlateStyle {
+    type Err = BroadcasterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "card" => Ok(SlateStyle::Card),
+            "bars" => Ok(SlateStyle::Bars),
+            other => Err(BroadcasterError::InvalidSlate(format!(
+                "unknown style {other:?}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmergencySlate {
+    pub style: SlateStyle,
+    pub text: String,
+    pub duration_s: u32,
+    pub tone_hz: u32,
+    pub background: String,
+    pub width: u32,
+    pub height: u32,
+    pub fps: u32,
+    pub font: Option<String>,
+    cache_dir: PathBuf,
+}
+
+impl EmergencySlate {
+    /// Slate described by `section`, cached in `cache_dir`; `None` when the
+    /// slate is disabled.
+    pub fn from_config(
+        section: &SlateSection,
+        cache_dir: &Path,
+    ) -> Result<Option<Self>, BroadcasterError> {
+        if !section.enabled {
+            return Ok(None);
+        }
+        let style: SlateStyle = section.style.parse()?;
+        let (width, height) = parse_resolution(&section.resolution)?;
+        if section.duration_seconds == 0 || section.fps == 0 {
+            return Err(BroadcasterError::InvalidSlate(
+                "duration_seconds and fps must be positive".to_string(),
+            ));
+        }
+        let tone_hz = section.tone_hz.unwrap_or(match style {
+            SlateStyle::Bars => DEFAULT_BARS_TONE_HZ,
+            SlateStyle::Card => 0,
+        });
+        Ok(Some(Self {
+            style,
+            text: section.text.clone(),
+            duration_s: section.duration_seconds,
+            tone_hz,
+            background: section.background.clone(),
+            width,
+            height,
+            fps: section.fps,
+            font: section.font.clone(),
+            cache_dir: cache_dir.to_path_buf(),
+        }))
+    }
+
+    /// Where the rendered clip is cached.
+    pub fn path(&self) -> PathBuf {
+        self.cache_dir.join(format!("slate-{}.mp4", self.key()))
+    }
+
+    fn text_path(&self) -> PathBuf {
+        self.cache_dir.join(format!("slate-{}.txt", self.key()))
+    }
+
+    /// Short digest of every setting that changes the rendered clip.
+    fn key(&self) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(
+            format!(
+                "{}|{}|{}|{}|{}|{}x{}|{}|{}",
+                self.style,
+                self.text,
+                self.duration_s,
+                self.tone_hz,
+                self.background,
+                self.width,
+                self.height,
+                self.fps,
+                self.font.as_deref().unwrap_or_default()
+            )
+            .as_bytes(),
+        );
+        hex::encode(hasher.finalize())[..12].to_string()
+    }
+
+    /// ffmpeg arguments rendering the slate to `output`.
+    pub fn render_args(&self, output: &Path) -> Vec<String> {
+        let duration = self.duration_s.to_string();
+        let size = format!("{}x{}", self.width, self.height);
+        let video = match self.style {
+            SlateStyle::Card => format!(
+                "color=c={}:s={size}:r={}:d={duration}",
+                self.background, self.fps
+            ),
+            SlateStyle::Bars => format!("smptehdbars=s={size}:r={}:d={duration}", self.fps),
+        };
+        let audio = if self.tone_hz > 0 {
+            format!(
+                "sine=frequency={}:sample_rate=48000:d={duration}",
+                self.tone_hz
+            )
+        } else {
+            "anullsrc=channel_layout=stereo:sample_rate=48000".to_string()
+        };
+        let mut args = vec![
+            "-y".to_string(),
+            "-f".to_string(),
+            "lavfi".to_string(),
+            "-i".to_string(),
+            video,
+            "-f".to_string(),
+            "lavfi".to_string(),
+            "-i".to_string(),
+            audio,
+        ];
+        if !self.text.trim().is_empty() {
+            args.push("-vf".to_string());
+            args.push(self.drawtext());
+        }
+        // Encoded like the processor's renditions so it airs like any asset.
+        args.extend(
+            [
+                "-t",
+                duration.as_str(),
+                "-c:v",
+                "libx264",
+                "-preset",
+                "veryfast",
+                "-crf",
+                "20",
+                "-profile:v",
+                "high",
+                "-pix_fmt",
+                "yuv420p",
+                "-c:a",
+                "aac",
+                "-ar",
+                "48000",
+                "-ac",
+                "2",
+                "-movflags",
+                "+faststart",
+            ]
+            .map(str::to_string),
+        );
+        args.push(output.to_string_lossy().to_string());
+        args
+    }
+
+    fn drawtext(&self) -> String {
+        let font = self
+            .font
+            .as_deref()
+            .map(|font| format!("fontfile='{font}':"))
+            .unwrap_or_default();
+        let text_file = self.text_path();
+        match self.style {
+            SlateStyle::Card => format!(
+                "drawtext={font}textfile='{}':fontsize={}:fontcolor=white:\
+                 x=(w-text_w)/2:y=(h-text_h)/2",
+                text_file.display(),
+                self.height / 12
+            ),
+            SlateStyle::Bars => format!(
+                "drawtext={font}textfile='{}':fontsize={}:fontcolor=white:\
+                 box=1:boxcolor=black@0.8:boxborderw=16:x=(w-text_w)/2:y=h-text_h-h/10",
+                text_file.display(),
+                self.height / 18
+            ),
+        }
+    }
+
+    /// Returns the cached clip, rendering it first when it is missing.
+    pub(super) async fn ensure(
+        &self,
+        ffmpeg: &Path,
+        executor: &dyn CommandExecutor,
+    ) -> Result<PathBuf, BroadcasterError> {
+        let path = self.path();
+        if async_fs::metadata(&path)
+            .await
+            .map(|meta| meta.len() > 0)
+            .unwrap_or(false)
+        {
+            return Ok(path);
+        }
+        async_fs::create_dir_all(&self.cache_dir).await?;
+        async_fs::write(self.text_path(), &self.text).await?;
+        // Rendered aside and moved into place, so an interrupted render is
+        // never mistaken for a cached clip.
+        let partial = path.with_extension("part.mp4");
+        let args = self.render_args(&partial);
+        let mut command = Command::new(ffmpeg);
+        command.args(&args).kill_on_drop(true);
+        let output = executor.run(&mut command).await?;
+        if !output.status.success() {
+            return Err(BroadcasterError::CommandFailure {
+                command: format!("{} {}", ffmpeg.display(), args.join(" ")),
+                status: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+            });
+        }
+        async_fs::rename(&partial, &path).await?;
+        Ok(path)
+    }
+}
+
+fn parse_resolution(value: &str) -> Result<(u32, u32), BroadcasterError> {
+    value
+        .split_once('x')
+        .and_then(|(width, height)| Some((width.trim().parse().ok()?, height.trim().parse().ok()?)))
+        .filter(|(width, height)| *width > 0 && *height > 0)
+        .ok_or_else(|| BroadcasterError::InvalidSlate(format!("invalid resolution {value:?}")))
+}
@@ -0,0 +1,355 @@
+//! Live encoder telemetry read from ffmpeg's `-progress` output.
+//!
+//! Feeders write their progress blocks to stderr alongside the log. Each
+//! complete block becomes an [`EncoderSample`] that is kept in a short
+//! history for the watchdog, broadcast to subscribers and, when a metrics
+//! store is attached, handed to a recorder thread that writes them in
+//! batches, so the feeder's stderr is never read behind a database write.
+//! Everything else on stderr is kept as the log reported when the feeder
+//! fails.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{mpsc, Arc, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::sync::{broadcast, oneshot};
+use tracing::warn;
+
+use crate::monitor::MetricsStore;
+
+/// Samples kept for [`EncoderTelemetry::health`].
+const HISTORY_SAMPLES: usize = 512;
+/// Samples buffered per subscriber before the slowest ones start lagging.
+const CHANNEL_CAPACITY: usize = 64;
+
+/// One progress report from the encoder.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncoderSample {
+    pub at: DateTime<Utc>,
+    /// Queue entry airing when the sample was taken.
+    pub queue_id: Option<i64>,
+    pub frame: u64,
+    pub fps: f64,
+    pub bitrate_kbps: Option<f64>,
+    /// Encoding speed relative to real time; below 1.0 the feed falls behind.
+    pub speed: Option<f64>,
+    pub drop_frames: u64,
+    pub dup_frames: u64,
+    /// Media time written so far by this feeder, in seconds.
+    pub out_time_s: f64,
+    /// Last block of the feeder.
+    pub finished: bool,
+}
+
+/// What a stderr line turned out to be.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressLine {
+    /// Not part of a progress block.
+    Log,
+    /// A field of a block still being read.
+    Field,
+    /// The line completing a block.
+    Sample(EncoderSample),
+}
+
+/// Assembles `key=value` progress blocks into samples.
+#[derive(Debug, Default)]
+pub struct ProgressParser {
+    fields: HashMap<String, String>,
+}
+
+impl ProgressParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, line: &str) -> ProgressLine {
+        let Some((key, value)) = line.trim().split_once('=') else {
+            return ProgressLine::Log;
+        };
+        let (key, value) = (key.trim(), value.trim());
+        if !is_progress_key(key) {
+            return ProgressLine::Log;
+        }
+        if key != "progress" {
+            self.fields.insert(key.to_string(), value.to_string());
+            return ProgressLine::Field;
+        }
+        let fields = std::mem::take(&mut self.fields);
+        let number = |key: &str| fields.get(key).and_then(|value| value.parse::<f64>().ok());
+        let count = |key: &str| {
+            fields
+                .get(key)
+                .and_then(|value| value.parse::<u64>().ok())
+                .unwrap_or(0)
+        };
+        let out_time_s = number("out_time_us")
+            .map(|us| us / 1_000_000.0)
+            .or_else(|| fields.get("out_time").and_then(|value| parse_clock(value)))
+            .unwrap_or(0.0)
+            .max(0.0);
+        ProgressLine::Sample(EncoderSample {
+            at: Utc::now(),
+            queue_id: None,
+            frame: count("frame"),
+            fps: number("fps").unwrap_or(0.0),
+            bitrate_kbps: fields
+                .get("bitrate")
+                .and_then(|value| value.trim_end_matches("kbits/s").parse().ok()),
+            speed: fields
+                .get("speed")
+                .and_then(|value| value.trim_end_matches('x').trim().parse().ok()),
+            drop_frames: count("drop_frames"),
+            dup_frames: count("dup_frames"),
+            out_time_s,
+            finished: value == "end",
+        })
+    }
+}
+
+fn is_progress_key(key: &str) -> bool {
+    matches!(
+        key,
+        "frame"
+            | "fps"
+            | "bitrate"
+            | "total_size"
+            | "out_time_us"
+            | "out_time_ms"
+            | "out_time"
+            | "dup_frames"
+            | "drop_frames"
+            | "speed"
+            | "progress"
+    ) || (key.starts_with("stream_") && key.ends_with("_q"))
+}
+
+/// `HH:MM:SS.micro` as seconds.
+fn parse_clock(value: &str) -> Option<f64> {
+    let mut parts = value.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+/// Summary of the samples taken within a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EncoderHealth {
+    pub samples: usize,
+    pub mean_speed: Option<f64>,
+    pub min_speed: Option<f64>,
+    /// Frames dropped within the window, across feeders.
+    pub dropped_frames: u64,
+    pub duplicated_frames: u64,
+}
+
+/// Shared handle to the encoder's samples; clones see the same stream.
+#[derive(Clone)]
+pub struct EncoderTelemetry {
+    inner: Arc<TelemetryInner>,
+}
+
+struct TelemetryInner {
+    sender: broadcast::Sender<EncoderSample>,
+    history: Mutex<VecDeque<EncoderSample>>,
+    entry: Mutex<Option<i64>>,
+    recorder: Mutex<Option<mpsc::Sender<Record>>>,
+}
+
+/// Work for the recorder thread.
+enum Record {
+    Sample(EncoderSample),
+    /// Answered once everything queued before it is written.
+    Flush(oneshot::Sender<()>),
+}
+
+impl Default for EncoderTelemetry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for EncoderTelemetry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EncoderTelemetry")
+            .field("entry", &*self.inner.entry.lock().unwrap())
+            .field("samples", &self.inner.history.lock().unwrap().len())
+            .finish()
+    }
+}
+
+impl EncoderTelemetry {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self {
+            inner: Arc::new(TelemetryInner {
+                sender,
+                history: Mutex::new(VecDeque::with_capacity(HISTORY_SAMPLES)),
+                entry: Mutex::new(None),
+                recorder: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Records every later sample in `store`, from a thread of its own.
+    pub fn record_to(&self, store: MetricsStore) {
+        let (recorder, records) = mpsc::channel();
+        let spawned = std::thread::Builder::new()
+            .name("encoder-telemetry".to_string())
+            .spawn(move || record_samples(&store, &records));
+        match spawned {
+            // Replacing a recorder lets the previous one finish its queue.
+            Ok(_) => *self.inner.recorder.lock().unwrap() = Some(recorder),
+            Err(err) => warn!(error = %err, "failed to start the encoder sample recorder"),
+        }
+    }
+
+    /// Waits until the samples published so far are recorded.
+    pub async fn flush(&self) {
+        let (done, flushed) = oneshot::channel();
+        let queued = self
+            .inner
+            .recorder
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|recorder| recorder.send(Record::Flush(done)).is_ok());
+        if queued {
+            let _ = flushed.await;
+        }
+    }
+
+    /// Samples published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<EncoderSample> {
+        self.inner.sender.subscribe()
+    }
+
+    /// Tags later samples with the queue entry on air.
+    pub fn set_entry(&self, queue_id: Option<i64>) {
+        *self.inner.entry.lock().unwrap() = queue_id;
+    }
+
+    pub fn latest(&self) -> Option<EncoderSample> {
+        self.inner.history.lock().unwrap().back().cloned()
+    }
+
+    /// Samples taken within `window` of now, oldest first.
+    pub fn recent(&self, window: Duration) -> Vec<EncoderSample> {
+        let since = Utc::now() - window;
+        self.inner
+            .history
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|sample| sample.at >= since)
+            .cloned()
+            .collect()
+    }
+
+    pub fn publish(&self, mut sample: EncoderSample) {
+        sample.queue_id = *self.inner.entry.lock().unwrap();
+        {
+            let mut history = self.inner.history.lock().unwrap();
+            if history.len() == HISTORY_SAMPLES {
+                history.pop_front();
+            }
+            history.push_back(sample.clone());
+        }
+        if let Some(recorder) = self.inner.recorder.lock().unwrap().as_ref() {
+            let _ = recorder.send(Record::Sample(sample.clone()));
+        }
+        // Nobody listening is fine.
+        let _ = self.inner.sender.send(sample);
+    }
+
+    /// Speed and frame counters over the samples within `window`, or `None`
+    /// when the encoder reported nothing in that time.
+    pub fn health(&self, window: Duration) -> Option<EncoderHealth> {
+        let samples = self.recent(window);
+        if samples.is_empty() {
+            return None;
+        }
+        let speeds: Vec<f64> = samples.iter().filter_map(|sample| sample.speed).collect();
+        let mean_speed =
+            (!speeds.is_empty()).then(|| speeds.iter().sum::<f64>() / speeds.len() as f64);
+        let min_speed = speeds.iter().copied().reduce(f64::min);
+        // Counters are cumulative per feeder, so only growth between
+        // consecutive samples of the same entry counts.
+        let (mut dropped_frames, mut duplicated_frames) = (0, 0);
+        for pair in samples.windows(2) {
+            let (before, after) = (&pair[0], &pair[1]);
+            if before.queue_id != after.queue_id || before.finished {
+                continue;
+            }
+            dropped_frames += after.drop_frames.saturating_sub(before.drop_frames);
+            duplicated_frames += after.dup_frames.saturating_sub(before.dup_frames);
+        }
+        Some(EncoderHealth {
+            samples: samples.len(),
+            mean_speed,
+            min_speed,
+            dropped_frames,
+            duplicated_frames,
+        })
+    }
+}
+
+/// Writes samples to `store` until every sender is gone, batching whatever
+/// queued up while the previous batch was written.
+fn record_samples(store: &MetricsStore, records: &mpsc::Receiver<Record>) {
+    while let Ok(first) = records.recv() {
+        let mut batch = Vec::new();
+        let mut flushes = Vec::new();
+        for record in std::iter::once(first).chain(records.try_iter()) {
+            match record {
+                Record::Sample(sample) => batch.push(sample),
+                Record::Flush(done) => flushes.push(done),
+            }
+        }
+        if !batch.is_empty() {
+            if let Err(err) = store.record_encoder_samples(&batch) {
+                warn!(error = %err, samples = batch.len(), "failed to record encoder samples");
+            }
+        }
+        for done in flushes {
+            let _ = done.send(());
+        }
+    }
+}
+
+/// What a feeder left on stderr besides the samples it published.
+#[derive(Debug, Clone, Default)]
+pub(super) struct FeederLog {
+    /// Lines that were not part of a progress block.
+    pub log: String,
+    /// Media time of the last sample, if the feeder reported any.
+    pub out_time_s: Option<f64>,
+}
+
+/// Reads a feeder's stderr to the end, publishing its progress blocks to
+/// `telemetry`.
+pub(super) async fn read_progress(
+    stderr: impl AsyncRead + Unpin,
+    telemetry: &EncoderTelemetry,
+) -> std::io::Result<FeederLog> {
+    let mut parser = ProgressParser::new();
+    let mut lines = BufReader::new(stderr).lines();
+    let mut feeder = FeederLog::default();
+    while let Some(line) = lines.next_line().await? {
+        match parser.push(&line) {
+            ProgressLine::Log => {
+                feeder.log.push_str(&line);
+                feeder.log.push('\n');
+            }
+            ProgressLine::Field => {}
+            ProgressLine::Sample(sample) => {
+                feeder.out_time_s = Some(sample.out_time_s);
+                telemetry.publish(sample);
+            }
+        }
+    }
+    Ok(feeder)
+}
@@ -0,0 +1,502 @@
+//! Transition library and transitions rendered ahead of time.
+//!
+//! The library maps the previous and next `content_kind` to a named style
+//! from the catalog in `broadcaster.toml`, so music blocks and sponsor breaks
+//! get different handoffs. While an entry airs, the broadcaster forecasts the
+//! entry that follows it and renders only the short transition clip between
+//! the two in the background. The next entry is then spliced after the clip
+//! through a concat playlist `inpoint`, so its body is stream-copied instead
+//! of re-encoded. When the clip is not ready by the time the next entry
+//! starts, or a different entry was selected, that entry airs with a cut.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tokio::fs as async_fs;
+use tokio::process::Command;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+
+use crate::config::TransitionsSection;
+use crate::queue::selection::kind_key;
+
+use super::{BroadcasterError, CommandExecutor, StreamingPlan};
+
+/// Length of the built-in `fade`, in seconds.
+const DEFAULT_FADE_SECONDS: f64 = 0.4;
+/// Entries shorter than this air with a cut.
+const MIN_CROSSFADE_DURATION_S: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransitionKind {
+    Cut,
+    Fade,
+    DipToBlack,
+    WipeLeft,
+    WipeRight,
+    WipeUp,
+    WipeDown,
+    /// Picture cuts, sound crossfades.
+    AudioCrossfade,
+    /// The next entry's sound starts under the previous picture.
+    JCut,
+    /// The previous entry's sound carries over the next picture.
+    LCut,
+}
+
+impl TransitionKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransitionKind::Cut => "cut",
+            TransitionKind::Fade => "fade",
+            TransitionKind::DipToBlack => "dip_to_black",
+            TransitionKind::WipeLeft => "wipe_left",
+            TransitionKind::WipeRight => "wipe_right",
+            TransitionKind::WipeUp => "wipe_up",
+            TransitionKind::WipeDown => "wipe_down",
+            TransitionKind::AudioCrossfade => "audio_crossfade",
+            TransitionKind::JCut => "j_cut",
+            TransitionKind::LCut => "l_cut",
+        }
+    }
+
+    /// `xfade` transition blending the two pictures, for kinds that blend.
+    fn xfade(&self) -> Option<&'static str> {
+        match self {
+            TransitionKind::Fade => Some("fade"),
+            TransitionKind::DipToBlack => Some("fadeblack"),
+            TransitionKind::WipeLeft => Some("wipeleft"),
+            TransitionKind::WipeRight => Some("wiperight"),
+            TransitionKind::WipeUp => Some("wipeup"),
+            TransitionKind::WipeDown => Some("wipedown"),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for TransitionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for TransitionKind {
+    type Err = BroadcasterError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "cut" => Ok(Self::Cut),
+            "fade" => Ok(Self::Fade),
+            "dip_to_black" => Ok(Self::DipToBlack),
+            "wipe_left" => Ok(Self::WipeLeft),
+            "wipe_right" => Ok(Self::WipeRight),
+            "wipe_up" => Ok(Self::WipeUp),
+            "wipe_down" => Ok(Self::WipeDown),
+            "audio_crossfade" => Ok(Self::AudioCrossfade),
+            "j_cut" => Ok(Self::JCut),
+            "l_cut" => Ok(Self::LCut),
+            other => Err(BroadcasterError::InvalidTransition(format!(
+                "unknown transition kind {other}"
+            ))),
+        }
+    }
+}
+
+/// A named catalog entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransitionStyle {
+    pub name: String,
+    pub kind: TransitionKind,
+    pub duration_s: f64,
+}
+
+impl TransitionStyle {
+    pub fn cut() -> Self {
+        Self {
+            name: "cut".to_string(),
+            kind: TransitionKind::Cut,
+            duration_s: 0.0,
+        }
+    }
+
+    fn fade() -> Self {
+        Self {
+            name: "fade".to_string(),
+            kind: TransitionKind::Fade,
+            duration_s: DEFAULT_FADE_SECONDS,
+        }
+    }
+
+    /// `filter_complex` producing `[vout]` and `[aout]` from the tail of
+    /// input 0, starting at `tail_start`, and the head of input 1.
+    fn filter(&self, tail_start: f64) -> Option<String> {
+        let d = self.duration_s;
+        let prev_v = format!("[0:v]trim=start={tail_start},setpts=PTS-STARTPTS");
+        let prev_a = format!("[0:a]atrim=start={tail_start},asetpts=PTS-STARTPTS");
+        let next_v = format!("[1:v]trim=end={d},setpts=PTS-STARTPTS");
+        let next_a = format!("[1:a]atrim=end={d},asetpts=PTS-STARTPTS");
+        let crossfade =
+            format!("{prev_a}[a0];{next_a}[a1];[a0][a1]acrossfade=d={d}:c1=sin:c2=sin[aout]");
+        let (video, audio) = match self.kind {
+            TransitionKind::Cut => return None,
+            TransitionKind::AudioCrossfade => (format!("{next_v}[vout]"), crossfade),
+            TransitionKind::JCut => (format!("{prev_v}[vout]"), format!("{next_a}[aout]")),
+            TransitionKind::LCut => (format!("{next_v}[vout]"), format!("{prev_a}[aout]")),
+            kind => {
+                let xfade = kind.xfade()?;
+                (
+                    format!("{prev_v}[v0];{next_v}[v1];[v0][v1]xfade=transition={xfade}:duration={d}:offset=0[vout]"),
+                    crossfade,
+                )
+            }
+        };
+        Some(format!("{video};{audio}"))
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TransitionRule {
+    from: Option<String>,
+    to: Option<String>,
+    transition: String,
+}
+
+impl TransitionRule {
+    fn matches(&self, from: Option<&str>, to: Option<&str>) -> bool {
+        side_matches(self.from.as_deref(), from) && side_matches(self.to.as_deref(), to)
+    }
+}
+
+fn side_matches(pattern: Option<&str>, kind: Option<&str>) -> bool {
+    match pattern.map(str::trim) {
+        None | Some("*") => true,
+        Some(pattern) => kind_key(Some(pattern)) == kind_key(kind),
+    }
+}
+
+/// Catalog of transitions plus the rules choosing between them.
+#[derive(Debug, Clone)]
+pub struct TransitionLibrary {
+    styles: HashMap<String, TransitionStyle>,
+    rules: Vec<TransitionRule>,
+    default: String,
+}
+
+impl Default for TransitionLibrary {
+    fn default() -> Self {
+        let styles = [TransitionStyle::cut(), TransitionStyle::fade()]
+            .into_iter()
+            .map(|style| (style.name.clone(), style))
+            .collect();
+        Self {
+            styles,
+            rules: Vec::new(),
+            default: "fade".to_string(),
+        }
+    }
+}
+
+impl TransitionLibrary {
+    pub fn from_config(section: &TransitionsSection) -> Result<Self, BroadcasterError> {
+        let mut library = Self::default();
+        for (name, style) in &section.catalog {
+            let kind: TransitionKind = style.kind.parse()?;
+            if kind != TransitionKind::Cut && style.duration_ms == 0 {
+                return Err(BroadcasterError::InvalidTransition(format!(
+                    "transition {name} needs a duration_ms"
+                )));
+            }
+            library.styles.insert(
+                name.clone(),
+                TransitionStyle {
+                    name: name.clone(),
+                    kind,
+                    duration_s: style.duration_ms as f64 / 1000.0,
+                },
+            );
+        }
+        for rule in &section.rules {
+            library.require(&rule.transition)?;
+            library.rules.push(TransitionRule {
+                from: rule.from.clone(),
+                to: rule.to.clone(),
+                transition: rule.transition.clone(),
+            });
+        }
+        library.require(&section.default)?;
+        library.default = section.default.clone();
+        Ok(library)
+    }
+
+    pub fn style(&self, name: &str) -> Option<&TransitionStyle> {
+        self.styles.get(name)
+    }
+
+    /// Style for the handoff from an entry of kind `from` to one of kind `to`.
+    pub fn select(&self, from: Option<&str>, to: Option<&str>) -> &TransitionStyle {
+        self.rules
+            .iter()
+            .find(|rule| rule.matches(from, to))
+            .and_then(|rule| self.styles.get(&rule.transition))
+            .or_else(|| self.styles.get(&self.default))
+            .expect("default transition is in the catalog")
+    }
+
+    fn require(&self, name: &str) -> Result<(), BroadcasterError> {
+        if self.styles.contains_key(name) {
+            Ok(())
+        } else {
+            Err(BroadcasterError::InvalidTransition(format!(
+                "transition {name} is not in the catalog"
+            )))
+        }
+    }
+}
+
+/// One side of a transition.
+#[derive(Debug, Clone)]
+pub(super) struct TransitionEnd {
+    pub queue_id: i64,
+    pub asset_path: String,
+    pub content_kind: Option<String>,
+    pub duration_s: i64,
+}
+
+impl TransitionEnd {
+    fn fits(&self, style: &TransitionStyle) -> bool {
+        self.duration_s >= MIN_CROSSFADE_DURATION_S && self.duration_s as f64 > style.duration_s
+    }
+}
+
+struct PendingTransition {
+    from: i64,
+    to: i64,
+    artifacts: Vec<PathBuf>,
+    handle: JoinHandle<Result<StreamingPlan, BroadcasterError>>,
+}
+
+/// Holds at most one transition being prepared: the one out of the entry
+/// currently on air.
+pub(super) struct TransitionPreparer {
+    ffmpeg: PathBuf,
+    temp_dir: PathBuf,
+    executor: Arc<dyn CommandExecutor>,
+    library: TransitionLibrary,
+    pending: Mutex<Option<PendingTransition>>,
+}
+
+impl TransitionPreparer {
+    pub(super) fn new(
+        ffmpeg: PathBuf,
+        temp_dir: PathBuf,
+        executor: Arc<dyn CommandExecutor>,
+        library: TransitionLibrary,
+    ) -> Self {
+        Self {
+            ffmpeg,
+            temp_dir,
+            executor,
+            library,
+            pending: Mutex::new(None),
+        }
+    }
+
+    pub(super) fn library(&self) -> &TransitionLibrary {
+        &self.library
+    }
+
+    /// Starts rendering the transition the library picks from `from` into
+    /// `to`, replacing any transition still pending.
+    pub(super) fn prepare(&self, from: TransitionEnd, to: TransitionEnd) {
+        let stale = self.pending.lock().unwrap().take();
+        if let Some(stale) = stale {
+            discard(stale);
+        }
+        let style = self
+            .library
+            .select(from.content_kind.as_deref(), to.content_kind.as_deref())
+            .clone();
+        if style.kind == TransitionKind::Cut || !from.fits(&style) || !to.fits(&style) {
+            return;
+        }
+        let paths = TransitionPaths::new(&self.temp_dir, from.queue_id, to.queue_id);
+        let artifacts = paths.artifacts();
+        let ffmpeg = self.ffmpeg.clone();
+        let executor = Arc::clone(&self.executor);
+        let (from_id, to_id) = (from.queue_id, to.queue_id);
+        let handle = tokio::spawn(async move {
+            render_transition(&ffmpeg, executor.as_ref(), &paths, &style, &from, &to).await
+        });
+        debug!(from = from_id, to = to_id, "preparing transition");
+        *self.pending.lock().unwrap() = Some(PendingTransition {
+            from: from_id,
+            to: to_id,
+            artifacts,
+            handle,
+        });
+    }
+
+    /// The transition from `from` into `to`, if it was prepared and has
+    /// finished rendering. Anything else pending is discarded.
+    pub(super) async fn take(&self, from: i64, to: i64) -> Option<StreamingPlan> {
+        let pending = self.pending.lock().unwrap().take()?;
+        if pending.from != from || pending.to != to {
+            debug!(
+                expected = pending.to,
+                selected = to,
+                "forecast missed the next entry, cutting"
+            );
+            discard(pending);
+            return None;
+        }
+        if !pending.handle.is_finished() {
+            info!(from, to, "transition not ready in time, cutting");
+            discard(pending);
+            return None;
+        }
+        match pending.handle.await {
+            Ok(Ok(plan)) => Some(plan),
+            Ok(Err(err)) => {
+                warn!(from, to, error = %err, "transition render failed, cutting");
+                remove_artifacts(&pending.artifacts).await;
+                None
+            }
+            Err(err) => {
+                warn!(from, to, error = %err, "transition task failed, cutting");
+                remove_artifacts(&pending.artifacts).await;
+                None
+            }
+        }
+    }
+
+    /// Abandons the pending transition, if any.
+    pub(super) fn clear(&self) {
+        let pending = self.pending.lock().unwrap().take();
+        if let Some(pending) = pending {
+            discard(pending);
+        }
+    }
+}
+
+struct TransitionPaths {
+    clip: PathBuf,
+    playlist: PathBuf,
+}
+
+impl TransitionPaths {
+    fn new(temp_dir: &Path, from: i64, to: i64) -> Self {
+        Self {
+            clip: temp_dir.join(format!("{from}_to_{to}_transition.mp4")),
+            playlist: temp_dir.join(format!("{from}_to_{to}_playlist.txt")),
+        }
+    }
+
+    fn artifacts(&self) -> Vec<PathBuf> {
+        vec![self.clip.clone(), self.playlist.clone()]
+    }
+}
+
+fn discard(pending: PendingTransition) {
+    pending.handle.abort();
+    let artifacts = pending.artifacts;
+    tokio::spawn(async move { remove_artifacts(&artifacts).await });
+}
+
+async fn remove_artifacts(paths: &[PathBuf]) {
+    for path in paths {
+        if let Err(error) = async_fs::remove_file(path).await {
+            debug!(path = %path.display(), %error, "failed to remove transition artifact");
+        }
+    }
+}
+
+/// Renders the transition clip and the concat playlist that plays it
+/// followed by the rest of `to`, starting where the clip left off.
+async fn render_transition(
+    ffmpeg: &Path,
+    executor: &dyn CommandExecutor,
+    paths: &TransitionPaths,
+    style: &TransitionStyle,
+    from: &TransitionEnd,
+    to: &TransitionEnd,
+) -> Result<StreamingPlan, BroadcasterError> {
+    let tail_start = from.duration_s as f64 - style.duration_s;
+    let Some(filter) = style.filter(tail_start) else {
+        return Err(BroadcasterError::InvalidTransition(format!(
+            "{} renders no clip",
+            style.name
+        )));
+    };
+    if let Some(dir) = paths.clip.parent() {
+        async_fs::create_dir_all(dir).await?;
+    }
+    // Encoded like the processor's renditions so the clip can be
+    // stream-copied next to the untouched asset.
+    let args = vec![
+        "-y".to_string(),
+        "-i".to_string(),
+        from.asset_path.clone(),
+        "-i".to_string(),
+        to.asset_path.clone(),
+        "-filter_complex".to_string(),
+        filter,
+        "-map".to_string(),
+        "[vout]".to_string(),
+        "-map".to_string(),
+        "[aout]".to_string(),
+        "-c:v".to_string(),
+        "libx264".to_string(),
+        "-preset".to_string(),
+        "veryfast".to_string(),
+        "-crf".to_string(),
+        "20".to_string(),
+        "-profile:v".to_string(),
+        "high".to_string(),
+        "-pix_fmt".to_string(),
+        "yuv420p".to_string(),
+        "-c:a".to_string(),
+        "aac".to_string(),
+        "-ar".to_string(),
+        "48000".to_string(),
+        "-ac".to_string(),
+        "2".to_string(),
+        "-movflags".to_string(),
+        "+faststart".to_string(),
+        paths.clip.to_string_lossy().to_string(),
+    ];
+    let mut command = Command::new(ffmpeg);
+    command.args(&args).kill_on_drop(true);
+    let output = executor.run(&mut command).await?;
+    if !output.status.success() {
+        return Err(BroadcasterError::CommandFailure {
+            command: format!("{} {}", ffmpeg.display(), args.join(" ")),
+            status: output.status.code(),
+            stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        });
+    }
+
+    let playlist = format!(
+        "file '{}'\nfile '{}'\ninpoint {}\n",
+        paths.clip.display(),
+        to.asset_path,
+        style.duration_s
+    );
+    async_fs::write(&paths.playlist, playlist).await?;
+
+    let inputs = vec![
+        "-re".to_string(),
+        "-f".to_string(),
+        "concat".to_string(),
+        "-safe".to_string(),
+        "0".to_string(),
+        "-i".to_string(),
+        paths.playlist.to_string_lossy().to_string(),
+    ];
+    Ok(StreamingPlan::new(
+        inputs,
+        paths.artifacts(),
+        style.name.clone(),
+    ))
+}
@@ -1,8 +1,20 @@
-use std::collections::VecDeque;
+//! Broadcaster watchdog.
+//!
+//! Each evaluation gathers the queue metrics, the stream's health, the
+//! encoder's speed and what the output monitor saw on air, and runs them
+//! through the rules in `[[watchdog.rule]]`.
+//! A rule maps conditions to ordered actions and may be limited by a
+//! cooldown and a sliding budget of firings; a rule whose budget is spent
+//! escalates instead of acting again, at most once per cooldown.
+//! Escalations are turned into incidents when the watchdog is given a
+//! [`WatchdogIncidents`] tracker.
+
+use std::collections::{HashMap, VecDeque};
 use std::fmt;
 use std::fs;
 use std::io::ErrorKind;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 
@@ -11,9 +23,14 @@ use serde::Deserialize;
 use tokio::process::Command;
 use tracing::warn;
 
-use crate::config::WatchdogSection;
-use crate::{PlayoutQueueStore, QueueMetrics};
+use crate::config::{WatchdogRuleSection, WatchdogSection};
+use crate::{
+    IncidentSeverity, LiveQualityCollector, MetricsStore, PlayoutQueueStore, QueueMetrics,
+};
 
+use super::escalation::{IncidentUpdate, WatchdogIncidents};
+use super::output_monitor::{OutputMonitor, OutputSample};
+use super::telemetry::EncoderTelemetry;
 use super::{BroadcasterPaths, CommandExecutor, SystemCommandExecutor};
 use thiserror::Error;
 
@@ -29,6 +46,8 @@ pub enum WatchdogError {
     Io(#[from] std::io::Error),
     #[error("queue error: {0}")]
     Queue(#[from] crate::queue::QueueError),
+    #[error("invalid watchdog rule: {0}")]
+    InvalidRule(String),
 }
 
 #[derive(Debug, Clone)]
@@ -36,6 +55,12 @@ pub struct WatchdogReport {
     pub metrics: QueueMetrics,
     pub actions: Vec<WatchdogAction>,
     pub observations: Vec<String>,
+    /// Rules whose conditions held, in rule order.
+    pub firings: Vec<RuleFiring>,
+    /// How the evaluation changed the watchdog's open incident.
+    pub incident: Option<IncidentUpdate>,
+    /// What the output monitor captured, when the stream was up.
+    pub output: Option<OutputSample>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -47,15 +72,420 @@ pub enum WatchdogAction {
     Escalate(String),
 }
 
+impl WatchdogAction {
+    /// Name of the action in `[[watchdog.rule]]`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WatchdogAction::RestartEncoder => "restart_encoder",
+            WatchdogAction::RestartNginx => "restart_nginx",
+            WatchdogAction::InjectEmergencyLoop => "inject_emergency_loop",
+            WatchdogAction::PauseDownloads => "pause_downloads",
+            WatchdogAction::Escalate(_) => "escalate",
+        }
+    }
+
+    pub(super) fn is_restart(&self) -> bool {
+        matches!(
+            self,
+            WatchdogAction::RestartEncoder | WatchdogAction::RestartNginx
+        )
+    }
+}
+
+/// Action names accepted in `[[watchdog.rule]]`. `escalate` carries the
+/// rule's name and conditions once it fires.
+impl FromStr for WatchdogAction {
+    type Err = WatchdogError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "restart_encoder" => Ok(WatchdogAction::RestartEncoder),
+            "restart_nginx" => Ok(WatchdogAction::RestartNginx),
+            "inject_emergency_loop" => Ok(WatchdogAction::InjectEmergencyLoop),
+            "pause_downloads" => Ok(WatchdogAction::PauseDownloads),
+            "escalate" => Ok(WatchdogAction::Escalate(String::new())),
+            other => Err(WatchdogError::InvalidRule(format!(
+                "unknown action {other:?}"
+            ))),
+        }
+    }
+}
+
+/// Values the rules are evaluated against.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WatchdogInputs {
+    pub buffer_hours: f64,
+    pub stream_inactive: bool,
+    pub failures_last_hour: i64,
+    /// Mean encoder speed over the window, when samples are available.
+    pub encoder_speed: Option<f64>,
+    /// How long the output has been black, frozen or silent, when it was
+    /// captured.
+    pub black_for_seconds: Option<f64>,
+    pub frozen_for_seconds: Option<f64>,
+    pub silent_for_seconds: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatchdogRule {
+    pub name: String,
+    pub buffer_hours_below: Option<f64>,
+    pub stream_inactive: Option<bool>,
+    pub failures_last_hour_at_least: Option<i64>,
+    pub encoder_speed_below: Option<f64>,
+    pub black_for_seconds: Option<f64>,
+    pub frozen_for_seconds: Option<f64>,
+    pub silent_for_seconds: Option<f64>,
+    pub actions: Vec<WatchdogAction>,
+    pub cooldown: Duration,
+    pub budget: Option<u32>,
+    pub budget_window: Duration,
+    pub severity: IncidentSeverity,
+}
+
+impl WatchdogRule {
+    fn from_section(
+        section: &WatchdogRuleSection,
+        watchdog: &WatchdogSection,
+    ) -> Result<Self, WatchdogError> {
+        let mut actions = section
+            .actions
+            .iter()
+            .map(|action| action.parse::<WatchdogAction>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| WatchdogError::InvalidRule(format!("{}: {err}", section.name)))?;
+        if section.stream_inactive == Some(true) && !watchdog.restart_on_freeze {
+            actions.retain(|action| *action != WatchdogAction::RestartNginx);
+        }
+        let budget = section.budget.or_else(|| {
+            actions
+                .iter()
+                .any(WatchdogAction::is_restart)
+                .then_some(watchdog.restart_max_attempts)
+        });
+        let rule = Self {
+            name: section.name.clone(),
+            buffer_hours_below: section.buffer_hours_below,
+            stream_inactive: section.stream_inactive,
+            failures_last_hour_at_least: section.failures_last_hour_at_least,
+            encoder_speed_below: section.encoder_speed_below,
+            black_for_seconds: section.black_for_seconds,
+            frozen_for_seconds: section.frozen_for_seconds,
+            silent_for_seconds: section.silent_for_seconds,
+            actions,
+            cooldown: Duration::seconds(section.cooldown_seconds as i64),
+            budget,
+            budget_window: Duration::seconds(section.budget_window_seconds as i64),
+            severity: section
+                .severity
+                .unwrap_or_else(|| derived_severity(section)),
+        };
+        if !rule.has_condition() {
+            return Err(WatchdogError::InvalidRule(format!(
+                "{} sets no condition",
+                rule.name
+            )));
+        }
+        Ok(rule)
+    }
+
+    /// Rules applied when `broadcaster.toml` declares none.
+    fn built_in(watchdog: &WatchdogSection) -> Vec<Self> {
+        let rule = |name: &str, actions: &[&str]| WatchdogRuleSection {
+            name: name.to_string(),
+            buffer_hours_below: None,
+            stream_inactive: None,
+            failures_last_hour_at_least: None,
+            encoder_speed_below: None,
+            black_for_seconds: None,
+            frozen_for_seconds: None,
+            silent_for_seconds: None,
+            actions: actions.iter().map(|action| action.to_string()).collect(),
+            cooldown_seconds: 0,
+            budget: None,
+            budget_window_seconds: 300,
+            severity: None,
+        };
+        let sections = [
+            WatchdogRuleSection {
+                buffer_hours_below: Some(1.0),
+                ..rule("buffer_low", &["inject_emergency_loop", "pause_downloads"])
+            },
+            WatchdogRuleSection {
+                buffer_hours_below: Some(2.0),
+                ..rule("buffer_critical", &["inject_emergency_loop"])
+            },
+            WatchdogRuleSection {
+                stream_inactive: Some(true),
+                ..rule("stream_inactive", &["restart_encoder", "restart_nginx"])
+            },
+            // Reported only; restarting a lagging encoder is opt-in.
+            WatchdogRuleSection {
+                encoder_speed_below: Some(watchdog.encoder_min_speed),
+                ..rule("encoder_lagging", &[])
+            },
+        ];
+        sections
+            .iter()
+            .filter_map(|section| Self::from_section(section, watchdog).ok())
+            .collect()
+    }
+
+    fn has_condition(&self) -> bool {
+        self.buffer_hours_below.is_some()
+            || self.stream_inactive.is_some()
+            || self.failures_last_hour_at_least.is_some()
+            || self.encoder_speed_below.is_some()
+            || self.black_for_seconds.is_some()
+            || self.frozen_for_seconds.is_some()
+            || self.silent_for_seconds.is_some()
+    }
+
+    /// What the rule observed, when all of its conditions hold.
+    pub fn matches(&self, inputs: &WatchdogInputs) -> Option<String> {
+        let mut observed = Vec::new();
+        if let Some(limit) = self.buffer_hours_below {
+            if inputs.buffer_hours >= limit {
+                return None;
+            }
+            observed.push(format!(
+                "buffer {:.2}h abaixo de {limit:.2}h",
+                inputs.buffer_hours
+            ));
+        }
+        if let Some(inactive) = self.stream_inactive {
+            if inputs.stream_inactive != inactive {
+                return None;
+            }
+            observed.push(if inactive {
+                "ffprobe detectou stream inativo".to_string()
+            } else {
+                "stream ativo".to_string()
+            });
+        }
+        if let Some(limit) = self.failures_last_hour_at_least {
+            if inputs.failures_last_hour < limit {
+                return None;
+            }
+            observed.push(format!(
+                "{} falhas na última hora",
+                inputs.failures_last_hour
+            ));
+        }
+        if let Some(limit) = self.encoder_speed_below {
+            let speed = inputs.encoder_speed.filter(|speed| *speed < limit)?;
+            observed.push(format!("encoder a {speed:.2}x, abaixo de {limit:.2}x"));
+        }
+        if let Some(limit) = self.black_for_seconds {
+            let seconds = inputs
+                .black_for_seconds
+                .filter(|seconds| *seconds >= limit)?;
+            observed.push(format!("saída em tela preta há {seconds:.0}s"));
+        }
+        if let Some(limit) = self.frozen_for_seconds {
+            let seconds = inputs
+                .frozen_for_seconds
+                .filter(|seconds| *seconds >= limit)?;
+            observed.push(format!("imagem congelada na saída há {seconds:.0}s"));
+        }
+        if let Some(limit) = self.silent_for_seconds {
+            let seconds = inputs
+                .silent_for_seconds
+                .filter(|seconds| *seconds >= limit)?;
+            observed.push(format!("áudio em silêncio na saída há {seconds:.0}s"));
+        }
+        if observed.is_empty() {
+            return None;
+        }
+        Some(format!("regra {}: {}", self.name, observed.join(", ")))
+    }
+}
+
+/// Severity of a rule's incident when `severity` is not configured.
+fn derived_severity(section: &WatchdogRuleSection) -> IncidentSeverity {
+    if section.stream_inactive == Some(true)
+        || section.black_for_seconds.is_some()
+        || section.frozen_for_seconds.is_some()
+    {
+        IncidentSeverity::Critical
+    } else if section.buffer_hours_below.is_some_and(|hours| hours <= 1.0)
+        || section.failures_last_hour_at_least.is_some()
+        || section.silent_for_seconds.is_some()
+    {
+        IncidentSeverity::High
+    } else {
+        IncidentSeverity::Medium
+    }
+}
+
+/// How a rule whose conditions held was handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FiringState {
+    /// Its actions were returned.
+    Acted,
+    /// It fired within its cooldown, so nothing was done.
+    CoolingDown,
+    /// Its budget is spent; it escalated instead of acting, unless it
+    /// already did within its cooldown.
+    BudgetExhausted,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleFiring {
+    pub rule: String,
+    pub observation: String,
+    pub state: FiringState,
+    pub severity: IncidentSeverity,
+    /// Whether the actions returned for it restart something.
+    pub restarted: bool,
+    /// Whether it escalated on this evaluation.
+    pub escalated: bool,
+}
+
+/// When a rule acted within its horizon and when it last escalated a spent
+/// budget.
+#[derive(Debug, Default)]
+struct RuleHistory {
+    fired: VecDeque<DateTime<Utc>>,
+    escalated_at: Option<DateTime<Utc>>,
+}
+
+/// The watchdog's rules and when each last fired.
+#[derive(Debug)]
+pub struct WatchdogRules {
+    rules: Vec<WatchdogRule>,
+    fired: Mutex<HashMap<String, RuleHistory>>,
+}
+
+impl WatchdogRules {
+    pub fn from_config(section: &WatchdogSection) -> Result<Self, WatchdogError> {
+        let rules = if section.rules.is_empty() {
+            WatchdogRule::built_in(section)
+        } else {
+            section
+                .rules
+                .iter()
+                .map(|rule| WatchdogRule::from_section(rule, section))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+        for (index, rule) in rules.iter().enumerate() {
+            if rules[..index].iter().any(|other| other.name == rule.name) {
+                return Err(WatchdogError::InvalidRule(format!(
+                    "duplicate rule {}",
+                    rule.name
+                )));
+            }
+        }
+        Ok(Self {
+            rules,
+            fired: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn rules(&self) -> &[WatchdogRule] {
+        &self.rules
+    }
+
+    /// Runs every rule against `inputs` at `now`. Actions of all rules that
+    /// acted are merged in rule order without repeats.
+    pub fn evaluate(
+        &self,
+        inputs: &WatchdogInputs,
+        now: DateTime<Utc>,
+    ) -> (Vec<WatchdogAction>, Vec<RuleFiring>) {
+        let mut actions: Vec<WatchdogAction> = Vec::new();
+        let mut firings = Vec::new();
+        let mut fired = self.fired.lock().unwrap();
+        for rule in &self.rules {
+            let Some(observation) = rule.matches(inputs) else {
+                continue;
+            };
+            let RuleHistory {
+                fired: history,
+                escalated_at,
+            } = fired.entry(rule.name.clone()).or_default();
+            let horizon = rule.budget_window.max(rule.cooldown);
+            while history.front().is_some_and(|at| *at <= now - horizon) {
+                history.pop_front();
+            }
+            let in_window = history
+                .iter()
+                .filter(|at| **at > now - rule.budget_window)
+                .count();
+            let state = if history
+                .back()
+                .is_some_and(|last| now - *last < rule.cooldown)
+            {
+                FiringState::CoolingDown
+            } else if rule
+                .budget
+                .is_some_and(|budget| in_window >= budget as usize)
+            {
+                // Without a cooldown a spent budget escalates once per window.
+                let hold = if rule.cooldown > Duration::zero() {
+                    rule.cooldown
+                } else {
+                    rule.budget_window
+                };
+                if escalated_at.is_none_or(|at| now - at >= hold) {
+                    *escalated_at = Some(now);
+                    actions.push(WatchdogAction::Escalate(format!(
+                        "regra {}: {} disparos em {}s, orçamento esgotado",
+                        rule.name,
+                        in_window,
+                        rule.budget_window.num_seconds()
+                    )));
+                }
+                FiringState::BudgetExhausted
+            } else {
+                history.push_back(now);
+                for action in &rule.actions {
+                    let action = match action {
+                        WatchdogAction::Escalate(_) => {
+                            WatchdogAction::Escalate(observation.clone())
+                        }
+                        other => other.clone(),
+                    };
+                    if !actions.contains(&action) {
+                        actions.push(action);
+                    }
+                }
+                FiringState::Acted
+            };
+            let escalated = match state {
+                FiringState::Acted => rule
+                    .actions
+                    .iter()
+                    .any(|action| matches!(action, WatchdogAction::Escalate(_))),
+                FiringState::CoolingDown => false,
+                FiringState::BudgetExhausted => *escalated_at == Some(now),
+            };
+            firings.push(RuleFiring {
+                rule: rule.name.clone(),
+                observation,
+                state,
+                severity: rule.severity,
+                restarted: state == FiringState::Acted
+                    && rule.actions.iter().any(WatchdogAction::is_restart),
+                escalated,
+            });
+        }
+        (actions, firings)
+    }
+}
+
 pub struct Watchdog {
     queue: PlayoutQueueStore,
     config: WatchdogSection,
     paths: BroadcasterPaths,
     executor: Arc<dyn CommandExecutor>,
-    restart_history: Mutex<VecDeque<DateTime<Utc>>>,
+    rules: WatchdogRules,
     scripts_dir: Option<PathBuf>,
     rtmp_url: String,
     selfcheck_reports_dir: PathBuf,
+    telemetry: Option<EncoderTelemetry>,
+    incidents: Option<WatchdogIncidents>,
+    output: Option<OutputMonitor>,
 }
 
 impl fmt::Debug for Watchdog {
@@ -69,6 +499,7 @@ impl fmt::Debug for Watchdog {
 }
 
 impl Watchdog {
+    /// Fails when `[[watchdog.rule]]` does not describe a valid set of rules.
     pub fn new(
         queue: PlayoutQueueStore,
         config: WatchdogSection,
@@ -77,67 +508,115 @@ impl Watchdog {
         executor: Option<Arc<dyn CommandExecutor>>,
         rtmp_url: String,
         selfcheck_reports_dir: Option<PathBuf>,
-    ) -> Self {
+    ) -> Result<Self, WatchdogError> {
         let executor = executor.unwrap_or_else(|| Arc::new(SystemCommandExecutor));
-        Self {
+        let rules = WatchdogRules::from_config(&config)?;
+        let output = config.output.enabled.then(|| {
+            OutputMonitor::new(
+                config.output.clone(),
+                paths.ffmpeg.clone(),
+                &rtmp_url,
+                executor.clone(),
+            )
+        });
+        Ok(Self {
             queue,
             config,
             paths,
             executor,
-            restart_history: Mutex::new(VecDeque::new()),
+            rules,
             scripts_dir,
             rtmp_url,
             selfcheck_reports_dir: selfcheck_reports_dir
                 .unwrap_or_else(|| PathBuf::from("/vvtv/system/reports")),
-        }
+            telemetry: None,
+            incidents: None,
+            output,
+        })
     }
 
-    pub async fn evaluate(&self) -> Result<WatchdogReport, WatchdogError> {
-        let metrics = self.queue.metrics()?;
-        let mut actions = Vec::new();
-        let mut observations = Vec::new();
+    /// Checks the broadcaster's encoder samples on each evaluation.
+    pub fn with_encoder_telemetry(mut self, telemetry: EncoderTelemetry) -> Self {
+        self.telemetry = Some(telemetry);
+        self
+    }
 
-        if metrics.buffer_duration_hours < 1.0 {
-            observations.push(format!(
-                "buffer abaixo de 1h ({:.2}h)",
-                metrics.buffer_duration_hours
-            ));
-            actions.push(WatchdogAction::InjectEmergencyLoop);
-            actions.push(WatchdogAction::PauseDownloads);
-        } else if metrics.buffer_duration_hours < 2.0 {
-            observations.push(format!(
-                "buffer crítico ({:.2}h)",
-                metrics.buffer_duration_hours
-            ));
-            actions.push(WatchdogAction::InjectEmergencyLoop);
-        }
+    /// Opens, updates and resolves incidents from each evaluation.
+    pub fn with_incidents(mut self, incidents: WatchdogIncidents) -> Self {
+        self.incidents = Some(incidents);
+        self
+    }
 
-        if !self.check_stream_health().await? {
-            observations.push("ffprobe detectou stream inativo".into());
-            actions.push(WatchdogAction::RestartEncoder);
-            if self.config.restart_on_freeze {
-                actions.push(WatchdogAction::RestartNginx);
-            }
-            let mut history = self.restart_history.lock().unwrap();
-            let now = Utc::now();
-            history.push_back(now);
-            let window = Duration::minutes(5);
-            while history
-                .front()
-                .map(|timestamp| *timestamp < now - window)
-                .unwrap_or(false)
-            {
-                history.pop_front();
-            }
-            if history.len() as u32 > self.config.restart_max_attempts {
-                actions.push(WatchdogAction::Escalate(
-                    "limite de restart excedido em 5 minutos".into(),
-                ));
-            }
-        }
+    pub fn incidents(&self) -> Option<&WatchdogIncidents> {
+        self.incidents.as_ref()
+    }
+
+    /// Records the output monitor's samples in `store`.
+    pub fn with_metrics_store(mut self, store: MetricsStore) -> Self {
+        self.output = self.output.map(|output| output.with_metrics_store(store));
+        self
+    }
+
+    /// Takes a frame sample of the output with `collector` on each
+    /// evaluation, alongside the output monitor's capture.
+    pub fn with_live_quality(mut self, collector: LiveQualityCollector) -> Self {
+        self.output = self
+            .output
+            .map(|output| output.with_live_quality(collector));
+        self
+    }
+
+    /// Output monitor from `[watchdog.output]`, unless it is disabled.
+    pub fn output_monitor(&self) -> Option<&OutputMonitor> {
+        self.output.as_ref()
+    }
+
+    /// Rules the watchdog evaluates, from `[[watchdog.rule]]`.
+    pub fn rules(&self) -> &[WatchdogRule] {
+        self.rules.rules()
+    }
+
+    pub async fn evaluate(&self) -> Result<WatchdogReport, WatchdogError> {
+        self.evaluate_at(Utc::now()).await
+    }
+
+    pub async fn evaluate_at(&self, now: DateTime<Utc>) -> Result<WatchdogReport, WatchdogError> {
+        let metrics = self.queue.metrics()?;
+        let stream_inactive = !self.check_stream_health().await?;
+        let health = self.telemetry.as_ref().and_then(|telemetry| {
+            telemetry.health(Duration::seconds(self.config.encoder_window_seconds as i64))
+        });
+        let output = match (&self.output, stream_inactive) {
+            (Some(monitor), false) => monitor
+                .sample(now)
+                .await
+                .map_err(|error| warn!(%error, "output capture failed"))
+                .ok(),
+            _ => None,
+        };
+        let inputs = WatchdogInputs {
+            buffer_hours: metrics.buffer_duration_hours,
+            stream_inactive,
+            failures_last_hour: metrics.failures_last_hour,
+            encoder_speed: health.as_ref().and_then(|health| health.mean_speed),
+            black_for_seconds: output.as_ref().map(|sample| sample.black_for_s),
+            frozen_for_seconds: output.as_ref().map(|sample| sample.frozen_for_s),
+            silent_for_seconds: output.as_ref().map(|sample| sample.silent_for_s),
+        };
+        let (mut actions, firings) = self.rules.evaluate(&inputs, now);
+        let mut observations: Vec<String> = firings
+            .iter()
+            .map(|firing| match firing.state {
+                FiringState::Acted => firing.observation.clone(),
+                FiringState::CoolingDown => format!("{} (em cooldown)", firing.observation),
+                FiringState::BudgetExhausted => {
+                    format!("{} (orçamento esgotado)", firing.observation)
+                }
+            })
+            .collect();
 
         if let Some(report) = self.latest_selfcheck_report()? {
-            let report_age = Utc::now() - report.timestamp();
+            let report_age = now - report.timestamp();
             if report.checks_failed > 0 && report_age < Duration::hours(24) {
                 observations.push(format!(
                     "selfcheck encontrou {} falhas: {}",
@@ -150,11 +629,21 @@ impl Watchdog {
             }
         }
 
-        Ok(WatchdogReport {
+        let mut report = WatchdogReport {
             metrics,
             actions,
             observations,
-        })
+            firings,
+            incident: None,
+            output,
+        };
+        if let Some(incidents) = &self.incidents {
+            report.incident = incidents.observe(&report, now).unwrap_or_else(|error| {
+                warn!(%error, "failed to record watchdog incident");
+                None
+            });
+        }
+        Ok(report)
     }
 
     async fn check_stream_health(&self) -> Result<bool, WatchdogError> {
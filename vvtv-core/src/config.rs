@@ -1,11 +1,14 @@
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
 use serde::de::DeserializeOwned;
 use serde::Deserialize;
 
 use crate::{
+    broadcaster::{slate::EmergencySlate, transitions::TransitionLibrary, watchdog::WatchdogRules},
     error::{ConfigError, Result},
-    incident::IncidentCommunicationsConfig,
+    incident::{IncidentCommunicationsConfig, IncidentSeverity},
+    queue::{selection, DaypartSchedule, ResumePolicy},
 };
 
 #[derive(Debug, Clone, Deserialize)]
@@ -387,6 +390,36 @@ pub struct HlsSection {
     pub vod_only: bool,
     pub verify_sequence: bool,
     pub rewrite_playlist: bool,
+    /// Caps on the variant picked from a master playlist. A variant that
+    /// does not declare an attribute is not held to its cap.
+    #[serde(default = "HlsSection::default_max_height")]
+    pub max_height: u32,
+    /// 0 disables the cap.
+    #[serde(default)]
+    pub max_bandwidth_kbps: u32,
+    #[serde(default = "HlsSection::default_max_frame_rate")]
+    pub max_frame_rate: f64,
+    /// Prefixes of the video codecs accepted from a variant's CODECS.
+    #[serde(default = "HlsSection::default_video_codecs")]
+    pub video_codecs: Vec<String>,
+    /// Language preferred among a variant's alternate audio renditions;
+    /// the group's default rendition otherwise.
+    #[serde(default)]
+    pub audio_language: Option<String>,
+}
+
+impl HlsSection {
+    fn default_max_height() -> u32 {
+        1080
+    }
+
+    fn default_max_frame_rate() -> f64 {
+        60.0
+    }
+
+    fn default_video_codecs() -> Vec<String> {
+        vec!["avc1".to_string(), "avc3".to_string()]
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -474,6 +507,36 @@ pub struct BroadcasterConfig {
     pub failover: FailoverSection,
     pub watchdog: WatchdogSection,
     pub ffmpeg: FfmpegSection,
+    #[serde(default)]
+    pub transitions: TransitionsSection,
+    #[serde(default)]
+    pub preflight: PreflightSection,
+}
+
+impl BroadcasterConfig {
+    /// Checks the sections that would otherwise only fail once the
+    /// broadcaster starts.
+    pub fn validate(&self) -> std::result::Result<(), String> {
+        if !selection::is_known_policy(&self.queue.policy) {
+            return Err(format!("[queue]: unknown policy {}", self.queue.policy));
+        }
+        if let Some(dayparts) = &self.queue.dayparts {
+            DaypartSchedule::from_config(dayparts)
+                .map_err(|error| format!("[queue.dayparts]: {error}"))?;
+        }
+        self.queue
+            .break_in
+            .default_policy
+            .parse::<ResumePolicy>()
+            .map_err(|error| format!("[queue.break_in]: {error}"))?;
+        EmergencySlate::from_config(&self.failover.slate, Path::new(""))
+            .map_err(|error| format!("[failover.slate]: {error}"))?;
+        TransitionLibrary::from_config(&self.transitions)
+            .map_err(|error| format!("[transitions]: {error}"))?;
+        WatchdogRules::from_config(&self.watchdog)
+            .map_err(|error| format!("[watchdog]: {error}"))?;
+        Ok(())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -481,38 +544,611 @@ pub struct QueueSection {
     pub policy: String,
     pub music_ratio: f32,
     pub curation_bump_threshold: f32,
+    #[serde(default = "QueueSection::default_anchor_tolerance_seconds")]
+    pub anchor_tolerance_seconds: u32,
+    /// How early a soft anchor may air when nothing else is eligible.
+    #[serde(default = "QueueSection::default_soft_anchor_tolerance_seconds")]
+    pub soft_anchor_tolerance_seconds: u32,
+    /// Relative weights per `content_kind` for `weighted_round_robin`.
+    #[serde(default)]
+    pub weights: HashMap<String, f64>,
+    #[serde(default)]
+    pub dayparts: Option<DaypartSection>,
+    #[serde(default)]
+    pub separation: Option<SeparationSection>,
+    #[serde(default)]
+    pub retry: PlayoutRetrySection,
+    #[serde(default)]
+    pub break_in: BreakInSection,
+}
+
+/// Requeue policy for entries whose playout failed.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PlayoutRetrySection {
+    #[serde(default = "PlayoutRetrySection::default_max_attempts")]
+    pub max_attempts: u32,
+    #[serde(default = "PlayoutRetrySection::default_backoff_seconds")]
+    pub backoff_seconds: u64,
+    #[serde(default = "PlayoutRetrySection::default_max_backoff_seconds")]
+    pub max_backoff_seconds: u64,
+}
+
+impl PlayoutRetrySection {
+    fn default_max_attempts() -> u32 {
+        3
+    }
+
+    fn default_backoff_seconds() -> u64 {
+        30
+    }
+
+    fn default_max_backoff_seconds() -> u64 {
+        900
+    }
+}
+
+impl Default for PlayoutRetrySection {
+    fn default() -> Self {
+        Self {
+            max_attempts: Self::default_max_attempts(),
+            backoff_seconds: Self::default_backoff_seconds(),
+            max_backoff_seconds: Self::default_max_backoff_seconds(),
+        }
+    }
+}
+
+/// How the broadcaster takes break-ins to air.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BreakInSection {
+    /// `resume` or `requeue`, used when a request names no policy.
+    #[serde(default = "BreakInSection::default_policy")]
+    pub default_policy: String,
+    /// How often the entry on air checks for pending break-ins.
+    #[serde(default = "BreakInSection::default_poll_ms")]
+    pub poll_ms: u64,
+    /// Time the feeder gets to stop cleanly before it is killed.
+    #[serde(default = "BreakInSection::default_stop_grace_ms")]
+    pub stop_grace_ms: u64,
+}
+
+impl BreakInSection {
+    fn default_policy() -> String {
+        "resume".to_string()
+    }
+
+    fn default_poll_ms() -> u64 {
+        500
+    }
+
+    fn default_stop_grace_ms() -> u64 {
+        3_000
+    }
+}
+
+impl Default for BreakInSection {
+    fn default() -> Self {
+        Self {
+            default_policy: Self::default_policy(),
+            poll_ms: Self::default_poll_ms(),
+            stop_grace_ms: Self::default_stop_grace_ms(),
+        }
+    }
+}
+
+/// Minimum minutes between airings sharing a tag, source domain or plan.
+/// Zero disables a rule.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SeparationSection {
+    #[serde(default)]
+    pub tag_minutes: u32,
+    #[serde(default)]
+    pub domain_minutes: u32,
+    #[serde(default)]
+    pub plan_minutes: u32,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DaypartSection {
+    /// IANA timezone name used to interpret the hour ranges.
+    pub timezone: String,
+    #[serde(default, rename = "slot")]
+    pub slots: Vec<DaypartSlotSection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct DaypartSlotSection {
+    pub name: String,
+    pub start_hour: u32,
+    pub end_hour: u32,
+    /// Target ratios per `content_kind`; normalised when loaded.
+    #[serde(default)]
+    pub ratios: HashMap<String, f64>,
+}
+
+impl QueueSection {
+    fn default_anchor_tolerance_seconds() -> u32 {
+        120
+    }
+
+    fn default_soft_anchor_tolerance_seconds() -> u32 {
+        300
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct RtmpSection {
+    /// Publish to `origin`; nodes serving only native HLS can turn it off.
+    #[serde(default = "default_true")]
+    pub enabled: bool,
     pub origin: String,
     pub chunk_size: u32,
     pub reconnect_attempts: u32,
     pub reconnect_delay_ms: u32,
+    /// Keep one publish connection open across entries instead of running
+    /// an ffmpeg per entry.
+    #[serde(default = "default_true")]
+    pub persistent_session: bool,
+    /// Simulcast outputs published alongside `origin`.
+    #[serde(default, rename = "destination")]
+    pub destinations: Vec<SimulcastDestinationSection>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimulcastDestinationSection {
+    pub name: String,
+    /// rtmp://, rtmps://, srt:// URL or a local `.m3u8` path.
+    pub url: String,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// flv | mpegts | hls; inferred from `url` when omitted.
+    #[serde(default)]
+    pub format: Option<String>,
+    /// Defaults to the `[rtmp]` value.
+    #[serde(default)]
+    pub reconnect_attempts: Option<u32>,
+    /// Defaults to the `[rtmp]` value.
+    #[serde(default)]
+    pub reconnect_delay_ms: Option<u32>,
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct BroadcasterHlsSection {
+    /// Have the broadcaster write the live playlist itself instead of
+    /// leaving segmentation to the RTMP origin.
+    #[serde(default)]
+    pub native: bool,
     pub output_path: String,
+    #[serde(default = "BroadcasterHlsSection::default_playlist_name")]
+    pub playlist_name: String,
     pub segment_duration: u32,
+    /// Rolling window kept in the playlist, e.g. `"48m"`, `"2h"` or `"90s"`.
     pub playlist_length: String,
+    /// fmp4 | ts
     pub segment_type: String,
     pub flags: Vec<String>,
 }
 
+impl BroadcasterHlsSection {
+    fn default_playlist_name() -> String {
+        "index.m3u8".to_string()
+    }
+}
+
+/// Checks an entry's asset passes before it goes live.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PreflightSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Hash the asset against the processor's `checksums.json`.
+    #[serde(default = "default_true")]
+    pub verify_checksum: bool,
+    /// Require ffprobe to read a duration from the asset.
+    #[serde(default = "default_true")]
+    pub probe: bool,
+    /// How far the probed duration may fall short of `manifest.json`.
+    #[serde(default = "PreflightSection::default_duration_tolerance_seconds")]
+    pub duration_tolerance_seconds: f64,
+}
+
+impl PreflightSection {
+    fn default_duration_tolerance_seconds() -> f64 {
+        2.0
+    }
+}
+
+impl Default for PreflightSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            verify_checksum: true,
+            probe: true,
+            duration_tolerance_seconds: Self::default_duration_tolerance_seconds(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 pub struct FailoverSection {
     pub enabled: bool,
     pub standby_encoder: bool,
     pub detection_timeout_seconds: u32,
     pub emergency_loop_hours: u32,
+    /// Card generated when the archive has no emergency assets.
+    #[serde(default)]
+    pub slate: SlateSection,
+    #[serde(default)]
+    pub leadership: LeadershipSection,
+}
+
+/// Lease deciding which of a primary and a standby broadcaster airs.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LeadershipSection {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "LeadershipSection::default_node_id")]
+    pub node_id: String,
+    /// primary | standby
+    #[serde(default = "LeadershipSection::default_role")]
+    pub role: String,
+    /// Lease store shared by both nodes.
+    #[serde(default = "LeadershipSection::default_store_path")]
+    pub store_path: PathBuf,
+    /// How long a lease lasts without being renewed.
+    #[serde(default = "LeadershipSection::default_lease_seconds")]
+    pub lease_seconds: u64,
+    #[serde(default = "LeadershipSection::default_heartbeat_seconds")]
+    pub heartbeat_seconds: u64,
+    /// Extra wait past the lease's expiry before a standby takes over.
+    #[serde(default = "LeadershipSection::default_standby_grace_seconds")]
+    pub standby_grace_seconds: u64,
+    /// Run when this node takes the lease.
+    #[serde(default)]
+    pub promote_script: Option<PathBuf>,
+    /// Run when this node loses the lease; stops anything still publishing.
+    #[serde(default)]
+    pub fence_script: Option<PathBuf>,
+}
+
+impl LeadershipSection {
+    fn default_node_id() -> String {
+        "vvtv-primary".to_string()
+    }
+
+    fn default_role() -> String {
+        "primary".to_string()
+    }
+
+    fn default_store_path() -> PathBuf {
+        PathBuf::from("/vvtv/system/leadership.sqlite")
+    }
+
+    fn default_lease_seconds() -> u64 {
+        15
+    }
+
+    fn default_heartbeat_seconds() -> u64 {
+        5
+    }
+
+    fn default_standby_grace_seconds() -> u64 {
+        5
+    }
+}
+
+impl Default for LeadershipSection {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            node_id: Self::default_node_id(),
+            role: Self::default_role(),
+            store_path: Self::default_store_path(),
+            lease_seconds: Self::default_lease_seconds(),
+            heartbeat_seconds: Self::default_heartbeat_seconds(),
+            standby_grace_seconds: Self::default_standby_grace_seconds(),
+            promote_script: None,
+            fence_script: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SlateSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// card | bars
+    #[serde(default = "SlateSection::default_style")]
+    pub style: String,
+    #[serde(default = "SlateSection::default_text")]
+    pub text: String,
+    /// Length of one pass; the slate is queued again while nothing else is.
+    #[serde(default = "SlateSection::default_duration_seconds")]
+    pub duration_seconds: u32,
+    /// Tone under the slate, in Hz. Defaults to 1 kHz over bars and
+    /// silence over a card; 0 forces silence.
+    #[serde(default)]
+    pub tone_hz: Option<u32>,
+    #[serde(default = "SlateSection::default_background")]
+    pub background: String,
+    #[serde(default = "SlateSection::default_resolution")]
+    pub resolution: String,
+    #[serde(default = "SlateSection::default_fps")]
+    pub fps: u32,
+    /// Font for the text; ffmpeg's default font when omitted.
+    #[serde(default)]
+    pub font: Option<String>,
+}
+
+impl SlateSection {
+    fn default_style() -> String {
+        "card".to_string()
+    }
+
+    fn default_text() -> String {
+        "We'll be right back".to_string()
+    }
+
+    fn default_duration_seconds() -> u32 {
+        300
+    }
+
+    fn default_background() -> String {
+        "black".to_string()
+    }
+
+    fn default_resolution() -> String {
+        "1280x720".to_string()
+    }
+
+    fn default_fps() -> u32 {
+        30
+    }
+}
+
+impl Default for SlateSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            style: Self::default_style(),
+            text: Self::default_text(),
+            duration_seconds: Self::default_duration_seconds(),
+            tone_hz: None,
+            background: Self::default_background(),
+            resolution: Self::default_resolution(),
+            fps: Self::default_fps(),
+            font: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
 pub struct WatchdogSection {
     pub interval_seconds: u32,
+    /// Allows `restart_nginx` on rules conditioned on `stream_inactive`.
     pub restart_on_freeze: bool,
+    /// Default budget of rules that restart the encoder or nginx.
     pub restart_max_attempts: u32,
+    /// Mean encoder speed below which the built-in rules report the encoder
+    /// as lagging.
+    #[serde(default = "WatchdogSection::default_encoder_min_speed")]
+    pub encoder_min_speed: f64,
+    /// Encoder samples considered on each evaluation.
+    #[serde(default = "WatchdogSection::default_encoder_window_seconds")]
+    pub encoder_window_seconds: u32,
+    /// Conditions mapped to actions, checked in order. Without any, the
+    /// built-in rules for a short buffer and an inactive stream apply.
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<WatchdogRuleSection>,
+    #[serde(default)]
+    pub incidents: WatchdogIncidentSection,
+    #[serde(default)]
+    pub output: OutputMonitorSection,
+}
+
+impl WatchdogSection {
+    fn default_encoder_min_speed() -> f64 {
+        0.95
+    }
+
+    fn default_encoder_window_seconds() -> u32 {
+        120
+    }
+}
+
+/// A watchdog rule fires when every condition it sets holds.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchdogRuleSection {
+    pub name: String,
+    #[serde(default)]
+    pub buffer_hours_below: Option<f64>,
+    #[serde(default)]
+    pub stream_inactive: Option<bool>,
+    #[serde(default)]
+    pub failures_last_hour_at_least: Option<i64>,
+    /// Compared with the encoder's mean speed over `encoder_window_seconds`.
+    #[serde(default)]
+    pub encoder_speed_below: Option<f64>,
+    /// How long the live output has been black, frozen or silent, across
+    /// consecutive captures of `[watchdog.output]`.
+    #[serde(default)]
+    pub black_for_seconds: Option<f64>,
+    #[serde(default)]
+    pub frozen_for_seconds: Option<f64>,
+    #[serde(default)]
+    pub silent_for_seconds: Option<f64>,
+    /// restart_encoder | restart_nginx | inject_emergency_loop |
+    /// pause_downloads | escalate, applied in order.
+    pub actions: Vec<String>,
+    /// Minimum time between two firings.
+    #[serde(default)]
+    pub cooldown_seconds: u64,
+    /// Firings allowed within `budget_window_seconds`; once spent the rule
+    /// escalates instead of acting. Rules restarting something default to
+    /// `restart_max_attempts`, others are unlimited.
+    #[serde(default)]
+    pub budget: Option<u32>,
+    #[serde(default = "WatchdogRuleSection::default_budget_window_seconds")]
+    pub budget_window_seconds: u64,
+    /// Severity of the incident the rule opens. Derived from its conditions
+    /// when unset: an inactive stream or a black or frozen picture is
+    /// critical, silence, a buffer under an hour or repeated failures high,
+    /// anything else medium.
+    #[serde(default)]
+    pub severity: Option<IncidentSeverity>,
+}
+
+impl WatchdogRuleSection {
+    fn default_budget_window_seconds() -> u64 {
+        300
+    }
+}
+
+/// Short captures of the live output run through ffmpeg's blackdetect,
+/// freezedetect and silencedetect filters on every watchdog evaluation.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputMonitorSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Output captured; the watchdog's origin URL when unset.
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default = "OutputMonitorSection::default_capture_seconds")]
+    pub capture_seconds: f64,
+    /// Shortest black, frozen or silent stretch reported by the filters.
+    #[serde(default = "OutputMonitorSection::default_min_detection_seconds")]
+    pub min_detection_seconds: f64,
+    /// blackdetect `pix_th`: luma below which a pixel counts as black.
+    #[serde(default = "OutputMonitorSection::default_black_pixel_threshold")]
+    pub black_pixel_threshold: f64,
+    /// freezedetect noise tolerance, in dB.
+    #[serde(default = "OutputMonitorSection::default_freeze_noise_db")]
+    pub freeze_noise_db: f64,
+    /// silencedetect level under which audio counts as silent, in dB.
+    #[serde(default = "OutputMonitorSection::default_silence_noise_db")]
+    pub silence_noise_db: f64,
+}
+
+impl OutputMonitorSection {
+    fn default_capture_seconds() -> f64 {
+        10.0
+    }
+
+    fn default_min_detection_seconds() -> f64 {
+        2.0
+    }
+
+    fn default_black_pixel_threshold() -> f64 {
+        0.10
+    }
+
+    fn default_freeze_noise_db() -> f64 {
+        -60.0
+    }
+
+    fn default_silence_noise_db() -> f64 {
+        -50.0
+    }
+}
+
+impl Default for OutputMonitorSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            url: None,
+            capture_seconds: Self::default_capture_seconds(),
+            min_detection_seconds: Self::default_min_detection_seconds(),
+            black_pixel_threshold: Self::default_black_pixel_threshold(),
+            freeze_noise_db: Self::default_freeze_noise_db(),
+            silence_noise_db: Self::default_silence_noise_db(),
+        }
+    }
+}
+
+/// Incidents opened from the watchdog's escalations.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WatchdogIncidentSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Consecutive evaluations restarting something before an incident is
+    /// opened without an escalation.
+    #[serde(default = "WatchdogIncidentSection::default_restarts_before_incident")]
+    pub restarts_before_incident: u32,
+    /// Time without the open incident's rules firing after which it resolves.
+    #[serde(default = "WatchdogIncidentSection::default_resolve_after_seconds")]
+    pub resolve_after_seconds: u64,
+    /// Also writes the incident as JSON next to its markdown.
+    #[serde(default = "default_true")]
+    pub include_json: bool,
+}
+
+impl WatchdogIncidentSection {
+    fn default_restarts_before_incident() -> u32 {
+        2
+    }
+
+    fn default_resolve_after_seconds() -> u64 {
+        300
+    }
+}
+
+impl Default for WatchdogIncidentSection {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            restarts_before_incident: Self::default_restarts_before_incident(),
+            resolve_after_seconds: Self::default_resolve_after_seconds(),
+            include_json: true,
+        }
+    }
+}
+
+/// Transition catalog and the rules choosing between its entries.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransitionsSection {
+    /// Catalog entry used when no rule matches.
+    #[serde(default = "TransitionsSection::default_transition")]
+    pub default: String,
+    /// Named transitions, in addition to the built-in `cut` and `fade`.
+    #[serde(default)]
+    pub catalog: HashMap<String, TransitionStyleSection>,
+    /// Checked in order; the first match wins.
+    #[serde(default, rename = "rule")]
+    pub rules: Vec<TransitionRuleSection>,
+}
+
+impl TransitionsSection {
+    fn default_transition() -> String {
+        "fade".to_string()
+    }
+}
+
+impl Default for TransitionsSection {
+    fn default() -> Self {
+        Self {
+            default: Self::default_transition(),
+            catalog: HashMap::new(),
+            rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransitionStyleSection {
+    /// cut | fade | dip_to_black | wipe_left | wipe_right | wipe_up |
+    /// wipe_down | audio_crossfade | j_cut | l_cut
+    pub kind: String,
+    #[serde(default)]
+    pub duration_ms: u32,
+}
+
+/// Picks `transition` between a previous and next `content_kind`; `"*"` or
+/// an omitted side matches any kind.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransitionRuleSection {
+    #[serde(default)]
+    pub from: Option<String>,
+    #[serde(default)]
+    pub to: Option<String>,
+    pub transition: String,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -522,6 +1158,113 @@ pub struct FfmpegSection {
     pub thread_queue_size: u32,
 }
 
+/// On-air graphics, loaded from `overlays.toml` next to `broadcaster.toml`.
+/// A missing section disables that element.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct OverlayConfig {
+    #[serde(default)]
+    pub logo: Option<LogoOverlaySection>,
+    #[serde(default)]
+    pub lower_third: Option<LowerThirdSection>,
+    #[serde(default)]
+    pub up_next: Option<UpNextSection>,
+}
+
+/// Channel logo kept on screen for the whole entry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogoOverlaySection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub image: String,
+    /// top_left | top_right | bottom_left | bottom_right
+    #[serde(default = "LogoOverlaySection::default_position")]
+    pub position: String,
+    #[serde(default = "LogoOverlaySection::default_margin")]
+    pub margin: u32,
+    #[serde(default = "LogoOverlaySection::default_opacity")]
+    pub opacity: f32,
+    /// `content_kind`s that show the element; empty means all.
+    #[serde(default)]
+    pub content_kinds: Vec<String>,
+}
+
+impl LogoOverlaySection {
+    fn default_position() -> String {
+        "top_right".to_string()
+    }
+
+    fn default_margin() -> u32 {
+        24
+    }
+
+    fn default_opacity() -> f32 {
+        0.85
+    }
+}
+
+/// Plan title shown near the start of an entry.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LowerThirdSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub font_file: String,
+    #[serde(default = "default_overlay_font_size")]
+    pub font_size: u32,
+    #[serde(default = "LowerThirdSection::default_show_after_seconds")]
+    pub show_after_seconds: u32,
+    #[serde(default = "default_overlay_duration_seconds")]
+    pub duration_seconds: u32,
+    #[serde(default)]
+    pub content_kinds: Vec<String>,
+}
+
+impl LowerThirdSection {
+    fn default_show_after_seconds() -> u32 {
+        5
+    }
+}
+
+/// Card announcing the forecast next entry near the end of the current one.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UpNextSection {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    pub font_file: String,
+    #[serde(default = "default_overlay_font_size")]
+    pub font_size: u32,
+    #[serde(default = "UpNextSection::default_label")]
+    pub label: String,
+    /// Seconds before the end of the entry at which the card appears.
+    #[serde(default = "UpNextSection::default_lead_seconds")]
+    pub lead_seconds: u32,
+    #[serde(default = "default_overlay_duration_seconds")]
+    pub duration_seconds: u32,
+    #[serde(default)]
+    pub content_kinds: Vec<String>,
+}
+
+impl UpNextSection {
+    fn default_label() -> String {
+        "A seguir".to_string()
+    }
+
+    fn default_lead_seconds() -> u32 {
+        20
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_overlay_font_size() -> u32 {
+    36
+}
+
+fn default_overlay_duration_seconds() -> u32 {
+    8
+}
+
 #[derive(Debug, Clone)]
 pub struct ConfigBundle {
     pub vvtv: VvtvConfig,
@@ -559,6 +1302,16 @@ pub fn load_processor_config<P: AsRef<Path>>(path: P) -> Result<ProcessorConfig>
 }
 
 pub fn load_broadcaster_config<P: AsRef<Path>>(path: P) -> Result<BroadcasterConfig> {
+    let path = path.as_ref();
+    let config: BroadcasterConfig = load_toml(path)?;
+    config.validate().map_err(|reason| ConfigError::Invalid {
+        reason,
+        path: path.to_path_buf(),
+    })?;
+    Ok(config)
+}
+
+pub fn load_overlay_config<P: AsRef<Path>>(path: P) -> Result<OverlayConfig> {
     load_toml(path)
 }
 
@@ -12,6 +12,8 @@ pub enum ConfigError {
         source: toml::de::Error,
         path: PathBuf,
     },
+    #[error("invalid config {path}: {reason}")]
+    Invalid { reason: String, path: PathBuf },
 }
 
 pub type Result<T> = std::result::Result<T, ConfigError>;
@@ -30,10 +30,27 @@ mod sqlite;
 pub mod test_framework;
 
 pub use broadcaster::{
+    collect_emergency_assets, emergency_loop_item, emergency_refill, emergency_slate_item,
+    escalation::{IncidentChange, IncidentUpdate, WatchdogIncidents},
     failover::{FailoverError, FailoverManager},
-    watchdog::{Watchdog, WatchdogAction, WatchdogError, WatchdogReport},
+    hls_destination,
+    leadership::{
+        Handover, LeaderElection, LeadershipError, LeadershipState, Lease, LeaseStore,
+        NodeHeartbeat, NodeRole, SqliteLeaseStore,
+    },
+    output_monitor::{parse_detections, Detection, DetectionKind, OutputMonitor, OutputSample},
+    overlay::{OverlayEntry, OverlayGraph, OverlayLayer},
+    preflight::{AssetExpectations, Preflight, PreflightFailure},
+    session::{DestinationStatus, OutputDestination, OutputFormat, OutputSession, SessionFeed},
+    slate::{EmergencySlate, SlateStyle},
+    telemetry::{EncoderHealth, EncoderSample, EncoderTelemetry, ProgressLine, ProgressParser},
+    transitions::{TransitionKind, TransitionLibrary, TransitionStyle},
+    watchdog::{
+        FiringState, RuleFiring, Watchdog, WatchdogAction, WatchdogError, WatchdogInputs,
+        WatchdogReport, WatchdogRule, WatchdogRules,
+    },
     Broadcaster, BroadcasterError, BroadcasterEvent, BroadcasterPaths, CommandExecutor,
-    SystemCommandExecutor,
+    SystemCommandExecutor, EMERGENCY_LOOP_ORIGIN, EMERGENCY_SLATE_ORIGIN,
 };
 pub use browser::{
     BrowserAutomation, BrowserCapture, BrowserCaptureKind, BrowserError, BrowserEvent,
@@ -58,8 +75,9 @@ pub use compliance::{
     LicenseAuditFindingKind, LicenseAuditReport, LicenseAuditSummary, LicenseAuditor,
 };
 pub use config::{
-    load_broadcaster_config, load_browser_config, load_processor_config, load_vvtv_config,
-    BroadcasterConfig, BrowserConfig, ConfigBundle, ProcessorConfig, VvtvConfig,
+    load_broadcaster_config, load_browser_config, load_overlay_config, load_processor_config,
+    load_vvtv_config, BroadcasterConfig, BrowserConfig, ConfigBundle, OverlayConfig,
+    ProcessorConfig, VvtvConfig,
 };
 pub use curation::{
     CuratorDecision, CuratorEvaluation, CuratorSignal, CuratorVigilante, CuratorVigilanteConfig,
@@ -116,15 +134,22 @@ pub use plan::{
     SqlitePlanStoreBuilder,
 };
 pub use processor::{
-    Processor, ProcessorError, ProcessorReport, ProcessorResult, StagingPaths, MASTER_PLAYLIST_NAME,
+    HlsMasterPlaylist, HlsRendition, HlsVariant, Processor, ProcessorError, ProcessorReport,
+    ProcessorResult, StagingPaths, VariantCaps, MASTER_PLAYLIST_NAME,
 };
 pub use quality::{
     QualityAction, QualityActionKind, QualityAnalyzer, QualityReport, QualityResult,
     QualityThresholds, SignatureProfile,
 };
 pub use queue::{
-    PlayoutQueueStore, PlayoutQueueStoreBuilder, QueueEntry, QueueError, QueueFilter, QueueItem,
-    QueueMetrics, QueueResult, QueueSelectionPolicy, QueueStatus, QueueSummary,
+    write_as_run, ActiveDaypart, AnchorKind, AsRunEntry, AsRunFormat, AsRunOutcome, AsRunRecord,
+    BreakIn, Daypart, DaypartSchedule, EmergencyRefill, FifoWithBump, Forecast, ForecastOptions,
+    ForecastSlot, ForecastSource, Interruption, PlayoutQueueStore, PlayoutQueueStoreBuilder,
+    QueueEntry, QueueError, QueueFilter, QueueItem, QueueMetrics, QueueResult, QueueSchedule,
+    QueueSelectionPolicy, QueueStatus, QueueSummary, RecentAiring, RecoveryAction,
+    RecoveryDecision, RestoreConflict, RestoreConflictKind, RestoreMode, RestoreOptions,
+    RestoreReport, ResumePolicy, RetryOutcome, RetryPolicy, ScheduledInjection, SelectionContext,
+    SelectionStrategy, SeparationRules, StrictPriority, WeightedRoundRobin,
 };
 pub use test_framework::{
     BenchmarkComparison, BenchmarkResult, CanaryTestResult, DailyStabilityMetric, DriftTestResult,
@@ -10,7 +10,7 @@ use thiserror::Error;
 use uuid::Uuid;
 
 use crate::monetization::economy::{EconomyError, EconomyEventType, EconomyStore, NewEconomyEvent};
-use crate::queue::{PlayoutQueueStore, QueueItem};
+use crate::queue::{PlayoutQueueStore, QueueItem, ScheduledInjection};
 
 #[derive(Debug, Error)]
 pub enum SpotsError {
@@ -69,6 +69,37 @@ impl MicroSpotContract {
             total_injections: row.get::<_, Option<i64>>("total_injections")?.unwrap_or(0),
         })
     }
+
+    /// Injection cadence used by `PlayoutQueueStore::forecast`. The random
+    /// cadence is approximated by the midpoint of its range.
+    pub fn forecast_injection(&self) -> Option<ScheduledInjection> {
+        if !self.active {
+            return None;
+        }
+        let min = self.cadence_min_minutes.max(1);
+        let max = self.cadence_max_minutes.max(min);
+        Some(ScheduledInjection {
+            item: self.queue_item(format!("microspot:{}", self.id)),
+            next_at: self.next_available_at,
+            every: Duration::minutes((min + max) / 2),
+            until: self.expires_at,
+        })
+    }
+
+    fn queue_item(&self, plan_id: String) -> QueueItem {
+        QueueItem {
+            plan_id,
+            asset_path: self.asset_path.clone(),
+            duration_s: Some(self.duration_s),
+            curation_score: Some(0.95),
+            priority: 50,
+            node_origin: Some("microspot".to_string()),
+            content_kind: Some("microspot".to_string()),
+            schedule: None,
+            tags: Vec::new(),
+            source_domain: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -192,15 +223,11 @@ impl MicroSpotManager {
         let mut injections = Vec::new();
         while let Some(row) = rows.next()? {
             let contract = MicroSpotContract::from_row(row)?;
-            let queue_id = queue.enqueue(&QueueItem {
-                plan_id: format!("microspot:{}:{}", contract.id, now.timestamp()),
-                asset_path: contract.asset_path.clone(),
-                duration_s: Some(contract.duration_s),
-                curation_score: Some(0.95),
-                priority: 50,
-                node_origin: Some("microspot".to_string()),
-                content_kind: Some("microspot".to_string()),
-            })?;
+            let queue_id = queue.enqueue(&contract.queue_item(format!(
+                "microspot:{}:{}",
+                contract.id,
+                now.timestamp()
+            )))?;
             let next_interval =
                 random_cadence(contract.cadence_min_minutes, contract.cadence_max_minutes);
             let next_available = now + Duration::minutes(next_interval as i64);
@@ -12,6 +12,7 @@ use tokio::process::Command;
 use tokio::time::timeout;
 
 use crate::{
+    broadcaster::{output_monitor::OutputSample, telemetry::EncoderSample},
     distribution::{
         cdn::{BackupSyncReport, CdnMetrics},
         edge::EdgeLatencyRecord,
@@ -653,6 +654,124 @@ impl MetricsStore {
         Ok(())
     }
 
+    /// Records `samples` in one transaction.
+    pub fn record_encoder_samples(&self, samples: &[EncoderSample]) -> Result<(), MonitorError> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        for sample in samples {
+            tx.execute(
+                "INSERT INTO encoder_samples (ts, queue_id, frame, fps, bitrate_kbps, speed, drop_frames, dup_frames, out_time_s)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    sample.at.to_rfc3339(),
+                    sample.queue_id,
+                    sample.frame as i64,
+                    sample.fps,
+                    sample.bitrate_kbps,
+                    sample.speed,
+                    sample.drop_frames as i64,
+                    sample.dup_frames as i64,
+                    sample.out_time_s,
+                ],
+            )?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Latest encoder samples, oldest first.
+    pub fn encoder_samples(&self, limit: usize) -> Result<Vec<EncoderSample>, MonitorError> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, queue_id, frame, fps, bitrate_kbps, speed, drop_frames, dup_frames, out_time_s
+             FROM encoder_samples ORDER BY ts DESC LIMIT ?1",
+        )?;
+        let mut rows = stmt.query([limit as i64])?;
+        let mut samples = Vec::new();
+        while let Some(row) = rows.next()? {
+            let ts: String = row.get(0)?;
+            let at = DateTime::parse_from_rfc3339(&ts)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(0, "ts".to_string(), rusqlite::types::Type::Text)
+                })?
+                .with_timezone(&Utc);
+            samples.push(EncoderSample {
+                at,
+                queue_id: row.get(1)?,
+                frame: row.get::<_, i64>(2)? as u64,
+                fps: row.get(3)?,
+                bitrate_kbps: row.get(4)?,
+                speed: row.get(5)?,
+                drop_frames: row.get::<_, i64>(6)? as u64,
+                dup_frames: row.get::<_, i64>(7)? as u64,
+                out_time_s: row.get(8)?,
+                finished: false,
+            });
+        }
+        samples.reverse();
+        Ok(samples)
+    }
+
+    pub fn record_output_sample(&self, sample: &OutputSample) -> Result<(), MonitorError> {
+        let conn = self.open()?;
+        conn.execute(
+            "INSERT INTO output_samples (ts, capture_s, black_s, frozen_s, silent_s, black_for_s, frozen_for_s, silent_for_s, freeze_events, vmaf_estimate, stream_bitrate_mbps, audio_peak_db, signature_deviation)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                sample.at.to_rfc3339(),
+                sample.capture_s,
+                sample.black_s,
+                sample.frozen_s,
+                sample.silent_s,
+                sample.black_for_s,
+                sample.frozen_for_s,
+                sample.silent_for_s,
+                sample.freeze_events as i64,
+                sample.vmaf_estimate,
+                sample.stream_bitrate_mbps,
+                sample.audio_peak_db,
+                sample.signature_deviation,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Latest samples of the live output, oldest first.
+    pub fn output_samples(&self, limit: usize) -> Result<Vec<OutputSample>, MonitorError> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT ts, capture_s, black_s, frozen_s, silent_s, black_for_s, frozen_for_s, silent_for_s, freeze_events, vmaf_estimate, stream_bitrate_mbps, audio_peak_db, signature_deviation
+             FROM output_samples ORDER BY ts DESC LIMIT ?1",
+        )?;
+        let mut rows = stmt.query([limit as i64])?;
+        let mut samples = Vec::new();
+        while let Some(row) = rows.next()? {
+            let ts: String = row.get(0)?;
+            let at = DateTime::parse_from_rfc3339(&ts)
+                .map_err(|_| {
+                    rusqlite::Error::InvalidColumnType(0, "ts".to_string(), rusqlite::types::Type::Text)
+                })?
+                .with_timezone(&Utc);
+            samples.push(OutputSample {
+                at,
+                capture_s: row.get(1)?,
+                black_s: row.get(2)?,
+                frozen_s: row.get(3)?,
+                silent_s: row.get(4)?,
+                black_for_s: row.get(5)?,
+                frozen_for_s: row.get(6)?,
+                silent_for_s: row.get(7)?,
+                freeze_events: row.get::<_, i64>(8)? as u32,
+                vmaf_estimate: row.get(9)?,
+                stream_bitrate_mbps: row.get(10)?,
+                audio_peak_db: row.get(11)?,
+                signature_deviation: row.get(12)?,
+            });
+        }
+        samples.reverse();
+        Ok(samples)
+    }
+
     pub fn record_cdn_token(&self, token: &SegmentToken) -> Result<(), MonitorError> {
         let conn = self.open()?;
         conn.execute(
@@ -787,6 +906,25 @@ impl MetricRecord {
         self.black_frame_ratio = sample.black_ratio;
         self.signature_deviation = sample.signature_deviation;
     }
+
+    /// Freeze and black figures measured by the output monitor's filters,
+    /// plus the frame sample taken alongside them.
+    pub fn apply_output_sample(&mut self, sample: &OutputSample) {
+        self.freeze_events = sample.freeze_events as i64;
+        self.black_frame_ratio = sample.black_ratio();
+        if let Some(vmaf) = sample.vmaf_estimate {
+            self.vmaf_live = vmaf;
+        }
+        if let Some(bitrate) = sample.stream_bitrate_mbps {
+            self.stream_bitrate_mbps = bitrate;
+        }
+        if let Some(peak) = sample.audio_peak_db {
+            self.audio_peak_db = peak;
+        }
+        if let Some(deviation) = sample.signature_deviation {
+            self.signature_deviation = deviation;
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
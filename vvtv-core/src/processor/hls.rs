@@ -0,0 +1,287 @@
+//! HLS master playlists.
+//!
+//! A capture may point at a master playlist rather than a media playlist.
+//! Its `#EXT-X-STREAM-INF` variants and `#EXT-X-MEDIA` audio renditions are
+//! parsed here, and the processor downloads the best variant within the
+//! `[hls]` caps together with the audio rendition of its group, when the
+//! audio is not muxed into the variant.
+
+use crate::config::HlsSection;
+
+/// One `#EXT-X-STREAM-INF` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HlsVariant {
+    pub uri: String,
+    pub bandwidth: u64,
+    pub average_bandwidth: Option<u64>,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Vec<String>,
+    pub frame_rate: Option<f64>,
+    /// GROUP-ID of the `#EXT-X-MEDIA` audio renditions the variant plays
+    /// with.
+    pub audio_group: Option<String>,
+}
+
+impl HlsVariant {
+    pub fn height(&self) -> Option<u32> {
+        self.resolution.map(|(_, height)| height)
+    }
+
+    /// Declares its codecs and none of them is video.
+    pub fn is_audio_only(&self) -> bool {
+        !self.codecs.is_empty() && self.video_codecs().next().is_none()
+    }
+
+    fn video_codecs(&self) -> impl Iterator<Item = &str> {
+        self.codecs
+            .iter()
+            .map(String::as_str)
+            .filter(|codec| !is_audio_codec(codec))
+    }
+}
+
+/// One `#EXT-X-MEDIA` entry of TYPE=AUDIO.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HlsRendition {
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    /// Absent when the audio is muxed into the variants.
+    pub uri: Option<String>,
+    pub default: bool,
+    pub autoselect: bool,
+    pub channels: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HlsMasterPlaylist {
+    pub variants: Vec<HlsVariant>,
+    pub audio: Vec<HlsRendition>,
+}
+
+impl HlsMasterPlaylist {
+    /// Whether `contents` is a master playlist rather than a media one.
+    pub fn is_master(contents: &str) -> bool {
+        contents
+            .lines()
+            .any(|line| line.trim_start().starts_with("#EXT-X-STREAM-INF:"))
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, String> {
+        if !contents.trim_start().starts_with("#EXTM3U") {
+            return Err("missing #EXTM3U header".into());
+        }
+        let mut playlist = Self::default();
+        let mut pending: Option<Vec<(String, String)>> = None;
+        for line in contents.lines().map(|line| line.trim()) {
+            if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+                pending = Some(parse_attributes(attributes));
+            } else if let Some(attributes) = line.strip_prefix("#EXT-X-MEDIA:") {
+                let attributes = parse_attributes(attributes);
+                if attribute(&attributes, "TYPE") != Some("AUDIO") {
+                    continue;
+                }
+                let group_id = attribute(&attributes, "GROUP-ID")
+                    .ok_or("EXT-X-MEDIA without GROUP-ID")?
+                    .to_string();
+                playlist.audio.push(HlsRendition {
+                    name: attribute(&attributes, "NAME")
+                        .unwrap_or(&group_id)
+                        .to_string(),
+                    group_id,
+                    language: attribute(&attributes, "LANGUAGE").map(str::to_string),
+                    uri: attribute(&attributes, "URI").map(str::to_string),
+                    default: attribute(&attributes, "DEFAULT") == Some("YES"),
+                    autoselect: attribute(&attributes, "AUTOSELECT") == Some("YES"),
+                    channels: attribute(&attributes, "CHANNELS").map(str::to_string),
+                });
+            } else if line.starts_with('#') || line.is_empty() {
+                continue;
+            } else if let Some(attributes) = pending.take() {
+                playlist.variants.push(variant(line, &attributes)?);
+            }
+        }
+        if playlist.variants.is_empty() {
+            return Err("master playlist missing variants".into());
+        }
+        Ok(playlist)
+    }
+
+    /// Highest variant within `caps`, by height and then bandwidth. When
+    /// none fits, the one with the lowest bandwidth. Audio-only variants
+    /// are never picked.
+    pub fn select_variant(&self, caps: &VariantCaps) -> Option<&HlsVariant> {
+        self.variants
+            .iter()
+            .filter(|variant| caps.allows(variant))
+            .max_by_key(|variant| (variant.height().unwrap_or(0), variant.bandwidth))
+            .or_else(|| {
+                self.variants
+                    .iter()
+                    .filter(|variant| !variant.is_audio_only())
+                    .min_by_key(|variant| variant.bandwidth)
+            })
+    }
+
+    /// Audio rendition `variant` plays with: the one in its group in
+    /// `language`, else the group's default, else its first.
+    pub fn audio_for(&self, variant: &HlsVariant, language: Option<&str>) -> Option<&HlsRendition> {
+        let group = variant.audio_group.as_deref()?;
+        let renditions: Vec<&HlsRendition> = self
+            .audio
+            .iter()
+            .filter(|rendition| rendition.group_id == group)
+            .collect();
+        language
+            .and_then(|language| {
+                renditions.iter().copied().find(|rendition| {
+                    rendition
+                        .language
+                        .as_deref()
+                        .is_some_and(|candidate| same_language(candidate, language))
+                })
+            })
+            .or_else(|| {
+                renditions
+                    .iter()
+                    .copied()
+                    .find(|rendition| rendition.default)
+            })
+            .or_else(|| renditions.first().copied())
+    }
+}
+
+/// Limits on the variant picked from a master playlist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantCaps {
+    pub max_height: Option<u32>,
+    pub max_bandwidth: Option<u64>,
+    pub max_frame_rate: Option<f64>,
+    /// Accepted video codec prefixes; empty accepts any.
+    pub video_codecs: Vec<String>,
+}
+
+impl VariantCaps {
+    pub fn from_config(section: &HlsSection) -> Self {
+        Self {
+            max_height: (section.max_height > 0).then_some(section.max_height),
+            max_bandwidth: (section.max_bandwidth_kbps > 0)
+                .then(|| section.max_bandwidth_kbps as u64 * 1000),
+            max_frame_rate: (section.max_frame_rate > 0.0).then_some(section.max_frame_rate),
+            video_codecs: section.video_codecs.clone(),
+        }
+    }
+
+    /// Whether `variant` carries video within the caps.
+    pub fn allows(&self, variant: &HlsVariant) -> bool {
+        if variant.is_audio_only() {
+            return false;
+        }
+        let height = match (self.max_height, variant.height()) {
+            (Some(max), Some(height)) => height <= max,
+            _ => true,
+        };
+        let bandwidth = self
+            .max_bandwidth
+            .is_none_or(|max| variant.bandwidth <= max);
+        let frame_rate = match (self.max_frame_rate, variant.frame_rate) {
+            (Some(max), Some(rate)) => rate <= max + 0.01,
+            _ => true,
+        };
+        let mut video = variant.video_codecs().peekable();
+        let codec = self.video_codecs.is_empty()
+            || video.peek().is_none()
+            || video.any(|codec| {
+                self.video_codecs
+                    .iter()
+                    .any(|prefix| codec.starts_with(prefix.as_str()))
+            });
+        height && bandwidth && frame_rate && codec
+    }
+}
+
+fn variant(uri: &str, attributes: &[(String, String)]) -> Result<HlsVariant, String> {
+    let bandwidth = attribute(attributes, "BANDWIDTH")
+        .ok_or("EXT-X-STREAM-INF without BANDWIDTH")?
+        .parse()
+        .map_err(|_| "invalid BANDWIDTH")?;
+    let resolution = match attribute(attributes, "RESOLUTION") {
+        Some(value) => Some(
+            value
+                .split_once('x')
+                .and_then(|(width, height)| Some((width.parse().ok()?, height.parse().ok()?)))
+                .ok_or("invalid RESOLUTION")?,
+        ),
+        None => None,
+    };
+    let frame_rate = match attribute(attributes, "FRAME-RATE") {
+        Some(value) => Some(value.parse().map_err(|_| "invalid FRAME-RATE")?),
+        None => None,
+    };
+    Ok(HlsVariant {
+        uri: uri.to_string(),
+        bandwidth,
+        average_bandwidth: attribute(attributes, "AVERAGE-BANDWIDTH")
+            .and_then(|value| value.parse().ok()),
+        resolution,
+        codecs: attribute(attributes, "CODECS")
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|codec| codec.trim().to_string())
+                    .filter(|codec| !codec.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        frame_rate,
+        audio_group: attribute(attributes, "AUDIO").map(str::to_string),
+    })
+}
+
+/// Splits an attribute list on the commas outside quoted strings; quotes
+/// are removed from the values.
+fn parse_attributes(list: &str) -> Vec<(String, String)> {
+    let mut attributes = Vec::new();
+    let mut rest = list.trim();
+    while !rest.is_empty() {
+        let Some((name, after)) = rest.split_once('=') else {
+            break;
+        };
+        let (value, remaining) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, remaining)) => (value, remaining),
+                None => (quoted, ""),
+            },
+            None => after
+                .split_once(',')
+                .map_or((after, ""), |(value, remaining)| (value, remaining)),
+        };
+        attributes.push((name.trim().to_ascii_uppercase(), value.trim().to_string()));
+        rest = remaining.trim_start_matches(',').trim_start();
+    }
+    attributes
+}
+
+fn attribute<'a>(attributes: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    attributes
+        .iter()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+fn is_audio_codec(codec: &str) -> bool {
+    ["mp4a", "ac-3", "ec-3", "opus", "flac", "mp3"]
+        .iter()
+        .any(|prefix| codec.starts_with(prefix))
+}
+
+/// Compares the primary subtags, so "pt" matches "pt-BR".
+fn same_language(candidate: &str, wanted: &str) -> bool {
+    let primary = |tag: &str| {
+        tag.split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    candidate.eq_ignore_ascii_case(wanted) || primary(candidate) == primary(wanted)
+}
@@ -1,4 +1,5 @@
 mod error;
+mod hls;
 mod types;
 
 use std::collections::HashMap;
@@ -32,10 +33,11 @@ use crate::quality::{
 use crate::queue::{PlayoutQueueStore, QueueItem};
 
 pub use error::{ProcessorError, ProcessorResult};
+pub use hls::{HlsMasterPlaylist, HlsRendition, HlsVariant, VariantCaps};
 pub use types::{
-    DashDownload, DownloadedMedia, HlsDownload, MasteringOutcome, MasteringStrategy,
-    MediaDescriptor, PackagingArtifacts, ProcessorReport, ProgressiveDownload, QcArtifacts,
-    RetryPolicy, RevalidationOutcome, SegmentRecord, StagingPaths,
+    DashDownload, DownloadedMedia, HlsAudioDownload, HlsDownload, MasteringOutcome,
+    MasteringStrategy, MediaDescriptor, PackagingArtifacts, ProcessorReport, ProgressiveDownload,
+    QcArtifacts, RetryPolicy, RevalidationOutcome, SegmentRecord, StagingPaths,
 };
 
 pub const MASTER_PLAYLIST_NAME: &str = "master.m3u8";
@@ -43,6 +45,25 @@ pub const MASTER_PLAYLIST_NAME: &str = "master.m3u8";
 const HLS_SEGMENT_PREFIX_720: &str = "hls_720p";
 const HLS_SEGMENT_PREFIX_480: &str = "hls_480p";
 
+/// Staging names of a downloaded media playlist and its segments.
+struct HlsStagingNames {
+    original: &'static str,
+    rewritten: &'static str,
+    segment_prefix: &'static str,
+}
+
+const HLS_VIDEO_NAMES: HlsStagingNames = HlsStagingNames {
+    original: "original.m3u8",
+    rewritten: "index.m3u8",
+    segment_prefix: "seg",
+};
+
+const HLS_AUDIO_NAMES: HlsStagingNames = HlsStagingNames {
+    original: "audio_original.m3u8",
+    rewritten: "audio.m3u8",
+    segment_prefix: "aud",
+};
+
 #[derive(Clone)]
 pub struct Processor {
     plan_store: SqlitePlanStore,
@@ -272,16 +293,135 @@ impl Processor {
 
     async fn download_hls(
         &self,
-        _plan: &Plan,
+        plan: &Plan,
         staging: &StagingPaths,
         revalidation: &RevalidationOutcome,
     ) -> ProcessorResult<DownloadedMedia> {
-        let playlist_url = &revalidation.capture.url;
-        let playlist_contents = self.fetch_text(playlist_url).await?;
-        let playlist = HlsPlaylist::parse(&playlist_contents)
+        let capture_url = &revalidation.capture.url;
+        let contents = self.fetch_text(capture_url).await?;
+        if !HlsMasterPlaylist::is_master(&contents) {
+            let media = self
+                .fetch_hls_media(staging, capture_url, contents, &HLS_VIDEO_NAMES)
+                .await?;
+            return Ok(DownloadedMedia::Hls(Box::new(
+                media.into_download(None, None),
+            )));
+        }
+
+        let master = HlsMasterPlaylist::parse(&contents).map_err(|err| {
+            ProcessorError::Download(format!("invalid HLS master playlist: {err}"))
+        })?;
+        let master_path = staging.source.join("master_original.m3u8");
+        fs::write(&master_path, &contents)
+            .await
+            .map_err(|source| ProcessorError::Io {
+                path: master_path.clone(),
+                source,
+            })?;
+        let caps = VariantCaps::from_config(&self.processor_config.hls);
+        let variant = master
+            .select_variant(&caps)
+            .ok_or_else(|| {
+                ProcessorError::Download("HLS master playlist has no video variants".into())
+            })?
+            .clone();
+        if !caps.allows(&variant) {
+            warn!(
+                plan_id = %plan.plan_id,
+                variant = %variant.uri,
+                bandwidth = variant.bandwidth,
+                "no HLS variant within caps; using the lightest"
+            );
+        }
+        info!(
+            plan_id = %plan.plan_id,
+            variant = %variant.uri,
+            bandwidth = variant.bandwidth,
+            height = ?variant.height(),
+            "selected HLS variant"
+        );
+        let variant_url = self.resolve_segment_url(capture_url, &variant.uri)?;
+        let variant_contents = self.fetch_text(&variant_url).await?;
+        let media = self
+            .fetch_hls_media(staging, &variant_url, variant_contents, &HLS_VIDEO_NAMES)
+            .await?;
+
+        // Audio without a URI is muxed into the variant itself.
+        let rendition = master
+            .audio_for(
+                &variant,
+                self.processor_config.hls.audio_language.as_deref(),
+            )
+            .filter(|rendition| rendition.uri.is_some())
+            .cloned();
+        let Some(rendition) = rendition else {
+            return Ok(DownloadedMedia::Hls(Box::new(
+                media.into_download(Some(variant), None),
+            )));
+        };
+        let audio_url =
+            self.resolve_segment_url(capture_url, rendition.uri.as_deref().unwrap_or_default())?;
+        let audio_contents = self.fetch_text(&audio_url).await?;
+        let audio = self
+            .fetch_hls_media(staging, &audio_url, audio_contents, &HLS_AUDIO_NAMES)
+            .await?;
+
+        // A local master ties the variant to its audio, so a transcode reads
+        // both.
+        let local_master = staging.source.join(MASTER_PLAYLIST_NAME);
+        let mut rewritten = String::from("#EXTM3U\n");
+        rewritten.push_str(&format!(
+            "#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"audio\",NAME=\"{}\",{}DEFAULT=YES,AUTOSELECT=YES,URI=\"{}\"\n",
+            rendition.name,
+            rendition
+                .language
+                .as_deref()
+                .map(|language| format!("LANGUAGE=\"{language}\","))
+                .unwrap_or_default(),
+            HLS_AUDIO_NAMES.rewritten
+        ));
+        let mut attributes = format!("BANDWIDTH={}", variant.bandwidth);
+        if let Some((width, height)) = variant.resolution {
+            attributes.push_str(&format!(",RESOLUTION={width}x{height}"));
+        }
+        if !variant.codecs.is_empty() {
+            attributes.push_str(&format!(",CODECS=\"{}\"", variant.codecs.join(",")));
+        }
+        rewritten.push_str(&format!(
+            "#EXT-X-STREAM-INF:{attributes},AUDIO=\"audio\"\n{}\n",
+            HLS_VIDEO_NAMES.rewritten
+        ));
+        fs::write(&local_master, rewritten)
+            .await
+            .map_err(|source| ProcessorError::Io {
+                path: local_master.clone(),
+                source,
+            })?;
+
+        let mut download = media.into_download(Some(variant), None);
+        download.playlist_path = master_path;
+        download.rewritten_playlist = local_master;
+        download.audio = Some(HlsAudioDownload {
+            rendition,
+            rewritten_playlist: audio.rewritten_path,
+            segments: audio.segments,
+        });
+        Ok(DownloadedMedia::Hls(Box::new(download)))
+    }
+
+    /// Downloads the segments of the media playlist at `url` into the
+    /// staging area and writes a copy of the playlist pointing at them.
+    async fn fetch_hls_media(
+        &self,
+        staging: &StagingPaths,
+        url: &str,
+        contents: String,
+        names: &HlsStagingNames,
+    ) -> ProcessorResult<HlsMedia> {
+        let playlist = HlsPlaylist::parse(&contents)
             .map_err(|err| ProcessorError::Download(format!("invalid HLS playlist: {err}")))?;
-        let original_path = staging.source.join("original.m3u8");
-        fs::write(&original_path, playlist_contents)
+        let original_path = staging.source.join(names.original);
+        fs::write(&original_path, contents)
             .await
             .map_err(|source| ProcessorError::Io {
                 path: original_path.clone(),
@@ -290,13 +430,13 @@ impl Processor {
 
         let mut local_segments = Vec::new();
         for (index, segment) in playlist.segments.iter().enumerate() {
-            let resolved = self.resolve_segment_url(playlist_url, &segment.uri)?;
+            let resolved = self.resolve_segment_url(url, &segment.uri)?;
             let extension = segment
                 .uri
                 .rsplit_once('.')
                 .map(|(_, ext)| format!(".{ext}"))
                 .unwrap_or_else(|| ".m4s".to_string());
-            let local_name = format!("seg_{:04}{}", index + 1, extension);
+            let local_name = format!("{}_{:04}{}", names.segment_prefix, index + 1, extension);
             let local_path = staging.source.join(&local_name);
             self.fetch_to_file(&resolved, &local_path).await?;
             local_segments.push(SegmentRecord {
@@ -313,7 +453,7 @@ impl Processor {
             ));
         }
 
-        let rewritten_path = staging.source.join("index.m3u8");
+        let rewritten_path = staging.source.join(names.rewritten);
         let mut rewritten = String::new();
         rewritten.push_str("#EXTM3U\n");
         rewritten.push_str(&format!("#EXT-X-VERSION:{}\n", playlist.version));
@@ -339,15 +479,12 @@ impl Processor {
                 source,
             })?;
 
-        let total_duration: f64 = local_segments.iter().map(|s| s.duration).sum();
-        Ok(DownloadedMedia::Hls(HlsDownload {
-            playlist_path: original_path,
-            rewritten_playlist: rewritten_path,
+        Ok(HlsMedia {
+            playlist,
+            original_path,
+            rewritten_path,
             segments: local_segments,
-            media_sequence: playlist.media_sequence,
-            target_duration: playlist.target_duration,
-            total_duration,
-        }))
+        })
     }
 
     async fn download_dash(
@@ -461,7 +598,11 @@ impl Processor {
                 self.copy_file(&progressive.file_path, &master_path).await?;
             }
             DownloadedMedia::Hls(hls) if matches!(strategy, MasteringStrategy::Remux) => {
-                self.write_remux_stub(&master_path, "hls", &hls.segments)
+                let mut segments = hls.segments.clone();
+                if let Some(audio) = &hls.audio {
+                    segments.extend(audio.segments.iter().cloned());
+                }
+                self.write_remux_stub(&master_path, "hls", &segments)
                     .await?;
                 descriptor.container = "hls".into();
             }
@@ -654,11 +795,20 @@ impl Processor {
             })?;
 
         let mut checksums = HashMap::new();
+        let mut sizes = HashMap::new();
         for path in &packaging.artifact_paths {
             if let Ok(relative) = path.strip_prefix(ready_dir) {
                 if let Some(rel) = relative.to_str() {
                     let checksum = self.compute_sha256(path).await?;
                     checksums.insert(rel.to_string(), checksum);
+                    let size = fs::metadata(path)
+                        .await
+                        .map_err(|source| ProcessorError::Io {
+                            path: path.clone(),
+                            source,
+                        })?
+                        .len();
+                    sizes.insert(rel.to_string(), size);
                 }
             }
         }
@@ -684,6 +834,7 @@ impl Processor {
                 .iter()
                 .filter_map(|path| path.file_name().map(|n| n.to_string_lossy().to_string()))
                 .collect(),
+            sizes,
             created_at: Utc::now(),
             quality: ManifestQuality::from_report(&quality_report, &frame_path, ready_dir),
         };
@@ -762,6 +913,13 @@ impl Processor {
             priority: 0,
             node_origin: Some(self.vvtv_config.system.node_name.clone()),
             content_kind: Some(plan.kind.clone()),
+            schedule: None,
+            tags: plan.tags.clone(),
+            source_domain: plan
+                .source_url
+                .as_deref()
+                .and_then(|url| Url::parse(url).ok())
+                .and_then(|parsed| parsed.host_str().map(|host| host.to_lowercase())),
         };
         self.queue_store.enqueue(&item)?;
         Ok(())
@@ -1193,6 +1351,34 @@ struct HlsPlaylist {
     segments: Vec<HlsSegment>,
 }
 
+/// A media playlist downloaded to the staging area.
+struct HlsMedia {
+    playlist: HlsPlaylist,
+    original_path: PathBuf,
+    rewritten_path: PathBuf,
+    segments: Vec<SegmentRecord>,
+}
+
+impl HlsMedia {
+    fn into_download(
+        self,
+        variant: Option<HlsVariant>,
+        audio: Option<HlsAudioDownload>,
+    ) -> HlsDownload {
+        let total_duration: f64 = self.segments.iter().map(|s| s.duration).sum();
+        HlsDownload {
+            playlist_path: self.original_path,
+            rewritten_playlist: self.rewritten_path,
+            segments: self.segments,
+            media_sequence: self.playlist.media_sequence,
+            target_duration: self.playlist.target_duration,
+            total_duration,
+            variant,
+            audio,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct TranscodeCommand {
     program: OsString,
@@ -1278,6 +1464,9 @@ impl HlsPlaylist {
         if !contents.trim_start().starts_with("#EXTM3U") {
             return Err("missing #EXTM3U header".into());
         }
+        if HlsMasterPlaylist::is_master(contents) {
+            return Err("master playlist, not a media playlist".into());
+        }
         let mut version = 3u32;
         let mut target_duration = 4.0f64;
         let mut media_sequence = 0u64;
@@ -1363,6 +1552,9 @@ struct Manifest {
     strategy: MasteringStrategy,
     duration: Option<f64>,
     playlists: Vec<String>,
+    /// Byte size of each artifact, keyed like `checksums.json`, so the
+    /// broadcaster can spot truncated files before hashing them.
+    sizes: HashMap<String, u64>,
     created_at: chrono::DateTime<Utc>,
     quality: ManifestQuality,
 }
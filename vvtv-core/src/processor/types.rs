@@ -8,6 +8,7 @@ use crate::browser::{BrowserCapture, ContentMetadata, PlaybackValidation};
 use crate::quality::QualityReport;
 
 use super::error::ProcessorError;
+use super::hls::{HlsRendition, HlsVariant};
 
 #[derive(Debug, Clone)]
 pub struct StagingPaths {
@@ -64,6 +65,17 @@ pub struct HlsDownload {
     pub media_sequence: u64,
     pub target_duration: f64,
     pub total_duration: f64,
+    /// Variant picked when the capture was a master playlist.
+    pub variant: Option<HlsVariant>,
+    /// Alternate audio rendition downloaded alongside the variant.
+    pub audio: Option<HlsAudioDownload>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HlsAudioDownload {
+    pub rendition: HlsRendition,
+    pub rewritten_playlist: PathBuf,
+    pub segments: Vec<SegmentRecord>,
 }
 
 #[derive(Debug, Clone)]
@@ -81,7 +93,7 @@ pub struct ProgressiveDownload {
 
 #[derive(Debug, Clone)]
 pub enum DownloadedMedia {
-    Hls(HlsDownload),
+    Hls(Box<HlsDownload>),
     Dash(DashDownload),
     Progressive(ProgressiveDownload),
 }
@@ -0,0 +1,297 @@
+//! As-run log: the append-only record of what actually went to air.
+//!
+//! Queue rows are updated in place and eventually removed by
+//! `cleanup_played`, so proof of play lives in its own `as_run_log` table.
+//! Triggers reject updates and deletes; corrections are new rows.
+
+use std::fmt;
+use std::io::Write;
+
+use chrono::{DateTime, NaiveDateTime, SecondsFormat, Utc};
+use rusqlite::{params, Row};
+use serde::Serialize;
+
+use super::{parse_timestamp, QueueEntry, QueueError, QueueResult};
+
+pub(crate) const AS_RUN_SCHEMA: &str = include_str!("../../../sql/as_run.sql");
+
+const CSV_HEADER: &[&str] = &[
+    "id",
+    "queue_id",
+    "plan_id",
+    "asset_path",
+    "asset_checksum",
+    "content_kind",
+    "node_origin",
+    "destination",
+    "transition",
+    "outcome",
+    "started_at",
+    "finished_at",
+    "duration_s",
+    "note",
+    "recorded_at",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AsRunOutcome {
+    /// Aired in full.
+    Played,
+    /// Playout failed before the entry finished.
+    Failed,
+    /// The process stopped mid-item; the entry was requeued.
+    Interrupted,
+}
+
+impl AsRunOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AsRunOutcome::Played => "played",
+            AsRunOutcome::Failed => "failed",
+            AsRunOutcome::Interrupted => "interrupted",
+        }
+    }
+}
+
+impl fmt::Display for AsRunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AsRunOutcome {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "played" => Ok(Self::Played),
+            "failed" => Ok(Self::Failed),
+            "interrupted" => Ok(Self::Interrupted),
+            other => Err(QueueError::InvalidStatus(other.to_string())),
+        }
+    }
+}
+
+/// One airing to append to the as-run log.
+#[derive(Debug, Clone)]
+pub struct AsRunRecord {
+    pub queue_id: Option<i64>,
+    pub plan_id: String,
+    pub asset_path: String,
+    /// SHA-256 of the asset that aired, when known.
+    pub asset_checksum: Option<String>,
+    pub content_kind: Option<String>,
+    pub node_origin: Option<String>,
+    /// Names of the destinations the entry went out to, comma-separated.
+    pub destination: Option<String>,
+    pub transition: Option<String>,
+    pub outcome: AsRunOutcome,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+}
+
+impl AsRunRecord {
+    pub fn from_entry(
+        entry: &QueueEntry,
+        outcome: AsRunOutcome,
+        started_at: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            queue_id: Some(entry.id),
+            plan_id: entry.plan_id.clone(),
+            asset_path: entry.asset_path.clone(),
+            asset_checksum: None,
+            content_kind: entry.content_kind.clone(),
+            node_origin: entry.node_origin.clone(),
+            destination: None,
+            transition: None,
+            outcome,
+            started_at,
+            finished_at: None,
+            note: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AsRunEntry {
+    pub id: i64,
+    pub queue_id: Option<i64>,
+    pub plan_id: String,
+    pub asset_path: String,
+    pub asset_checksum: Option<String>,
+    pub content_kind: Option<String>,
+    pub node_origin: Option<String>,
+    pub destination: Option<String>,
+    pub transition: Option<String>,
+    pub outcome: AsRunOutcome,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub note: Option<String>,
+    pub recorded_at: Option<DateTime<Utc>>,
+}
+
+impl AsRunEntry {
+    pub(crate) fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let outcome: String = row.get("outcome")?;
+        Ok(Self {
+            id: row.get("id")?,
+            queue_id: row.get("queue_id")?,
+            plan_id: row.get("plan_id")?,
+            asset_path: row.get("asset_path")?,
+            asset_checksum: row.get("asset_checksum")?,
+            content_kind: row.get("content_kind")?,
+            node_origin: row.get("node_origin")?,
+            destination: row.get("destination")?,
+            transition: row.get("transition")?,
+            outcome: outcome.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(0, "outcome".into(), rusqlite::types::Type::Text)
+            })?,
+            started_at: DateTime::from_naive_utc_and_offset(
+                row.get::<_, NaiveDateTime>("started_at")?,
+                Utc,
+            ),
+            finished_at: parse_timestamp(row.get("finished_at")?)?,
+            note: row.get("note")?,
+            recorded_at: parse_timestamp(row.get("recorded_at")?)?,
+        })
+    }
+
+    /// Seconds on air, when the airing has an end time.
+    pub fn duration_s(&self) -> Option<i64> {
+        self.finished_at
+            .map(|finished| (finished - self.started_at).num_seconds())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AsRunFormat {
+    Csv,
+    Json,
+}
+
+impl AsRunFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AsRunFormat::Csv => "csv",
+            AsRunFormat::Json => "json",
+        }
+    }
+}
+
+impl fmt::Display for AsRunFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for AsRunFormat {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            other => Err(QueueError::InvalidFormat(other.to_string())),
+        }
+    }
+}
+
+pub(crate) fn insert(conn: &rusqlite::Connection, record: &AsRunRecord) -> QueueResult<i64> {
+    conn.execute(
+        "INSERT INTO as_run_log (
+            queue_id, plan_id, asset_path, asset_checksum, content_kind, node_origin,
+            destination, transition, outcome, started_at, finished_at, note
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![
+            record.queue_id,
+            &record.plan_id,
+            &record.asset_path,
+            &record.asset_checksum,
+            &record.content_kind,
+            &record.node_origin,
+            &record.destination,
+            &record.transition,
+            record.outcome.as_str(),
+            record.started_at.naive_utc(),
+            record.finished_at.map(|at| at.naive_utc()),
+            &record.note,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Writes `entries` as CSV with a header row, or as a JSON array.
+pub fn write_as_run<W: Write>(
+    entries: &[AsRunEntry],
+    format: AsRunFormat,
+    mut writer: W,
+) -> QueueResult<()> {
+    match format {
+        AsRunFormat::Json => {
+            let rows: Vec<_> = entries.iter().map(JsonRow::from).collect();
+            serde_json::to_writer_pretty(&mut writer, &rows)
+                .map_err(|err| QueueError::Io(err.into()))?;
+            writeln!(writer)?;
+        }
+        AsRunFormat::Csv => {
+            writeln!(writer, "{}", CSV_HEADER.join(","))?;
+            for entry in entries {
+                let fields = [
+                    entry.id.to_string(),
+                    optional(entry.queue_id),
+                    csv_field(&entry.plan_id),
+                    csv_field(&entry.asset_path),
+                    csv_field(entry.asset_checksum.as_deref().unwrap_or("")),
+                    csv_field(entry.content_kind.as_deref().unwrap_or("")),
+                    csv_field(entry.node_origin.as_deref().unwrap_or("")),
+                    csv_field(entry.destination.as_deref().unwrap_or("")),
+                    csv_field(entry.transition.as_deref().unwrap_or("")),
+                    entry.outcome.to_string(),
+                    timestamp(entry.started_at),
+                    entry.finished_at.map(timestamp).unwrap_or_default(),
+                    optional(entry.duration_s()),
+                    csv_field(entry.note.as_deref().unwrap_or("")),
+                    entry.recorded_at.map(timestamp).unwrap_or_default(),
+                ];
+                writeln!(writer, "{}", fields.join(","))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// JSON export row, with the derived duration alongside the stored fields.
+#[derive(Serialize)]
+struct JsonRow<'a> {
+    #[serde(flatten)]
+    entry: &'a AsRunEntry,
+    duration_s: Option<i64>,
+}
+
+impl<'a> From<&'a AsRunEntry> for JsonRow<'a> {
+    fn from(entry: &'a AsRunEntry) -> Self {
+        Self {
+            entry,
+            duration_s: entry.duration_s(),
+        }
+    }
+}
+
+fn timestamp(at: DateTime<Utc>) -> String {
+    at.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn optional(value: Option<i64>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(['"', ',', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,130 @@
+//! Daypart table used to steer the content mix by time of day.
+//!
+//! Each daypart covers a local hour range in the configured timezone and sets
+//! target ratios per `content_kind`. While a daypart is active the selector
+//! airs whichever kind lags its target the most since the daypart began.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+
+use crate::config::DaypartSection;
+
+use super::selection::kind_key;
+use super::{QueueError, QueueResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Daypart {
+    pub name: String,
+    /// First local hour covered (0-23).
+    pub start_hour: u32,
+    /// Local hour at which the daypart ends, exclusive. Ranges may wrap past
+    /// midnight; `start_hour == end_hour` covers the whole day.
+    pub end_hour: u32,
+    /// Target share per normalised `content_kind`, summing to 1.
+    pub ratios: HashMap<String, f64>,
+}
+
+impl Daypart {
+    pub fn contains_hour(&self, hour: u32) -> bool {
+        if self.start_hour == self.end_hour {
+            true
+        } else if self.start_hour < self.end_hour {
+            hour >= self.start_hour && hour < self.end_hour
+        } else {
+            hour >= self.start_hour || hour < self.end_hour
+        }
+    }
+
+    pub fn window_label(&self) -> String {
+        format!("{:02}:00-{:02}:00", self.start_hour, self.end_hour)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DaypartSchedule {
+    timezone: Tz,
+    parts: Vec<Daypart>,
+}
+
+/// Active daypart resolved for a given instant.
+#[derive(Debug, Clone)]
+pub struct ActiveDaypart<'a> {
+    pub daypart: &'a Daypart,
+    pub started_at: DateTime<Utc>,
+}
+
+impl DaypartSchedule {
+    pub fn new(timezone: Tz, parts: Vec<Daypart>) -> Self {
+        Self { timezone, parts }
+    }
+
+    pub fn from_config(section: &DaypartSection) -> QueueResult<Self> {
+        let timezone: Tz = section.timezone.parse().map_err(|_| {
+            QueueError::InvalidDaypart(format!("unknown timezone {}", section.timezone))
+        })?;
+        let mut parts = Vec::with_capacity(section.slots.len());
+        for slot in &section.slots {
+            if slot.start_hour > 23 || slot.end_hour > 24 {
+                return Err(QueueError::InvalidDaypart(format!(
+                    "{}: hours must be within 0-24",
+                    slot.name
+                )));
+            }
+            let total: f64 = slot.ratios.values().filter(|ratio| **ratio > 0.0).sum();
+            if total <= 0.0 {
+                return Err(QueueError::InvalidDaypart(format!(
+                    "{}: at least one positive ratio is required",
+                    slot.name
+                )));
+            }
+            let ratios = slot
+                .ratios
+                .iter()
+                .filter(|(_, ratio)| **ratio > 0.0)
+                .map(|(kind, ratio)| (kind_key(Some(kind)), ratio / total))
+                .collect();
+            parts.push(Daypart {
+                name: slot.name.clone(),
+                start_hour: slot.start_hour,
+                end_hour: slot.end_hour % 24,
+                ratios,
+            });
+        }
+        Ok(Self { timezone, parts })
+    }
+
+    pub fn timezone(&self) -> Tz {
+        self.timezone
+    }
+
+    pub fn parts(&self) -> &[Daypart] {
+        &self.parts
+    }
+
+    /// First daypart, in table order, covering `now`'s local hour.
+    pub fn active(&self, now: DateTime<Utc>) -> Option<ActiveDaypart<'_>> {
+        let local = now.with_timezone(&self.timezone);
+        let daypart = self
+            .parts
+            .iter()
+            .find(|part| part.contains_hour(local.hour()))?;
+        let mut start_date = local.date_naive();
+        if daypart.start_hour > local.hour() {
+            start_date = start_date.pred_opt().unwrap_or(start_date);
+        }
+        let start_time = NaiveTime::from_hms_opt(daypart.start_hour, 0, 0).unwrap_or_default();
+        let started_at = self
+            .timezone
+            .from_local_datetime(&start_date.and_time(start_time))
+            .earliest()
+            .map(|start| start.with_timezone(&Utc))
+            // The start fell into a DST gap; the hour boundary is close enough.
+            .unwrap_or_else(|| now - Duration::minutes(local.minute() as i64));
+        Some(ActiveDaypart {
+            daypart,
+            started_at,
+        })
+    }
+}
@@ -0,0 +1,371 @@
+//! Read-only projection of upcoming airings.
+//!
+//! The forecast replays `begin_playback` against an in-memory snapshot of the
+//! queue, advancing a simulated clock by each entry's duration. Injections the
+//! live system performs on its own (emergency loop refills, micro spots) are
+//! modelled from the options so the projection matches what will air.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{params, Connection};
+
+use super::{
+    AnchorKind, PlayoutQueueStore, QueueEntry, QueueItem, QueueResult, QueueSelectionPolicy,
+};
+
+/// Entries without a known duration are assumed to run this long.
+pub(crate) const DEFAULT_FALLBACK_DURATION_S: i64 = 300;
+const DEFAULT_MAX_SLOTS: usize = 2_000;
+
+/// Refill performed when the queued buffer drops below `threshold` and no
+/// entry from `items` is still waiting.
+#[derive(Debug, Clone)]
+pub struct EmergencyRefill {
+    pub threshold: Duration,
+    pub items: Vec<QueueItem>,
+}
+
+/// Item injected on a fixed cadence, such as a sponsored micro spot.
+#[derive(Debug, Clone)]
+pub struct ScheduledInjection {
+    /// Template for each injection; `plan_id` is suffixed with the
+    /// injection timestamp.
+    pub item: QueueItem,
+    /// First time the item may be injected; `None` means immediately.
+    pub next_at: Option<DateTime<Utc>>,
+    pub every: Duration,
+    pub until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ForecastOptions {
+    pub start: DateTime<Utc>,
+    pub horizon: Duration,
+    pub emergency: Option<EmergencyRefill>,
+    pub injections: Vec<ScheduledInjection>,
+    pub fallback_duration: Duration,
+    pub max_slots: usize,
+}
+
+impl ForecastOptions {
+    pub fn new(start: DateTime<Utc>, horizon: Duration) -> Self {
+        Self {
+            start,
+            horizon,
+            emergency: None,
+            injections: Vec::new(),
+            fallback_duration: Duration::seconds(DEFAULT_FALLBACK_DURATION_S),
+            max_slots: DEFAULT_MAX_SLOTS,
+        }
+    }
+
+    pub fn with_emergency(mut self, emergency: EmergencyRefill) -> Self {
+        self.emergency = Some(emergency);
+        self
+    }
+
+    pub fn with_injections(mut self, injections: Vec<ScheduledInjection>) -> Self {
+        self.injections = injections;
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForecastSource {
+    /// Entry already on air when the forecast started.
+    OnAir,
+    Queue,
+    EmergencyLoop,
+    Injection,
+}
+
+impl ForecastSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ForecastSource::OnAir => "on_air",
+            ForecastSource::Queue => "queue",
+            ForecastSource::EmergencyLoop => "emergency_loop",
+            ForecastSource::Injection => "injection",
+        }
+    }
+}
+
+impl fmt::Display for ForecastSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ForecastSlot {
+    pub starts_at: DateTime<Utc>,
+    pub ends_at: DateTime<Utc>,
+    /// Queue id for entries already in the queue; simulated injections have none.
+    pub queue_id: Option<i64>,
+    pub plan_id: String,
+    pub asset_path: String,
+    pub content_kind: Option<String>,
+    pub anchor: Option<AnchorKind>,
+    pub source: ForecastSource,
+    /// The entry had no duration and `fallback_duration` was assumed.
+    pub estimated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Forecast {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub slots: Vec<ForecastSlot>,
+}
+
+impl Forecast {
+    /// Slot on air at `at`, if any.
+    pub fn slot_at(&self, at: DateTime<Utc>) -> Option<&ForecastSlot> {
+        self.slots
+            .iter()
+            .find(|slot| slot.starts_at <= at && at < slot.ends_at)
+    }
+}
+
+struct Simulation<'a> {
+    store: &'a PlayoutQueueStore,
+    conn: &'a mut Connection,
+    policy: &'a QueueSelectionPolicy,
+    options: &'a ForecastOptions,
+    injections: Vec<ScheduledInjection>,
+    injected: HashMap<i64, ForecastSource>,
+    refills: usize,
+}
+
+pub(crate) fn simulate(
+    store: &PlayoutQueueStore,
+    conn: &mut Connection,
+    policy: &QueueSelectionPolicy,
+    options: &ForecastOptions,
+) -> QueueResult<Forecast> {
+    let end = options.start + options.horizon;
+    let mut simulation = Simulation {
+        store,
+        conn,
+        policy,
+        options,
+        injections: options.injections.clone(),
+        injected: HashMap::new(),
+        refills: 0,
+    };
+    let mut slots = Vec::new();
+    let mut clock = options.start;
+
+    for entry in simulation.on_air()? {
+        let starts_at = entry.play_started_at.unwrap_or(options.start);
+        let (duration, estimated) = simulation.duration_of(&entry);
+        let ends_at = (starts_at + duration).max(options.start);
+        simulation.finish(entry.id, ends_at)?;
+        slots.push(slot(
+            entry,
+            starts_at,
+            ends_at,
+            ForecastSource::OnAir,
+            estimated,
+        ));
+        clock = clock.max(ends_at);
+    }
+
+    while clock < end && slots.len() < options.max_slots {
+        simulation.inject_due(clock)?;
+        simulation.refill_emergency()?;
+        let selected = simulation
+            .store
+            .begin_playback_on(simulation.conn, policy, clock)?;
+        let Some(entry) = selected else {
+            match simulation.next_wake(clock)? {
+                Some(wake) => {
+                    clock = wake;
+                    continue;
+                }
+                None => break,
+            }
+        };
+        let (duration, estimated) = simulation.duration_of(&entry);
+        let ends_at = clock + duration;
+        simulation.finish(entry.id, ends_at)?;
+        let source = simulation
+            .injected
+            .get(&entry.id)
+            .copied()
+            .unwrap_or(ForecastSource::Queue);
+        slots.push(slot(entry, clock, ends_at, source, estimated));
+        clock = ends_at;
+    }
+
+    Ok(Forecast {
+        start: options.start,
+        end,
+        slots,
+    })
+}
+
+impl Simulation<'_> {
+    fn on_air(&self) -> QueueResult<Vec<QueueEntry>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT * FROM playout_queue WHERE status='playing' ORDER BY id")?;
+        let mut rows = stmt.query([])?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push(QueueEntry::from_row(row)?);
+        }
+        Ok(entries)
+    }
+
+    fn duration_of(&self, entry: &QueueEntry) -> (Duration, bool) {
+        match entry.duration_s {
+            // A resumed entry airs only what is left past its offset.
+            Some(seconds) if seconds > 0 => (
+                Duration::seconds(entry.remaining_duration_s().unwrap_or(seconds)),
+                false,
+            ),
+            _ => (self.options.fallback_duration, true),
+        }
+    }
+
+    fn finish(&mut self, id: i64, at: DateTime<Utc>) -> QueueResult<()> {
+        self.conn.execute(
+            "UPDATE playout_queue SET status='played', play_finished_at=?2 WHERE id=?1",
+            params![id, at.naive_utc()],
+        )?;
+        Ok(())
+    }
+
+    fn insert(&mut self, item: &QueueItem, source: ForecastSource) -> QueueResult<()> {
+        let id = super::insert_item(self.conn, item)?;
+        self.injected.insert(id, source);
+        Ok(())
+    }
+
+    fn inject_due(&mut self, now: DateTime<Utc>) -> QueueResult<()> {
+        let mut due = Vec::new();
+        for injection in &mut self.injections {
+            let expired = injection.until.map(|until| until <= now).unwrap_or(false);
+            let ready = injection.next_at.map(|next| next <= now).unwrap_or(true);
+            if expired || !ready {
+                continue;
+            }
+            let mut item = injection.item.clone();
+            item.plan_id = format!("{}:{}", item.plan_id, now.timestamp());
+            due.push(item);
+            injection.next_at = Some(now + injection.every.max(Duration::seconds(1)));
+        }
+        for item in &due {
+            self.insert(item, ForecastSource::Injection)?;
+        }
+        Ok(())
+    }
+
+    fn refill_emergency(&mut self) -> QueueResult<()> {
+        let Some(refill) = self.options.emergency.as_ref() else {
+            return Ok(());
+        };
+        if refill.items.is_empty() {
+            return Ok(());
+        }
+        let buffer_s: i64 = self.conn.query_row(
+            "SELECT COALESCE(SUM(duration_s), 0) FROM playout_queue WHERE status='queued'",
+            [],
+            |row| row.get(0),
+        )?;
+        if Duration::seconds(buffer_s) >= refill.threshold {
+            return Ok(());
+        }
+        let origins: Vec<&str> = refill
+            .items
+            .iter()
+            .filter_map(|item| item.node_origin.as_deref())
+            .collect();
+        let mut stmt = self.conn.prepare(
+            "SELECT node_origin FROM playout_queue WHERE status='queued' AND node_origin IS NOT NULL",
+        )?;
+        let queued_origins = stmt
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        drop(stmt);
+        if queued_origins
+            .iter()
+            .any(|origin| origins.contains(&origin.as_str()))
+        {
+            return Ok(());
+        }
+        self.refills += 1;
+        let items: Vec<QueueItem> = refill
+            .items
+            .iter()
+            .enumerate()
+            .map(|(index, item)| {
+                let mut item = item.clone();
+                item.plan_id = format!("{}-forecast-{}-{}", item.plan_id, self.refills, index + 1);
+                item
+            })
+            .collect();
+        for item in &items {
+            self.insert(item, ForecastSource::EmergencyLoop)?;
+        }
+        Ok(())
+    }
+
+    /// Earliest future instant at which selection could change: a retry
+    /// becoming due, an anchor entering its window or an injection.
+    fn next_wake(&self, now: DateTime<Utc>) -> QueueResult<Option<DateTime<Utc>>> {
+        let mut candidates = Vec::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM playout_queue
+             WHERE status='queued' AND (next_attempt_at IS NOT NULL OR scheduled_start IS NOT NULL)",
+        )?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let entry = QueueEntry::from_row(row)?;
+            candidates.extend(entry.next_attempt_at);
+            if let Some(schedule) = entry.schedule {
+                let tolerance = match schedule.anchor {
+                    AnchorKind::Hard => self.policy.anchor_early_tolerance,
+                    AnchorKind::Soft => self.policy.soft_anchor_early_tolerance,
+                };
+                let early = schedule.start - tolerance;
+                candidates.push(if early > now { early } else { schedule.start });
+            }
+        }
+        for injection in &self.injections {
+            if let Some(next) = injection.next_at {
+                if injection.until.map(|until| next < until).unwrap_or(true) {
+                    candidates.push(next);
+                }
+            }
+        }
+        Ok(candidates.into_iter().filter(|at| *at > now).min())
+    }
+}
+
+fn slot(
+    entry: QueueEntry,
+    starts_at: DateTime<Utc>,
+    ends_at: DateTime<Utc>,
+    source: ForecastSource,
+    estimated: bool,
+) -> ForecastSlot {
+    let simulated = matches!(
+        source,
+        ForecastSource::EmergencyLoop | ForecastSource::Injection
+    );
+    ForecastSlot {
+        starts_at,
+        ends_at,
+        queue_id: (!simulated).then_some(entry.id),
+        plan_id: entry.plan_id,
+        asset_path: entry.asset_path,
+        content_kind: entry.content_kind,
+        anchor: entry.schedule.map(|schedule| schedule.anchor),
+        source,
+        estimated,
+    }
+}
@@ -0,0 +1,1646 @@
+pub mod as_run;
+pub mod daypart;
+pub mod forecast;
+pub mod preemption;
+pub mod recovery;
+pub mod restore;
+pub mod retry;
+pub mod selection;
+pub mod separation;
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use rusqlite::backup::Backup;
+use rusqlite::types::Value;
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension, Row};
+use thiserror::Error;
+use tracing::{debug, warn};
+
+use crate::config::QueueSection;
+use crate::plan::Plan;
+use crate::sqlite::{configure_connection, ensure_columns};
+
+pub use self::as_run::{write_as_run, AsRunEntry, AsRunFormat, AsRunOutcome, AsRunRecord};
+pub use self::daypart::{ActiveDaypart, Daypart, DaypartSchedule};
+pub use self::forecast::{
+    EmergencyRefill, Forecast, ForecastOptions, ForecastSlot, ForecastSource, ScheduledInjection,
+};
+pub use self::preemption::{BreakIn, Interruption, ResumePolicy};
+pub use self::recovery::{RecoveryAction, RecoveryDecision};
+pub use self::restore::{
+    RestoreConflict, RestoreConflictKind, RestoreMode, RestoreOptions, RestoreReport,
+};
+pub use self::retry::{RetryOutcome, RetryPolicy};
+pub use self::selection::{
+    FifoWithBump, SelectionContext, SelectionStrategy, StrictPriority, WeightedRoundRobin,
+};
+pub use self::separation::{RecentAiring, SeparationRules};
+
+const QUEUE_SCHEMA: &str = include_str!("../../../sql/queue.sql");
+/// Number of recent airings handed to selection strategies.
+const RECENT_HISTORY_LIMIT: usize = 50;
+/// Columns written by `export_backup`, in table order.
+const QUEUE_COLUMNS: &[&str] = &[
+    "id",
+    "plan_id",
+    "asset_path",
+    "duration_s",
+    "status",
+    "curation_score",
+    "priority",
+    "created_at",
+    "updated_at",
+    "node_origin",
+    "play_started_at",
+    "play_finished_at",
+    "failure_reason",
+    "content_kind",
+    "scheduled_start",
+    "anchor",
+    "tags",
+    "source_domain",
+    "retry_count",
+    "next_attempt_at",
+    "queue_position",
+    "resume_offset_s",
+];
+/// Columns added after the original schema, backfilled on older databases.
+const LATER_COLUMNS: &[(&str, &str)] = &[
+    ("scheduled_start", "DATETIME"),
+    ("anchor", "TEXT"),
+    ("tags", "TEXT"),
+    ("source_domain", "TEXT"),
+    ("retry_count", "INTEGER DEFAULT 0"),
+    ("next_attempt_at", "DATETIME"),
+    ("queue_position", "INTEGER"),
+    ("resume_offset_s", "REAL"),
+];
+
+#[derive(Debug, Error)]
+pub enum QueueError {
+    #[error("failed to open queue database {path}: {source}")]
+    Open {
+        source: rusqlite::Error,
+        path: PathBuf,
+    },
+    #[error("failed to execute statement on queue database: {0}")]
+    Execute(#[from] rusqlite::Error),
+    #[error("queue path not configured")]
+    MissingStore,
+    #[error("invalid queue status: {0}")]
+    InvalidStatus(String),
+    #[error("invalid schedule anchor: {0}")]
+    InvalidAnchor(String),
+    #[error("invalid daypart configuration: {0}")]
+    InvalidDaypart(String),
+    #[error("cannot reorder queue entry {id}: {reason}")]
+    InvalidOrdering { id: i64, reason: String },
+    #[error("cannot break in with queue entry {id}: {reason}")]
+    InvalidBreakIn { id: i64, reason: String },
+    #[error("invalid resume policy: {0}")]
+    InvalidPolicy(String),
+    #[error("invalid queue backup: {0}")]
+    InvalidBackup(String),
+    #[error("invalid as-run export format: {0}")]
+    InvalidFormat(String),
+    #[error("queue record not found: {0}")]
+    NotFound(i64),
+    #[error("io error: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type QueueResult<T> = Result<T, QueueError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueueStatus {
+    Queued,
+    Playing,
+    Played,
+    Failed,
+    /// Exhausted its retries; never selected again.
+    Dead,
+}
+
+impl QueueStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueStatus::Queued => "queued",
+            QueueStatus::Playing => "playing",
+            QueueStatus::Played => "played",
+            QueueStatus::Failed => "failed",
+            QueueStatus::Dead => "dead",
+        }
+    }
+}
+
+impl std::fmt::Display for QueueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for QueueStatus {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "queued" => Ok(Self::Queued),
+            "playing" => Ok(Self::Playing),
+            "played" => Ok(Self::Played),
+            "failed" => Ok(Self::Failed),
+            "dead" => Ok(Self::Dead),
+            other => Err(QueueError::InvalidStatus(other.to_string())),
+        }
+    }
+}
+
+/// How strictly a scheduled entry must honour its start time.
+///
+/// Hard anchors never let filler content overrun them; soft anchors prefer
+/// fillers that fit but may slip behind the item on air.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnchorKind {
+    Hard,
+    Soft,
+}
+
+impl AnchorKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AnchorKind::Hard => "hard",
+            AnchorKind::Soft => "soft",
+        }
+    }
+}
+
+impl std::fmt::Display for AnchorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl std::str::FromStr for AnchorKind {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "hard" => Ok(Self::Hard),
+            "soft" => Ok(Self::Soft),
+            other => Err(QueueError::InvalidAnchor(other.to_string())),
+        }
+    }
+}
+
+/// Wall-clock slot for an entry that must air at a fixed time (EPG slot).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueueSchedule {
+    pub start: DateTime<Utc>,
+    pub anchor: AnchorKind,
+}
+
+impl QueueSchedule {
+    pub fn hard(start: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            anchor: AnchorKind::Hard,
+        }
+    }
+
+    pub fn soft(start: DateTime<Utc>) -> Self {
+        Self {
+            start,
+            anchor: AnchorKind::Soft,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueItem {
+    pub plan_id: String,
+    pub asset_path: String,
+    pub duration_s: Option<i64>,
+    pub curation_score: Option<f64>,
+    pub priority: i64,
+    pub node_origin: Option<String>,
+    pub content_kind: Option<String>,
+    pub schedule: Option<QueueSchedule>,
+    pub tags: Vec<String>,
+    pub source_domain: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueEntry {
+    pub id: i64,
+    pub plan_id: String,
+    pub asset_path: String,
+    pub duration_s: Option<i64>,
+    pub status: QueueStatus,
+    pub curation_score: Option<f64>,
+    pub priority: i64,
+    pub created_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub node_origin: Option<String>,
+    pub play_started_at: Option<DateTime<Utc>>,
+    pub play_finished_at: Option<DateTime<Utc>>,
+    pub failure_reason: Option<String>,
+    pub content_kind: Option<String>,
+    pub schedule: Option<QueueSchedule>,
+    pub tags: Vec<String>,
+    pub source_domain: Option<String>,
+    /// Failed playout attempts so far.
+    pub retry_count: u32,
+    /// Earliest time a requeued entry may be selected again.
+    pub next_attempt_at: Option<DateTime<Utc>>,
+    /// Slot in the operator-ordered lane, starting at 1.
+    pub queue_position: Option<i64>,
+    /// Seconds already aired before a break-in stopped the entry; it resumes
+    /// from there.
+    pub resume_offset_s: Option<f64>,
+}
+
+impl QueueEntry {
+    fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let scheduled_start = parse_timestamp(row.get("scheduled_start")?)?;
+        let anchor = row
+            .get::<_, Option<String>>("anchor")?
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(AnchorKind::Hard);
+        Ok(Self {
+            id: row.get("id")?,
+            plan_id: row.get("plan_id")?,
+            asset_path: row.get("asset_path")?,
+            duration_s: row.get("duration_s")?,
+            status: row
+                .get::<_, String>("status")?
+                .parse()
+                .unwrap_or(QueueStatus::Queued),
+            curation_score: row.get("curation_score")?,
+            priority: row.get::<_, Option<i64>>("priority")?.unwrap_or(0),
+            created_at: parse_timestamp(row.get("created_at")?)?,
+            updated_at: parse_timestamp(row.get("updated_at")?)?,
+            node_origin: row.get("node_origin")?,
+            play_started_at: parse_timestamp(row.get("play_started_at")?)?,
+            play_finished_at: parse_timestamp(row.get("play_finished_at")?)?,
+            failure_reason: row.get("failure_reason")?,
+            content_kind: row.get("content_kind")?,
+            schedule: scheduled_start.map(|start| QueueSchedule { start, anchor }),
+            tags: parse_tags(row.get("tags")?),
+            source_domain: row.get("source_domain")?,
+            retry_count: row.get::<_, Option<u32>>("retry_count")?.unwrap_or(0),
+            next_attempt_at: parse_timestamp(row.get("next_attempt_at")?)?,
+            queue_position: row.get("queue_position")?,
+            resume_offset_s: row.get("resume_offset_s")?,
+        })
+    }
+
+    /// Seconds left to air, net of the offset a resumed entry starts from.
+    pub fn remaining_duration_s(&self) -> Option<i64> {
+        let offset = self.resume_offset_s.unwrap_or(0.0).max(0.0) as i64;
+        self.duration_s
+            .map(|duration| duration.saturating_sub(offset).max(0))
+    }
+
+    pub fn is_music(&self) -> bool {
+        kind_is_music(self.content_kind.as_deref())
+    }
+
+    pub fn waiting_seconds(&self, now: DateTime<Utc>) -> f64 {
+        self.created_at
+            .map(|created| (now - created).num_seconds().max(0) as f64)
+            .unwrap_or_default()
+    }
+
+    /// Whether the entry finishes within `gap`. Entries without a duration
+    /// are taken to last the forecast's fallback estimate, so they stay
+    /// eligible until an anchor comes that close.
+    pub fn fits_within(&self, gap: Duration) -> bool {
+        let duration = self
+            .duration_s
+            .unwrap_or(forecast::DEFAULT_FALLBACK_DURATION_S);
+        Duration::seconds(duration) <= gap
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueSummary {
+    pub counts: HashMap<QueueStatus, i64>,
+    pub buffer_duration_hours: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueMetrics {
+    pub buffer_duration_hours: f64,
+    pub queue_length: i64,
+    pub played_last_hour: i64,
+    pub failures_last_hour: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct QueueSelectionPolicy {
+    pub strategy: Arc<dyn SelectionStrategy>,
+    pub music_every: Option<usize>,
+    pub curation_bump_threshold: f64,
+    pub curation_bump_min_age: Duration,
+    /// How early a hard anchor may air when no filler fits the remaining gap.
+    pub anchor_early_tolerance: Duration,
+    /// How early a soft anchor may air when nothing else is eligible.
+    pub soft_anchor_early_tolerance: Duration,
+    /// Time-of-day content mix; replaces `music_every` while a daypart is active.
+    pub dayparts: Option<DaypartSchedule>,
+    pub separation: SeparationRules,
+}
+
+impl QueueSelectionPolicy {
+    pub fn new(
+        music_every: Option<usize>,
+        curation_bump_threshold: f64,
+        curation_bump_min_age: Duration,
+    ) -> Self {
+        Self {
+            strategy: Arc::new(FifoWithBump),
+            music_every,
+            curation_bump_threshold,
+            curation_bump_min_age,
+            anchor_early_tolerance: Duration::seconds(120),
+            soft_anchor_early_tolerance: Duration::seconds(300),
+            dayparts: None,
+            separation: SeparationRules::default(),
+        }
+    }
+
+    pub fn with_dayparts(mut self, dayparts: DaypartSchedule) -> Self {
+        self.dayparts = Some(dayparts);
+        self
+    }
+
+    pub fn with_separation(mut self, separation: SeparationRules) -> Self {
+        self.separation = separation;
+        self
+    }
+
+    pub fn with_strategy(mut self, strategy: Arc<dyn SelectionStrategy>) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    /// Policy for `[queue]`. An invalid daypart table, which configs loaded
+    /// from disk have already rejected, leaves dayparting off.
+    pub fn from_queue_config(config: &QueueSection) -> Self {
+        let dayparts = config.dayparts.as_ref().and_then(|section| {
+            match DaypartSchedule::from_config(section) {
+                Ok(schedule) => Some(schedule),
+                Err(error) => {
+                    warn!(%error, "ignoring invalid daypart table");
+                    None
+                }
+            }
+        });
+        let music_every = if config.music_ratio > 0.0 {
+            let every = (1.0 / config.music_ratio).round() as usize;
+            Some(every.max(1))
+        } else {
+            None
+        };
+        Self {
+            strategy: selection::strategy_from_config(config),
+            music_every,
+            curation_bump_threshold: config.curation_bump_threshold as f64,
+            curation_bump_min_age: Duration::hours(24),
+            anchor_early_tolerance: Duration::seconds(config.anchor_tolerance_seconds as i64),
+            soft_anchor_early_tolerance: Duration::seconds(
+                config.soft_anchor_tolerance_seconds as i64,
+            ),
+            dayparts,
+            separation: config
+                .separation
+                .as_ref()
+                .map(SeparationRules::from_config)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct QueueFilter {
+    pub status: Option<QueueStatus>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayoutQueueStoreBuilder {
+    path: Option<PathBuf>,
+    read_only: bool,
+    create_if_missing: bool,
+}
+
+impl Default for PlayoutQueueStoreBuilder {
+    fn default() -> Self {
+        Self {
+            path: None,
+            read_only: false,
+            create_if_missing: true,
+        }
+    }
+}
+
+impl PlayoutQueueStoreBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn read_only(mut self, value: bool) -> Self {
+        self.read_only = value;
+        self
+    }
+
+    pub fn create_if_missing(mut self, value: bool) -> Self {
+        self.create_if_missing = value;
+        self
+    }
+
+    pub fn build(self) -> QueueResult<PlayoutQueueStore> {
+        let path = self.path.ok_or(QueueError::MissingStore)?;
+        let mut flags = if self.read_only {
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+        } else {
+            OpenFlags::SQLITE_OPEN_READ_WRITE
+        };
+        if !self.read_only && self.create_if_missing {
+            flags |= OpenFlags::SQLITE_OPEN_CREATE;
+        }
+        Ok(PlayoutQueueStore { path, flags })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PlayoutQueueStore {
+    path: PathBuf,
+    flags: OpenFlags,
+}
+
+impl PlayoutQueueStore {
+    pub fn builder() -> PlayoutQueueStoreBuilder {
+        PlayoutQueueStoreBuilder::new()
+    }
+
+    pub fn new(path: impl AsRef<Path>) -> QueueResult<Self> {
+        PlayoutQueueStoreBuilder::new().path(path).build()
+    }
+
+    fn open(&self) -> QueueResult<Connection> {
+        let conn = Connection::open_with_flags(&self.path, self.flags).map_err(|source| {
+            QueueError::Open {
+                source,
+                path: self.path.clone(),
+            }
+        })?;
+        configure_connection(&conn).map_err(|source| QueueError::Open {
+            source,
+            path: self.path.clone(),
+        })?;
+        Ok(conn)
+    }
+
+    pub fn initialize(&self) -> QueueResult<()> {
+        let conn = self.open()?;
+        conn.execute_batch(QUEUE_SCHEMA)?;
+        ensure_columns(&conn, "playout_queue", LATER_COLUMNS)?;
+        conn.execute_batch(as_run::AS_RUN_SCHEMA)?;
+        conn.execute_batch(preemption::BREAK_IN_SCHEMA)?;
+        Ok(())
+    }
+
+    pub fn enqueue(&self, item: &QueueItem) -> QueueResult<i64> {
+        let conn = self.open()?;
+        insert_item(&conn, item)
+    }
+
+    pub fn list(&self, filter: &QueueFilter) -> QueueResult<Vec<QueueEntry>> {
+        let conn = self.open()?;
+        let mut query = String::from("SELECT * FROM playout_queue");
+        let mut params: Vec<Value> = Vec::new();
+        if let Some(status) = filter.status {
+            query.push_str(" WHERE status = ?");
+            params.push(Value::Text(status.as_str().to_string()));
+        }
+        query.push_str(" ORDER BY priority DESC, created_at ASC");
+        if let Some(limit) = filter.limit {
+            query.push_str(" LIMIT ?");
+            params.push(Value::Integer(limit as i64));
+        }
+        let mut stmt = conn.prepare(&query)?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(
+            params.iter().map(|value| value as &dyn rusqlite::ToSql),
+        ))?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push(QueueEntry::from_row(row)?);
+        }
+        Ok(entries)
+    }
+
+    pub fn summary(&self) -> QueueResult<QueueSummary> {
+        let conn = self.open()?;
+        let mut counts = HashMap::new();
+        let mut stmt = conn.prepare(
+            "SELECT status, COUNT(*), COALESCE(SUM(duration_s), 0) FROM playout_queue GROUP BY status",
+        )?;
+        let mut total_buffer_s = 0.0;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let status: String = row.get(0)?;
+            let count: i64 = row.get(1)?;
+            let duration: f64 = row.get::<_, Option<f64>>(2)?.unwrap_or(0.0);
+            let status = status.parse().unwrap_or(QueueStatus::Queued);
+            if status == QueueStatus::Queued {
+                total_buffer_s += duration;
+            }
+            counts.insert(status, count);
+        }
+        Ok(QueueSummary {
+            counts,
+            buffer_duration_hours: total_buffer_s / 3600.0,
+        })
+    }
+
+    pub fn metrics(&self) -> QueueResult<QueueMetrics> {
+        let conn = self.open()?;
+        let mut metrics = QueueMetrics::default();
+        metrics.buffer_duration_hours = conn.query_row(
+            "SELECT COALESCE(SUM(duration_s), 0) FROM playout_queue WHERE status='queued'",
+            [],
+            |row| row.get::<_, f64>(0),
+        )? / 3600.0;
+        metrics.queue_length = conn.query_row(
+            "SELECT COUNT(*) FROM playout_queue WHERE status='queued'",
+            [],
+            |row| row.get(0),
+        )?;
+        let cutoff = (Utc::now() - Duration::hours(1)).naive_utc();
+        metrics.played_last_hour = conn
+            .query_row(
+                "SELECT COUNT(*) FROM playout_queue WHERE status='played' AND play_finished_at >= ?1",
+                [cutoff],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+        metrics.failures_last_hour = conn
+            .query_row(
+                "SELECT COUNT(*) FROM playout_queue
+                 WHERE (status IN ('failed', 'dead') OR (status='queued' AND retry_count > 0))
+                   AND play_finished_at >= ?1",
+                [cutoff],
+                |row| row.get(0),
+            )
+            .unwrap_or(0);
+        Ok(metrics)
+    }
+
+    pub fn begin_playback(&self, policy: &QueueSelectionPolicy) -> QueueResult<Option<QueueEntry>> {
+        self.begin_playback_at(policy, Utc::now())
+    }
+
+    /// Selects and marks the next entry to air as of `now`.
+    ///
+    /// Anchored entries air once their start time is reached. Until then the
+    /// gap is filled with entries that finish before the next hard anchor,
+    /// preferring those that also finish before the next soft one. When
+    /// nothing fits, a hard anchor may air up to `anchor_early_tolerance`
+    /// early and a soft one up to `soft_anchor_early_tolerance`; otherwise
+    /// the slot is left empty.
+    pub fn begin_playback_at(
+        &self,
+        policy: &QueueSelectionPolicy,
+        now: DateTime<Utc>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        let mut conn = self.open()?;
+        self.begin_playback_on(&mut conn, policy, now)
+    }
+
+    /// Selects and marks the next entry on `conn`, which may be the live
+    /// database or a snapshot used for projections.
+    fn begin_playback_on(
+        &self,
+        conn: &mut Connection,
+        policy: &QueueSelectionPolicy,
+        now: DateTime<Utc>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        let tx = conn.transaction()?;
+        let entries = self.fetch_candidates(&tx, now)?;
+        if entries.is_empty() {
+            tx.commit()?;
+            return Ok(None);
+        }
+
+        let selected = self.select_candidate(&tx, policy, now, entries)?;
+
+        if let Some(mut chosen) = selected {
+            tx.execute(
+                "UPDATE playout_queue SET status='playing', play_started_at=?2, play_finished_at=NULL, failure_reason=NULL, queue_position=NULL WHERE id=?1",
+                params![chosen.id, now.naive_utc()],
+            )?;
+            tx.commit()?;
+            chosen.status = QueueStatus::Playing;
+            chosen.play_started_at = Some(now);
+            chosen.play_finished_at = None;
+            chosen.queue_position = None;
+            return Ok(Some(chosen));
+        }
+
+        tx.commit()?;
+        Ok(None)
+    }
+
+    /// Entry the selection would pick once the entry on air, `current`,
+    /// finishes at `at`, without modifying the queue.
+    ///
+    /// Runs on an in-memory snapshot, as [`forecast`](Self::forecast) does,
+    /// where `current` is marked played so it counts for rotation and
+    /// separation just as it will once it finishes.
+    pub fn peek_next_at(
+        &self,
+        policy: &QueueSelectionPolicy,
+        current: i64,
+        at: DateTime<Utc>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        let snapshot = self.snapshot()?;
+        snapshot.execute(
+            "UPDATE playout_queue SET status='played', play_finished_at=?2 WHERE id=?1 AND status='playing'",
+            params![current, at.naive_utc()],
+        )?;
+        let entries = self.fetch_candidates(&snapshot, at)?;
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        self.select_candidate(&snapshot, policy, at, entries)
+    }
+
+    /// Entries in the operator-ordered lane, in airing order.
+    ///
+    /// Queued entries with a `queue_position` air in that order ahead of the
+    /// selection strategy; only due or hard anchors the entry would overrun
+    /// take precedence.
+    pub fn pinned_order(&self) -> QueueResult<Vec<QueueEntry>> {
+        let conn = self.open()?;
+        fetch_pinned(&conn)
+    }
+
+    /// Moves `id` to the head of the ordered lane so it airs next.
+    pub fn play_next(&self, id: i64) -> QueueResult<Vec<QueueEntry>> {
+        self.pin(id, 1)
+    }
+
+    /// Places `id` at `position` (1-based) in the ordered lane. Positions
+    /// past the end append to the lane.
+    pub fn pin(&self, id: i64, position: usize) -> QueueResult<Vec<QueueEntry>> {
+        if position == 0 {
+            return Err(QueueError::InvalidOrdering {
+                id,
+                reason: "positions start at 1".to_string(),
+            });
+        }
+        self.reorder(&[id], |lane| {
+            lane.retain(|entry| *entry != id);
+            let index = (position - 1).min(lane.len());
+            lane.insert(index, id);
+        })
+    }
+
+    /// Places `id` immediately before `target`. A target outside the ordered
+    /// lane is appended to it first, so the pair keeps its relative order.
+    pub fn move_before(&self, id: i64, target: i64) -> QueueResult<Vec<QueueEntry>> {
+        if id == target {
+            return Err(QueueError::InvalidOrdering {
+                id,
+                reason: "entry cannot be moved before itself".to_string(),
+            });
+        }
+        self.reorder(&[id, target], |lane| {
+            lane.retain(|entry| *entry != id);
+            let index = match lane.iter().position(|entry| *entry == target) {
+                Some(index) => index,
+                None => {
+                    lane.push(target);
+                    lane.len() - 1
+                }
+            };
+            lane.insert(index, id);
+        })
+    }
+
+    /// Returns `id` to strategy-driven selection.
+    pub fn unpin(&self, id: i64) -> QueueResult<Vec<QueueEntry>> {
+        self.reorder(&[id], |lane| lane.retain(|entry| *entry != id))
+    }
+
+    fn reorder(
+        &self,
+        ids: &[i64],
+        update: impl FnOnce(&mut Vec<i64>),
+    ) -> QueueResult<Vec<QueueEntry>> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        for id in ids {
+            ensure_orderable(&tx, *id)?;
+        }
+        let mut lane: Vec<i64> = fetch_pinned(&tx)?.iter().map(|entry| entry.id).collect();
+        update(&mut lane);
+        tx.execute(
+            "UPDATE playout_queue SET queue_position=NULL WHERE queue_position IS NOT NULL",
+            [],
+        )?;
+        for (index, id) in lane.iter().enumerate() {
+            tx.execute(
+                "UPDATE playout_queue SET queue_position=?2 WHERE id=?1",
+                params![id, index as i64 + 1],
+            )?;
+        }
+        let pinned = fetch_pinned(&tx)?;
+        tx.commit()?;
+        Ok(pinned)
+    }
+
+    /// Asks the broadcaster to stop the entry on air and take `id` to air
+    /// instead. The entry is held out of normal selection until it airs.
+    pub fn request_break_in(
+        &self,
+        id: i64,
+        policy: ResumePolicy,
+        reason: Option<&str>,
+    ) -> QueueResult<BreakIn> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let status: Option<String> = tx
+            .query_row(
+                "SELECT status FROM playout_queue WHERE id=?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(status) = status else {
+            return Err(QueueError::NotFound(id));
+        };
+        if status != QueueStatus::Queued.as_str() {
+            return Err(QueueError::InvalidBreakIn {
+                id,
+                reason: format!("status is {status}"),
+            });
+        }
+        if preemption::fetch_pending_for(&tx, id)?.is_some() {
+            return Err(QueueError::InvalidBreakIn {
+                id,
+                reason: "a break-in is already pending for this entry".to_string(),
+            });
+        }
+        tx.execute(
+            "INSERT INTO break_ins (queue_id, policy, reason) VALUES (?1, ?2, ?3)",
+            params![id, policy.as_str(), reason],
+        )?;
+        let break_in_id = tx.last_insert_rowid();
+        let break_in = tx.query_row(
+            "SELECT * FROM break_ins WHERE id=?1",
+            [break_in_id],
+            BreakIn::from_row,
+        )?;
+        tx.commit()?;
+        Ok(break_in)
+    }
+
+    /// Oldest break-in the broadcaster has not taken to air yet.
+    pub fn pending_break_in(&self) -> QueueResult<Option<BreakIn>> {
+        let conn = self.open()?;
+        preemption::fetch_pending(&conn)
+    }
+
+    /// Most recent break-ins, newest first.
+    pub fn break_ins(&self, limit: usize) -> QueueResult<Vec<BreakIn>> {
+        let conn = self.open()?;
+        preemption::fetch_recent(&conn, limit)
+    }
+
+    /// Withdraws a break-in that has not aired; its entry returns to normal
+    /// selection.
+    pub fn cancel_break_in(&self, break_in_id: i64) -> QueueResult<()> {
+        let conn = self.open()?;
+        let affected = conn.execute(
+            "DELETE FROM break_ins WHERE id=?1 AND claimed_at IS NULL",
+            [break_in_id],
+        )?;
+        if affected == 0 {
+            return Err(QueueError::NotFound(break_in_id));
+        }
+        Ok(())
+    }
+
+    pub fn begin_break_in(
+        &self,
+        break_in: &BreakIn,
+        interrupted: Option<Interruption>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        self.begin_break_in_at(break_in, interrupted, Utc::now())
+    }
+
+    /// Claims `break_in` and marks its entry playing. The entry it stopped, if
+    /// any, goes back to the queue: under [`ResumePolicy::Resume`] at the head
+    /// of the ordered lane with its offset kept, under
+    /// [`ResumePolicy::Requeue`] to normal selection from the start.
+    ///
+    /// Returns `None` when the break-in was withdrawn or already claimed, or
+    /// when its entry left the queue after the request; the interrupted entry
+    /// is settled either way.
+    pub fn begin_break_in_at(
+        &self,
+        break_in: &BreakIn,
+        interrupted: Option<Interruption>,
+        now: DateTime<Utc>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let claimed = tx.execute(
+            "UPDATE break_ins SET claimed_at=?2, interrupted_id=?3
+             WHERE id=?1 AND claimed_at IS NULL",
+            params![
+                break_in.id,
+                now.naive_utc(),
+                interrupted.map(|interruption| interruption.queue_id)
+            ],
+        )?;
+        if let Some(interruption) = interrupted {
+            match break_in.policy {
+                ResumePolicy::Resume => requeue_at_head(&tx, interruption)?,
+                ResumePolicy::Requeue => {
+                    tx.execute(
+                        "UPDATE playout_queue
+                         SET status='queued', play_started_at=NULL, resume_offset_s=NULL
+                         WHERE id=?1",
+                        [interruption.queue_id],
+                    )?;
+                }
+            }
+        }
+        if claimed == 0 {
+            tx.commit()?;
+            return Ok(None);
+        }
+        let entry = tx
+            .query_row(
+                "SELECT * FROM playout_queue WHERE id=?1 AND status='queued'",
+                [break_in.queue_id],
+                QueueEntry::from_row,
+            )
+            .optional()?;
+        let Some(mut entry) = entry else {
+            tx.commit()?;
+            return Ok(None);
+        };
+        tx.execute(
+            "UPDATE playout_queue SET status='playing', play_started_at=?2, play_finished_at=NULL, failure_reason=NULL, queue_position=NULL WHERE id=?1",
+            params![entry.id, now.naive_utc()],
+        )?;
+        tx.commit()?;
+        entry.status = QueueStatus::Playing;
+        entry.play_started_at = Some(now);
+        entry.play_finished_at = None;
+        entry.queue_position = None;
+        Ok(Some(entry))
+    }
+
+    /// Puts an entry stopped mid-air back at the head of the ordered lane
+    /// with its offset kept, as when a node hands the air to another.
+    pub fn suspend_playback(&self, interruption: Interruption) -> QueueResult<()> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        requeue_at_head(&tx, interruption)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    pub fn mark_priority(&self, id: i64, priority: i64) -> QueueResult<()> {
+        let conn = self.open()?;
+        let affected = conn.execute(
+            "UPDATE playout_queue SET priority=?1, updated_at=CURRENT_TIMESTAMP WHERE id=?2",
+            params![priority, id],
+        )?;
+        if affected == 0 {
+            return Err(QueueError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    pub fn remove(&self, id: i64) -> QueueResult<()> {
+        let conn = self.open()?;
+        let affected = conn.execute("DELETE FROM playout_queue WHERE id=?1", [id])?;
+        if affected == 0 {
+            return Err(QueueError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    pub fn mark_playback_result(
+        &self,
+        id: i64,
+        status: QueueStatus,
+        actual_duration: Option<i64>,
+        failure_reason: Option<&str>,
+    ) -> QueueResult<()> {
+        let conn = self.open()?;
+        let (finish_ts, failure) = match status {
+            QueueStatus::Played => (Some(Utc::now().naive_utc()), None),
+            QueueStatus::Failed | QueueStatus::Dead => (
+                Some(Utc::now().naive_utc()),
+                failure_reason.map(str::to_string),
+            ),
+            QueueStatus::Playing | QueueStatus::Queued => {
+                (None, failure_reason.map(str::to_string))
+            }
+        };
+        // A finished entry no longer resumes; one still on air keeps its offset.
+        let affected = conn.execute(
+            "UPDATE playout_queue SET status=?1, play_finished_at=?2, failure_reason=?3, duration_s=COALESCE(?4, duration_s), resume_offset_s=CASE WHEN ?2 IS NULL THEN resume_offset_s END WHERE id=?5",
+            params![status.as_str(), finish_ts, failure, actual_duration, id],
+        )?;
+        if affected == 0 {
+            return Err(QueueError::NotFound(id));
+        }
+        Ok(())
+    }
+
+    /// Records a failed playout and requeues the entry with backoff, or marks
+    /// it `dead` once `retry.max_attempts` plays have failed.
+    pub fn record_failure(
+        &self,
+        id: i64,
+        failure_reason: &str,
+        retry: &RetryPolicy,
+    ) -> QueueResult<RetryOutcome> {
+        self.record_failure_at(id, failure_reason, retry, Utc::now())
+    }
+
+    pub fn record_failure_at(
+        &self,
+        id: i64,
+        failure_reason: &str,
+        retry: &RetryPolicy,
+        now: DateTime<Utc>,
+    ) -> QueueResult<RetryOutcome> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let retry_count: Option<u32> = tx
+            .query_row(
+                "SELECT COALESCE(retry_count, 0) FROM playout_queue WHERE id=?1",
+                [id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        let Some(retry_count) = retry_count else {
+            return Err(QueueError::NotFound(id));
+        };
+        let attempts = retry_count + 1;
+        let outcome = if attempts >= retry.max_attempts {
+            tx.execute(
+                "UPDATE playout_queue
+                 SET status='dead', retry_count=?2, next_attempt_at=NULL,
+                     play_finished_at=?3, failure_reason=?4, resume_offset_s=NULL
+                 WHERE id=?1",
+                params![id, attempts, now.naive_utc(), failure_reason],
+            )?;
+            RetryOutcome::Dead { attempts }
+        } else {
+            let next_attempt_at = now + retry.delay_for(attempts);
+            tx.execute(
+                "UPDATE playout_queue
+                 SET status='queued', retry_count=?2, next_attempt_at=?3,
+                     play_finished_at=?4, failure_reason=?5, resume_offset_s=NULL
+                 WHERE id=?1",
+                params![
+                    id,
+                    attempts,
+                    next_attempt_at.naive_utc(),
+                    now.naive_utc(),
+                    failure_reason
+                ],
+            )?;
+            RetryOutcome::Requeued {
+                retry_count: attempts,
+                next_attempt_at,
+            }
+        };
+        tx.commit()?;
+        Ok(outcome)
+    }
+
+    /// Reconciles entries left `playing` by a previous process.
+    pub fn recover_orphaned(&self) -> QueueResult<Vec<RecoveryDecision>> {
+        self.recover_orphaned_at(Utc::now())
+    }
+
+    /// Marks each `playing` entry as played when the time elapsed since it
+    /// started covers its duration, and requeues it otherwise. The decision is
+    /// written to the as-run log and returned to the caller.
+    pub fn recover_orphaned_at(&self, now: DateTime<Utc>) -> QueueResult<Vec<RecoveryDecision>> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let orphans = {
+            let mut stmt =
+                tx.prepare("SELECT * FROM playout_queue WHERE status='playing' ORDER BY id")?;
+            let mut rows = stmt.query([])?;
+            let mut entries = Vec::new();
+            while let Some(row) = rows.next()? {
+                entries.push(QueueEntry::from_row(row)?);
+            }
+            entries
+        };
+        let mut decisions = Vec::with_capacity(orphans.len());
+        for entry in &orphans {
+            let decision = recovery::decide(entry, now);
+            match decision.action {
+                RecoveryAction::MarkedPlayed => {
+                    let finished = entry
+                        .play_started_at
+                        .zip(entry.remaining_duration_s())
+                        .map(|(started, duration)| started + Duration::seconds(duration))
+                        .unwrap_or(now);
+                    tx.execute(
+                        "UPDATE playout_queue SET status='played', play_finished_at=?2, resume_offset_s=NULL WHERE id=?1",
+                        params![entry.id, finished.naive_utc()],
+                    )?;
+                    let started = entry.play_started_at.unwrap_or(now);
+                    let mut record = AsRunRecord::from_entry(entry, AsRunOutcome::Played, started);
+                    record.finished_at = Some(finished);
+                    record.note = Some(decision.note.clone());
+                    as_run::insert(&tx, &record)?;
+                }
+                RecoveryAction::Requeued => {
+                    tx.execute(
+                        "UPDATE playout_queue
+                         SET status='queued', play_started_at=NULL, play_finished_at=NULL
+                         WHERE id=?1",
+                        params![entry.id],
+                    )?;
+                    // Without a recorded start the interruption is logged at
+                    // recovery time.
+                    let started = entry.play_started_at.unwrap_or(now);
+                    let mut record =
+                        AsRunRecord::from_entry(entry, AsRunOutcome::Interrupted, started);
+                    record.note = Some(decision.note.clone());
+                    as_run::insert(&tx, &record)?;
+                }
+            }
+            decisions.push(decision);
+        }
+        tx.commit()?;
+        Ok(decisions)
+    }
+
+    pub fn cleanup_played(&self, older_than: Duration) -> QueueResult<usize> {
+        let conn = self.open()?;
+        let cutoff = (Utc::now() - older_than).naive_utc();
+        let affected = conn.execute(
+            "DELETE FROM playout_queue WHERE status='played' AND play_finished_at IS NOT NULL AND play_finished_at < ?1",
+            [cutoff],
+        )?;
+        Ok(affected as usize)
+    }
+
+    /// Appends an airing to the as-run log.
+    pub fn record_as_run(&self, record: &AsRunRecord) -> QueueResult<i64> {
+        let conn = self.open()?;
+        as_run::insert(&conn, record)
+    }
+
+    /// As-run rows whose airing started in `[start, end)`, oldest first.
+    pub fn as_run_between(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> QueueResult<Vec<AsRunEntry>> {
+        let conn = self.open()?;
+        let mut stmt = conn.prepare(
+            "SELECT * FROM as_run_log WHERE started_at >= ?1 AND started_at < ?2
+             ORDER BY started_at, id",
+        )?;
+        let mut rows = stmt.query(params![start.naive_utc(), end.naive_utc()])?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push(AsRunEntry::from_row(row)?);
+        }
+        Ok(entries)
+    }
+
+    /// Writes the as-run rows for `[start, end)` to `output` and returns how
+    /// many were exported.
+    pub fn export_as_run(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        format: AsRunFormat,
+        output: impl AsRef<Path>,
+    ) -> QueueResult<usize> {
+        let output = output.as_ref();
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let entries = self.as_run_between(start, end)?;
+        let mut file = io::BufWriter::new(File::create(output)?);
+        write_as_run(&entries, format, &mut file)?;
+        file.flush()?;
+        Ok(entries.len())
+    }
+
+    pub fn export_backup(&self, output: impl AsRef<Path>) -> QueueResult<()> {
+        let output = output.as_ref();
+        if let Some(parent) = output.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = self.open()?;
+        let mut dump = String::new();
+        dump.push_str(QUEUE_SCHEMA);
+        dump.push('\n');
+        dump.push_str("BEGIN;\n");
+
+        let columns = QUEUE_COLUMNS.join(", ");
+        let mut stmt = conn.prepare(&format!("SELECT {columns} FROM playout_queue ORDER BY id"))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let mut values = Vec::with_capacity(QUEUE_COLUMNS.len());
+            for index in 0..QUEUE_COLUMNS.len() {
+                values.push(format_sql_value(row.get::<_, Value>(index)?));
+            }
+            dump.push_str(&format!(
+                "INSERT INTO playout_queue ({columns}) VALUES ({});\n",
+                values.join(", ")
+            ));
+        }
+
+        dump.push_str("COMMIT;\n");
+
+        let file = File::create(output)?;
+        let mut encoder = GzEncoder::new(file, Compression::default());
+        encoder.write_all(dump.as_bytes())?;
+        encoder.finish()?;
+        Ok(())
+    }
+
+    /// Projects the airings between `options.start` and the end of
+    /// `options.horizon` without modifying the queue.
+    ///
+    /// Selection runs on an in-memory copy of the database with a simulated
+    /// clock, so the result follows the same policy as `begin_playback`.
+    pub fn forecast(
+        &self,
+        policy: &QueueSelectionPolicy,
+        options: &ForecastOptions,
+    ) -> QueueResult<Forecast> {
+        let mut snapshot = self.snapshot()?;
+        forecast::simulate(self, &mut snapshot, policy, options)
+    }
+
+    fn snapshot(&self) -> QueueResult<Connection> {
+        let source = self.open()?;
+        let mut snapshot = Connection::open_in_memory()?;
+        Backup::new(&source, &mut snapshot)?.run_to_completion(
+            64,
+            StdDuration::from_millis(0),
+            None,
+        )?;
+        // Read-only stores may point at a database predating newer columns.
+        ensure_columns(&snapshot, "playout_queue", LATER_COLUMNS)?;
+        snapshot.execute_batch(preemption::BREAK_IN_SCHEMA)?;
+        Ok(snapshot)
+    }
+
+    pub fn backup_to(&self, destination: impl AsRef<Path>) -> QueueResult<()> {
+        let destination_path = destination.as_ref();
+        let source = self.open()?;
+        let mut dest = Connection::open(destination_path)?;
+        configure_connection(&dest).map_err(|source| QueueError::Open {
+            source,
+            path: destination_path.to_path_buf(),
+        })?;
+        let backup = Backup::new(&source, &mut dest)?;
+        backup.run_to_completion(10, StdDuration::from_millis(50), None)?;
+        Ok(())
+    }
+
+    /// Restores entries from a gzip archive written by [`export_backup`].
+    ///
+    /// [`export_backup`]: Self::export_backup
+    pub fn import_backup(
+        &self,
+        input: impl AsRef<Path>,
+        options: &RestoreOptions,
+    ) -> QueueResult<RestoreReport> {
+        let staging = restore::stage_export(input.as_ref())?;
+        self.restore_staged(&staging, options)
+    }
+
+    /// Restores entries from a SQLite copy written by [`backup_to`].
+    ///
+    /// [`backup_to`]: Self::backup_to
+    pub fn restore_from(
+        &self,
+        source: impl AsRef<Path>,
+        options: &RestoreOptions,
+    ) -> QueueResult<RestoreReport> {
+        let staging = restore::stage_database(source.as_ref())?;
+        self.restore_staged(&staging, options)
+    }
+
+    fn restore_staged(
+        &self,
+        staging: &Connection,
+        options: &RestoreOptions,
+    ) -> QueueResult<RestoreReport> {
+        restore::validate_staging(staging)?;
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let report = restore::apply(&tx, staging, options)?;
+        if options.dry_run {
+            tx.rollback()?;
+        } else {
+            tx.commit()?;
+        }
+        Ok(report)
+    }
+
+    fn select_candidate(
+        &self,
+        conn: &Connection,
+        policy: &QueueSelectionPolicy,
+        now: DateTime<Utc>,
+        entries: Vec<QueueEntry>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        let (mut anchored, free): (Vec<QueueEntry>, Vec<QueueEntry>) = entries
+            .into_iter()
+            .partition(|entry| entry.schedule.is_some());
+        anchored.sort_by(compare_anchored);
+
+        let Some(next_anchor) = anchored.first().and_then(|entry| entry.schedule) else {
+            let mut free = free;
+            if let Some(pinned) = take_pinned_head(&mut free) {
+                return Ok(Some(pinned));
+            }
+            return self.select_with_strategy(conn, policy, now, free);
+        };
+        if next_anchor.start <= now {
+            return Ok(Some(anchored.remove(0)));
+        }
+
+        let next_hard = anchored
+            .iter()
+            .position(|entry| matches!(entry.schedule, Some(s) if s.anchor == AnchorKind::Hard));
+        let hard_gap = next_hard
+            .and_then(|index| anchored[index].schedule)
+            .map(|schedule| schedule.start - now);
+        let soft_gap = next_anchor.start - now;
+
+        let mut eligible: Vec<QueueEntry> = free
+            .into_iter()
+            .filter(|entry| hard_gap.map(|gap| entry.fits_within(gap)).unwrap_or(true))
+            .collect();
+        if let Some(pinned) = take_pinned_head(&mut eligible) {
+            return Ok(Some(pinned));
+        }
+        let (preferred, overrunning): (Vec<QueueEntry>, Vec<QueueEntry>) = eligible
+            .into_iter()
+            .partition(|entry| entry.fits_within(soft_gap));
+        if !preferred.is_empty() {
+            return self.select_with_strategy(conn, policy, now, preferred);
+        }
+        if !overrunning.is_empty() {
+            return self.select_with_strategy(conn, policy, now, overrunning);
+        }
+
+        if let (Some(index), Some(gap)) = (next_hard, hard_gap) {
+            if gap <= policy.anchor_early_tolerance {
+                return Ok(Some(anchored.remove(index)));
+            }
+        }
+        if next_anchor.anchor == AnchorKind::Soft && soft_gap <= policy.soft_anchor_early_tolerance
+        {
+            return Ok(Some(anchored.remove(0)));
+        }
+        debug!(
+            queue_id = anchored[0].id,
+            gap_s = soft_gap.num_seconds(),
+            "no filler fits before the next anchor"
+        );
+        Ok(None)
+    }
+
+    fn select_with_strategy(
+        &self,
+        conn: &Connection,
+        policy: &QueueSelectionPolicy,
+        now: DateTime<Utc>,
+        entries: Vec<QueueEntry>,
+    ) -> QueueResult<Option<QueueEntry>> {
+        if entries.is_empty() {
+            return Ok(None);
+        }
+        let entries = if policy.separation.is_enabled() {
+            let recent = self.fetch_recent_airings(conn, now - policy.separation.lookback())?;
+            separation::apply_separation(&policy.separation, entries, &recent, now)
+        } else {
+            entries
+        };
+        let active = policy
+            .dayparts
+            .as_ref()
+            .and_then(|schedule| schedule.active(now));
+        let Some(active) = active else {
+            let recent_kinds = self.fetch_recent_kinds(conn, RECENT_HISTORY_LIMIT)?;
+            let context = SelectionContext {
+                now,
+                policy,
+                recent_kinds: &recent_kinds,
+            };
+            return Ok(policy.strategy.select(entries, &context));
+        };
+
+        // The daypart decides which kind airs; the strategy orders within it.
+        let aired = self.fetch_kinds_since(conn, active.started_at)?;
+        let entries = filter_to_daypart_kind(entries, active.daypart, &aired);
+        let recent_kinds = self.fetch_recent_kinds(conn, RECENT_HISTORY_LIMIT)?;
+        let daypart_policy = QueueSelectionPolicy {
+            music_every: None,
+            ..policy.clone()
+        };
+        let context = SelectionContext {
+            now,
+            policy: &daypart_policy,
+            recent_kinds: &recent_kinds,
+        };
+        Ok(policy.strategy.select(entries, &context))
+    }
+
+    /// Counts airings per normalised `content_kind` finished at or after `since`.
+    pub fn kind_counts_since(&self, since: DateTime<Utc>) -> QueueResult<HashMap<String, i64>> {
+        let conn = self.open()?;
+        let mut counts = HashMap::new();
+        for kind in self.fetch_kinds_since(&conn, since)? {
+            *counts
+                .entry(selection::kind_key(kind.as_deref()))
+                .or_insert(0) += 1;
+        }
+        Ok(counts)
+    }
+
+    fn fetch_candidates(
+        &self,
+        conn: &Connection,
+        now: DateTime<Utc>,
+    ) -> QueueResult<Vec<QueueEntry>> {
+        // Entries waiting to break in air through `begin_break_in` only.
+        let mut stmt = conn.prepare(
+            "SELECT * FROM playout_queue
+             WHERE status='queued' AND (next_attempt_at IS NULL OR next_attempt_at <= ?1)
+               AND id NOT IN (SELECT queue_id FROM break_ins WHERE claimed_at IS NULL)",
+        )?;
+        let mut rows = stmt.query([now.naive_utc()])?;
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push(QueueEntry::from_row(row)?);
+        }
+        Ok(entries)
+    }
+
+    /// Entries played or on air since `since`, for separation checks.
+    pub fn recent_airings(&self, since: DateTime<Utc>) -> QueueResult<Vec<RecentAiring>> {
+        let conn = self.open()?;
+        self.fetch_recent_airings(&conn, since)
+    }
+
+    fn fetch_recent_airings(
+        &self,
+        conn: &Connection,
+        since: DateTime<Utc>,
+    ) -> QueueResult<Vec<RecentAiring>> {
+        let mut stmt = conn.prepare(
+            "SELECT plan_id, tags, source_domain, COALESCE(play_finished_at, play_started_at) AS aired_at
+             FROM playout_queue
+             WHERE status IN ('played', 'playing')
+               AND COALESCE(play_finished_at, play_started_at) >= ?1",
+        )?;
+        let mut rows = stmt.query([since.naive_utc()])?;
+        let mut airings = Vec::new();
+        while let Some(row) = rows.next()? {
+            let Some(aired_at) = parse_timestamp(row.get("aired_at")?)? else {
+                continue;
+            };
+            airings.push(RecentAiring {
+                plan_id: row.get("plan_id")?,
+                tags: parse_tags(row.get("tags")?),
+                source_domain: row.get("source_domain")?,
+                aired_at,
+            });
+        }
+        Ok(airings)
+    }
+
+    fn fetch_kinds_since(
+        &self,
+        conn: &Connection,
+        since: DateTime<Utc>,
+    ) -> QueueResult<Vec<Option<String>>> {
+        let mut stmt = conn.prepare(
+            "SELECT content_kind FROM playout_queue WHERE status='played' AND play_finished_at >= ?1 ORDER BY play_finished_at DESC",
+        )?;
+        let kinds = stmt
+            .query_map([since.naive_utc()], |row| row.get::<_, Option<String>>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(kinds)
+    }
+
+    fn fetch_recent_kinds(
+        &self,
+        conn: &Connection,
+        limit: usize,
+    ) -> QueueResult<Vec<Option<String>>> {
+        let mut stmt = conn.prepare(
+            "SELECT content_kind FROM playout_queue WHERE status='played' ORDER BY play_finished_at DESC LIMIT ?1",
+        )?;
+        let kinds = stmt
+            .query_map([limit as i64], |row| row.get::<_, Option<String>>(0))?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(kinds)
+    }
+}
+
+/// Moves the ordered lane down one place and puts the interrupted entry at
+/// its head, to resume from where it stopped.
+fn requeue_at_head(conn: &Connection, interruption: Interruption) -> QueueResult<()> {
+    conn.execute(
+        "UPDATE playout_queue SET queue_position=queue_position + 1
+         WHERE queue_position IS NOT NULL",
+        [],
+    )?;
+    conn.execute(
+        "UPDATE playout_queue
+         SET status='queued', queue_position=1, resume_offset_s=?2
+         WHERE id=?1",
+        params![interruption.queue_id, interruption.offset_s],
+    )?;
+    Ok(())
+}
+
+/// Keeps only candidates of the kind lagging furthest behind the daypart
+/// targets. Kinds without a target air only when no targeted kind is queued.
+fn filter_to_daypart_kind(
+    entries: Vec<QueueEntry>,
+    daypart: &Daypart,
+    aired: &[Option<String>],
+) -> Vec<QueueEntry> {
+    let mut available: Vec<String> = Vec::new();
+    for entry in &entries {
+        let kind = selection::kind_key(entry.content_kind.as_deref());
+        if daypart.ratios.contains_key(&kind) && !available.contains(&kind) {
+            available.push(kind);
+        }
+    }
+    let Some(kind) = selection::most_lagging_kind(&available, &daypart.ratios, aired) else {
+        return entries;
+    };
+    entries
+        .into_iter()
+        .filter(|entry| selection::kind_key(entry.content_kind.as_deref()) == kind)
+        .collect()
+}
+
+fn compare_anchored(a: &QueueEntry, b: &QueueEntry) -> Ordering {
+    match (a.schedule, b.schedule) {
+        (Some(a_slot), Some(b_slot)) => a_slot.start.cmp(&b_slot.start).then_with(|| {
+            // Hard anchors win ties against soft ones.
+            (a_slot.anchor == AnchorKind::Soft).cmp(&(b_slot.anchor == AnchorKind::Soft))
+        }),
+        _ => Ordering::Equal,
+    }
+    .then_with(|| a.id.cmp(&b.id))
+}
+
+pub(crate) fn kind_is_music(kind: Option<&str>) -> bool {
+    kind.map(|value| value.eq_ignore_ascii_case("music") || value.to_lowercase().contains("music"))
+        .unwrap_or(false)
+}
+
+fn sql_quote(value: &str) -> String {
+    let escaped = value.replace('\'', "''");
+    format!("'{}'", escaped)
+}
+
+fn format_sql_value(value: Value) -> String {
+    match value {
+        Value::Null => "NULL".to_string(),
+        Value::Integer(v) => v.to_string(),
+        Value::Real(v) => v.to_string(),
+        Value::Text(v) => sql_quote(&v),
+        Value::Blob(v) => format!("X'{}'", hex::encode(v)),
+    }
+}
+
+fn insert_item(conn: &Connection, item: &QueueItem) -> QueueResult<i64> {
+    conn.execute(
+        "INSERT INTO playout_queue (
+            plan_id, asset_path, duration_s, status, curation_score, priority,
+            node_origin, content_kind, scheduled_start, anchor, tags, source_domain
+        ) VALUES (?1, ?2, ?3, 'queued', ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+        params![
+            &item.plan_id,
+            &item.asset_path,
+            &item.duration_s,
+            &item.curation_score,
+            item.priority,
+            &item.node_origin,
+            &item.content_kind,
+            item.schedule.map(|schedule| schedule.start.naive_utc()),
+            item.schedule.map(|schedule| schedule.anchor.as_str()),
+            Plan::serialize_tags(&item.tags),
+            &item.source_domain,
+        ],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn fetch_pinned(conn: &Connection) -> QueueResult<Vec<QueueEntry>> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM playout_queue
+         WHERE status='queued' AND queue_position IS NOT NULL
+         ORDER BY queue_position, id",
+    )?;
+    let mut rows = stmt.query([])?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next()? {
+        entries.push(QueueEntry::from_row(row)?);
+    }
+    Ok(entries)
+}
+
+/// Only queued, unanchored entries can be ordered by hand; anchored entries
+/// already have a fixed air time.
+fn ensure_orderable(conn: &Connection, id: i64) -> QueueResult<()> {
+    let row: Option<(String, Option<String>)> = conn
+        .query_row(
+            "SELECT status, scheduled_start FROM playout_queue WHERE id=?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let Some((status, scheduled_start)) = row else {
+        return Err(QueueError::NotFound(id));
+    };
+    if status != QueueStatus::Queued.as_str() {
+        return Err(QueueError::InvalidOrdering {
+            id,
+            reason: format!("status is {status}"),
+        });
+    }
+    if scheduled_start.is_some() {
+        return Err(QueueError::InvalidOrdering {
+            id,
+            reason: "entry is anchored to a start time".to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Removes and returns the lowest-positioned entry of the ordered lane.
+fn take_pinned_head(entries: &mut Vec<QueueEntry>) -> Option<QueueEntry> {
+    let index = entries
+        .iter()
+        .enumerate()
+        .filter_map(|(index, entry)| entry.queue_position.map(|position| (position, index)))
+        .min()?
+        .1;
+    Some(entries.remove(index))
+}
+
+/// Tags are stored comma-separated, matching the `plans` table.
+fn parse_tags(value: Option<String>) -> Vec<String> {
+    value
+        .map(|value| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|tag| !tag.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn parse_timestamp(value: Option<NaiveDateTime>) -> Result<Option<DateTime<Utc>>, rusqlite::Error> {
+    Ok(value.map(|dt| DateTime::<Utc>::from_naive_utc_and_offset(dt, Utc)))
+}
@@ -0,0 +1,128 @@
+//! Break-ins: urgent entries that take the air from the entry playing.
+//!
+//! A break-in is requested against a queued entry and stays pending in
+//! `break_ins` until the broadcaster claims it; meanwhile the entry is held
+//! out of normal selection. The broadcaster airing another entry stops it at
+//! a clean point, airs the urgent entry next and then resumes or requeues the
+//! interrupted entry according to the break-in's [`ResumePolicy`].
+
+use std::fmt;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::{parse_timestamp, QueueError, QueueResult};
+
+pub(crate) const BREAK_IN_SCHEMA: &str = include_str!("../../../sql/break_ins.sql");
+
+/// What happens to the entry a break-in interrupts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResumePolicy {
+    /// Airs right after the break-in, from where it was stopped.
+    Resume,
+    /// Goes back to the queue and later airs from the start.
+    Requeue,
+}
+
+impl ResumePolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResumePolicy::Resume => "resume",
+            ResumePolicy::Requeue => "requeue",
+        }
+    }
+}
+
+impl fmt::Display for ResumePolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for ResumePolicy {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "resume" => Ok(Self::Resume),
+            "requeue" => Ok(Self::Requeue),
+            other => Err(QueueError::InvalidPolicy(other.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BreakIn {
+    pub id: i64,
+    /// Urgent entry to air.
+    pub queue_id: i64,
+    pub policy: ResumePolicy,
+    pub reason: Option<String>,
+    pub requested_at: DateTime<Utc>,
+    /// When the broadcaster took the break-in to air.
+    pub claimed_at: Option<DateTime<Utc>>,
+    /// Entry that was on air and had to make room.
+    pub interrupted_id: Option<i64>,
+}
+
+impl BreakIn {
+    pub(crate) fn from_row(row: &Row<'_>) -> rusqlite::Result<Self> {
+        let policy: String = row.get("policy")?;
+        Ok(Self {
+            id: row.get("id")?,
+            queue_id: row.get("queue_id")?,
+            policy: policy.parse().map_err(|_| {
+                rusqlite::Error::InvalidColumnType(0, "policy".into(), rusqlite::types::Type::Text)
+            })?,
+            reason: row.get("reason")?,
+            requested_at: DateTime::from_naive_utc_and_offset(
+                row.get::<_, NaiveDateTime>("requested_at")?,
+                Utc,
+            ),
+            claimed_at: parse_timestamp(row.get("claimed_at")?)?,
+            interrupted_id: row.get("interrupted_id")?,
+        })
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.claimed_at.is_none()
+    }
+}
+
+/// Entry stopped to make room for a break-in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Interruption {
+    pub queue_id: i64,
+    /// Position in the entry's media, in seconds, where it was stopped.
+    pub offset_s: f64,
+}
+
+pub(crate) fn fetch_pending(conn: &Connection) -> QueueResult<Option<BreakIn>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM break_ins WHERE claimed_at IS NULL ORDER BY id LIMIT 1",
+            [],
+            BreakIn::from_row,
+        )
+        .optional()?)
+}
+
+pub(crate) fn fetch_pending_for(conn: &Connection, queue_id: i64) -> QueueResult<Option<BreakIn>> {
+    Ok(conn
+        .query_row(
+            "SELECT * FROM break_ins WHERE claimed_at IS NULL AND queue_id=?1",
+            [queue_id],
+            BreakIn::from_row,
+        )
+        .optional()?)
+}
+
+pub(crate) fn fetch_recent(conn: &Connection, limit: usize) -> QueueResult<Vec<BreakIn>> {
+    let mut stmt = conn.prepare("SELECT * FROM break_ins ORDER BY id DESC LIMIT ?1")?;
+    let break_ins = stmt
+        .query_map([limit as i64], BreakIn::from_row)?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    Ok(break_ins)
+}
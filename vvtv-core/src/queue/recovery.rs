@@ -0,0 +1,83 @@
+//! Reconciliation of entries left `playing` by a process that died mid-item.
+//!
+//! An orphan whose elapsed time covers its duration is assumed to have aired
+//! in full and is marked `played`; anything else goes back to `queued` so it
+//! airs again. Interruptions do not count against the entry's retry budget.
+
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use super::QueueEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecoveryAction {
+    MarkedPlayed,
+    Requeued,
+}
+
+impl RecoveryAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RecoveryAction::MarkedPlayed => "marked_played",
+            RecoveryAction::Requeued => "requeued",
+        }
+    }
+}
+
+impl fmt::Display for RecoveryAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Outcome recorded for one orphaned `playing` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecoveryDecision {
+    pub id: i64,
+    pub plan_id: String,
+    pub action: RecoveryAction,
+    pub play_started_at: Option<DateTime<Utc>>,
+    /// Wall-clock time since playback began, when it is known.
+    pub elapsed: Option<Duration>,
+    pub note: String,
+}
+
+pub(crate) fn decide(entry: &QueueEntry, now: DateTime<Utc>) -> RecoveryDecision {
+    let elapsed = entry.play_started_at.map(|started| now - started);
+    let (action, note) = match (elapsed, entry.remaining_duration_s()) {
+        (Some(elapsed), Some(duration)) if elapsed >= Duration::seconds(duration) => (
+            RecoveryAction::MarkedPlayed,
+            format!(
+                "recovered at startup: {}s elapsed covers {duration}s duration",
+                elapsed.num_seconds()
+            ),
+        ),
+        (Some(elapsed), Some(duration)) => (
+            RecoveryAction::Requeued,
+            format!(
+                "recovered at startup: interrupted after {}s of {duration}s",
+                elapsed.num_seconds()
+            ),
+        ),
+        (Some(elapsed), None) => (
+            RecoveryAction::Requeued,
+            format!(
+                "recovered at startup: interrupted after {}s, duration unknown",
+                elapsed.num_seconds()
+            ),
+        ),
+        (None, _) => (
+            RecoveryAction::Requeued,
+            "recovered at startup: playback start not recorded".to_string(),
+        ),
+    };
+    RecoveryDecision {
+        id: entry.id,
+        plan_id: entry.plan_id.clone(),
+        action,
+        play_started_at: entry.play_started_at,
+        elapsed,
+        note,
+    }
+}
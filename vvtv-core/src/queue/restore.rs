@@ -0,0 +1,515 @@
+//! Restoring the queue from `export_backup` archives and `backup_to` copies.
+//!
+//! Archives are first loaded into an in-memory staging database with the
+//! current schema, which validates them without touching the live queue. Rows
+//! are then merged into, or replace, the live table in a single transaction.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OpenFlags, Transaction};
+
+use crate::sqlite::ensure_columns;
+
+use super::{
+    fetch_pinned, AnchorKind, QueueError, QueueResult, QueueStatus, QUEUE_COLUMNS, QUEUE_SCHEMA,
+};
+
+const REQUIRED_COLUMNS: &[&str] = &["id", "plan_id", "asset_path"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RestoreMode {
+    /// Keep the current queue and add archived entries that are missing.
+    /// Archived entries in the ordered lane follow the live lane.
+    #[default]
+    Merge,
+    /// Discard the current queue, except the entry on air, and load the
+    /// archive as-is.
+    Replace,
+}
+
+impl RestoreMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreMode::Merge => "merge",
+            RestoreMode::Replace => "replace",
+        }
+    }
+}
+
+impl fmt::Display for RestoreMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for RestoreMode {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "merge" => Ok(Self::Merge),
+            "replace" => Ok(Self::Replace),
+            other => Err(QueueError::InvalidBackup(format!(
+                "unknown restore mode {other}"
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RestoreOptions {
+    pub mode: RestoreMode,
+    /// Validate and report without changing the live queue.
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RestoreConflictKind {
+    /// The live queue already holds this entry; it was skipped.
+    SameEntry,
+    /// The plan is already queued or playing under another id; skipped.
+    DuplicatePlan,
+    /// The archived id belongs to another entry; restored under a new id.
+    IdReassigned,
+    /// A live queued plan that replace mode dropped.
+    Discarded,
+    /// A live entry on air that replace mode kept; archived rows for the
+    /// same plan are skipped.
+    KeptPlaying,
+}
+
+impl RestoreConflictKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RestoreConflictKind::SameEntry => "same_entry",
+            RestoreConflictKind::DuplicatePlan => "duplicate_plan",
+            RestoreConflictKind::IdReassigned => "id_reassigned",
+            RestoreConflictKind::Discarded => "discarded",
+            RestoreConflictKind::KeptPlaying => "kept_playing",
+        }
+    }
+}
+
+impl fmt::Display for RestoreConflictKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestoreConflict {
+    pub kind: RestoreConflictKind,
+    pub plan_id: String,
+    /// Id of the entry in the archive, when the conflict concerns one.
+    pub archived_id: Option<i64>,
+    /// Id of the live entry involved in the conflict.
+    pub existing_id: Option<i64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RestoreReport {
+    pub mode: RestoreMode,
+    pub dry_run: bool,
+    /// Entries found in the archive.
+    pub archived: usize,
+    pub restored: usize,
+    pub skipped: usize,
+    /// Live entries deleted by replace mode; entries on air are kept.
+    pub removed: usize,
+    pub conflicts: Vec<RestoreConflict>,
+}
+
+/// Loads a gzip SQL export into a staging database. Only `INSERT INTO
+/// playout_queue` statements are replayed; the rest of the dump is ignored.
+pub(crate) fn stage_export(path: &Path) -> QueueResult<Connection> {
+    let mut dump = String::new();
+    GzDecoder::new(std::fs::File::open(path)?)
+        .read_to_string(&mut dump)
+        .map_err(|err| QueueError::InvalidBackup(format!("unreadable export: {err}")))?;
+    if !dump.contains("CREATE TABLE IF NOT EXISTS playout_queue") {
+        return Err(QueueError::InvalidBackup(
+            "export does not contain a playout_queue table".to_string(),
+        ));
+    }
+    let staging = staging_connection()?;
+    for (index, statement) in split_statements(&dump).into_iter().enumerate() {
+        let statement = statement.trim_start();
+        let is_queue_insert = statement
+            .get(..25)
+            .map(|head| head.eq_ignore_ascii_case("INSERT INTO playout_queue"))
+            .unwrap_or(false);
+        if !is_queue_insert {
+            continue;
+        }
+        staging
+            .execute(statement, [])
+            .map_err(|err| QueueError::InvalidBackup(format!("statement {}: {err}", index + 1)))?;
+    }
+    Ok(staging)
+}
+
+/// Copies the queue table of a SQLite backup into a staging database.
+pub(crate) fn stage_database(path: &Path) -> QueueResult<Connection> {
+    let source =
+        Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(|source| {
+            QueueError::Open {
+                source,
+                path: path.to_path_buf(),
+            }
+        })?;
+    let mut columns = Vec::new();
+    {
+        let mut stmt = source
+            .prepare("PRAGMA table_info(playout_queue)")
+            .map_err(|err| QueueError::InvalidBackup(format!("not a queue database: {err}")))?;
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(1)?;
+            if QUEUE_COLUMNS.contains(&name.as_str()) {
+                columns.push(name);
+            }
+        }
+    }
+    if let Some(missing) = REQUIRED_COLUMNS
+        .iter()
+        .find(|required| !columns.iter().any(|column| column == *required))
+    {
+        return Err(QueueError::InvalidBackup(format!(
+            "playout_queue is missing column {missing}"
+        )));
+    }
+
+    let staging = staging_connection()?;
+    let list = columns.join(", ");
+    let placeholders = vec!["?"; columns.len()].join(", ");
+    let insert = format!("INSERT INTO playout_queue ({list}) VALUES ({placeholders})");
+    let mut select = source.prepare(&format!("SELECT {list} FROM playout_queue ORDER BY id"))?;
+    let mut rows = select.query([])?;
+    while let Some(row) = rows.next()? {
+        let values = (0..columns.len())
+            .map(|index| row.get::<_, Value>(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        staging
+            .execute(&insert, params_from_iter(values))
+            .map_err(|err| QueueError::InvalidBackup(format!("invalid row: {err}")))?;
+    }
+    Ok(staging)
+}
+
+/// Checks staged values the schema cannot enforce and parks entries that were
+/// on air when the archive was taken back in the queue.
+pub(crate) fn validate_staging(staging: &Connection) -> QueueResult<()> {
+    let mut stmt = staging.prepare("SELECT DISTINCT status FROM playout_queue")?;
+    let statuses = stmt
+        .query_map([], |row| row.get::<_, Option<String>>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for status in statuses.into_iter().flatten() {
+        status
+            .parse::<QueueStatus>()
+            .map_err(|_| QueueError::InvalidBackup(format!("unknown status {status}")))?;
+    }
+    let mut stmt =
+        staging.prepare("SELECT DISTINCT anchor FROM playout_queue WHERE anchor IS NOT NULL")?;
+    let anchors = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    for anchor in anchors {
+        anchor
+            .parse::<AnchorKind>()
+            .map_err(|_| QueueError::InvalidBackup(format!("unknown anchor {anchor}")))?;
+    }
+    staging.execute(
+        "UPDATE playout_queue SET status='queued', play_started_at=NULL WHERE status='playing'",
+        [],
+    )?;
+    Ok(())
+}
+
+pub(crate) fn apply(
+    tx: &Transaction<'_>,
+    staging: &Connection,
+    options: &RestoreOptions,
+) -> QueueResult<RestoreReport> {
+    let archived = staged_rows(staging)?;
+    let mut report = RestoreReport {
+        mode: options.mode,
+        dry_run: options.dry_run,
+        archived: archived.len(),
+        restored: 0,
+        skipped: 0,
+        removed: 0,
+        conflicts: Vec::new(),
+    };
+    let live = live_rows(tx)?;
+
+    match options.mode {
+        RestoreMode::Replace => {
+            let archived_plans: HashSet<&str> =
+                archived.iter().map(|row| row.plan_id.as_str()).collect();
+            // The entry on air stays so its as-run record can be finished.
+            let playing: HashMap<i64, &LiveRow> = live
+                .values()
+                .filter(|row| row.playing)
+                .map(|row| (row.id, row))
+                .collect();
+            for row in live.values() {
+                let kind = if row.playing {
+                    RestoreConflictKind::KeptPlaying
+                } else if row.active && !archived_plans.contains(row.plan_id.as_str()) {
+                    RestoreConflictKind::Discarded
+                } else {
+                    continue;
+                };
+                report.conflicts.push(RestoreConflict {
+                    kind,
+                    plan_id: row.plan_id.clone(),
+                    archived_id: None,
+                    existing_id: Some(row.id),
+                });
+            }
+            report
+                .conflicts
+                .sort_by_key(|conflict| conflict.existing_id);
+            report.removed = tx.execute(
+                "DELETE FROM playout_queue WHERE status IS NOT 'playing'",
+                [],
+            )?;
+            // Restored rows may take the ids of removed ones, so pending
+            // break-ins for removed entries are withdrawn with them.
+            tx.execute(
+                "DELETE FROM break_ins
+                 WHERE claimed_at IS NULL AND queue_id NOT IN (SELECT id FROM playout_queue)",
+                [],
+            )?;
+            let mut reassigned = Vec::new();
+            for row in &archived {
+                if let Some(on_air) = playing
+                    .values()
+                    .find(|on_air| row.active && on_air.plan_id == row.plan_id)
+                {
+                    report.conflicts.push(RestoreConflict {
+                        kind: RestoreConflictKind::DuplicatePlan,
+                        plan_id: row.plan_id.clone(),
+                        archived_id: Some(row.id),
+                        existing_id: Some(on_air.id),
+                    });
+                    report.skipped += 1;
+                    continue;
+                }
+                if let Some(on_air) = playing.get(&row.id) {
+                    report.conflicts.push(RestoreConflict {
+                        kind: RestoreConflictKind::IdReassigned,
+                        plan_id: row.plan_id.clone(),
+                        archived_id: Some(row.id),
+                        existing_id: Some(on_air.id),
+                    });
+                    reassigned.push(row);
+                } else {
+                    insert_row(tx, &row.values, true)?;
+                }
+                report.restored += 1;
+            }
+            for row in reassigned {
+                insert_row(tx, &row.values, false)?;
+            }
+        }
+        RestoreMode::Merge => {
+            // Archived lane positions go after the live lane, keeping their
+            // order, and the lane is renumbered once they are in.
+            let lane_end: i64 = tx.query_row(
+                "SELECT COALESCE(MAX(queue_position), 0) FROM playout_queue",
+                [],
+                |row| row.get(0),
+            )?;
+            let mut active_plans: HashMap<String, i64> = live
+                .values()
+                .filter(|row| row.active)
+                .map(|row| (row.plan_id.clone(), row.id))
+                .collect();
+            let mut reassigned = Vec::new();
+            for row in &archived {
+                let existing = live.get(&row.id);
+                let conflict = |kind, existing_id| RestoreConflict {
+                    kind,
+                    plan_id: row.plan_id.clone(),
+                    archived_id: Some(row.id),
+                    existing_id,
+                };
+                if let Some(existing) = existing.filter(|existing| {
+                    existing.plan_id == row.plan_id && existing.asset_path == row.asset_path
+                }) {
+                    report
+                        .conflicts
+                        .push(conflict(RestoreConflictKind::SameEntry, Some(existing.id)));
+                    report.skipped += 1;
+                    continue;
+                }
+                if row.active {
+                    if let Some(&existing_id) = active_plans.get(&row.plan_id) {
+                        report.conflicts.push(conflict(
+                            RestoreConflictKind::DuplicatePlan,
+                            Some(existing_id),
+                        ));
+                        report.skipped += 1;
+                        continue;
+                    }
+                }
+                if let Some(existing) = existing {
+                    report.conflicts.push(conflict(
+                        RestoreConflictKind::IdReassigned,
+                        Some(existing.id),
+                    ));
+                    reassigned.push(row);
+                } else {
+                    insert_row(tx, &after_lane(&row.values, lane_end), true)?;
+                }
+                if row.active {
+                    active_plans.insert(row.plan_id.clone(), row.id);
+                }
+                report.restored += 1;
+            }
+            // New ids are allocated once every archived id is in place, so
+            // they cannot collide with rows restored later.
+            for row in reassigned {
+                insert_row(tx, &after_lane(&row.values, lane_end), false)?;
+            }
+            renumber_lane(tx)?;
+        }
+    }
+    Ok(report)
+}
+
+/// `values` with its lane position moved past `lane_end`.
+fn after_lane(values: &[Value], lane_end: i64) -> Vec<Value> {
+    let mut values = values.to_vec();
+    let index = QUEUE_COLUMNS
+        .iter()
+        .position(|column| *column == "queue_position")
+        .expect("queue_position is a queue column");
+    if let Value::Integer(position) = values[index] {
+        values[index] = Value::Integer(lane_end + position);
+    }
+    values
+}
+
+/// Numbers the ordered lane from 1 in its current order.
+fn renumber_lane(tx: &Transaction<'_>) -> QueueResult<()> {
+    let lane = fetch_pinned(tx)?;
+    tx.execute(
+        "UPDATE playout_queue SET queue_position=NULL WHERE queue_position IS NOT NULL",
+        [],
+    )?;
+    for (index, entry) in lane.iter().enumerate() {
+        tx.execute(
+            "UPDATE playout_queue SET queue_position=?2 WHERE id=?1",
+            params![entry.id, index as i64 + 1],
+        )?;
+    }
+    Ok(())
+}
+
+struct StagedRow {
+    id: i64,
+    plan_id: String,
+    asset_path: String,
+    active: bool,
+    values: Vec<Value>,
+}
+
+struct LiveRow {
+    id: i64,
+    plan_id: String,
+    asset_path: String,
+    active: bool,
+    playing: bool,
+}
+
+fn staging_connection() -> QueueResult<Connection> {
+    let staging = Connection::open_in_memory()?;
+    staging.execute_batch(QUEUE_SCHEMA)?;
+    ensure_columns(&staging, "playout_queue", super::LATER_COLUMNS)?;
+    Ok(staging)
+}
+
+fn staged_rows(staging: &Connection) -> QueueResult<Vec<StagedRow>> {
+    let columns = QUEUE_COLUMNS.join(", ");
+    let mut stmt = staging.prepare(&format!("SELECT {columns} FROM playout_queue ORDER BY id"))?;
+    let mut rows = stmt.query([])?;
+    let mut staged = Vec::new();
+    while let Some(row) = rows.next()? {
+        let values = (0..QUEUE_COLUMNS.len())
+            .map(|index| row.get::<_, Value>(index))
+            .collect::<Result<Vec<_>, _>>()?;
+        let status: Option<String> = row.get("status")?;
+        staged.push(StagedRow {
+            id: row.get("id")?,
+            plan_id: row.get("plan_id")?,
+            asset_path: row.get("asset_path")?,
+            active: is_active(status.as_deref()),
+            values,
+        });
+    }
+    Ok(staged)
+}
+
+fn live_rows(conn: &Connection) -> QueueResult<HashMap<i64, LiveRow>> {
+    let mut stmt = conn.prepare("SELECT id, plan_id, asset_path, status FROM playout_queue")?;
+    let mut rows = stmt.query([])?;
+    let mut live = HashMap::new();
+    while let Some(row) = rows.next()? {
+        let id: i64 = row.get(0)?;
+        let status: Option<String> = row.get(3)?;
+        live.insert(
+            id,
+            LiveRow {
+                id,
+                plan_id: row.get(1)?,
+                asset_path: row.get(2)?,
+                active: is_active(status.as_deref()),
+                playing: status.as_deref() == Some("playing"),
+            },
+        );
+    }
+    Ok(live)
+}
+
+fn insert_row(tx: &Transaction<'_>, values: &[Value], keep_id: bool) -> QueueResult<()> {
+    let skip = usize::from(!keep_id);
+    let columns = QUEUE_COLUMNS[skip..].join(", ");
+    let placeholders = vec!["?"; QUEUE_COLUMNS.len() - skip].join(", ");
+    tx.execute(
+        &format!("INSERT INTO playout_queue ({columns}) VALUES ({placeholders})"),
+        params_from_iter(values[skip..].iter()),
+    )?;
+    Ok(())
+}
+
+fn is_active(status: Option<&str>) -> bool {
+    matches!(status, None | Some("queued") | Some("playing"))
+}
+
+/// Splits a SQL dump on `;` outside of quoted literals.
+fn split_statements(dump: &str) -> Vec<&str> {
+    let mut statements = Vec::new();
+    let mut in_quote = false;
+    let mut start = 0;
+    for (index, ch) in dump.char_indices() {
+        match ch {
+            '\'' => in_quote = !in_quote,
+            ';' if !in_quote => {
+                statements.push(&dump[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if !dump[start..].trim().is_empty() {
+        statements.push(&dump[start..]);
+    }
+    statements
+}
@@ -0,0 +1,56 @@
+//! Retry policy for entries whose playout failed.
+//!
+//! A failed entry goes back to `queued` with `next_attempt_at` pushed out by
+//! an exponential backoff. Once `max_attempts` plays have failed the entry is
+//! marked `dead` and no longer considered for selection.
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::PlayoutRetrySection;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Total playout attempts, including the first, before an entry is dead.
+    pub max_attempts: u32,
+    /// Delay before the first retry; doubles on every further failure.
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::from_config(&PlayoutRetrySection::default())
+    }
+}
+
+impl RetryPolicy {
+    pub fn from_config(section: &PlayoutRetrySection) -> Self {
+        Self {
+            max_attempts: section.max_attempts.max(1),
+            base_delay: Duration::seconds(section.backoff_seconds as i64),
+            max_delay: Duration::seconds(section.max_backoff_seconds as i64),
+        }
+    }
+
+    /// Backoff applied after the `failures`-th failed attempt.
+    pub fn delay_for(&self, failures: u32) -> Duration {
+        let exponent = failures.saturating_sub(1).min(30);
+        let delay = self
+            .base_delay
+            .checked_mul(1i32 << exponent)
+            .unwrap_or(self.max_delay);
+        delay.min(self.max_delay).max(Duration::zero())
+    }
+}
+
+/// What happened to an entry after a failed playout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetryOutcome {
+    Requeued {
+        retry_count: u32,
+        next_attempt_at: DateTime<Utc>,
+    },
+    Dead {
+        attempts: u32,
+    },
+}
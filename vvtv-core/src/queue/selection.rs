@@ -0,0 +1,279 @@
+//! Selection strategies used by [`PlayoutQueueStore::begin_playback`].
+//!
+//! The store resolves anchors and fetches candidates; a strategy only decides
+//! which of the remaining candidates airs next. Strategies are pure so they can
+//! be reused for read-only projections of the queue.
+//!
+//! [`PlayoutQueueStore::begin_playback`]: super::PlayoutQueueStore::begin_playback
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use tracing::warn;
+
+use crate::config::QueueSection;
+
+use super::{QueueEntry, QueueSelectionPolicy};
+
+pub const FIFO_WITH_BUMP: &str = "fifo_with_bump";
+pub const WEIGHTED_ROUND_ROBIN: &str = "weighted_round_robin";
+pub const STRICT_PRIORITY: &str = "strict_priority";
+
+/// State available to a strategy when choosing the next entry.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectionContext<'a> {
+    pub now: DateTime<Utc>,
+    pub policy: &'a QueueSelectionPolicy,
+    /// Content kinds of recently aired entries, most recent first.
+    pub recent_kinds: &'a [Option<String>],
+}
+
+pub trait SelectionStrategy: fmt::Debug + Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn select(
+        &self,
+        candidates: Vec<QueueEntry>,
+        context: &SelectionContext<'_>,
+    ) -> Option<QueueEntry>;
+}
+
+/// Whether `policy` names one of the selection strategies.
+pub fn is_known_policy(policy: &str) -> bool {
+    [FIFO_WITH_BUMP, WEIGHTED_ROUND_ROBIN, STRICT_PRIORITY].contains(&policy)
+}
+
+/// Builds the strategy named by `[queue].policy`, falling back to
+/// `fifo_with_bump` for unknown names. Configs loaded from disk have
+/// already been checked against [`is_known_policy`].
+pub fn strategy_from_config(config: &QueueSection) -> Arc<dyn SelectionStrategy> {
+    match config.policy.as_str() {
+        FIFO_WITH_BUMP => Arc::new(FifoWithBump),
+        WEIGHTED_ROUND_ROBIN => Arc::new(WeightedRoundRobin::new(config.weights.clone())),
+        STRICT_PRIORITY => Arc::new(StrictPriority),
+        other => {
+            warn!(policy = other, "unknown queue policy, using fifo_with_bump");
+            Arc::new(FifoWithBump)
+        }
+    }
+}
+
+/// Priority first, then curation bump and weighted waiting time, with a music
+/// slot every `music_every` entries.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FifoWithBump;
+
+impl SelectionStrategy for FifoWithBump {
+    fn name(&self) -> &'static str {
+        FIFO_WITH_BUMP
+    }
+
+    fn select(
+        &self,
+        candidates: Vec<QueueEntry>,
+        context: &SelectionContext<'_>,
+    ) -> Option<QueueEntry> {
+        let mut ordered = rank_by_waiting(candidates, context);
+        if prefer_music(context) {
+            if let Some(pos) = ordered.iter().position(QueueEntry::is_music) {
+                return Some(ordered.remove(pos));
+            }
+        }
+        ordered.into_iter().next()
+    }
+}
+
+/// Interleaves content kinds according to configured weights. The kind whose
+/// share of recent airings lags its target the most goes next; within a kind
+/// entries follow the `fifo_with_bump` order.
+#[derive(Debug, Clone, Default)]
+pub struct WeightedRoundRobin {
+    weights: HashMap<String, f64>,
+}
+
+impl WeightedRoundRobin {
+    pub fn new(weights: HashMap<String, f64>) -> Self {
+        let weights = weights
+            .into_iter()
+            .map(|(kind, weight)| (kind.to_lowercase(), weight.max(0.0)))
+            .collect();
+        Self { weights }
+    }
+
+    fn weight(&self, kind: &str) -> f64 {
+        self.weights.get(kind).copied().unwrap_or(1.0)
+    }
+}
+
+impl SelectionStrategy for WeightedRoundRobin {
+    fn name(&self) -> &'static str {
+        WEIGHTED_ROUND_ROBIN
+    }
+
+    fn select(
+        &self,
+        candidates: Vec<QueueEntry>,
+        context: &SelectionContext<'_>,
+    ) -> Option<QueueEntry> {
+        let ordered = rank_by_waiting(candidates, context);
+        let mut available: Vec<String> = Vec::new();
+        for entry in &ordered {
+            let kind = kind_key(entry.content_kind.as_deref());
+            if !available.contains(&kind) {
+                available.push(kind);
+            }
+        }
+        let total_weight: f64 = available.iter().map(|kind| self.weight(kind)).sum();
+        let kind = if total_weight > 0.0 {
+            let targets: HashMap<String, f64> = available
+                .iter()
+                .map(|kind| (kind.clone(), self.weight(kind) / total_weight))
+                .collect();
+            most_lagging_kind(&available, &targets, context.recent_kinds)
+        } else {
+            available.first().cloned()
+        }?;
+        ordered
+            .into_iter()
+            .find(|entry| kind_key(entry.content_kind.as_deref()) == kind)
+    }
+}
+
+/// Highest priority wins; ties air strictly in arrival order.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StrictPriority;
+
+impl SelectionStrategy for StrictPriority {
+    fn name(&self) -> &'static str {
+        STRICT_PRIORITY
+    }
+
+    fn select(
+        &self,
+        candidates: Vec<QueueEntry>,
+        _context: &SelectionContext<'_>,
+    ) -> Option<QueueEntry> {
+        candidates.into_iter().min_by(|a, b| {
+            a.priority
+                .cmp(&b.priority)
+                .reverse()
+                .then_with(|| compare_created(a, b))
+                .then_with(|| a.id.cmp(&b.id))
+        })
+    }
+}
+
+/// Normalised key used to group entries by `content_kind`.
+pub fn kind_key(kind: Option<&str>) -> String {
+    kind.map(|value| value.trim().to_lowercase())
+        .filter(|value| !value.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Picks the kind with the largest gap between its target share and its share
+/// of `recent_kinds`. Ties go to the larger target, then to `available` order.
+pub(crate) fn most_lagging_kind(
+    available: &[String],
+    targets: &HashMap<String, f64>,
+    recent_kinds: &[Option<String>],
+) -> Option<String> {
+    let window = recent_kinds.len().max(1) as f64;
+    let mut best: Option<(&String, f64, f64)> = None;
+    for kind in available {
+        let target = targets.get(kind).copied().unwrap_or(0.0);
+        let aired = recent_kinds
+            .iter()
+            .filter(|recent| kind_key(recent.as_deref()) == *kind)
+            .count() as f64;
+        let deficit = target - aired / window;
+        let better = match best {
+            None => true,
+            Some((_, best_deficit, best_target)) => {
+                deficit > best_deficit + f64::EPSILON
+                    || ((deficit - best_deficit).abs() <= f64::EPSILON && target > best_target)
+            }
+        };
+        if better {
+            best = Some((kind, deficit, target));
+        }
+    }
+    best.map(|(kind, _, _)| kind.clone())
+}
+
+fn rank_by_waiting(candidates: Vec<QueueEntry>, context: &SelectionContext<'_>) -> Vec<QueueEntry> {
+    let policy = context.policy;
+    let bump_cutoff = context.now - policy.curation_bump_min_age;
+    let mut weighted: Vec<WeightedEntry> = candidates
+        .into_iter()
+        .map(|entry| {
+            let bump = entry
+                .curation_score
+                .map(|score| score >= policy.curation_bump_threshold)
+                .unwrap_or(false)
+                && entry
+                    .created_at
+                    .map(|created| created <= bump_cutoff)
+                    .unwrap_or(false);
+            let waiting_score =
+                entry.waiting_seconds(context.now) * (1.0 + entry.curation_score.unwrap_or(0.0));
+            WeightedEntry {
+                entry,
+                bump,
+                waiting_score,
+            }
+        })
+        .collect();
+    weighted.sort_by(compare_weighted);
+    weighted
+        .into_iter()
+        .map(|weighted| weighted.entry)
+        .collect()
+}
+
+fn prefer_music(context: &SelectionContext<'_>) -> bool {
+    let window = match context.policy.music_every {
+        Some(value) if value > 0 => value,
+        _ => return false,
+    };
+    if window <= 1 {
+        return true;
+    }
+    let considered = &context.recent_kinds[..context.recent_kinds.len().min(window - 1)];
+    if considered.len() < window - 1 {
+        return false;
+    }
+    !considered
+        .iter()
+        .any(|kind| super::kind_is_music(kind.as_deref()))
+}
+
+struct WeightedEntry {
+    entry: QueueEntry,
+    bump: bool,
+    waiting_score: f64,
+}
+
+fn compare_weighted(a: &WeightedEntry, b: &WeightedEntry) -> Ordering {
+    a.entry
+        .priority
+        .cmp(&b.entry.priority)
+        .reverse()
+        .then_with(|| a.bump.cmp(&b.bump).reverse())
+        .then_with(|| {
+            a.waiting_score
+                .partial_cmp(&b.waiting_score)
+                .unwrap_or(Ordering::Equal)
+                .reverse()
+        })
+        .then_with(|| compare_created(&a.entry, &b.entry))
+}
+
+fn compare_created(a: &QueueEntry, b: &QueueEntry) -> Ordering {
+    match (a.created_at, b.created_at) {
+        (Some(a_ts), Some(b_ts)) => a_ts.cmp(&b_ts),
+        _ => Ordering::Equal,
+    }
+}
@@ -0,0 +1,120 @@
+//! Air-time separation between related entries.
+//!
+//! Keeps items that share a tag, a source domain or a `plan_id` apart by a
+//! minimum interval. When every candidate would violate the rules, they are
+//! relaxed one at a time (tags, then domain, then plan) so the queue never
+//! starves because of separation alone.
+
+use chrono::{DateTime, Duration, Utc};
+use tracing::debug;
+
+use crate::config::SeparationSection;
+
+use super::QueueEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SeparationRules {
+    pub tag: Duration,
+    pub source_domain: Duration,
+    pub plan: Duration,
+}
+
+impl SeparationRules {
+    pub fn from_config(section: &SeparationSection) -> Self {
+        Self {
+            tag: Duration::minutes(section.tag_minutes as i64),
+            source_domain: Duration::minutes(section.domain_minutes as i64),
+            plan: Duration::minutes(section.plan_minutes as i64),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.lookback() > Duration::zero()
+    }
+
+    /// Longest interval any rule looks back over.
+    pub fn lookback(&self) -> Duration {
+        self.tag.max(self.source_domain).max(self.plan)
+    }
+
+    /// Rules in decreasing strictness, dropping the weakest rule each step.
+    fn relaxations(&self) -> [SeparationRules; 4] {
+        let without_tag = SeparationRules {
+            tag: Duration::zero(),
+            ..*self
+        };
+        let plan_only = SeparationRules {
+            source_domain: Duration::zero(),
+            ..without_tag
+        };
+        [*self, without_tag, plan_only, SeparationRules::default()]
+    }
+
+    fn violated_by(&self, entry: &QueueEntry, airing: &RecentAiring, now: DateTime<Utc>) -> bool {
+        let elapsed = now - airing.aired_at;
+        if elapsed < self.plan && entry.plan_id == airing.plan_id {
+            return true;
+        }
+        if elapsed < self.source_domain {
+            if let (Some(domain), Some(previous)) = (&entry.source_domain, &airing.source_domain) {
+                if domain.eq_ignore_ascii_case(previous) {
+                    return true;
+                }
+            }
+        }
+        elapsed < self.tag
+            && entry.tags.iter().any(|tag| {
+                airing
+                    .tags
+                    .iter()
+                    .any(|previous| previous.eq_ignore_ascii_case(tag))
+            })
+    }
+}
+
+/// Entry that aired (or is airing) recently, used to enforce separation.
+#[derive(Debug, Clone)]
+pub struct RecentAiring {
+    pub plan_id: String,
+    pub tags: Vec<String>,
+    pub source_domain: Option<String>,
+    pub aired_at: DateTime<Utc>,
+}
+
+/// Drops candidates that break the separation rules, relaxing them when no
+/// candidate would remain.
+pub(crate) fn apply_separation(
+    rules: &SeparationRules,
+    entries: Vec<QueueEntry>,
+    recent: &[RecentAiring],
+    now: DateTime<Utc>,
+) -> Vec<QueueEntry> {
+    if !rules.is_enabled() || recent.is_empty() {
+        return entries;
+    }
+    for (level, relaxed) in rules.relaxations().iter().enumerate() {
+        let allowed: Vec<&QueueEntry> = entries
+            .iter()
+            .filter(|entry| {
+                !recent
+                    .iter()
+                    .any(|airing| relaxed.violated_by(entry, airing, now))
+            })
+            .collect();
+        if allowed.is_empty() {
+            continue;
+        }
+        if level > 0 {
+            debug!(
+                level,
+                "separation rules relaxed to avoid starving the queue"
+            );
+        }
+        let allowed_ids: Vec<i64> = allowed.iter().map(|entry| entry.id).collect();
+        return entries
+            .into_iter()
+            .filter(|entry| allowed_ids.contains(&entry.id))
+            .collect();
+    }
+    entries
+}
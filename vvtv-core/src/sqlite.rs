@@ -10,3 +10,25 @@ pub fn configure_connection(conn: &Connection) -> rusqlite::Result<()> {
          PRAGMA busy_timeout = 5000;\n",
     )
 }
+
+/// Adds columns introduced after a table was first created. `CREATE TABLE IF
+/// NOT EXISTS` leaves older databases untouched, so new columns are appended
+/// here when missing.
+pub fn ensure_columns(
+    conn: &Connection,
+    table: &str,
+    columns: &[(&str, &str)],
+) -> rusqlite::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let existing = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    for (name, definition) in columns {
+        if !existing.iter().any(|column| column == name) {
+            conn.execute_batch(&format!(
+                "ALTER TABLE {table} ADD COLUMN {name} {definition};"
+            ))?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,1922 @@
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{ExitStatus, Output, Stdio};
+use std::sync::{Arc, Mutex};
+
+use chrono::{Duration, Utc};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+use tokio::process::{Child, Command};
+use vvtv_core::config::{load_broadcaster_config, load_overlay_config};
+use vvtv_core::config::{BroadcasterConfig, LeadershipSection, SimulcastDestinationSection};
+use vvtv_core::plan::{Plan, SqlitePlanStore};
+use vvtv_core::{
+    collect_emergency_assets, hls_destination, Broadcaster, BroadcasterError, BroadcasterPaths,
+    CommandExecutor, ConfigError, MetricsStore, OutputFormat, OverlayEntry, OverlayLayer,
+    PlayoutQueueStore, ProgressLine, ProgressParser, QueueFilter, QueueItem, QueueStatus,
+    ResumePolicy, TransitionKind, TransitionLibrary, Watchdog, EMERGENCY_SLATE_ORIGIN,
+};
+use vvtv_core::{
+    parse_detections, Detection, DetectionKind, FiringState, IncidentChange, IncidentHistoryWriter,
+    IncidentNotifier, IncidentSeverity, QueueMetrics, SeverityRouting, WatchdogAction,
+    WatchdogError, WatchdogIncidents, WatchdogInputs, WatchdogReport, WatchdogRules,
+};
+use vvtv_core::{
+    FailoverManager, Handover, LeaderElection, LeadershipError, LeadershipState, Lease, LeaseStore,
+    NodeHeartbeat, SqliteLeaseStore,
+};
+
+/// Origin URL in the shipped `broadcaster.toml`.
+const ORIGIN: &str = "rtmp://localhost/live/main";
+
+/// Stands in for ffmpeg: each destination's output appends its stdin to a
+/// file (`published` for the origin) and each feeder writes the name of its
+/// input after `airtime`, preceded by `padding` zero bytes, reporting
+/// `progress` on stderr. Outputs to URLs containing "broken" cannot start,
+/// those containing "stalled" never read their input and those containing
+/// "dies" exit as soon as they start.
+/// One-off runs such as transition renders succeed after `render_delay`;
+/// lavfi renders such as the emergency slate also write their output file,
+/// and ffprobe reports every asset as `probed` seconds long. Captures of the
+/// output report the next of `detections` on stderr.
+struct FakeFfmpeg {
+    published: PathBuf,
+    spawned: Mutex<Vec<Vec<String>>>,
+    ran: Mutex<Vec<Vec<String>>>,
+    airtime: f64,
+    padding: usize,
+    progress: String,
+    render_delay: std::time::Duration,
+    probed: f64,
+    detections: Mutex<Vec<String>>,
+}
+
+impl FakeFfmpeg {
+    fn new(published: PathBuf) -> Self {
+        Self {
+            published,
+            spawned: Mutex::new(Vec::new()),
+            ran: Mutex::new(Vec::new()),
+            airtime: 0.0,
+            padding: 0,
+            progress: String::new(),
+            render_delay: std::time::Duration::ZERO,
+            probed: 60.0,
+            detections: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn output_file(&self, url: &str) -> PathBuf {
+        if url == ORIGIN {
+            return self.published.clone();
+        }
+        let name: String = url
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        self.published.with_file_name(format!("{name}.ts"))
+    }
+}
+
+#[async_trait::async_trait]
+impl CommandExecutor for FakeFfmpeg {
+    async fn run(&self, command: &mut Command) -> std::io::Result<Output> {
+        let args: Vec<String> = command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        if args.iter().any(|arg| arg == "lavfi") {
+            std::fs::write(args.last().unwrap(), b"slate")?;
+        }
+        let probe = command.as_std().get_program() == "ffprobe";
+        self.ran.lock().unwrap().push(args);
+        if probe {
+            return Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: format!("{}\n", self.probed).into_bytes(),
+                stderr: Vec::new(),
+            });
+        }
+        let capture = self.ran.lock().unwrap().last().unwrap().join(" ");
+        if capture.contains("blackdetect") {
+            let mut detections = self.detections.lock().unwrap();
+            let stderr = if detections.is_empty() {
+                String::new()
+            } else {
+                detections.remove(0)
+            };
+            return Ok(Output {
+                status: ExitStatus::from_raw(0),
+                stdout: Vec::new(),
+                stderr: stderr.into_bytes(),
+            });
+        }
+        tokio::time::sleep(self.render_delay).await;
+        Ok(Output {
+            status: ExitStatus::from_raw(0),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        })
+    }
+
+    async fn spawn(&self, command: &mut Command) -> std::io::Result<Child> {
+        let args: Vec<String> = command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string())
+            .collect();
+        self.spawned.lock().unwrap().push(args.clone());
+        let mut fake = Command::new("sh");
+        if args.iter().any(|arg| arg == "pipe:0") {
+            let url = args.last().cloned().unwrap_or_default();
+            if url.contains("broken") {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    "connection refused",
+                ));
+            }
+            let script = if url.contains("stalled") {
+                "exec sleep 30".to_string()
+            } else if url.contains("dies") {
+                "exec true".to_string()
+            } else {
+                format!("cat >> '{}'", self.output_file(&url).display())
+            };
+            fake.arg("-c").arg(script).stdin(Stdio::piped());
+        } else {
+            let input = args
+                .iter()
+                .skip_while(|arg| *arg != "-i")
+                .nth(1)
+                .cloned()
+                .unwrap_or_default();
+            fake.arg("-c")
+                .arg(format!(
+                    "printf '%s' '{}' >&2; head -c {} /dev/zero; sleep {}; printf '%s;' '{input}'",
+                    self.progress, self.padding, self.airtime
+                ))
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped());
+        }
+        fake.spawn()
+    }
+}
+
+fn config_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../configs/broadcaster.toml")
+}
+
+/// Loads the shipped broadcaster config with `from` replaced by `to`.
+fn load_patched_config(from: &str, to: &str) -> Result<BroadcasterConfig, ConfigError> {
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("broadcaster.toml");
+    let shipped = std::fs::read_to_string(config_path()).unwrap();
+    assert!(shipped.contains(from), "{from} not in the shipped config");
+    std::fs::write(&path, shipped.replace(from, to)).unwrap();
+    load_broadcaster_config(&path)
+}
+
+#[test]
+fn invalid_sections_are_rejected_when_the_config_loads() {
+    let error = load_patched_config(
+        "policy = \"fifo_with_bump\"",
+        "policy = \"fifo_with_bumps\"",
+    )
+    .unwrap_err();
+    assert!(matches!(
+        &error,
+        ConfigError::Invalid { reason, .. } if reason.contains("fifo_with_bumps")
+    ));
+    let error = load_patched_config(
+        "timezone = \"America/Sao_Paulo\"",
+        "timezone = \"America/Sao_Pablo\"",
+    )
+    .unwrap_err();
+    assert!(matches!(
+        &error,
+        ConfigError::Invalid { reason, .. } if reason.contains("[queue.dayparts]")
+    ));
+    let error = load_patched_config(
+        "default_policy = \"resume\"",
+        "default_policy = \"resumes\"",
+    )
+    .unwrap_err();
+    assert!(matches!(
+        &error,
+        ConfigError::Invalid { reason, .. } if reason.contains("[queue.break_in]")
+    ));
+    let error = load_patched_config("style = \"card\"", "style = \"cards\"").unwrap_err();
+    assert!(matches!(
+        &error,
+        ConfigError::Invalid { reason, .. } if reason.contains("[failover.slate]")
+    ));
+}
+
+fn overlay_config_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../configs/overlays.toml")
+}
+
+fn queue_store(dir: &Path) -> PlayoutQueueStore {
+    let store = PlayoutQueueStore::builder()
+        .path(dir.join("queue.sqlite"))
+        .create_if_missing(true)
+        .build()
+        .unwrap();
+    store.initialize().unwrap();
+    store
+}
+
+fn broadcaster(dir: &Path, store: &PlayoutQueueStore, executor: Arc<FakeFfmpeg>) -> Broadcaster {
+    let config = load_broadcaster_config(config_path()).unwrap();
+    broadcaster_with(config, dir, store, executor)
+}
+
+fn broadcaster_with(
+    config: BroadcasterConfig,
+    dir: &Path,
+    store: &PlayoutQueueStore,
+    executor: Arc<FakeFfmpeg>,
+) -> Broadcaster {
+    let mut config = config;
+    // Fixture assets are placeholder paths; pre-flight has its own tests.
+    config.preflight.enabled = false;
+    verified_broadcaster(config, dir, store, executor)
+}
+
+fn verified_broadcaster(
+    config: BroadcasterConfig,
+    dir: &Path,
+    store: &PlayoutQueueStore,
+    executor: Arc<FakeFfmpeg>,
+) -> Broadcaster {
+    Broadcaster::new(
+        store.clone(),
+        config,
+        BroadcasterPaths {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            archive_dir: dir.join("archive"),
+            temp_dir: dir.join("tmp"),
+        },
+        Some(executor),
+    )
+    .unwrap()
+}
+
+fn enqueue_videos(store: &PlayoutQueueStore, plans: &[&str], duration_s: i64) {
+    enqueue_kind(store, plans, duration_s, "video");
+}
+
+fn enqueue_kind(store: &PlayoutQueueStore, plans: &[&str], duration_s: i64, kind: &str) {
+    for plan in plans {
+        store
+            .enqueue(&QueueItem {
+                plan_id: plan.to_string(),
+                asset_path: format!("/tmp/{plan}.mp4"),
+                duration_s: Some(duration_s),
+                content_kind: Some(kind.into()),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+}
+
+fn aired_transitions(store: &PlayoutQueueStore) -> Vec<String> {
+    let now = Utc::now();
+    store
+        .as_run_between(now - Duration::hours(1), now + Duration::hours(1))
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.transition.unwrap_or_default())
+        .collect()
+}
+
+#[tokio::test]
+async fn persistent_session_keeps_one_publish_across_entries() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    // Too short to crossfade, so every entry cuts.
+    enqueue_videos(&store, &["a", "b"], 1);
+    let config = load_broadcaster_config(config_path()).unwrap();
+    assert!(config.rtmp.persistent_session);
+    // Configs that leave the key out get the single session too.
+    let unset = load_patched_config("persistent_session = true", "").unwrap();
+    assert!(unset.rtmp.persistent_session);
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    let first = broadcaster.run_once().await.unwrap().unwrap();
+    let second = broadcaster.run_once().await.unwrap().unwrap();
+    assert_eq!(first.plan_id, "a");
+    assert_eq!(second.status, QueueStatus::Played);
+    broadcaster.shutdown().await.unwrap();
+
+    let session = broadcaster.session().unwrap();
+    assert_eq!(session.sessions_started().await, 1);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("published.ts")).unwrap(),
+        "/tmp/a.mp4;/tmp/b.mp4;"
+    );
+    let spawned = executor.spawned.lock().unwrap();
+    assert_eq!(spawned.len(), 3);
+    assert!(spawned[0].iter().any(|arg| arg == ORIGIN));
+    // Feeders only write MPEG-TS to stdout; the session owns the publish.
+    for feeder in &spawned[1..] {
+        assert_eq!(feeder.last().map(String::as_str), Some("pipe:1"));
+        assert!(!feeder.iter().any(|arg| arg.starts_with("rtmp://")));
+    }
+
+    assert_eq!(aired_transitions(&store), vec!["cut", "cut"]);
+}
+
+#[tokio::test]
+async fn transitions_are_prepared_while_the_previous_entry_airs() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b", "c"], 30);
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.airtime = 0.2;
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    for _ in 0..3 {
+        broadcaster.run_once().await.unwrap().unwrap();
+    }
+    broadcaster.shutdown().await.unwrap();
+
+    assert_eq!(aired_transitions(&store), vec!["cut", "fade", "fade"]);
+    // Only the short clips a→b and b→c are rendered; the next entry itself
+    // is spliced from the original asset rather than re-encoded.
+    let ran = executor.ran.lock().unwrap();
+    assert_eq!(ran.len(), 2);
+    assert!(ran
+        .iter()
+        .all(|args| args.iter().any(|arg| arg == "-filter_complex")));
+    assert!(!ran.iter().any(|args| args.iter().any(|arg| arg == "-ss")));
+    let published = std::fs::read_to_string(dir.path().join("published.ts")).unwrap();
+    let playlists: Vec<&str> = published
+        .split(';')
+        .filter(|input| input.ends_with(".txt"))
+        .collect();
+    assert_eq!(playlists.len(), 2);
+    // Played playlists and clips are removed after airing.
+    assert!(!Path::new(playlists[0]).exists());
+}
+
+#[tokio::test]
+async fn late_transition_falls_back_to_a_cut() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 30);
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.render_delay = std::time::Duration::from_secs(30);
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    assert_eq!(executor.ran.lock().unwrap().len(), 1);
+    assert_eq!(aired_transitions(&store), vec!["cut", "cut"]);
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("published.ts")).unwrap(),
+        "/tmp/a.mp4;/tmp/b.mp4;"
+    );
+}
+
+#[test]
+fn transition_rules_pick_styles_by_content_kind() {
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let library = TransitionLibrary::from_config(&config.transitions).unwrap();
+
+    let blend = library.select(Some("music"), Some("Music"));
+    assert_eq!(blend.name, "music_blend");
+    assert_eq!(blend.kind, TransitionKind::AudioCrossfade);
+    assert_eq!(blend.duration_s, 2.0);
+    assert_eq!(library.select(Some("video"), Some("microspot")).name, "dip");
+    assert_eq!(library.select(Some("microspot"), Some("video")).name, "dip");
+    assert_eq!(
+        library.select(Some("music"), Some("video")).kind,
+        TransitionKind::JCut
+    );
+    assert_eq!(library.select(Some("video"), Some("video")).name, "fade");
+    assert_eq!(library.select(None, None).name, "fade");
+
+    let mut broken = config.transitions.clone();
+    broken.catalog.get_mut("wipe").unwrap().kind = "star_wipe".into();
+    assert!(matches!(
+        TransitionLibrary::from_config(&broken),
+        Err(BroadcasterError::InvalidTransition(_))
+    ));
+    let mut broken = config.transitions.clone();
+    broken.default = "missing".into();
+    assert!(TransitionLibrary::from_config(&broken).is_err());
+
+    // A broken catalog is rejected when the config loads.
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("broadcaster.toml");
+    let shipped = std::fs::read_to_string(config_path()).unwrap();
+    std::fs::write(
+        &path,
+        shipped.replace("default = \"fade\"", "default = \"missing\""),
+    )
+    .unwrap();
+    assert!(matches!(
+        load_broadcaster_config(&path),
+        Err(ConfigError::Invalid { .. })
+    ));
+}
+
+#[tokio::test]
+async fn music_blocks_air_with_the_configured_audio_crossfade() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_kind(&store, &["m1", "m2"], 30, "music");
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.airtime = 0.2;
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    assert_eq!(aired_transitions(&store), vec!["cut", "music_blend"]);
+    let ran = executor.ran.lock().unwrap();
+    assert_eq!(ran.len(), 1);
+    let filter = ran[0]
+        .iter()
+        .skip_while(|arg| *arg != "-filter_complex")
+        .nth(1)
+        .unwrap();
+    // The picture cuts while the sound crossfades over two seconds.
+    assert!(filter.contains("acrossfade=d=2"));
+    assert!(!filter.contains("xfade=transition"));
+    assert!(filter.contains("[0:a]atrim=start=28"));
+}
+
+#[test]
+fn overlay_elements_follow_content_kind_toggles() {
+    let layer = OverlayLayer::new(load_overlay_config(overlay_config_path()).unwrap());
+    let text_dir = Path::new("/tmp/overlay");
+
+    let video = layer
+        .graph(
+            &OverlayEntry {
+                content_kind: Some("video"),
+                title: Some("Alpha"),
+                duration_s: Some(60),
+                up_next: Some("Beta"),
+            },
+            text_dir,
+            "7",
+        )
+        .unwrap();
+    assert_eq!(
+        video.inputs,
+        vec!["-i", "/vvtv/broadcast/graphics/logo.png"]
+    );
+    assert!(video.filter.contains("overlay=W-w-24:24[vout]"));
+    // Lower third from 5 s for 8 s; up next 20 s before the end for 10 s.
+    assert!(video.filter.contains("between(t,5,13)"));
+    assert!(video.filter.contains("between(t,40,50)"));
+    assert_eq!(
+        video.text_files,
+        vec![
+            (text_dir.join("7_lower_third.txt"), "Alpha".to_string()),
+            (text_dir.join("7_up_next.txt"), "A seguir: Beta".to_string()),
+        ]
+    );
+    assert!(video.output_args().contains(&"libx264".to_string()));
+
+    let music = layer
+        .graph(
+            &OverlayEntry {
+                content_kind: Some("music"),
+                title: Some("Gamma"),
+                duration_s: Some(60),
+                up_next: Some("Beta"),
+            },
+            text_dir,
+            "8",
+        )
+        .unwrap();
+    assert_eq!(music.text_files.len(), 1);
+    assert!(!music.filter.contains("A seguir"));
+
+    let spot = OverlayEntry {
+        content_kind: Some("microspot"),
+        title: Some("Sponsor"),
+        duration_s: Some(15),
+        up_next: Some("Beta"),
+    };
+    assert!(layer.graph(&spot, text_dir, "9").is_none());
+    assert!(layer.is_enabled());
+
+    // Paths are escaped for the option and graph parsers, and titles are
+    // drawn verbatim.
+    let odd = layer
+        .graph(
+            &OverlayEntry {
+                content_kind: Some("music"),
+                title: Some("100%{pts}"),
+                duration_s: Some(60),
+                up_next: None,
+            },
+            Path::new("/tmp/o:l'a,y"),
+            "10",
+        )
+        .unwrap();
+    assert!(odd
+        .filter
+        .contains(r"textfile=/tmp/o\\:l\\\'a\,y/10_lower_third.txt:expansion=none:"));
+}
+
+#[tokio::test]
+async fn overlays_are_burned_into_the_feed() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 30);
+    let plans = SqlitePlanStore::builder()
+        .path(dir.path().join("plans.sqlite"))
+        .build()
+        .unwrap();
+    plans.initialize().unwrap();
+    for (plan_id, title) in [("a", "Alpha"), ("b", "Beta")] {
+        let mut plan = Plan::new(plan_id, "video");
+        plan.title = Some(title.into());
+        plans.upsert_plan(&plan).unwrap();
+    }
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone())
+        .with_plan_store(plans)
+        .with_overlays(load_overlay_config(overlay_config_path()).unwrap());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    let spawned = executor.spawned.lock().unwrap();
+    let feeder = &spawned[1];
+    let filter = feeder
+        .iter()
+        .skip_while(|arg| *arg != "-filter_complex")
+        .nth(1)
+        .unwrap();
+    assert!(filter.contains("drawtext"));
+    assert!(filter.contains("between(t,10,20)"));
+    assert!(feeder
+        .iter()
+        .any(|arg| arg == "/vvtv/broadcast/graphics/logo.png"));
+    assert!(feeder
+        .windows(2)
+        .any(|pair| pair[0] == "-c:v" && pair[1] == "libx264"));
+    // Text files are temporary artifacts of the entry.
+    assert!(!dir
+        .path()
+        .join("tmp")
+        .join("1_overlay_lower_third.txt")
+        .exists());
+}
+
+#[tokio::test]
+async fn overlays_keep_entries_without_graphics_encoded() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_kind(&store, &["spot"], 15, "microspot");
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone())
+        .with_overlays(load_overlay_config(overlay_config_path()).unwrap());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    let spawned = executor.spawned.lock().unwrap();
+    let feeder = &spawned[1];
+    assert!(feeder
+        .windows(2)
+        .any(|pair| pair[0] == "-filter_complex" && pair[1] == "[0:v]null[vout]"));
+    assert!(feeder
+        .windows(2)
+        .any(|pair| pair[0] == "-c:v" && pair[1] == "libx264"));
+}
+
+fn simulcast(name: &str, url: &str) -> SimulcastDestinationSection {
+    SimulcastDestinationSection {
+        name: name.into(),
+        url: url.into(),
+        enabled: true,
+        format: None,
+        reconnect_attempts: Some(0),
+        reconnect_delay_ms: None,
+    }
+}
+
+#[tokio::test]
+async fn simulcast_keeps_healthy_destinations_when_one_fails() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 1);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.rtmp.destinations = vec![
+        simulcast("partner", "rtmps://partner.example/app/key"),
+        simulcast("backup", "srt://broken.example:9000"),
+    ];
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.run_once().await.unwrap().unwrap();
+
+    let status = broadcaster.destination_status().await;
+    let names: Vec<&str> = status.iter().map(|s| s.name.as_str()).collect();
+    assert_eq!(names, vec!["origin", "partner", "backup"]);
+    assert!(status[0].connected && status[1].connected);
+    assert_eq!((status[0].started, status[1].started), (1, 1));
+    // The unreachable destination is retried on every entry without
+    // affecting the others.
+    assert!(!status[2].connected);
+    assert_eq!(status[2].failures, 2);
+    assert!(status[2].last_error.is_some());
+    broadcaster.shutdown().await.unwrap();
+
+    for url in [ORIGIN, "rtmps://partner.example/app/key"] {
+        assert_eq!(
+            std::fs::read_to_string(executor.output_file(url)).unwrap(),
+            "/tmp/a.mp4;/tmp/b.mp4;"
+        );
+    }
+    let spawned = executor.spawned.lock().unwrap();
+    let srt = spawned
+        .iter()
+        .find(|args| args.last().map(String::as_str) == Some("srt://broken.example:9000"))
+        .unwrap();
+    assert!(srt.windows(2).any(|pair| pair == ["-f", "mpegts"]));
+    drop(spawned);
+
+    // The as-run log names the destinations that carried each entry.
+    let now = Utc::now();
+    let as_run = store
+        .as_run_between(now - Duration::hours(1), now + Duration::hours(1))
+        .unwrap();
+    assert_eq!(as_run.len(), 2);
+    assert!(as_run
+        .iter()
+        .all(|record| record.destination.as_deref() == Some("origin,partner")));
+}
+
+#[tokio::test]
+async fn stalled_destination_is_dropped_without_holding_up_the_others() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a"], 1);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.rtmp.destinations = vec![simulcast("partner", "rtmps://stalled.example/app/key")];
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.padding = 8 * 1024 * 1024;
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    let started = std::time::Instant::now();
+    broadcaster.run_once().await.unwrap().unwrap();
+    let status = broadcaster.destination_status().await;
+    broadcaster.shutdown().await.unwrap();
+
+    // The partner never reads, yet the origin gets the whole entry long
+    // before the partner's process would exit.
+    assert!(started.elapsed() < std::time::Duration::from_secs(20));
+    assert!(status[0].connected);
+    assert!(!status[1].connected);
+    assert_eq!(status[1].failures, 1);
+    assert!(status[1]
+        .last_error
+        .as_deref()
+        .unwrap()
+        .starts_with("fell behind"));
+    let published = std::fs::read(executor.output_file(ORIGIN)).unwrap();
+    assert_eq!(published.len(), 8 * 1024 * 1024 + "/tmp/a.mp4;".len());
+    assert!(published.ends_with(b"/tmp/a.mp4;"));
+}
+
+#[tokio::test]
+async fn destination_lost_mid_entry_is_restarted_within_its_attempts() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a"], 1);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.rtmp.destinations = vec![SimulcastDestinationSection {
+        reconnect_attempts: Some(1),
+        reconnect_delay_ms: Some(0),
+        ..simulcast("partner", "srt://dies.example:9000")
+    }];
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.padding = 8 * 1024 * 1024;
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    let status = broadcaster.destination_status().await;
+    broadcaster.shutdown().await.unwrap();
+
+    // Started for the entry, restarted once when it died, then given up on
+    // until the next entry.
+    assert_eq!((status[1].started, status[1].failures), (2, 2));
+    assert_eq!(status[0].failures, 0);
+    let published = std::fs::read(executor.output_file(ORIGIN)).unwrap();
+    assert!(published.ends_with(b"/tmp/a.mp4;"));
+}
+
+#[tokio::test]
+async fn standalone_playout_fans_out_through_the_tee_muxer() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a"], 1);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.rtmp.persistent_session = false;
+    config.rtmp.destinations = vec![simulcast("backup", "srt://backup.example:9000")];
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+
+    let ran = executor.ran.lock().unwrap();
+    let args = ran.last().unwrap();
+    assert!(args.windows(2).any(|pair| pair == ["-f", "tee"]));
+    assert_eq!(
+        args.last().unwrap(),
+        &format!("[f=flv:onfail=ignore]{ORIGIN}|[f=mpegts:onfail=ignore]srt://backup.example:9000")
+    );
+}
+
+#[test]
+fn native_hls_follows_the_hls_section() {
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let destination = hls_destination(&config.hls).unwrap();
+    assert_eq!(destination.url, "/vvtv/broadcast/hls/index.m3u8");
+    assert_eq!(destination.format, OutputFormat::Hls);
+    let args = destination.muxer_args.join(" ");
+    // 48 minutes of 4 s segments.
+    assert!(args.contains("-hls_time 4 -hls_list_size 720"));
+    assert!(args.contains("-hls_segment_type fmp4"));
+    // A restart continues the media sequence and flags the discontinuity.
+    assert!(args.contains(
+        "-hls_flags independent_segments+delete_segments+append_list+discont_start \
+         -hls_start_number_source epoch"
+    ));
+    assert!(args.contains("/vvtv/broadcast/hls/segment_%06d.m4s"));
+    assert!(args.contains("-hls_fmp4_init_filename init.mp4"));
+
+    let mut ts = config.hls.clone();
+    ts.segment_type = "ts".into();
+    ts.playlist_length = "90s".into();
+    let args = hls_destination(&ts).unwrap().muxer_args.join(" ");
+    assert!(args.contains("-hls_list_size 22 -hls_segment_type mpegts"));
+    assert!(!args.contains("init.mp4"));
+
+    let mut broken = config.hls.clone();
+    broken.playlist_length = "forever".into();
+    assert!(matches!(
+        hls_destination(&broken),
+        Err(BroadcasterError::InvalidDestination(_))
+    ));
+}
+
+#[tokio::test]
+async fn native_hls_replaces_the_rtmp_origin() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a"], 1);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.rtmp.enabled = false;
+    config.hls.native = true;
+    config.hls.output_path = dir.path().join("hls").to_string_lossy().to_string();
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    let status = broadcaster.destination_status().await;
+    assert_eq!(status.len(), 1);
+    assert_eq!(status[0].name, "hls");
+    assert!(dir.path().join("hls").is_dir());
+    let playlist = dir.path().join("hls/index.m3u8");
+    let spawned = executor.spawned.lock().unwrap();
+    let output = &spawned[0];
+    assert_eq!(output.last().unwrap(), &playlist.to_string_lossy());
+    assert!(output.windows(2).any(|pair| pair == ["-f", "hls"]));
+    assert!(!spawned
+        .iter()
+        .flatten()
+        .any(|arg| arg.starts_with("rtmp://")));
+    assert_eq!(
+        std::fs::read_to_string(executor.output_file(&playlist.to_string_lossy())).unwrap(),
+        "/tmp/a.mp4;"
+    );
+}
+
+/// Two `-progress` blocks of a feeder falling behind and dropping frames.
+const LAGGING_PROGRESS: &str = "frame=100\nfps=21.3\nstream_0_0_q=28.0\nbitrate=2500.4kbits/s\n\
+total_size=1250000\nout_time_us=4000000\nout_time=00:00:04.000000\ndup_frames=1\n\
+drop_frames=2\nspeed=0.85x\nprogress=continue\nframe=190\nfps=20.9\nbitrate=N/A\n\
+out_time_us=7600000\ndup_frames=1\ndrop_frames=5\nspeed=0.85x\nprogress=end\n";
+
+#[test]
+fn progress_blocks_become_encoder_samples() {
+    let mut parser = ProgressParser::new();
+    let mut samples = Vec::new();
+    for line in "[tls @ 0x1] error reading\n"
+        .lines()
+        .chain(LAGGING_PROGRESS.lines())
+    {
+        match parser.push(line) {
+            ProgressLine::Sample(sample) => samples.push(sample),
+            ProgressLine::Log => assert!(line.starts_with("[tls")),
+            ProgressLine::Field => {}
+        }
+    }
+    assert_eq!(samples.len(), 2);
+    let first = &samples[0];
+    assert_eq!(
+        (first.frame, first.drop_frames, first.dup_frames),
+        (100, 2, 1)
+    );
+    assert_eq!(first.fps, 21.3);
+    assert_eq!(first.bitrate_kbps, Some(2500.4));
+    assert_eq!(first.speed, Some(0.85));
+    assert_eq!(first.out_time_s, 4.0);
+    assert!(!first.finished);
+    assert_eq!(samples[1].bitrate_kbps, None);
+    assert!(samples[1].finished);
+}
+
+#[tokio::test]
+async fn session_timeline_follows_media_time() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 1);
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.progress = LAGGING_PROGRESS.to_string();
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    // The first feeder reported 7.6s of media in well under a second.
+    let spawned = executor.spawned.lock().unwrap();
+    let offsets: Vec<&str> = spawned[1..]
+        .iter()
+        .map(|feeder| {
+            let at = feeder
+                .iter()
+                .position(|arg| arg == "-output_ts_offset")
+                .unwrap();
+            feeder[at + 1].as_str()
+        })
+        .collect();
+    assert_eq!(offsets, vec!["0.000", "7.600"]);
+}
+
+#[tokio::test]
+async fn encoder_progress_is_recorded_and_watched() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a"], 1);
+    let metrics = MetricsStore::new(dir.path().join("metrics.sqlite")).unwrap();
+    metrics.initialize().unwrap();
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.progress = LAGGING_PROGRESS.to_string();
+    let executor = Arc::new(fake);
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let watchdog_config = config.watchdog.clone();
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone())
+        .with_metrics_store(metrics.clone());
+    let mut samples = broadcaster.telemetry().subscribe();
+
+    let event = broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    let first = samples.recv().await.unwrap();
+    assert_eq!(first.queue_id, Some(event.queue_id));
+    assert_eq!(first.speed, Some(0.85));
+    assert!(samples.recv().await.unwrap().finished);
+    let feeder = executor.spawned.lock().unwrap()[1].clone();
+    assert!(feeder
+        .windows(2)
+        .any(|pair| pair == ["-progress", "pipe:2"]));
+
+    let recorded = metrics.encoder_samples(10).unwrap();
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[1].drop_frames, 5);
+    assert_eq!(recorded[1].queue_id, Some(event.queue_id));
+
+    let watchdog = Watchdog::new(
+        store.clone(),
+        watchdog_config,
+        BroadcasterPaths {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            archive_dir: dir.path().join("archive"),
+            temp_dir: dir.path().join("tmp"),
+        },
+        None,
+        Some(executor),
+        ORIGIN.to_string(),
+        Some(dir.path().join("reports")),
+    )
+    .unwrap()
+    .with_encoder_telemetry(broadcaster.telemetry().clone());
+    let report = watchdog.evaluate().await.unwrap();
+    assert!(report
+        .observations
+        .contains(&"regra encoder_lagging: encoder a 0.85x, abaixo de 0.90x".to_string()));
+}
+
+#[tokio::test]
+async fn break_in_stops_the_entry_on_air_and_resumes_it_afterwards() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "urgent"], 60);
+    let mut config = load_broadcaster_config(config_path()).unwrap();
+    config.queue.break_in.poll_ms = 20;
+    config.queue.break_in.stop_grace_ms = 100;
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.airtime = 1.0;
+    let executor = Arc::new(fake);
+    let broadcaster = broadcaster_with(config, dir.path(), &store, executor.clone());
+
+    let requester = {
+        let store = store.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            store
+                .request_break_in(2, ResumePolicy::Resume, Some("breaking news"))
+                .unwrap();
+        })
+    };
+    let event = broadcaster.run_once().await.unwrap().unwrap();
+    requester.await.unwrap();
+    assert_eq!(event.plan_id, "urgent");
+
+    let interrupted = store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.plan_id == "a")
+        .unwrap();
+    assert_eq!(interrupted.status, QueueStatus::Queued);
+    let offset = interrupted.resume_offset_s.unwrap();
+    assert!(offset > 0.0 && offset < 60.0);
+
+    // The interrupted entry airs next, cut in from where it stopped.
+    let resumed = broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+    assert_eq!(resumed.plan_id, "a");
+    let feeder = executor.spawned.lock().unwrap().last().unwrap().clone();
+    assert!(feeder.windows(2).any(|pair| pair[0] == "-ss"));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("published.ts")).unwrap(),
+        "/tmp/urgent.mp4;/tmp/a.mp4;"
+    );
+
+    let now = Utc::now();
+    let as_run = store
+        .as_run_between(now - Duration::hours(1), now + Duration::hours(1))
+        .unwrap();
+    let outcomes: Vec<_> = as_run
+        .iter()
+        .map(|entry| (entry.plan_id.as_str(), entry.outcome.as_str()))
+        .collect();
+    assert_eq!(
+        outcomes,
+        vec![("a", "interrupted"), ("urgent", "played"), ("a", "played")]
+    );
+    assert!(as_run[0]
+        .note
+        .as_deref()
+        .unwrap()
+        .contains("(resume): breaking news"));
+    assert_eq!(as_run[2].transition.as_deref(), Some("cut"));
+}
+
+fn leadership(node_id: &str, role: &str) -> LeadershipSection {
+    LeadershipSection {
+        enabled: true,
+        node_id: node_id.to_string(),
+        role: role.to_string(),
+        lease_seconds: 15,
+        standby_grace_seconds: 5,
+        promote_script: Some(PathBuf::from("/vvtv/system/promote_failover.sh")),
+        fence_script: Some(PathBuf::from("/vvtv/system/halt_stream.sh")),
+        ..LeadershipSection::default()
+    }
+}
+
+fn election(
+    store: &Arc<SqliteLeaseStore>,
+    node_id: &str,
+    role: &str,
+    executor: Arc<FakeFfmpeg>,
+) -> LeaderElection {
+    let store: Arc<dyn LeaseStore> = store.clone();
+    LeaderElection::new(leadership(node_id, role), store)
+        .unwrap()
+        .with_failover(FailoverManager::new(
+            PathBuf::from("rsync"),
+            PathBuf::from("/vvtv/archive"),
+            Some(executor),
+        ))
+}
+
+#[tokio::test]
+async fn standby_takes_over_an_expired_lease_and_the_old_primary_fences() {
+    let dir = TempDir::new().unwrap();
+    let store = Arc::new(SqliteLeaseStore::new(dir.path().join("leadership.sqlite")).unwrap());
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let primary = election(&store, "node-a", "primary", executor.clone());
+    let standby = election(&store, "node-b", "standby", executor.clone());
+    let start = Utc::now();
+
+    assert_eq!(
+        primary.tick_at(start).await.unwrap(),
+        LeadershipState::Active { epoch: 1 }
+    );
+    assert_eq!(
+        standby.tick_at(start).await.unwrap(),
+        LeadershipState::Standby
+    );
+
+    // The primary stops renewing. Expiry alone is not enough: the standby
+    // waits out the grace period first.
+    let expired = start + Duration::seconds(16);
+    assert_eq!(
+        standby.tick_at(expired).await.unwrap(),
+        LeadershipState::Standby
+    );
+    let after_grace = start + Duration::seconds(21);
+    assert_eq!(
+        standby.tick_at(after_grace).await.unwrap(),
+        LeadershipState::Active { epoch: 2 }
+    );
+
+    // The old primary finds the lease taken and fences itself rather than
+    // taking it back.
+    let state = primary.tick_at(after_grace).await.unwrap();
+    assert!(matches!(
+        &state,
+        LeadershipState::Fenced { epoch: 1, reason } if reason.contains("node-b")
+    ));
+    assert!(!primary.is_active());
+    // Promote scripts on both nodes, then the fence script.
+    assert_eq!(executor.ran.lock().unwrap().len(), 3);
+
+    let lease = store.lease().unwrap().unwrap();
+    assert_eq!((lease.holder.as_str(), lease.epoch), ("node-b", 2));
+    let handovers = store.handovers(10).unwrap();
+    let summary: Vec<_> = handovers
+        .iter()
+        .map(|handover| {
+            (
+                handover.from_node.as_deref(),
+                handover.to_node.as_str(),
+                handover.reason.as_str(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (Some("node-a"), "node-b", "expired"),
+            (None, "node-a", "initial")
+        ]
+    );
+    let nodes: Vec<_> = store
+        .nodes()
+        .unwrap()
+        .into_iter()
+        .map(|node| (node.node_id, node.state))
+        .collect();
+    assert_eq!(
+        nodes,
+        vec![
+            ("node-a".to_string(), "fenced".to_string()),
+            ("node-b".to_string(), "active".to_string())
+        ]
+    );
+
+    // Stepping down hands the lease over without waiting for it to expire.
+    standby.step_down(after_grace).await.unwrap();
+    let back = after_grace + Duration::seconds(1);
+    assert_eq!(
+        primary.tick_at(back).await.unwrap(),
+        LeadershipState::Active { epoch: 3 }
+    );
+    assert_eq!(store.handovers(1).unwrap()[0].reason, "released");
+}
+
+/// Lease store whose renewals hang after the first until `release` drops.
+struct HangingStore {
+    inner: SqliteLeaseStore,
+    acquired: Mutex<bool>,
+    release: Mutex<std::sync::mpsc::Receiver<()>>,
+}
+
+impl LeaseStore for HangingStore {
+    fn acquire(
+        &self,
+        node: &str,
+        ttl: Duration,
+        now: chrono::DateTime<Utc>,
+    ) -> Result<Lease, LeadershipError> {
+        if std::mem::replace(&mut *self.acquired.lock().unwrap(), true) {
+            let _ = self.release.lock().unwrap().recv();
+        }
+        self.inner.acquire(node, ttl, now)
+    }
+
+    fn release(&self, node: &str, now: chrono::DateTime<Utc>) -> Result<(), LeadershipError> {
+        self.inner.release(node, now)
+    }
+
+    fn lease(&self) -> Result<Option<Lease>, LeadershipError> {
+        self.inner.lease()
+    }
+
+    fn record_node(&self, status: &NodeHeartbeat) -> Result<(), LeadershipError> {
+        self.inner.record_node(status)
+    }
+
+    fn nodes(&self) -> Result<Vec<NodeHeartbeat>, LeadershipError> {
+        self.inner.nodes()
+    }
+
+    fn handovers(&self, limit: usize) -> Result<Vec<Handover>, LeadershipError> {
+        self.inner.handovers(limit)
+    }
+}
+
+#[tokio::test]
+async fn node_fences_before_its_lease_runs_out_when_the_store_hangs() {
+    let dir = TempDir::new().unwrap();
+    let (release, hang) = std::sync::mpsc::channel();
+    let store = Arc::new(HangingStore {
+        inner: SqliteLeaseStore::new(dir.path().join("leadership.sqlite")).unwrap(),
+        acquired: Mutex::new(false),
+        release: Mutex::new(hang),
+    });
+    let section = LeadershipSection {
+        lease_seconds: 3,
+        heartbeat_seconds: 1,
+        ..leadership("node-a", "primary")
+    };
+    let election = Arc::new(LeaderElection::new(section, store.clone()).unwrap());
+    let mut state = election.subscribe();
+    let running = {
+        let election = election.clone();
+        tokio::spawn(async move { election.run().await })
+    };
+
+    state
+        .wait_for(|state| matches!(state, LeadershipState::Active { .. }))
+        .await
+        .unwrap();
+    let expires_at = store.lease().unwrap().unwrap().expires_at;
+    let fenced = tokio::time::timeout(
+        std::time::Duration::from_secs(5),
+        state.wait_for(|state| matches!(state, LeadershipState::Fenced { epoch: 1, .. })),
+    )
+    .await;
+    assert!(fenced.is_ok());
+    assert!(Utc::now() < expires_at);
+
+    running.abort();
+    drop(release);
+}
+
+#[tokio::test]
+async fn broadcaster_airs_only_while_it_holds_the_lease() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 60);
+    let leases = Arc::new(SqliteLeaseStore::new(dir.path().join("leadership.sqlite")).unwrap());
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.airtime = 1.0;
+    let executor = Arc::new(fake);
+    let primary = Arc::new(election(&leases, "node-a", "primary", executor.clone()));
+    let standby = election(&leases, "node-b", "standby", executor.clone());
+    let broadcaster =
+        broadcaster(dir.path(), &store, executor.clone()).with_leadership(primary.clone());
+
+    // Nothing airs before the node holds the lease.
+    assert!(broadcaster.run_once().await.unwrap().is_none());
+    assert!(store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .iter()
+        .all(|entry| entry.status == QueueStatus::Queued));
+
+    let start = Utc::now();
+    primary.tick_at(start).await.unwrap();
+    let takeover = {
+        let primary = primary.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+            let later = start + Duration::seconds(30);
+            standby.tick_at(later).await.unwrap();
+            primary.tick_at(later).await.unwrap();
+        })
+    };
+    let error = broadcaster.run_once().await.unwrap_err();
+    takeover.await.unwrap();
+    assert!(matches!(error, BroadcasterError::Fenced(ref reason) if reason.contains("node-b")));
+
+    // The entry on air goes back to the head of the queue to resume on
+    // whichever node airs it next.
+    let suspended = store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.plan_id == "a")
+        .unwrap();
+    assert_eq!(suspended.status, QueueStatus::Queued);
+    assert_eq!(suspended.queue_position, Some(1));
+    let offset = suspended.resume_offset_s.unwrap();
+    assert!(offset > 0.0 && offset < 60.0);
+    let now = Utc::now();
+    let as_run = store
+        .as_run_between(now - Duration::hours(1), now + Duration::hours(1))
+        .unwrap();
+    assert_eq!(as_run.len(), 1);
+    assert_eq!(as_run[0].outcome.as_str(), "interrupted");
+    assert!(as_run[0].note.as_deref().unwrap().starts_with("fenced at"));
+
+    assert!(broadcaster.run_once().await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn empty_archive_airs_the_generated_slate_when_the_queue_runs_dry() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+    let slate = broadcaster.slate().unwrap().path();
+
+    let first = broadcaster.run_once().await.unwrap().unwrap();
+    let second = broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    assert!(first.plan_id.starts_with("emergency-slate"));
+    assert_ne!(first.plan_id, second.plan_id);
+    let entries = store.list(&QueueFilter::default()).unwrap();
+    assert!(entries
+        .iter()
+        .all(|entry| entry.node_origin.as_deref() == Some(EMERGENCY_SLATE_ORIGIN)));
+    // Rendered once through lavfi, then looped from the cache.
+    let ran = executor.ran.lock().unwrap();
+    assert_eq!(ran.len(), 1);
+    assert!(ran[0].contains(&"color=c=black:s=1280x720:r=30:d=300".to_string()));
+    assert!(ran[0].iter().any(|arg| arg.starts_with("anullsrc")));
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("published.ts")).unwrap(),
+        format!("{0};{0};", slate.display())
+    );
+}
+
+#[tokio::test]
+async fn slate_is_not_queued_while_other_entries_remain() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    enqueue_videos(&store, &["a", "b"], 1);
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let broadcaster = broadcaster(dir.path(), &store, executor.clone());
+
+    let event = broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    assert_eq!(event.plan_id, "a");
+    assert!(executor.ran.lock().unwrap().is_empty());
+    assert_eq!(store.list(&QueueFilter::default()).unwrap().len(), 2);
+}
+
+#[test]
+fn emergency_assets_are_ranked_by_qc_metadata() {
+    let dir = TempDir::new().unwrap();
+    let archive = dir.path().join("archive");
+    let manifest = |name: &str, vmaf: f64, black_ratio: f64, qc_warning: bool| {
+        let ready = archive.join(name);
+        std::fs::create_dir_all(&ready).unwrap();
+        std::fs::write(ready.join("master.mp4"), b"").unwrap();
+        let quality = serde_json::json!({
+            "plan_id": name,
+            "quality": {
+                "mid": {
+                    "vmaf_score": vmaf,
+                    "ssim_score": 0.98,
+                    "black_ratio": black_ratio,
+                    "freeze_score": 0.0,
+                },
+                "qc_warning": qc_warning,
+            },
+        });
+        std::fs::write(ready.join("manifest.json"), quality.to_string()).unwrap();
+    };
+    manifest("flagged", 92.0, 0.3, true);
+    manifest("clean", 95.0, 0.0, false);
+    std::fs::write(archive.join("zz-unchecked.mp4"), b"").unwrap();
+    std::fs::write(archive.join("aa-unchecked.mp4"), b"").unwrap();
+
+    let ranked: Vec<String> = collect_emergency_assets(&archive)
+        .unwrap()
+        .iter()
+        .map(|path| {
+            path.strip_prefix(&archive)
+                .unwrap()
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    assert_eq!(
+        ranked,
+        vec![
+            "clean/master.mp4",
+            "zz-unchecked.mp4",
+            "aa-unchecked.mp4",
+            "flagged/master.mp4",
+        ]
+    );
+}
+
+/// Writes `asset` into a ready directory of its own, with the
+/// `checksums.json` and `manifest.json` the processor would record for
+/// `recorded` and a `duration_s` long rendition.
+fn ready_asset(dir: &Path, name: &str, recorded: &[u8], actual: &[u8], duration_s: f64) -> String {
+    let ready = dir.join("ready").join(name);
+    std::fs::create_dir_all(&ready).unwrap();
+    let asset = ready.join("master.mp4");
+    std::fs::write(&asset, actual).unwrap();
+    let checksum = hex::encode(Sha256::digest(recorded));
+    let checksums = serde_json::json!({ "master.mp4": checksum });
+    std::fs::write(ready.join("checksums.json"), checksums.to_string()).unwrap();
+    let manifest = serde_json::json!({
+        "plan_id": name,
+        "duration": duration_s,
+        "sizes": { "master.mp4": recorded.len() },
+    });
+    std::fs::write(ready.join("manifest.json"), manifest.to_string()).unwrap();
+    asset.to_string_lossy().to_string()
+}
+
+#[tokio::test]
+async fn assets_failing_preflight_are_skipped_for_the_next_candidate() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    let assets = [
+        (
+            "corrupt",
+            ready_asset(dir.path(), "corrupt", b"original", b"0riginal", 30.0),
+        ),
+        (
+            "short",
+            ready_asset(dir.path(), "short", b"original", b"origin", 30.0),
+        ),
+        (
+            "truncated",
+            ready_asset(dir.path(), "truncated", b"original", b"original", 60.0),
+        ),
+        (
+            "missing",
+            dir.path()
+                .join("ready/missing/master.mp4")
+                .to_string_lossy()
+                .to_string(),
+        ),
+        (
+            "good",
+            ready_asset(dir.path(), "good", b"original", b"original", 30.0),
+        ),
+    ];
+    for (plan, asset) in &assets {
+        store
+            .enqueue(&QueueItem {
+                plan_id: plan.to_string(),
+                asset_path: asset.clone(),
+                duration_s: Some(30),
+                content_kind: Some("video".into()),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+    let config = load_broadcaster_config(config_path()).unwrap();
+    assert!(config.preflight.enabled);
+    let mut fake = FakeFfmpeg::new(dir.path().join("published.ts"));
+    fake.probed = 30.0;
+    let executor = Arc::new(fake);
+    let broadcaster = verified_broadcaster(config, dir.path(), &store, executor.clone());
+
+    let event = broadcaster.run_once().await.unwrap().unwrap();
+    broadcaster.shutdown().await.unwrap();
+
+    assert_eq!(event.plan_id, "good");
+    let good = &assets[4].1;
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("published.ts")).unwrap(),
+        format!("{good};")
+    );
+    let reasons: Vec<(String, String)> = store
+        .list(&QueueFilter {
+            status: Some(QueueStatus::Queued),
+            limit: None,
+        })
+        .unwrap()
+        .into_iter()
+        .map(|entry| {
+            assert_eq!(entry.retry_count, 1);
+            (entry.plan_id, entry.failure_reason.unwrap())
+        })
+        .collect();
+    let reason = |plan: &str| {
+        reasons
+            .iter()
+            .find(|(id, _)| id == plan)
+            .map(|(_, reason)| reason.as_str())
+            .unwrap()
+    };
+    assert!(reason("corrupt").starts_with("pre-flight: checksum"));
+    assert!(reason("short").contains("size 6 bytes, manifest lists 8"));
+    assert!(reason("truncated").contains("short of manifest's 60.0s"));
+    assert_eq!(reason("missing"), "pre-flight: asset missing");
+
+    // The asset that passed is not probed again while unchanged.
+    let probes = |executor: &FakeFfmpeg| {
+        executor
+            .ran
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|args| args.iter().any(|arg| arg == good))
+            .count()
+    };
+    let before = probes(&executor);
+    broadcaster
+        .preflight()
+        .verify(Path::new(good))
+        .await
+        .unwrap();
+    assert_eq!(probes(&executor), before);
+}
+
+#[test]
+fn watchdog_rules_follow_the_watchdog_section() {
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let rules = WatchdogRules::from_config(&config.watchdog).unwrap();
+    let names: Vec<&str> = rules
+        .rules()
+        .iter()
+        .map(|rule| rule.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "buffer_low",
+            "buffer_critical",
+            "stream_inactive",
+            "playout_failures",
+            "encoder_lagging",
+            "output_frozen",
+            "output_black",
+            "output_silent"
+        ]
+    );
+    // Restart rules default to `restart_max_attempts`.
+    assert_eq!(rules.rules()[2].budget, Some(3));
+    assert_eq!(rules.rules()[3].budget, None);
+    assert_eq!(rules.rules()[4].budget, Some(2));
+
+    let mut section = config.watchdog.clone();
+    section.rules[0].actions.push("reboot".into());
+    assert!(matches!(
+        WatchdogRules::from_config(&section),
+        Err(WatchdogError::InvalidRule(_))
+    ));
+    let mut section = config.watchdog.clone();
+    section.rules[3].failures_last_hour_at_least = None;
+    assert!(WatchdogRules::from_config(&section).is_err());
+
+    // Invalid rules are rejected when the config loads.
+    let dir = TempDir::new().unwrap();
+    let path = dir.path().join("broadcaster.toml");
+    let shipped = std::fs::read_to_string(config_path()).unwrap();
+    std::fs::write(
+        &path,
+        shipped.replace(
+            "actions = [\"inject_emergency_loop\"]",
+            "actions = [\"reboot\"]",
+        ),
+    )
+    .unwrap();
+    assert!(matches!(
+        load_broadcaster_config(&path),
+        Err(ConfigError::Invalid { .. })
+    ));
+
+    // Without rules the built-in ones apply, nginx only on freeze restarts.
+    let mut section = config.watchdog.clone();
+    section.rules.clear();
+    section.restart_on_freeze = false;
+    let built_in = WatchdogRules::from_config(&section).unwrap();
+    assert_eq!(built_in.rules().len(), 4);
+    assert_eq!(
+        built_in.rules()[2].actions,
+        vec![WatchdogAction::RestartEncoder]
+    );
+    // A lagging encoder is only reported, below encoder_min_speed.
+    assert_eq!(built_in.rules()[3].encoder_speed_below, Some(0.95));
+    assert!(built_in.rules()[3].actions.is_empty());
+}
+
+#[test]
+fn watchdog_rules_cool_down_and_escalate_once_the_budget_is_spent() {
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let rules = WatchdogRules::from_config(&config.watchdog).unwrap();
+    let start = Utc::now();
+    let inactive = WatchdogInputs {
+        buffer_hours: 5.0,
+        stream_inactive: true,
+        ..Default::default()
+    };
+
+    for minute in 0..3 {
+        let (actions, firings) = rules.evaluate(&inactive, start + Duration::minutes(minute));
+        assert_eq!(
+            actions,
+            vec![WatchdogAction::RestartEncoder, WatchdogAction::RestartNginx]
+        );
+        assert_eq!(firings[0].state, FiringState::Acted);
+    }
+    let (actions, firings) = rules.evaluate(&inactive, start + Duration::minutes(3));
+    assert_eq!(firings[0].state, FiringState::BudgetExhausted);
+    assert!(matches!(
+        actions.as_slice(),
+        [WatchdogAction::Escalate(reason)] if reason.contains("stream_inactive")
+    ));
+    // A spent budget escalates once, not on every evaluation.
+    let (actions, firings) = rules.evaluate(&inactive, start + Duration::minutes(4));
+    assert_eq!(firings[0].state, FiringState::BudgetExhausted);
+    assert!(actions.is_empty());
+    // The budget slides: restarts resume once the window has passed.
+    let (actions, _) = rules.evaluate(&inactive, start + Duration::minutes(6));
+    assert_eq!(actions[0], WatchdogAction::RestartEncoder);
+
+    let lagging = WatchdogInputs {
+        buffer_hours: 5.0,
+        encoder_speed: Some(0.5),
+        ..Default::default()
+    };
+    let later = start + Duration::hours(2);
+    let (actions, _) = rules.evaluate(&lagging, later);
+    assert_eq!(actions, vec![WatchdogAction::RestartEncoder]);
+    let (actions, firings) = rules.evaluate(&lagging, later + Duration::minutes(5));
+    assert!(actions.is_empty());
+    assert_eq!(firings[0].state, FiringState::CoolingDown);
+    let (actions, _) = rules.evaluate(&lagging, later + Duration::minutes(11));
+    assert_eq!(actions, vec![WatchdogAction::RestartEncoder]);
+    let (actions, firings) = rules.evaluate(&lagging, later + Duration::minutes(22));
+    assert_eq!(firings[0].state, FiringState::BudgetExhausted);
+    assert_eq!(actions.len(), 1);
+    let (actions, _) = rules.evaluate(&lagging, later + Duration::minutes(27));
+    assert!(actions.is_empty());
+    let (actions, _) = rules.evaluate(&lagging, later + Duration::minutes(32));
+    assert!(matches!(actions.as_slice(), [WatchdogAction::Escalate(_)]));
+
+    let failing = WatchdogInputs {
+        buffer_hours: 5.0,
+        failures_last_hour: 4,
+        ..Default::default()
+    };
+    let (actions, _) = rules.evaluate(&failing, later);
+    assert_eq!(
+        actions,
+        vec![WatchdogAction::Escalate(
+            "regra playout_failures: 4 falhas na última hora".into()
+        )]
+    );
+}
+
+#[tokio::test]
+async fn watchdog_evaluation_applies_matching_rules() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    let watchdog = Watchdog::new(
+        store,
+        config.watchdog,
+        BroadcasterPaths {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            archive_dir: dir.path().join("archive"),
+            temp_dir: dir.path().join("tmp"),
+        },
+        None,
+        Some(executor),
+        ORIGIN.to_string(),
+        Some(dir.path().join("reports")),
+    )
+    .unwrap();
+
+    let report = watchdog.evaluate().await.unwrap();
+
+    assert_eq!(
+        report.actions,
+        vec![
+            WatchdogAction::InjectEmergencyLoop,
+            WatchdogAction::PauseDownloads
+        ]
+    );
+    let fired: Vec<&str> = report
+        .firings
+        .iter()
+        .map(|firing| firing.rule.as_str())
+        .collect();
+    assert_eq!(fired, vec!["buffer_low", "buffer_critical"]);
+    assert_eq!(
+        report.observations[0],
+        "regra buffer_low: buffer 0.00h abaixo de 1.00h"
+    );
+}
+
+fn watchdog_report(
+    rules: &WatchdogRules,
+    inputs: &WatchdogInputs,
+    now: chrono::DateTime<Utc>,
+) -> WatchdogReport {
+    let (actions, firings) = rules.evaluate(inputs, now);
+    WatchdogReport {
+        metrics: QueueMetrics {
+            buffer_duration_hours: inputs.buffer_hours,
+            failures_last_hour: inputs.failures_last_hour,
+            ..Default::default()
+        },
+        actions,
+        observations: firings
+            .iter()
+            .map(|firing| firing.observation.clone())
+            .collect(),
+        firings,
+        incident: None,
+        output: None,
+    }
+}
+
+#[test]
+fn watchdog_escalations_open_raise_and_resolve_an_incident() {
+    let dir = TempDir::new().unwrap();
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let rules = WatchdogRules::from_config(&config.watchdog).unwrap();
+    let incidents = WatchdogIncidents::new(
+        config.watchdog.incidents.clone(),
+        IncidentNotifier::new(SeverityRouting::default(), None, None).with_dry_run(true),
+        IncidentHistoryWriter::new(dir.path().join("incidents")),
+    );
+    let start = Utc::now();
+    let failing = WatchdogInputs {
+        buffer_hours: 5.0,
+        failures_last_hour: 4,
+        ..Default::default()
+    };
+    let off_air = WatchdogInputs {
+        stream_inactive: true,
+        ..failing.clone()
+    };
+    let healthy = WatchdogInputs {
+        buffer_hours: 5.0,
+        ..Default::default()
+    };
+
+    let opened = incidents
+        .observe(&watchdog_report(&rules, &failing, start), start)
+        .unwrap()
+        .unwrap();
+    assert_eq!(opened.change, IncidentChange::Opened);
+    assert_eq!(opened.incident.severity, IncidentSeverity::High);
+    assert_eq!(
+        opened.incident.summary,
+        "regra playout_failures: 4 falhas na última hora"
+    );
+    assert!(opened.record.markdown_path.exists());
+    assert!(opened.dispatch.is_some());
+    let unchanged = start + Duration::seconds(30);
+    assert!(incidents
+        .observe(&watchdog_report(&rules, &failing, unchanged), unchanged)
+        .unwrap()
+        .is_none());
+
+    let later = start + Duration::minutes(1);
+    let raised = incidents
+        .observe(&watchdog_report(&rules, &off_air, later), later)
+        .unwrap()
+        .unwrap();
+    assert_eq!(raised.change, IncidentChange::Raised);
+    assert_eq!(raised.incident.severity, IncidentSeverity::Critical);
+    assert_eq!(
+        raised.incident.actions_taken,
+        vec!["restart_encoder", "restart_nginx"]
+    );
+    assert!(raised
+        .incident
+        .timeline
+        .iter()
+        .any(|entry| entry.description.contains("stream inativo")));
+
+    let recovering = start + Duration::minutes(3);
+    assert!(incidents
+        .observe(&watchdog_report(&rules, &healthy, recovering), recovering)
+        .unwrap()
+        .is_none());
+    assert!(incidents.open_incident().is_some());
+    let recovered = start + Duration::minutes(7);
+    let resolved = incidents
+        .observe(&watchdog_report(&rules, &healthy, recovered), recovered)
+        .unwrap()
+        .unwrap();
+    assert_eq!(resolved.change, IncidentChange::Resolved);
+    assert_eq!(resolved.incident.resolved_at, Some(recovered));
+    assert_eq!(resolved.incident.incident_id, opened.incident.incident_id);
+    assert!(incidents.open_incident().is_none());
+}
+
+#[test]
+fn repeated_restarts_open_an_incident_without_an_escalation() {
+    let dir = TempDir::new().unwrap();
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let rules = WatchdogRules::from_config(&config.watchdog).unwrap();
+    let incidents = WatchdogIncidents::new(
+        config.watchdog.incidents.clone(),
+        IncidentNotifier::new(SeverityRouting::default(), None, None).with_dry_run(true),
+        IncidentHistoryWriter::new(dir.path().join("incidents")),
+    );
+    let start = Utc::now();
+    let off_air = WatchdogInputs {
+        buffer_hours: 5.0,
+        stream_inactive: true,
+        ..Default::default()
+    };
+
+    assert!(incidents
+        .observe(&watchdog_report(&rules, &off_air, start), start)
+        .unwrap()
+        .is_none());
+    let next = start + Duration::seconds(30);
+    let opened = incidents
+        .observe(&watchdog_report(&rules, &off_air, next), next)
+        .unwrap()
+        .unwrap();
+    assert_eq!(opened.change, IncidentChange::Opened);
+    assert_eq!(opened.incident.severity, IncidentSeverity::Critical);
+    assert_eq!(
+        opened.incident.timeline[0].description,
+        "regra stream_inactive: ffprobe detectou stream inativo"
+    );
+    assert!(opened.record.json_path.unwrap().exists());
+
+    // A low buffer keeps its own rule firing but not the restart incident.
+    let draining = WatchdogInputs {
+        buffer_hours: 1.5,
+        ..Default::default()
+    };
+    for minute in 1..=5 {
+        let at = next + Duration::minutes(minute);
+        let report = watchdog_report(&rules, &draining, at);
+        assert_eq!(report.firings[0].rule, "buffer_critical");
+        let update = incidents.observe(&report, at).unwrap();
+        // Logged on the timeline once, then resolved when stream_inactive
+        // has been quiet for resolve_after_seconds.
+        assert_eq!(
+            update.map(|update| update.change == IncidentChange::Resolved),
+            match minute {
+                1 => Some(false),
+                5 => Some(true),
+                _ => None,
+            }
+        );
+    }
+    assert!(incidents.open_incident().is_none());
+}
+
+#[test]
+fn detection_filters_are_read_from_ffmpeg_stderr() {
+    let stderr = "\
+[blackdetect @ 0x55d0c8] black_start:1.2 black_end:3.4 black_duration:2.2
+[freezedetect @ 0x55d0c9] lavfi.freezedetect.freeze_start: 4.004
+[freezedetect @ 0x55d0c9] lavfi.freezedetect.freeze_duration: 2.002
+[freezedetect @ 0x55d0c9] lavfi.freezedetect.freeze_end: 6.006
+[silencedetect @ 0x55d0ca] silence_start: 7.5
+[freezedetect @ 0x55d0c9] lavfi.freezedetect.freeze_start: 8
+";
+
+    assert_eq!(
+        parse_detections(stderr),
+        vec![
+            Detection {
+                kind: DetectionKind::Black,
+                start_s: 1.2,
+                end_s: Some(3.4),
+            },
+            Detection {
+                kind: DetectionKind::Freeze,
+                start_s: 4.004,
+                end_s: Some(6.006),
+            },
+            Detection {
+                kind: DetectionKind::Silence,
+                start_s: 7.5,
+                end_s: None,
+            },
+            Detection {
+                kind: DetectionKind::Freeze,
+                start_s: 8.0,
+                end_s: None,
+            },
+        ]
+    );
+}
+
+#[tokio::test]
+async fn frozen_output_carries_over_captures_until_the_rule_fires() {
+    let dir = TempDir::new().unwrap();
+    let store = queue_store(dir.path());
+    let metrics = MetricsStore::new(dir.path().join("metrics.db")).unwrap();
+    metrics.initialize().unwrap();
+    let config = load_broadcaster_config(config_path()).unwrap();
+    let executor = Arc::new(FakeFfmpeg::new(dir.path().join("published.ts")));
+    *executor.detections.lock().unwrap() = vec![
+        "[freezedetect @ 0x1] lavfi.freezedetect.freeze_start: 4\n".to_string(),
+        "[freezedetect @ 0x1] lavfi.freezedetect.freeze_start: 0\n\
+         [silencedetect @ 0x2] silence_start: 2\n\
+         [silencedetect @ 0x2] silence_end: 5 | silence_duration: 3\n"
+            .to_string(),
+    ];
+    let watchdog = Watchdog::new(
+        store,
+        config.watchdog,
+        BroadcasterPaths {
+            ffmpeg: PathBuf::from("ffmpeg"),
+            ffprobe: PathBuf::from("ffprobe"),
+            archive_dir: dir.path().join("archive"),
+            temp_dir: dir.path().join("tmp"),
+        },
+        None,
+        Some(executor.clone()),
+        ORIGIN.to_string(),
+        Some(dir.path().join("reports")),
+    )
+    .unwrap()
+    .with_metrics_store(metrics.clone());
+    assert_eq!(watchdog.output_monitor().unwrap().url(), ORIGIN);
+
+    let first = watchdog.evaluate().await.unwrap();
+    let sample = first.output.unwrap();
+    assert_eq!(sample.frozen_for_s, 6.0);
+    assert!(!first
+        .firings
+        .iter()
+        .any(|firing| firing.rule == "output_frozen"));
+
+    let second = watchdog.evaluate().await.unwrap();
+    let sample = second.output.unwrap();
+    assert_eq!(sample.frozen_for_s, 16.0);
+    assert_eq!(sample.silent_s, 3.0);
+    assert_eq!(sample.silent_for_s, 0.0);
+    assert!(second.actions.contains(&WatchdogAction::RestartEncoder));
+    assert!(second
+        .observations
+        .contains(&"regra output_frozen: imagem congelada na saída há 16s".to_string()));
+    let firing = second
+        .firings
+        .iter()
+        .find(|firing| firing.rule == "output_frozen")
+        .unwrap();
+    assert_eq!(firing.severity, IncidentSeverity::Critical);
+
+    let recorded = metrics.output_samples(10).unwrap();
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[1].frozen_for_s, 16.0);
+    assert_eq!(recorded[1].freeze_events, 1);
+
+    // A freeze still running a whole interval later also covered the time
+    // between the captures.
+    executor
+        .detections
+        .lock()
+        .unwrap()
+        .push("[freezedetect @ 0x1] lavfi.freezedetect.freeze_start: 0\n".to_string());
+    let monitor = watchdog.output_monitor().unwrap();
+    let third = monitor
+        .sample(sample.at + Duration::seconds(30))
+        .await
+        .unwrap();
+    assert_eq!(third.frozen_for_s, 46.0);
+}
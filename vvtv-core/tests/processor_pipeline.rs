@@ -8,7 +8,9 @@ use vvtv_core::browser::{
 };
 use vvtv_core::config::{load_processor_config, load_vvtv_config, ProcessorConfig, VvtvConfig};
 use vvtv_core::plan::{Plan, PlanStatus, SqlitePlanStore};
-use vvtv_core::processor::{MasteringStrategy, Processor, ProcessorResult};
+use vvtv_core::processor::{
+    HlsMasterPlaylist, MasteringStrategy, Processor, ProcessorResult, VariantCaps,
+};
 use vvtv_core::queue::{PlayoutQueueStore, QueueItem};
 
 fn adjust_vvtv_config(base: &TempDir, mut config: VvtvConfig) -> VvtvConfig {
@@ -158,6 +160,9 @@ fn read_queue_items(queue_path: &Path) -> Vec<QueueItem> {
                 priority: row.get(4)?,
                 node_origin: row.get(5)?,
                 content_kind: row.get(6)?,
+                schedule: None,
+                tags: Vec::new(),
+                source_domain: None,
             })
         })
         .unwrap();
@@ -195,6 +200,16 @@ async fn processor_hls_pipeline_success() {
         .join("plan-hls");
     assert!(ready_dir.join("hls_720p.m3u8").exists());
     assert!(ready_dir.join("checksums.json").exists());
+    let manifest: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(ready_dir.join("manifest.json")).unwrap()).unwrap();
+    assert_eq!(
+        manifest["sizes"]["master.mp4"].as_u64(),
+        Some(
+            std::fs::metadata(ready_dir.join("master.mp4"))
+                .unwrap()
+                .len()
+        )
+    );
 
     let staging_dir = Path::new(&vvtv_config.paths.cache_dir)
         .join("tmp_downloads")
@@ -206,6 +221,127 @@ async fn processor_hls_pipeline_success() {
     assert!(queue_items[0].asset_path.ends_with("hls_720p.m3u8"));
 }
 
+const MASTER_PLAYLIST: &str = r#"#EXTM3U
+#EXT-X-VERSION:6
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="English",LANGUAGE="en",DEFAULT=YES,AUTOSELECT=YES,URI="audio/en/media.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",NAME="Português",LANGUAGE="pt-BR",AUTOSELECT=YES,URI="audio/pt/media.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=14000000,RESOLUTION=3840x2160,CODECS="hvc1.2.4.L153.B0,mp4a.40.2",FRAME-RATE=30.000,AUDIO="aac"
+2160p/media.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,AVERAGE-BANDWIDTH=4500000,RESOLUTION=1920x1080,CODECS="avc1.640028,mp4a.40.2",FRAME-RATE=30.000,AUDIO="aac"
+1080p/media.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2500000,RESOLUTION=1280x720,CODECS="avc1.4d401f,mp4a.40.2",FRAME-RATE=30.000,AUDIO="aac"
+720p/media.m3u8
+"#;
+
+#[test]
+fn hls_master_selects_best_variant_within_caps() {
+    let master = HlsMasterPlaylist::parse(MASTER_PLAYLIST).unwrap();
+    assert_eq!(master.variants.len(), 3);
+    assert_eq!(master.audio.len(), 2);
+    assert_eq!(master.variants[1].codecs, ["avc1.640028", "mp4a.40.2"]);
+    assert_eq!(master.variants[1].average_bandwidth, Some(4_500_000));
+
+    let caps = VariantCaps {
+        max_height: Some(1080),
+        max_bandwidth: None,
+        max_frame_rate: Some(60.0),
+        video_codecs: vec!["avc1".into()],
+    };
+    let variant = master.select_variant(&caps).unwrap();
+    assert_eq!(variant.uri, "1080p/media.m3u8");
+
+    let capped = VariantCaps {
+        max_bandwidth: Some(3_000_000),
+        ..caps.clone()
+    };
+    assert_eq!(
+        master.select_variant(&capped).unwrap().uri,
+        "720p/media.m3u8"
+    );
+
+    let unreachable = VariantCaps {
+        max_bandwidth: Some(1_000_000),
+        ..caps
+    };
+    assert_eq!(
+        master.select_variant(&unreachable).unwrap().uri,
+        "720p/media.m3u8"
+    );
+
+    let audio = master.audio_for(variant, Some("pt")).unwrap();
+    assert_eq!(audio.name, "Português");
+    let audio = master.audio_for(variant, Some("de")).unwrap();
+    assert_eq!(audio.language.as_deref(), Some("en"));
+
+    assert!(HlsMasterPlaylist::is_master(MASTER_PLAYLIST));
+    assert!(HlsMasterPlaylist::parse("#EXTM3U\n#EXTINF:4,\nsegment.ts\n").is_err());
+}
+
+#[test]
+fn hls_master_never_selects_an_audio_only_variant() {
+    let contents = format!(
+        "{MASTER_PLAYLIST}#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"\naudio/media.m3u8\n"
+    );
+    let master = HlsMasterPlaylist::parse(&contents).unwrap();
+    assert!(master.variants[3].is_audio_only());
+    assert!(!master.variants[0].is_audio_only());
+
+    let caps = VariantCaps {
+        max_height: Some(1080),
+        max_bandwidth: Some(100_000),
+        max_frame_rate: None,
+        video_codecs: Vec::new(),
+    };
+    assert!(!caps.allows(&master.variants[3]));
+    // Nothing with video fits, and the lightest video variant wins over the
+    // audio-only one.
+    assert_eq!(master.select_variant(&caps).unwrap().uri, "720p/media.m3u8");
+
+    let audio_only = HlsMasterPlaylist::parse(
+        "#EXTM3U\n#EXT-X-STREAM-INF:BANDWIDTH=64000,CODECS=\"mp4a.40.2\"\naudio/media.m3u8\n",
+    )
+    .unwrap();
+    assert!(audio_only.select_variant(&caps).is_none());
+}
+
+#[tokio::test]
+async fn processor_hls_master_downloads_selected_variant_and_audio() {
+    let base = TempDir::new().unwrap();
+    let (processor, plan_store, _queue_store, vvtv_config, _processor_cfg, _queue_path) =
+        build_processor(&base).await.unwrap();
+
+    let fixtures = base.path().join("fixtures");
+    for dir in ["2160p", "1080p", "720p", "audio/en", "audio/pt"] {
+        let dir = fixtures.join(dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        hls_playlist(&dir, &[4.0, 4.0]);
+    }
+    let master_path = fixtures.join("master.m3u8");
+    std::fs::write(&master_path, MASTER_PLAYLIST).unwrap();
+    let master_url = format!("file://{}", master_path.display());
+
+    let plan = make_plan("plan-hls-master", &master_url);
+    plan_store.upsert_plan(&plan).unwrap();
+
+    let outcome = pbd_outcome(master_url, BrowserCaptureKind::HlsMaster, 1080);
+    let report = processor
+        .process_with_capture(&plan, outcome)
+        .await
+        .unwrap();
+    assert_eq!(report.strategy, MasteringStrategy::Remux);
+
+    let ready_dir = Path::new(&vvtv_config.paths.storage_dir)
+        .join("ready")
+        .join("plan-hls-master");
+    let stub = std::fs::read_to_string(ready_dir.join("master.mp4")).unwrap();
+    assert!(stub.contains("1080p/segment_0.ts"));
+    assert!(stub.contains("1080p/segment_1.ts"));
+    assert!(!stub.contains("2160p/"));
+    assert!(!stub.contains("720p/"));
+    assert!(stub.contains("audio/en/segment_0.ts"));
+    assert!(!stub.contains("audio/pt/"));
+}
+
 #[tokio::test]
 async fn processor_progressive_transcode_and_hd_missing() {
     let base = TempDir::new().unwrap();
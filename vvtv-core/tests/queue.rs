@@ -1,8 +1,16 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 
-use chrono::Duration;
+use chrono::{Duration, TimeZone, Utc};
 use tempfile::TempDir;
-use vvtv_core::{PlayoutQueueStore, QueueFilter, QueueItem, QueueSelectionPolicy, QueueStatus};
+use vvtv_core::{
+    AnchorKind, AsRunFormat, AsRunOutcome, AsRunRecord, Daypart, DaypartSchedule, EmergencyRefill,
+    ForecastOptions, ForecastSource, Interruption, PlayoutQueueStore, QueueError, QueueFilter,
+    QueueItem, QueueSchedule, QueueSelectionPolicy, QueueStatus, RecoveryAction,
+    RestoreConflictKind, RestoreMode, RestoreOptions, ResumePolicy, RetryOutcome, RetryPolicy,
+    ScheduledInjection, SeparationRules, StrictPriority, WeightedRoundRobin,
+};
 
 fn temp_store(dir: &Path) -> (PlayoutQueueStore, std::path::PathBuf) {
     let path = dir.join("queue.sqlite");
@@ -27,6 +35,9 @@ fn enqueue_and_list_items() {
         priority: 0,
         node_origin: Some("node-a".into()),
         content_kind: Some("video".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
     };
     store.enqueue(&item).unwrap();
 
@@ -54,6 +65,9 @@ fn begin_playback_prefers_priority_and_music_ratio() {
         priority: 1,
         node_origin: None,
         content_kind: Some("video".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
     };
     store.enqueue(&high_priority).unwrap();
 
@@ -65,6 +79,9 @@ fn begin_playback_prefers_priority_and_music_ratio() {
         priority: 0,
         node_origin: None,
         content_kind: Some("music".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
     };
     store.enqueue(&music).unwrap();
 
@@ -96,6 +113,9 @@ fn metrics_and_cleanup() {
         priority: 0,
         node_origin: None,
         content_kind: Some("video".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
     };
     let queued_id = store.enqueue(&queued).unwrap();
 
@@ -107,6 +127,9 @@ fn metrics_and_cleanup() {
         priority: 0,
         node_origin: None,
         content_kind: Some("video".into()),
+        schedule: None,
+        tags: Vec::new(),
+        source_domain: None,
     };
     let played_id = store.enqueue(&played).unwrap();
     store
@@ -135,3 +158,1053 @@ fn metrics_and_cleanup() {
     assert_eq!(list.len(), 1);
     assert_eq!(list[0].id, queued_id);
 }
+
+#[test]
+fn anchored_entries_fill_gap_and_air_on_time() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let now = Utc::now();
+
+    let anchor = QueueItem {
+        plan_id: "sponsor-block".into(),
+        asset_path: "/tmp/sponsor.mp4".into(),
+        duration_s: Some(300),
+        curation_score: Some(0.5),
+        priority: 0,
+        content_kind: Some("video".into()),
+        schedule: Some(QueueSchedule::hard(now + Duration::minutes(10))),
+        ..Default::default()
+    };
+    store.enqueue(&anchor).unwrap();
+
+    let long_filler = QueueItem {
+        plan_id: "long".into(),
+        asset_path: "/tmp/long.mp4".into(),
+        duration_s: Some(1200),
+        curation_score: Some(0.9),
+        priority: 5,
+        content_kind: Some("video".into()),
+        ..Default::default()
+    };
+    store.enqueue(&long_filler).unwrap();
+
+    let short_filler = QueueItem {
+        plan_id: "short".into(),
+        asset_path: "/tmp/short.mp4".into(),
+        duration_s: Some(420),
+        curation_score: Some(0.6),
+        priority: 0,
+        content_kind: Some("video".into()),
+        ..Default::default()
+    };
+    store.enqueue(&short_filler).unwrap();
+
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let first = store.begin_playback_at(&policy, now).unwrap().unwrap();
+    assert_eq!(first.plan_id, "short");
+    store
+        .mark_playback_result(first.id, QueueStatus::Played, first.duration_s, None)
+        .unwrap();
+
+    // Three minutes remain and nothing fits: the hard anchor waits.
+    let waiting = store
+        .begin_playback_at(&policy, now + Duration::minutes(7))
+        .unwrap();
+    assert!(waiting.is_none());
+
+    let on_time = store
+        .begin_playback_at(&policy, now + Duration::minutes(10))
+        .unwrap()
+        .unwrap();
+    assert_eq!(on_time.plan_id, "sponsor-block");
+    assert_eq!(
+        on_time.schedule.unwrap().start.timestamp(),
+        (now + Duration::minutes(10)).timestamp()
+    );
+}
+
+#[test]
+fn soft_anchor_slips_behind_overrunning_filler() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let now = Utc::now();
+
+    store
+        .enqueue(&QueueItem {
+            plan_id: "themed-block".into(),
+            asset_path: "/tmp/themed.mp4".into(),
+            duration_s: Some(600),
+            content_kind: Some("video".into()),
+            schedule: Some(QueueSchedule::soft(now + Duration::minutes(5))),
+            ..Default::default()
+        })
+        .unwrap();
+    store
+        .enqueue(&QueueItem {
+            plan_id: "filler".into(),
+            asset_path: "/tmp/filler.mp4".into(),
+            duration_s: Some(900),
+            content_kind: Some("video".into()),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let first = store.begin_playback_at(&policy, now).unwrap().unwrap();
+    assert_eq!(first.plan_id, "filler");
+}
+
+#[test]
+fn soft_anchor_airs_early_only_within_tolerance() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let now = Utc::now();
+
+    store
+        .enqueue(&QueueItem {
+            plan_id: "themed-block".into(),
+            asset_path: "/tmp/themed.mp4".into(),
+            duration_s: Some(600),
+            content_kind: Some("video".into()),
+            schedule: Some(QueueSchedule::soft(now + Duration::minutes(30))),
+            ..Default::default()
+        })
+        .unwrap();
+
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    assert!(store.begin_playback_at(&policy, now).unwrap().is_none());
+    assert!(store
+        .begin_playback_at(&policy, now + Duration::minutes(20))
+        .unwrap()
+        .is_none());
+
+    let early = store
+        .begin_playback_at(&policy, now + Duration::minutes(26))
+        .unwrap()
+        .unwrap();
+    assert_eq!(early.plan_id, "themed-block");
+}
+
+#[test]
+fn entries_of_unknown_length_fill_until_an_anchor_is_near() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let now = Utc::now();
+
+    store
+        .enqueue(&QueueItem {
+            plan_id: "evening-news".into(),
+            asset_path: "/tmp/news.mp4".into(),
+            duration_s: Some(1800),
+            content_kind: Some("video".into()),
+            schedule: Some(QueueSchedule::hard(now + Duration::hours(3))),
+            ..Default::default()
+        })
+        .unwrap();
+    for plan_id in ["emergency-loop", "unprobed"] {
+        store
+            .enqueue(&QueueItem {
+                plan_id: plan_id.into(),
+                asset_path: format!("/tmp/{plan_id}.mp4"),
+                duration_s: None,
+                content_kind: Some("video".into()),
+                ..Default::default()
+            })
+            .unwrap();
+    }
+
+    // Hours ahead of the anchor, an unknown length is taken as the
+    // fallback estimate rather than left off the air.
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let first = store.begin_playback_at(&policy, now).unwrap().unwrap();
+    assert_eq!(first.duration_s, None);
+    store
+        .mark_playback_result(first.id, QueueStatus::Played, None, None)
+        .unwrap();
+
+    // Within the estimate of the anchor it no longer fits.
+    let near = now + Duration::hours(3) - Duration::minutes(4);
+    assert!(store.begin_playback_at(&policy, near).unwrap().is_none());
+}
+
+fn enqueue_kind(store: &PlayoutQueueStore, plan_id: &str, kind: &str, priority: i64) -> i64 {
+    store
+        .enqueue(&QueueItem {
+            plan_id: plan_id.into(),
+            asset_path: format!("/tmp/{plan_id}.mp4"),
+            duration_s: Some(60),
+            curation_score: Some(0.5),
+            priority,
+            content_kind: Some(kind.into()),
+            ..Default::default()
+        })
+        .unwrap()
+}
+
+#[test]
+fn weighted_round_robin_interleaves_kinds() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    for index in 0..4 {
+        enqueue_kind(&store, &format!("video-{index}"), "video", 0);
+    }
+    for index in 0..2 {
+        enqueue_kind(&store, &format!("music-{index}"), "music", 0);
+    }
+
+    let weights = HashMap::from([("video".to_string(), 2.0), ("music".to_string(), 1.0)]);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24))
+        .with_strategy(Arc::new(WeightedRoundRobin::new(weights)));
+    let mut aired = Vec::new();
+    let mut now = Utc::now();
+    while let Some(entry) = store.begin_playback_at(&policy, now).unwrap() {
+        store
+            .mark_playback_result(entry.id, QueueStatus::Played, entry.duration_s, None)
+            .unwrap();
+        aired.push(entry.content_kind.unwrap());
+        now += Duration::seconds(60);
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert_eq!(
+        aired,
+        vec!["video", "music", "video", "video", "music", "video"]
+    );
+}
+
+#[test]
+fn peek_next_counts_the_entry_on_air_without_touching_the_queue() {
+    let dir = TempDir::new().unwrap();
+    let (store, path) = temp_store(dir.path());
+    enqueue_kind(&store, "video-0", "video", 0);
+    enqueue_kind(&store, "video-1", "video", 0);
+    enqueue_kind(&store, "music-0", "music", 0);
+
+    let weights = HashMap::from([("video".to_string(), 1.0), ("music".to_string(), 1.0)]);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24))
+        .with_strategy(Arc::new(WeightedRoundRobin::new(weights)));
+    let now = Utc::now();
+    let current = store.begin_playback_at(&policy, now).unwrap().unwrap();
+    assert_eq!(current.plan_id, "video-0");
+    let before = store.list(&QueueFilter::default()).unwrap();
+
+    // It only reads the queue, so a writer holding the lock does not hold
+    // it up.
+    let mut writer = rusqlite::Connection::open(&path).unwrap();
+    let write = writer
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .unwrap();
+    let next = store
+        .peek_next_at(&policy, current.id, now + Duration::seconds(60))
+        .unwrap()
+        .unwrap();
+    assert_eq!(next.plan_id, "music-0");
+    write.rollback().unwrap();
+    let after = store.list(&QueueFilter::default()).unwrap();
+    assert_eq!(
+        after.iter().map(|e| (e.id, e.status)).collect::<Vec<_>>(),
+        before.iter().map(|e| (e.id, e.status)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn strict_priority_ignores_music_ratio_and_bump() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    enqueue_kind(&store, "first", "video", 0);
+    enqueue_kind(&store, "music", "music", 0);
+    enqueue_kind(&store, "urgent", "video", 3);
+
+    let policy = QueueSelectionPolicy::new(Some(1), 0.0, Duration::zero())
+        .with_strategy(Arc::new(StrictPriority));
+    let first = store.begin_playback(&policy).unwrap().unwrap();
+    assert_eq!(first.plan_id, "urgent");
+    store
+        .mark_playback_result(first.id, QueueStatus::Played, first.duration_s, None)
+        .unwrap();
+    let second = store.begin_playback(&policy).unwrap().unwrap();
+    assert_eq!(second.plan_id, "first");
+}
+
+#[test]
+fn daypart_ratios_drive_content_mix() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    for index in 0..3 {
+        enqueue_kind(&store, &format!("video-{index}"), "video", 1);
+    }
+    for index in 0..3 {
+        enqueue_kind(&store, &format!("music-{index}"), "music", 0);
+    }
+
+    let schedule = DaypartSchedule::new(
+        chrono_tz::UTC,
+        vec![Daypart {
+            name: "all_day".into(),
+            start_hour: 0,
+            end_hour: 0,
+            ratios: HashMap::from([("video".to_string(), 0.5), ("music".to_string(), 0.5)]),
+        }],
+    );
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24)).with_dayparts(schedule);
+    let mut aired = Vec::new();
+    while let Some(entry) = store.begin_playback(&policy).unwrap() {
+        store
+            .mark_playback_result(entry.id, QueueStatus::Played, entry.duration_s, None)
+            .unwrap();
+        aired.push(entry.content_kind.unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(5));
+    }
+    assert_eq!(
+        aired,
+        vec!["video", "music", "video", "music", "video", "music"]
+    );
+}
+
+#[test]
+fn daypart_windows_wrap_midnight_in_local_time() {
+    let schedule = DaypartSchedule::new(
+        chrono_tz::America::Sao_Paulo,
+        vec![
+            Daypart {
+                name: "late_night".into(),
+                start_hour: 22,
+                end_hour: 6,
+                ratios: HashMap::from([("music".to_string(), 1.0)]),
+            },
+            Daypart {
+                name: "day".into(),
+                start_hour: 6,
+                end_hour: 22,
+                ratios: HashMap::from([("video".to_string(), 1.0)]),
+            },
+        ],
+    );
+    // 04:30 UTC is 01:30 in São Paulo (UTC-3).
+    let now = Utc.with_ymd_and_hms(2024, 5, 10, 4, 30, 0).unwrap();
+    let active = schedule.active(now).unwrap();
+    assert_eq!(active.daypart.name, "late_night");
+    assert_eq!(
+        active.started_at,
+        Utc.with_ymd_and_hms(2024, 5, 10, 1, 0, 0).unwrap()
+    );
+
+    let afternoon = Utc.with_ymd_and_hms(2024, 5, 10, 18, 0, 0).unwrap();
+    assert_eq!(schedule.active(afternoon).unwrap().daypart.name, "day");
+}
+
+#[test]
+fn separation_keeps_shared_tags_apart_and_relaxes_when_starving() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let item = |plan_id: &str, tags: &[&str], domain: &str, priority: i64| QueueItem {
+        plan_id: plan_id.into(),
+        asset_path: format!("/tmp/{plan_id}.mp4"),
+        duration_s: Some(60),
+        priority,
+        content_kind: Some("video".into()),
+        tags: tags.iter().map(|tag| tag.to_string()).collect(),
+        source_domain: Some(domain.into()),
+        ..Default::default()
+    };
+    store
+        .enqueue(&item("jazz-1", &["jazz", "live"], "a.example", 2))
+        .unwrap();
+    store
+        .enqueue(&item("jazz-2", &["Jazz"], "b.example", 1))
+        .unwrap();
+    store
+        .enqueue(&item("same-domain", &["rock"], "a.example", 1))
+        .unwrap();
+    store
+        .enqueue(&item("other", &["ambient"], "c.example", 0))
+        .unwrap();
+
+    let rules = SeparationRules {
+        tag: Duration::minutes(30),
+        source_domain: Duration::minutes(20),
+        plan: Duration::minutes(180),
+    };
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24)).with_separation(rules);
+    let mut aired = Vec::new();
+    while let Some(entry) = store.begin_playback(&policy).unwrap() {
+        store
+            .mark_playback_result(entry.id, QueueStatus::Played, entry.duration_s, None)
+            .unwrap();
+        aired.push(entry.plan_id);
+    }
+    // jazz-2 shares a tag and same-domain shares a domain with jazz-1, so the
+    // lowest priority entry airs second; the rest air through relaxation.
+    assert_eq!(aired[0], "jazz-1");
+    assert_eq!(aired[1], "other");
+    assert_eq!(aired.len(), 4);
+}
+
+#[test]
+fn failed_entries_retry_with_backoff_until_dead() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let id = enqueue_kind(&store, "flaky", "video", 0);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let retry = RetryPolicy {
+        max_attempts: 3,
+        base_delay: Duration::seconds(30),
+        max_delay: Duration::seconds(45),
+    };
+    let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+    let entry = store.begin_playback_at(&policy, start).unwrap().unwrap();
+    assert_eq!(entry.id, id);
+    let outcome = store
+        .record_failure_at(id, "rtmp reset", &retry, start)
+        .unwrap();
+    assert_eq!(
+        outcome,
+        RetryOutcome::Requeued {
+            retry_count: 1,
+            next_attempt_at: start + Duration::seconds(30),
+        }
+    );
+    assert!(store
+        .begin_playback_at(&policy, start + Duration::seconds(29))
+        .unwrap()
+        .is_none());
+
+    let second = start + Duration::seconds(30);
+    let entry = store.begin_playback_at(&policy, second).unwrap().unwrap();
+    assert_eq!(entry.retry_count, 1);
+    let playing = store
+        .list(&QueueFilter {
+            status: Some(QueueStatus::Playing),
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(playing[0].play_finished_at, None);
+    let outcome = store
+        .record_failure_at(id, "rtmp reset", &retry, second)
+        .unwrap();
+    // 60s of doubled backoff is capped at max_delay.
+    assert_eq!(
+        outcome,
+        RetryOutcome::Requeued {
+            retry_count: 2,
+            next_attempt_at: second + Duration::seconds(45),
+        }
+    );
+
+    let third = second + Duration::seconds(45);
+    store.begin_playback_at(&policy, third).unwrap().unwrap();
+    let outcome = store
+        .record_failure_at(id, "rtmp reset", &retry, third)
+        .unwrap();
+    assert_eq!(outcome, RetryOutcome::Dead { attempts: 3 });
+    assert!(store
+        .begin_playback_at(&policy, third + Duration::hours(1))
+        .unwrap()
+        .is_none());
+
+    let dead = store
+        .list(&QueueFilter {
+            status: Some(QueueStatus::Dead),
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0].retry_count, 3);
+    assert_eq!(dead[0].failure_reason.as_deref(), Some("rtmp reset"));
+}
+
+#[test]
+fn orphaned_playing_entries_are_reconciled_on_startup() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+    let finished = enqueue_kind(&store, "finished", "video", 1);
+    store.begin_playback_at(&policy, start).unwrap().unwrap();
+    let interrupted = enqueue_kind(&store, "interrupted", "video", 1);
+    store
+        .begin_playback_at(&policy, start + Duration::seconds(50))
+        .unwrap()
+        .unwrap();
+
+    // enqueue_kind items last 60s: the first ran its course, the second did not.
+    let decisions = store
+        .recover_orphaned_at(start + Duration::seconds(70))
+        .unwrap();
+    assert_eq!(decisions.len(), 2);
+    assert_eq!(decisions[0].id, finished);
+    assert_eq!(decisions[0].action, RecoveryAction::MarkedPlayed);
+    assert_eq!(decisions[1].id, interrupted);
+    assert_eq!(decisions[1].action, RecoveryAction::Requeued);
+    assert_eq!(decisions[1].elapsed, Some(Duration::seconds(20)));
+
+    let summary = store.summary().unwrap();
+    assert_eq!(summary.counts.get(&QueueStatus::Playing), None);
+    assert_eq!(summary.counts.get(&QueueStatus::Played), Some(&1));
+    let requeued = store
+        .list(&QueueFilter {
+            status: Some(QueueStatus::Queued),
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(requeued.len(), 1);
+    assert_eq!(requeued[0].plan_id, "interrupted");
+    assert!(requeued[0].play_started_at.is_none());
+    assert_eq!(requeued[0].retry_count, 0);
+    assert_eq!(requeued[0].failure_reason, None);
+    let played = store
+        .list(&QueueFilter {
+            status: Some(QueueStatus::Played),
+            limit: None,
+        })
+        .unwrap();
+    assert_eq!(played[0].failure_reason, None);
+
+    let as_run = store
+        .as_run_between(start, start + Duration::minutes(5))
+        .unwrap();
+    assert_eq!(as_run.len(), 2);
+    assert_eq!(as_run[0].outcome, AsRunOutcome::Played);
+    assert!(as_run[0].note.as_deref().unwrap().contains("covers 60s"));
+    assert_eq!(as_run[1].outcome, AsRunOutcome::Interrupted);
+    assert!(as_run[1]
+        .note
+        .as_deref()
+        .unwrap()
+        .contains("interrupted after 20s"));
+
+    assert!(store.recover_orphaned_at(start).unwrap().is_empty());
+}
+
+#[test]
+fn import_backup_merges_or_replaces_and_reports_conflicts() {
+    let dir = TempDir::new().unwrap();
+    let source_dir = dir.path().join("source");
+    std::fs::create_dir_all(&source_dir).unwrap();
+    let (source, _) = temp_store(&source_dir);
+    enqueue_kind(&source, "archived-a", "video", 0);
+    let failed = enqueue_kind(&source, "archived-b", "music", 0);
+    source
+        .mark_playback_result(
+            failed,
+            QueueStatus::Failed,
+            None,
+            Some("rtmp: it's gone;\nretry later"),
+        )
+        .unwrap();
+    let archive = dir.path().join("queue.sql.gz");
+    source.export_backup(&archive).unwrap();
+
+    let (store, _) = temp_store(dir.path());
+    let local = enqueue_kind(&store, "local", "video", 0);
+    let merge = RestoreOptions::default();
+
+    let report = store.import_backup(&archive, &merge).unwrap();
+    assert_eq!(report.archived, 2);
+    assert_eq!(report.restored, 2);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].kind, RestoreConflictKind::IdReassigned);
+    assert_eq!(report.conflicts[0].existing_id, Some(local));
+    let restored = store.list(&QueueFilter::default()).unwrap();
+    assert_eq!(restored.len(), 3);
+    let failed_entry = restored
+        .iter()
+        .find(|entry| entry.plan_id == "archived-b")
+        .unwrap();
+    assert_eq!(failed_entry.id, failed);
+    assert_eq!(failed_entry.status, QueueStatus::Failed);
+    assert_eq!(
+        failed_entry.failure_reason.as_deref(),
+        Some("rtmp: it's gone;\nretry later")
+    );
+
+    let dry_run = RestoreOptions {
+        dry_run: true,
+        ..merge
+    };
+    let report = store.import_backup(&archive, &dry_run).unwrap();
+    assert_eq!(report.restored, 0);
+    assert_eq!(report.skipped, 2);
+    let kinds: Vec<_> = report.conflicts.iter().map(|c| c.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            RestoreConflictKind::DuplicatePlan,
+            RestoreConflictKind::SameEntry
+        ]
+    );
+
+    let replace = RestoreOptions {
+        mode: RestoreMode::Replace,
+        dry_run: true,
+    };
+    let report = store.import_backup(&archive, &replace).unwrap();
+    assert_eq!(report.removed, 3);
+    assert_eq!(report.restored, 2);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].kind, RestoreConflictKind::Discarded);
+    assert_eq!(report.conflicts[0].plan_id, "local");
+    assert_eq!(store.list(&QueueFilter::default()).unwrap().len(), 3);
+
+    let report = store
+        .import_backup(
+            &archive,
+            &RestoreOptions {
+                dry_run: false,
+                ..replace
+            },
+        )
+        .unwrap();
+    assert_eq!(report.restored, 2);
+    let plans: Vec<_> = store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.plan_id)
+        .collect();
+    assert!(!plans.contains(&"local".to_string()));
+    assert_eq!(plans.len(), 2);
+}
+
+#[test]
+fn replace_restore_keeps_the_entry_on_air() {
+    let dir = TempDir::new().unwrap();
+    let source_dir = dir.path().join("source");
+    std::fs::create_dir_all(&source_dir).unwrap();
+    let (source, _) = temp_store(&source_dir);
+    enqueue_kind(&source, "archived-a", "video", 0);
+    enqueue_kind(&source, "on-air", "video", 0);
+    let archive = dir.path().join("queue.sql.gz");
+    source.export_backup(&archive).unwrap();
+
+    let (store, _) = temp_store(dir.path());
+    let on_air = enqueue_kind(&store, "on-air", "video", 1);
+    let local = enqueue_kind(&store, "local", "video", 0);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    assert_eq!(store.begin_playback(&policy).unwrap().unwrap().id, on_air);
+    store
+        .request_break_in(local, ResumePolicy::Resume, None)
+        .unwrap();
+
+    let report = store
+        .import_backup(
+            &archive,
+            &RestoreOptions {
+                mode: RestoreMode::Replace,
+                dry_run: false,
+            },
+        )
+        .unwrap();
+    assert_eq!(report.removed, 1);
+    assert_eq!(report.restored, 1);
+    assert_eq!(report.skipped, 1);
+    let conflicts: Vec<_> = report
+        .conflicts
+        .iter()
+        .map(|conflict| (conflict.kind, conflict.plan_id.as_str()))
+        .collect();
+    assert_eq!(
+        conflicts,
+        vec![
+            (RestoreConflictKind::KeptPlaying, "on-air"),
+            (RestoreConflictKind::Discarded, "local"),
+            (RestoreConflictKind::IdReassigned, "archived-a"),
+            (RestoreConflictKind::DuplicatePlan, "on-air"),
+        ]
+    );
+    assert_eq!(report.conflicts[1].existing_id, Some(local));
+
+    let entries = store.list(&QueueFilter::default()).unwrap();
+    assert_eq!(entries.len(), 2);
+    let playing = entries.iter().find(|entry| entry.id == on_air).unwrap();
+    assert_eq!(playing.status, QueueStatus::Playing);
+    assert!(entries.iter().any(|entry| entry.plan_id == "archived-a"));
+    // The break-in for the discarded entry went with it.
+    assert!(store.pending_break_in().unwrap().is_none());
+}
+
+#[test]
+fn merge_restore_orders_the_archived_lane_after_the_live_one() {
+    let dir = TempDir::new().unwrap();
+    let source_dir = dir.path().join("source");
+    std::fs::create_dir_all(&source_dir).unwrap();
+    let (source, _) = temp_store(&source_dir);
+    let first = enqueue_kind(&source, "archived-a", "video", 0);
+    let second = enqueue_kind(&source, "archived-b", "video", 0);
+    source.pin(second, 1).unwrap();
+    source.pin(first, 2).unwrap();
+    let archive = dir.path().join("queue.sql.gz");
+    source.export_backup(&archive).unwrap();
+
+    let (store, _) = temp_store(dir.path());
+    let local = enqueue_kind(&store, "local", "video", 0);
+    store.play_next(local).unwrap();
+    store
+        .import_backup(&archive, &RestoreOptions::default())
+        .unwrap();
+
+    let lane: Vec<_> = store
+        .pinned_order()
+        .unwrap()
+        .into_iter()
+        .map(|entry| (entry.plan_id, entry.queue_position))
+        .collect();
+    assert_eq!(
+        lane,
+        vec![
+            ("local".to_string(), Some(1)),
+            ("archived-b".to_string(), Some(2)),
+            ("archived-a".to_string(), Some(3)),
+        ]
+    );
+}
+
+#[test]
+fn restore_from_sqlite_copy_and_reject_invalid_archives() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    enqueue_kind(&store, "keep", "video", 0);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    store.begin_playback(&policy).unwrap().unwrap();
+    let copy = dir.path().join("queue-copy.sqlite");
+    store.backup_to(&copy).unwrap();
+
+    let target_dir = dir.path().join("target");
+    std::fs::create_dir_all(&target_dir).unwrap();
+    let (target, _) = temp_store(&target_dir);
+    let report = target
+        .restore_from(&copy, &RestoreOptions::default())
+        .unwrap();
+    assert_eq!(report.restored, 1);
+    let entries = target.list(&QueueFilter::default()).unwrap();
+    // Entries on air when the copy was taken go back to the queue.
+    assert_eq!(entries[0].status, QueueStatus::Queued);
+    assert!(entries[0].play_started_at.is_none());
+
+    let garbage = dir.path().join("garbage.sql.gz");
+    std::fs::write(&garbage, b"not gzip").unwrap();
+    let err = target
+        .import_backup(&garbage, &RestoreOptions::default())
+        .unwrap_err();
+    assert!(matches!(err, QueueError::InvalidBackup(_)));
+    assert_eq!(target.list(&QueueFilter::default()).unwrap().len(), 1);
+}
+
+#[test]
+fn manual_ordering_overrides_strategy_until_aired() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let a = enqueue_kind(&store, "a", "video", 1);
+    let b = enqueue_kind(&store, "b", "video", 0);
+    let c = enqueue_kind(&store, "c", "video", 0);
+    let d = enqueue_kind(&store, "d", "video", 0);
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+
+    let lane = store.play_next(c).unwrap();
+    assert_eq!(ids(&lane), vec![c]);
+    // d is outside the lane, so it joins it with b right before it.
+    let lane = store.move_before(b, d).unwrap();
+    assert_eq!(ids(&lane), vec![c, b, d]);
+    let lane = store.pin(d, 1).unwrap();
+    assert_eq!(ids(&lane), vec![d, c, b]);
+    assert_eq!(
+        lane.iter().map(|e| e.queue_position).collect::<Vec<_>>(),
+        vec![Some(1), Some(2), Some(3)]
+    );
+    let lane = store.unpin(c).unwrap();
+    assert_eq!(ids(&lane), vec![d, b]);
+    assert!(matches!(
+        store.move_before(a, a),
+        Err(QueueError::InvalidOrdering { .. })
+    ));
+    assert!(matches!(
+        store.pin(a, 0),
+        Err(QueueError::InvalidOrdering { .. })
+    ));
+
+    let mut aired = Vec::new();
+    while let Some(entry) = store.begin_playback(&policy).unwrap() {
+        store
+            .mark_playback_result(entry.id, QueueStatus::Played, entry.duration_s, None)
+            .unwrap();
+        assert!(matches!(
+            store.pin(entry.id, 1),
+            Err(QueueError::InvalidOrdering { .. })
+        ));
+        aired.push(entry.id);
+    }
+    // The lane airs first despite a's higher priority.
+    assert_eq!(aired, vec![d, b, a, c]);
+    assert!(store.pinned_order().unwrap().is_empty());
+}
+
+#[test]
+fn break_ins_take_the_air_and_settle_the_interrupted_entry() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let a = enqueue_kind(&store, "a", "video", 0);
+    let b = enqueue_kind(&store, "b", "video", 0);
+    let urgent = enqueue_kind(&store, "urgent", "video", 0);
+    let takedown = enqueue_kind(&store, "takedown", "video", 0);
+
+    let break_in = store
+        .request_break_in(urgent, ResumePolicy::Resume, Some("breaking news"))
+        .unwrap();
+    assert!(break_in.is_pending());
+    assert!(matches!(
+        store.request_break_in(urgent, ResumePolicy::Resume, None),
+        Err(QueueError::InvalidBreakIn { .. })
+    ));
+    // Held out of normal selection while pending.
+    let on_air = store.begin_playback(&policy).unwrap().unwrap();
+    assert_eq!(on_air.id, a);
+
+    let aired = store
+        .begin_break_in(
+            &break_in,
+            Some(Interruption {
+                queue_id: a,
+                offset_s: 42.5,
+            }),
+        )
+        .unwrap()
+        .unwrap();
+    assert_eq!(aired.id, urgent);
+    assert_eq!(aired.status, QueueStatus::Playing);
+    assert!(store.pending_break_in().unwrap().is_none());
+    let claimed = &store.break_ins(10).unwrap()[0];
+    assert_eq!(claimed.interrupted_id, Some(a));
+    assert!(!claimed.is_pending());
+    store
+        .mark_playback_result(urgent, QueueStatus::Played, None, None)
+        .unwrap();
+
+    // Resume: the interrupted entry airs next, from its offset.
+    let resumed = store.begin_playback(&policy).unwrap().unwrap();
+    assert_eq!(resumed.id, a);
+    assert_eq!(resumed.resume_offset_s, Some(42.5));
+    // The offset stays on the row until the entry finishes, so a restart
+    // mid-resume picks up from the same place.
+    let on_air = store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.id == a)
+        .unwrap();
+    assert_eq!(on_air.status, QueueStatus::Playing);
+    assert_eq!(on_air.resume_offset_s, Some(42.5));
+
+    // Requeue: the interrupted entry starts over under normal selection.
+    let break_in = store
+        .request_break_in(takedown, ResumePolicy::Requeue, None)
+        .unwrap();
+    store
+        .begin_break_in(
+            &break_in,
+            Some(Interruption {
+                queue_id: a,
+                offset_s: 10.0,
+            }),
+        )
+        .unwrap()
+        .unwrap();
+    let requeued = store
+        .list(&QueueFilter::default())
+        .unwrap()
+        .into_iter()
+        .find(|entry| entry.id == a)
+        .unwrap();
+    assert_eq!(requeued.status, QueueStatus::Queued);
+    assert_eq!(requeued.resume_offset_s, None);
+    assert_eq!(requeued.queue_position, None);
+
+    // A withdrawn break-in returns its entry to selection.
+    let break_in = store
+        .request_break_in(b, ResumePolicy::Resume, None)
+        .unwrap();
+    store.cancel_break_in(break_in.id).unwrap();
+    assert!(store.begin_break_in(&break_in, None).unwrap().is_none());
+    assert!(matches!(
+        store.cancel_break_in(break_in.id),
+        Err(QueueError::NotFound(_))
+    ));
+    store
+        .mark_playback_result(takedown, QueueStatus::Played, None, None)
+        .unwrap();
+    let next = store.begin_playback(&policy).unwrap().unwrap();
+    assert!(next.id == a || next.id == b);
+}
+
+#[test]
+fn forecast_projects_airings_without_touching_the_queue() {
+    let dir = TempDir::new().unwrap();
+    let (store, _) = temp_store(dir.path());
+    let start = Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap();
+    enqueue_kind(&store, "a", "video", 0);
+    enqueue_kind(&store, "b", "video", 0);
+    store
+        .enqueue(&QueueItem {
+            plan_id: "news".into(),
+            asset_path: "/tmp/news.mp4".into(),
+            duration_s: Some(300),
+            content_kind: Some("video".into()),
+            schedule: Some(QueueSchedule::hard(start + Duration::minutes(10))),
+            ..Default::default()
+        })
+        .unwrap();
+    let before = store.list(&QueueFilter::default()).unwrap();
+
+    let options = ForecastOptions::new(start, Duration::hours(1))
+        .with_emergency(EmergencyRefill {
+            threshold: Duration::minutes(30),
+            items: vec![QueueItem {
+                plan_id: "emergency-loop".into(),
+                asset_path: "/tmp/loop.mp4".into(),
+                node_origin: Some("emergency-loop".into()),
+                ..Default::default()
+            }],
+        })
+        .with_injections(vec![ScheduledInjection {
+            item: QueueItem {
+                plan_id: "microspot:acme".into(),
+                asset_path: "/tmp/acme.mp4".into(),
+                duration_s: Some(30),
+                priority: 50,
+                ..Default::default()
+            },
+            next_at: Some(start + Duration::minutes(5)),
+            every: Duration::minutes(30),
+            until: None,
+        }]);
+    let mut policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    policy.anchor_early_tolerance = Duration::zero();
+    let forecast = store.forecast(&policy, &options).unwrap();
+
+    let slots = &forecast.slots;
+    assert_eq!(slots[0].starts_at, start);
+    for pair in slots.windows(2) {
+        assert!(pair[0].ends_at <= pair[1].starts_at);
+    }
+    let news = slots.iter().find(|slot| slot.plan_id == "news").unwrap();
+    assert_eq!(news.starts_at, start + Duration::minutes(10));
+    assert_eq!(news.anchor, Some(AnchorKind::Hard));
+    let spots: Vec<_> = slots
+        .iter()
+        .filter(|slot| slot.source == ForecastSource::Injection)
+        .collect();
+    assert_eq!(spots.len(), 2);
+    assert!(spots.iter().all(|slot| slot.queue_id.is_none()));
+    let emergency = slots
+        .iter()
+        .find(|slot| slot.source == ForecastSource::EmergencyLoop)
+        .unwrap();
+    assert!(emergency.estimated);
+    assert!(forecast.end <= slots.last().unwrap().ends_at);
+    assert_eq!(
+        forecast
+            .slot_at(start + Duration::minutes(12))
+            .unwrap()
+            .plan_id,
+        "news"
+    );
+
+    // Same inputs, same grid; the live queue is left as it was.
+    let again = store.forecast(&policy, &options).unwrap();
+    assert_eq!(
+        again
+            .slots
+            .iter()
+            .map(|slot| (slot.plan_id.clone(), slot.starts_at))
+            .collect::<Vec<_>>(),
+        slots
+            .iter()
+            .map(|slot| (slot.plan_id.clone(), slot.starts_at))
+            .collect::<Vec<_>>()
+    );
+    let after = store.list(&QueueFilter::default()).unwrap();
+    assert_eq!(ids(&after), ids(&before));
+    assert!(after
+        .iter()
+        .all(|entry| entry.status == QueueStatus::Queued));
+}
+
+#[test]
+fn as_run_log_is_append_only_and_exports_by_range() {
+    let dir = TempDir::new().unwrap();
+    let (store, path) = temp_store(dir.path());
+    let policy = QueueSelectionPolicy::new(None, 0.85, Duration::hours(24));
+    let start = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+
+    enqueue_kind(&store, "aired", "video", 1);
+    let entry = store.begin_playback_at(&policy, start).unwrap().unwrap();
+    let mut record = AsRunRecord::from_entry(&entry, AsRunOutcome::Played, start);
+    record.asset_checksum = Some("abc123".into());
+    record.destination = Some("rtmp://localhost/live/main".into());
+    record.transition = Some("crossfade".into());
+    record.finished_at = Some(start + Duration::seconds(60));
+    store.record_as_run(&record).unwrap();
+    store
+        .mark_playback_result(entry.id, QueueStatus::Played, entry.duration_s, None)
+        .unwrap();
+
+    // Orphan recovery logs what it reconciles.
+    enqueue_kind(&store, "cut, short", "video", 1);
+    store
+        .begin_playback_at(&policy, start + Duration::minutes(5))
+        .unwrap()
+        .unwrap();
+    store
+        .recover_orphaned_at(start + Duration::minutes(5) + Duration::seconds(20))
+        .unwrap();
+
+    // Purging played queue rows leaves the history intact.
+    store.cleanup_played(Duration::zero()).unwrap();
+    let entries = store
+        .as_run_between(start, start + Duration::hours(1))
+        .unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].plan_id, "aired");
+    assert_eq!(entries[0].duration_s(), Some(60));
+    assert_eq!(entries[1].outcome, AsRunOutcome::Interrupted);
+    assert!(entries[1].finished_at.is_none());
+    assert!(store
+        .as_run_between(start + Duration::hours(1), start + Duration::hours(2))
+        .unwrap()
+        .is_empty());
+
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert!(conn
+        .execute("UPDATE as_run_log SET outcome='failed'", [])
+        .is_err());
+    assert!(conn.execute("DELETE FROM as_run_log", []).is_err());
+
+    let csv = dir.path().join("as_run.csv");
+    let exported = store
+        .export_as_run(start, start + Duration::hours(1), AsRunFormat::Csv, &csv)
+        .unwrap();
+    assert_eq!(exported, 2);
+    let csv = std::fs::read_to_string(csv).unwrap();
+    let lines: Vec<&str> = csv.lines().collect();
+    assert_eq!(lines.len(), 3);
+    assert!(lines[0].starts_with("id,queue_id,plan_id,asset_path,asset_checksum"));
+    assert!(lines[1].contains(",abc123,video,,rtmp://localhost/live/main,crossfade,played,"));
+    assert!(lines[1].contains(",2024-05-01T12:00:00Z,2024-05-01T12:01:00Z,60,"));
+    assert!(lines[2].contains(",\"cut, short\","));
+
+    let json = dir.path().join("as_run.json");
+    store
+        .export_as_run(start, start + Duration::hours(1), AsRunFormat::Json, &json)
+        .unwrap();
+    let rows: Vec<serde_json::Value> =
+        serde_json::from_str(&std::fs::read_to_string(json).unwrap()).unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0]["outcome"], "played");
+    assert_eq!(rows[0]["duration_s"], 60);
+    assert_eq!(rows[1]["outcome"], "interrupted");
+}
+
+fn ids(entries: &[vvtv_core::QueueEntry]) -> Vec<i64> {
+    entries.iter().map(|entry| entry.id).collect()
+}
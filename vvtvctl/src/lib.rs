@@ -10,6 +10,7 @@ mod commands;
 use std::collections::HashMap;
 use std::fmt;
 use std::fs;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::str::FromStr;
@@ -29,33 +30,26 @@ use thiserror::Error;
 use tokio::runtime::Builder;
 use tracing_subscriber::{fmt as tracing_fmt, EnvFilter};
 use vvtv_core::{
-    load_broadcaster_config, load_browser_config, load_processor_config, load_vvtv_config,
-    AdaptiveProgrammer, AdaptiveReport, AudienceReport, AudienceStore, AudienceStoreBuilder,
-    BrowserError, BrowserLauncher, BrowserPbdRunner, BrowserQaRunner, BrowserSearchSessionFactory,
-    BusinessLogic, BusinessLogicError, ConfigBundle, ContentSearcher,
-    ComplianceError, ComplianceSuite, ComplianceSuiteConfig, ComplianceSummary,
-    CsamScanReport, CsamScanner,
-    DashboardArtifacts, DashboardError, DashboardGenerator,
-    DiscoveryConfig, DiscoveryLoop, DiscoveryPbd, DiscoveryPlanStore, DiscoveryStats,
-    DispatchAction, DispatchStatus,
-    DrmDetectionConfig, DrmScanReport, DrmScanner,
-    EconomyError, EconomyEvent, EconomyEventType, EconomyStore, EconomyStoreBuilder, EconomySummary,
-    IncidentDispatch, IncidentError, IncidentHistoryWriter, IncidentNotifier, IncidentReport, IncidentSeverity,
-    LedgerExport, LicenseAuditReport, LicenseAuditor,
-    MetricRecord, MetricsStore,
-    MicroSpotContract, MicroSpotInjection, MicroSpotManager,
-    MonetizationDashboard, MonitorError,
-    NewEconomyEvent, NewViewerSession,
-    Plan, PlanAuditFinding, PlanAuditKind, PlanBlacklistEntry, PlanImportRecord, PlanMetrics, PlanStatus,
-    PlayBeforeDownload, PlayoutQueueStore,
-    ProfileManager,
-    QaMetricsStore, QaStatistics,
-    QueueEntry as QueueStoreEntry, QueueError, QueueFilter, QueueMetrics, QueueStatus,
-    SearchConfig, SearchEngine, SearchSessionFactory,
-    SessionRecorder, SessionRecorderConfig,
-    SmokeMode, SmokeTestOptions, SmokeTestResult,
-    SqlitePlanStore,
-    ViewerSession,
+    emergency_refill, load_broadcaster_config, load_browser_config, load_processor_config,
+    load_vvtv_config, AdaptiveProgrammer, AdaptiveReport, AsRunFormat, AudienceReport,
+    AudienceStore, AudienceStoreBuilder, BreakIn, BrowserError, BrowserLauncher, BrowserPbdRunner,
+    BrowserQaRunner, BrowserSearchSessionFactory, BusinessLogic, BusinessLogicError,
+    ComplianceError, ComplianceSuite, ComplianceSuiteConfig, ComplianceSummary, ConfigBundle,
+    ContentSearcher, CsamScanReport, CsamScanner, DashboardArtifacts, DashboardError,
+    DashboardGenerator, DaypartSchedule, DiscoveryConfig, DiscoveryLoop, DiscoveryPbd,
+    DiscoveryPlanStore, DiscoveryStats, DispatchAction, DispatchStatus, DrmDetectionConfig,
+    DrmScanReport, DrmScanner, EconomyError, EconomyEvent, EconomyEventType, EconomyStore,
+    EconomyStoreBuilder, EconomySummary, Forecast, ForecastOptions, Handover, IncidentDispatch,
+    IncidentError, IncidentHistoryWriter, IncidentNotifier, IncidentReport, IncidentSeverity,
+    LeadershipError, LeaseStore, LedgerExport, LicenseAuditReport, LicenseAuditor, MetricRecord,
+    MetricsStore, MicroSpotContract, MicroSpotInjection, MicroSpotManager, MonetizationDashboard,
+    MonitorError, NewEconomyEvent, NewViewerSession, NodeHeartbeat, Plan, PlanAuditFinding,
+    PlanAuditKind, PlanBlacklistEntry, PlanImportRecord, PlanMetrics, PlanStatus,
+    PlayBeforeDownload, PlayoutQueueStore, ProfileManager, QaMetricsStore, QaStatistics,
+    QueueEntry as QueueStoreEntry, QueueError, QueueFilter, QueueMetrics, QueueSelectionPolicy,
+    QueueStatus, RestoreMode, RestoreOptions, RestoreReport, ResumePolicy, SearchConfig,
+    SearchEngine, SearchSessionFactory, SessionRecorder, SessionRecorderConfig, SmokeMode,
+    SmokeTestOptions, SmokeTestResult, SqliteLeaseStore, SqlitePlanStore, ViewerSession,
 };
 
 #[cfg(test)]
@@ -98,6 +92,8 @@ pub enum AppError {
     Compliance(#[from] ComplianceError),
     #[error("incident error: {0}")]
     Incident(#[from] IncidentError),
+    #[error("leadership error: {0}")]
+    Leadership(#[from] LeadershipError),
     #[error("authentication failed")]
     Authentication,
     #[error("required resource missing: {0}")]
@@ -209,6 +205,9 @@ pub enum Commands {
     /// Comunicação e registro de incidentes
     #[command(subcommand)]
     Incident(IncidentCommands),
+    /// Coordenação primário/standby
+    #[command(subcommand)]
+    Failover(FailoverCommands),
 }
 
 #[derive(Args, Debug)]
@@ -303,12 +302,34 @@ pub enum QueueCommands {
     Summary,
     /// Ajusta prioridade de um item
     Promote(QueuePromoteArgs),
+    /// Exibe a ordem manual definida pelo operador
+    Order,
+    /// Coloca um item para tocar em seguida
+    Next(QueueNextArgs),
+    /// Move um item para antes de outro
+    Move(QueueMoveArgs),
+    /// Fixa um item numa posição da ordem manual
+    Pin(QueuePinArgs),
+    /// Devolve um item à seleção automática
+    Unpin(QueueUnpinArgs),
+    /// Interrompe o item no ar e coloca um item urgente
+    BreakIn(QueueBreakInArgs),
+    /// Lista interrupções pedidas e executadas
+    BreakIns(QueueBreakInsArgs),
+    /// Cancela uma interrupção que ainda não foi ao ar
+    CancelBreakIn(QueueCancelBreakInArgs),
     /// Remove item da fila
     Remove(QueueRemoveArgs),
     /// Limpa itens reproduzidos mais antigos
     Cleanup(QueueCleanupArgs),
     /// Exporta backup compactado da fila
     Backup(QueueBackupArgs),
+    /// Restaura a fila a partir de um backup
+    Restore(QueueRestoreArgs),
+    /// Projeta a grade das próximas horas sem alterar a fila
+    Forecast(QueueForecastArgs),
+    /// Exporta o registro as-run (o que foi ao ar) do período
+    AsRun(QueueAsRunArgs),
 }
 
 #[derive(Args, Debug)]
@@ -330,6 +351,62 @@ pub struct QueuePromoteArgs {
     pub priority: i64,
 }
 
+#[derive(Args, Debug)]
+pub struct QueueNextArgs {
+    /// ID do item na fila
+    pub id: i64,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueMoveArgs {
+    /// ID do item na fila
+    pub id: i64,
+    /// ID do item que deve tocar logo depois
+    #[arg(long)]
+    pub before: i64,
+}
+
+#[derive(Args, Debug)]
+pub struct QueuePinArgs {
+    /// ID do item na fila
+    pub id: i64,
+    /// Posição na ordem manual (1 = próximo)
+    #[arg(long, default_value_t = 1)]
+    pub position: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueUnpinArgs {
+    /// ID do item na fila
+    pub id: i64,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueBreakInArgs {
+    /// ID do item urgente na fila
+    pub id: i64,
+    /// Destino do item interrompido: resume (retoma de onde parou) ou
+    /// requeue (volta à fila desde o início). Padrão: [queue.break_in]
+    #[arg(long)]
+    pub policy: Option<String>,
+    /// Motivo registrado no as-run
+    #[arg(long)]
+    pub reason: Option<String>,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueBreakInsArgs {
+    /// Limite de registros
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueCancelBreakInArgs {
+    /// ID da interrupção
+    pub id: i64,
+}
+
 #[derive(Args, Debug)]
 pub struct QueueRemoveArgs {
     /// ID do item na fila
@@ -349,6 +426,47 @@ pub struct QueueBackupArgs {
     pub output: PathBuf,
 }
 
+#[derive(Args, Debug)]
+pub struct QueueRestoreArgs {
+    /// Arquivo `.sql.gz` exportado ou cópia SQLite da fila
+    pub input: PathBuf,
+    /// Modo de restauração (merge ou replace)
+    #[arg(long, default_value = "merge")]
+    pub mode: String,
+    /// Apenas valida o backup e reporta conflitos
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueForecastArgs {
+    /// Horizonte da projeção em horas
+    #[arg(long, default_value_t = 6)]
+    pub hours: i64,
+    /// Início da projeção (RFC3339); padrão: agora
+    #[arg(long)]
+    pub start: Option<String>,
+    /// Diretório de assets do loop de emergência (padrão: <storage_dir>/archive)
+    #[arg(long)]
+    pub archive_dir: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+pub struct QueueAsRunArgs {
+    /// Início do período (RFC3339). Padrão: agora - 24 horas.
+    #[arg(long)]
+    pub start: Option<String>,
+    /// Fim do período (RFC3339). Padrão: agora.
+    #[arg(long)]
+    pub end: Option<String>,
+    /// Formato do arquivo (csv ou json)
+    #[arg(long, default_value = "csv")]
+    pub format: String,
+    /// Arquivo de saída (padrão: <reports_dir>/as_run_<início>.<formato>)
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum BufferCommands {
     /// Dispara o script fill_buffer.sh
@@ -523,6 +641,74 @@ pub struct IncidentReportResultView {
     pub notification: Option<IncidentDispatchView>,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverStatusView {
+    pub enabled: bool,
+    /// Node this vvtvctl's configuration describes.
+    pub node_id: String,
+    pub lease: Option<FailoverLeaseView>,
+    pub nodes: Vec<FailoverNodeView>,
+    pub handovers: Vec<FailoverHandoverView>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverLeaseView {
+    pub holder: String,
+    pub epoch: i64,
+    pub acquired_at: String,
+    pub renewed_at: String,
+    pub expires_at: String,
+    pub expired: bool,
+    pub released: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverNodeView {
+    pub node_id: String,
+    pub role: String,
+    pub state: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub updated_at: String,
+}
+
+impl From<NodeHeartbeat> for FailoverNodeView {
+    fn from(status: NodeHeartbeat) -> Self {
+        Self {
+            node_id: status.node_id,
+            role: status.role.to_string(),
+            state: status.state,
+            epoch: status.epoch,
+            detail: status.detail,
+            updated_at: format_datetime(Some(status.updated_at)).unwrap_or_default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FailoverHandoverView {
+    pub at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub from_node: Option<String>,
+    pub to_node: String,
+    pub epoch: i64,
+    pub reason: String,
+}
+
+impl From<Handover> for FailoverHandoverView {
+    fn from(handover: Handover) -> Self {
+        Self {
+            at: format_datetime(Some(handover.at)).unwrap_or_default(),
+            from_node: handover.from_node,
+            to_node: handover.to_node,
+            epoch: handover.epoch,
+            reason: handover.reason,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct IncidentDispatchView {
     pub subject: String,
@@ -601,6 +787,19 @@ pub enum IncidentCommands {
     Report(IncidentReportArgs),
 }
 
+#[derive(Subcommand, Debug)]
+pub enum FailoverCommands {
+    /// Exibe a lease, o estado de cada nó e as últimas trocas de comando
+    Status(FailoverStatusArgs),
+}
+
+#[derive(Args, Debug)]
+pub struct FailoverStatusArgs {
+    /// Limite de trocas de comando listadas
+    #[arg(long, default_value_t = 20)]
+    pub limit: usize,
+}
+
 #[derive(Subcommand, Debug)]
 pub enum LedgerCommands {
     /// Registra um evento financeiro
@@ -782,6 +981,38 @@ pub fn run(cli: Cli) -> Result<()> {
                 let result = context.queue_promote(args)?;
                 render(&result, cli.format)?;
             }
+            QueueCommands::Order => {
+                let result = context.queue_order()?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::Next(args) => {
+                let result = context.queue_next(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::Move(args) => {
+                let result = context.queue_move(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::Pin(args) => {
+                let result = context.queue_pin(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::Unpin(args) => {
+                let result = context.queue_unpin(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::BreakIn(args) => {
+                let result = context.queue_break_in(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::BreakIns(args) => {
+                let result = context.queue_break_ins(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::CancelBreakIn(args) => {
+                let result = context.queue_cancel_break_in(args)?;
+                render(&result, cli.format)?;
+            }
             QueueCommands::Remove(args) => {
                 let result = context.queue_remove(args)?;
                 render(&result, cli.format)?;
@@ -794,6 +1025,18 @@ pub fn run(cli: Cli) -> Result<()> {
                 let result = context.queue_backup(args)?;
                 render(&result, cli.format)?;
             }
+            QueueCommands::Restore(args) => {
+                let result = context.queue_restore(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::Forecast(args) => {
+                let result = context.queue_forecast(args)?;
+                render(&result, cli.format)?;
+            }
+            QueueCommands::AsRun(args) => {
+                let result = context.queue_as_run(args)?;
+                render(&result, cli.format)?;
+            }
         },
         Commands::Buffer(BufferCommands::Fill(args)) => {
             let result = context.buffer_fill(args)?;
@@ -937,6 +1180,12 @@ pub fn run(cli: Cli) -> Result<()> {
                 render(&result, cli.format)?;
             }
         },
+        Commands::Failover(command) => match command {
+            FailoverCommands::Status(args) => {
+                let result = context.failover_status(args)?;
+                render(&result, cli.format)?;
+            }
+        },
     }
 
     Ok(())
@@ -1538,14 +1787,36 @@ impl AppContext {
             .into_iter()
             .map(|(status, count)| (status.to_string(), count))
             .collect();
+        let daypart = self.daypart_summary(&store)?;
         Ok(QueueSummaryOutput {
             buffer_hours: summary.buffer_duration_hours,
             counts,
             played_last_hour: metrics.played_last_hour,
             failures_last_hour: metrics.failures_last_hour,
+            daypart,
         })
     }
 
+    fn daypart_summary(&self, store: &PlayoutQueueStore) -> Result<Option<DaypartSummaryView>> {
+        let Some(section) = &self.bundle.broadcaster.queue.dayparts else {
+            return Ok(None);
+        };
+        let schedule = DaypartSchedule::from_config(section)?;
+        let now = Utc::now();
+        let Some(active) = schedule.active(now) else {
+            return Ok(None);
+        };
+        let aired = store.kind_counts_since(active.started_at)?;
+        Ok(Some(DaypartSummaryView {
+            name: active.daypart.name.clone(),
+            timezone: schedule.timezone().name().to_string(),
+            window: active.daypart.window_label(),
+            started_at: format_datetime(Some(active.started_at)),
+            targets: active.daypart.ratios.clone(),
+            aired,
+        }))
+    }
+
     fn queue_promote(&self, args: &QueuePromoteArgs) -> Result<AckMessage> {
         let store = self.queue_store(false)?;
         store.mark_priority(args.id, args.priority)?;
@@ -1557,6 +1828,94 @@ impl AppContext {
         })
     }
 
+    fn queue_order(&self) -> Result<QueueOrderOutput> {
+        let store = self.queue_store(true)?;
+        let pinned = store.pinned_order()?;
+        Ok(QueueOrderOutput::new(None, pinned))
+    }
+
+    fn queue_next(&self, args: &QueueNextArgs) -> Result<QueueOrderOutput> {
+        let store = self.queue_store(false)?;
+        let pinned = store.play_next(args.id)?;
+        Ok(QueueOrderOutput::new(
+            Some(format!("Item {} toca em seguida", args.id)),
+            pinned,
+        ))
+    }
+
+    fn queue_move(&self, args: &QueueMoveArgs) -> Result<QueueOrderOutput> {
+        let store = self.queue_store(false)?;
+        let pinned = store.move_before(args.id, args.before)?;
+        Ok(QueueOrderOutput::new(
+            Some(format!(
+                "Item {} movido para antes de {}",
+                args.id, args.before
+            )),
+            pinned,
+        ))
+    }
+
+    fn queue_pin(&self, args: &QueuePinArgs) -> Result<QueueOrderOutput> {
+        if args.position == 0 {
+            return Err(AppError::InvalidArgument(
+                "posição deve ser maior que zero".to_string(),
+            ));
+        }
+        let store = self.queue_store(false)?;
+        let pinned = store.pin(args.id, args.position)?;
+        Ok(QueueOrderOutput::new(
+            Some(format!(
+                "Item {} fixado na posição {}",
+                args.id, args.position
+            )),
+            pinned,
+        ))
+    }
+
+    fn queue_unpin(&self, args: &QueueUnpinArgs) -> Result<QueueOrderOutput> {
+        let store = self.queue_store(false)?;
+        let pinned = store.unpin(args.id)?;
+        Ok(QueueOrderOutput::new(
+            Some(format!("Item {} devolvido à seleção automática", args.id)),
+            pinned,
+        ))
+    }
+
+    fn queue_break_in(&self, args: &QueueBreakInArgs) -> Result<QueueBreakInOutput> {
+        let policy = args
+            .policy
+            .as_deref()
+            .unwrap_or(&self.bundle.broadcaster.queue.break_in.default_policy);
+        let policy = ResumePolicy::from_str(policy)
+            .map_err(|_| AppError::InvalidArgument(format!("política inválida: {policy}")))?;
+        let store = self.queue_store(false)?;
+        let break_in = store.request_break_in(args.id, policy, args.reason.as_deref())?;
+        Ok(QueueBreakInOutput {
+            message: Some(format!(
+                "Item {} entra no ar na próxima verificação do broadcaster",
+                args.id
+            )),
+            break_ins: vec![QueueBreakInView::from(break_in)],
+        })
+    }
+
+    fn queue_break_ins(&self, args: &QueueBreakInsArgs) -> Result<QueueBreakInOutput> {
+        let store = self.queue_store(true)?;
+        let break_ins = store.break_ins(args.limit)?;
+        Ok(QueueBreakInOutput {
+            message: None,
+            break_ins: break_ins.into_iter().map(QueueBreakInView::from).collect(),
+        })
+    }
+
+    fn queue_cancel_break_in(&self, args: &QueueCancelBreakInArgs) -> Result<AckMessage> {
+        let store = self.queue_store(false)?;
+        store.cancel_break_in(args.id)?;
+        Ok(AckMessage {
+            message: format!("Interrupção {} cancelada", args.id),
+        })
+    }
+
     fn queue_remove(&self, args: &QueueRemoveArgs) -> Result<AckMessage> {
         let store = self.queue_store(false)?;
         store.remove(args.id)?;
@@ -1582,6 +1941,93 @@ impl AppContext {
         })
     }
 
+    fn queue_restore(&self, args: &QueueRestoreArgs) -> Result<QueueRestoreOutput> {
+        if !args.input.exists() {
+            return Err(AppError::MissingResource(format!(
+                "Arquivo não encontrado: {}",
+                args.input.display()
+            )));
+        }
+        let mode = RestoreMode::from_str(&args.mode)
+            .map_err(|_| AppError::InvalidArgument(format!("modo inválido: {}", args.mode)))?;
+        let options = RestoreOptions {
+            mode,
+            dry_run: args.dry_run,
+        };
+        let store = self.queue_store(false)?;
+        let report = if is_gzip(&args.input)? {
+            store.import_backup(&args.input, &options)?
+        } else {
+            store.restore_from(&args.input, &options)?
+        };
+        Ok(QueueRestoreOutput::from(report))
+    }
+
+    fn queue_forecast(&self, args: &QueueForecastArgs) -> Result<QueueForecastOutput> {
+        if args.hours <= 0 {
+            return Err(AppError::InvalidArgument(
+                "--hours deve ser maior que zero".to_string(),
+            ));
+        }
+        let start = match &args.start {
+            Some(value) => parse_datetime(value)?,
+            None => Utc::now(),
+        };
+        let horizon = Duration::try_hours(args.hours)
+            .filter(|horizon| start.checked_add_signed(*horizon).is_some())
+            .ok_or_else(|| AppError::InvalidArgument("--hours fora do intervalo".to_string()))?;
+        let archive_dir = args
+            .archive_dir
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(&self.bundle.vvtv.paths.storage_dir).join("archive"));
+        let store = self.queue_store(true)?;
+        let policy = QueueSelectionPolicy::from_queue_config(&self.bundle.broadcaster.queue);
+        let manager = MicroSpotManager::new(self.economy_store(true)?);
+        let injections = manager
+            .list()?
+            .iter()
+            .filter_map(MicroSpotContract::forecast_injection)
+            .collect();
+        let options = ForecastOptions::new(start, horizon)
+            .with_emergency(emergency_refill(&archive_dir)?)
+            .with_injections(injections);
+        let forecast = store.forecast(&policy, &options)?;
+        Ok(QueueForecastOutput::from(forecast))
+    }
+
+    fn queue_as_run(&self, args: &QueueAsRunArgs) -> Result<QueueAsRunOutput> {
+        let format = AsRunFormat::from_str(&args.format)
+            .map_err(|_| AppError::InvalidArgument(format!("formato inválido: {}", args.format)))?;
+        let end = args
+            .end
+            .as_ref()
+            .map(|value| parse_datetime(value))
+            .transpose()?
+            .unwrap_or_else(Utc::now);
+        let start = args
+            .start
+            .as_ref()
+            .map(|value| parse_datetime(value))
+            .transpose()?
+            .unwrap_or_else(|| end - Duration::hours(24));
+        let output = args.output.clone().unwrap_or_else(|| {
+            self.reports_dir.join(format!(
+                "as_run_{}.{}",
+                start.format("%Y-%m-%dT%H%M"),
+                format.as_str()
+            ))
+        });
+        let store = self.queue_store(true)?;
+        let rows = store.export_as_run(start, end, format, &output)?;
+        Ok(QueueAsRunOutput {
+            path: output.display().to_string(),
+            format: format.to_string(),
+            start: start.to_rfc3339_opts(SecondsFormat::Secs, true),
+            end: end.to_rfc3339_opts(SecondsFormat::Secs, true),
+            rows,
+        })
+    }
+
     fn metrics_store(&self) -> Result<MetricsStore> {
         let store = MetricsStore::new(&self.metrics_db)?;
         store.initialize()?;
@@ -1730,6 +2176,13 @@ impl AppContext {
             record.ssd_wear_percent = thermal.ssd_wear_percent;
             record.fan_rpm = thermal.fan_rpm;
         }
+        if let Some(sample) = store
+            .output_samples(1)?
+            .pop()
+            .filter(|sample| Utc::now() - sample.at < Duration::minutes(5))
+        {
+            record.apply_output_sample(&sample);
+        }
         store.record(&record)?;
         Ok(())
     }
@@ -1942,6 +2395,43 @@ impl AppContext {
         })
     }
 
+    fn failover_status(&self, args: &FailoverStatusArgs) -> Result<FailoverStatusView> {
+        let section = &self.bundle.broadcaster.failover.leadership;
+        let path = self.bundle.vvtv.resolve_path(&section.store_path);
+        if !path.exists() {
+            return Err(AppError::MissingResource(format!(
+                "Banco de liderança ausente: {}",
+                path.display()
+            )));
+        }
+        let store = SqliteLeaseStore::new(&path)?;
+        let now = Utc::now();
+        let lease = store.lease()?.map(|lease| FailoverLeaseView {
+            holder: lease.holder,
+            epoch: lease.epoch,
+            acquired_at: format_datetime(Some(lease.acquired_at)).unwrap_or_default(),
+            renewed_at: format_datetime(Some(lease.renewed_at)).unwrap_or_default(),
+            expires_at: format_datetime(Some(lease.expires_at)).unwrap_or_default(),
+            expired: lease.expires_at <= now,
+            released: lease.released,
+        });
+        Ok(FailoverStatusView {
+            enabled: section.enabled,
+            node_id: section.node_id.clone(),
+            lease,
+            nodes: store
+                .nodes()?
+                .into_iter()
+                .map(FailoverNodeView::from)
+                .collect(),
+            handovers: store
+                .handovers(args.limit)?
+                .into_iter()
+                .map(FailoverHandoverView::from)
+                .collect(),
+        })
+    }
+
     fn health_dashboard(&self, args: &HealthDashboardArgs) -> Result<AckMessage> {
         let store = self.metrics_store()?;
         let output = args
@@ -2587,6 +3077,12 @@ fn parse_plan_import(content: &str, overwrite: bool) -> Result<Vec<PlanImportRec
     ))
 }
 
+fn is_gzip(path: &Path) -> Result<bool> {
+    let mut magic = [0u8; 2];
+    let read = fs::File::open(path)?.read(&mut magic)?;
+    Ok(read == 2 && magic == [0x1f, 0x8b])
+}
+
 fn parse_queue_status(value: &str) -> Result<QueueStatus> {
     QueueStatus::from_str(value)
         .map_err(|_| AppError::InvalidArgument(format!("status inválido: {value}")))
@@ -2604,6 +3100,9 @@ impl DisplayFallback for QueueList {
                 .map(|v| format!("{v}s"))
                 .unwrap_or_else(|| "-".to_string());
             let mut extras = Vec::new();
+            if let Some(position) = entry.queue_position {
+                extras.push(format!("pos={position}"));
+            }
             if let Some(score) = entry.curation_score {
                 extras.push(format!("score={:.2}", score));
             }
@@ -2613,6 +3112,27 @@ impl DisplayFallback for QueueList {
             if let Some(origin) = &entry.node_origin {
                 extras.push(format!("origin={origin}"));
             }
+            if let Some(domain) = &entry.source_domain {
+                extras.push(format!("domain={domain}"));
+            }
+            if !entry.tags.is_empty() {
+                extras.push(format!("tags={}", entry.tags.join("|")));
+            }
+            if let Some(start) = &entry.scheduled_start {
+                let anchor = entry.anchor.as_deref().unwrap_or("hard");
+                extras.push(format!("at={start} ({anchor})"));
+            }
+            if let Some(offset) = entry.resume_offset_s {
+                extras.push(format!("resume={offset:.1}s"));
+            }
+            if entry.retry_count > 0 {
+                match &entry.next_attempt_at {
+                    Some(next) if entry.status == "queued" => {
+                        extras.push(format!("retries={} next={next}", entry.retry_count))
+                    }
+                    _ => extras.push(format!("retries={}", entry.retry_count)),
+                }
+            }
             let extra = if extras.is_empty() {
                 String::new()
             } else {
@@ -2642,6 +3162,161 @@ impl DisplayFallback for QueueSummaryOutput {
             "Última hora: reproduzidos={}, falhas={}",
             self.played_last_hour, self.failures_last_hour
         ));
+        if let Some(daypart) = &self.daypart {
+            lines.push(format!(
+                "Daypart ativo: {} ({} {})",
+                daypart.name, daypart.window, daypart.timezone
+            ));
+            let total: i64 = daypart.aired.values().sum();
+            let mut kinds: Vec<&String> = daypart.targets.keys().collect();
+            kinds.sort();
+            for kind in kinds {
+                let target = daypart.targets[kind] * 100.0;
+                let aired = daypart.aired.get(kind).copied().unwrap_or(0);
+                let share = if total > 0 {
+                    aired as f64 / total as f64 * 100.0
+                } else {
+                    0.0
+                };
+                lines.push(format!(
+                    "  - {kind}: alvo {target:.0}% / exibido {share:.0}% ({aired})"
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl DisplayFallback for QueueOrderOutput {
+    fn display(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(message) = &self.message {
+            lines.push(message.clone());
+        }
+        if self.pinned.is_empty() {
+            lines.push("Nenhum item com ordem manual; a estratégia escolhe o próximo".to_string());
+        } else {
+            lines.push("Ordem manual (antes da estratégia):".to_string());
+            for entry in &self.pinned {
+                let duration = entry
+                    .duration_s
+                    .map(|v| format!("{v}s"))
+                    .unwrap_or_else(|| "-".to_string());
+                lines.push(format!(
+                    "  {}. #{} plan={} dur={duration}",
+                    entry.queue_position.unwrap_or_default(),
+                    entry.id,
+                    entry.plan_id
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl DisplayFallback for QueueRestoreOutput {
+    fn display(&self) -> String {
+        let mut lines = Vec::new();
+        let heading = if self.dry_run {
+            "Simulação de restauração"
+        } else {
+            "Restauração concluída"
+        };
+        lines.push(format!("{heading} (modo {})", self.mode));
+        lines.push(format!(
+            "  - No backup: {} | restaurados: {} | ignorados: {} | removidos: {}",
+            self.archived, self.restored, self.skipped, self.removed
+        ));
+        if self.conflicts.is_empty() {
+            lines.push("  - Nenhum conflito".to_string());
+        } else {
+            lines.push(format!("  - Conflitos ({}):", self.conflicts.len()));
+            for conflict in &self.conflicts {
+                let archived = conflict
+                    .archived_id
+                    .map(|id| format!("#{id}"))
+                    .unwrap_or_else(|| "-".to_string());
+                let existing = conflict
+                    .existing_id
+                    .map(|id| format!("#{id}"))
+                    .unwrap_or_else(|| "-".to_string());
+                lines.push(format!(
+                    "    - {} plan={} backup={archived} atual={existing}",
+                    conflict.kind, conflict.plan_id
+                ));
+            }
+        }
+        lines.join("\n")
+    }
+}
+
+impl DisplayFallback for QueueBreakInOutput {
+    fn display(&self) -> String {
+        let mut lines = Vec::new();
+        if let Some(message) = &self.message {
+            lines.push(message.clone());
+        }
+        if self.break_ins.is_empty() {
+            lines.push("Nenhuma interrupção registrada".to_string());
+        }
+        for break_in in &self.break_ins {
+            let state = match (&break_in.claimed_at, break_in.interrupted_id) {
+                (None, _) => "pendente".to_string(),
+                (Some(at), Some(id)) => format!("no ar em {at}, interrompeu #{id}"),
+                (Some(at), None) => format!("no ar em {at}"),
+            };
+            let reason = break_in
+                .reason
+                .as_deref()
+                .map(|reason| format!(" motivo={reason}"))
+                .unwrap_or_default();
+            lines.push(format!(
+                "  [{}] #{} política={} {state}{reason}",
+                break_in.id, break_in.queue_id, break_in.policy
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+impl DisplayFallback for QueueAsRunOutput {
+    fn display(&self) -> String {
+        format!(
+            "As-run exportado ({}): {} registros de {} a {}\n  - Arquivo: {}",
+            self.format, self.rows, self.start, self.end, self.path
+        )
+    }
+}
+
+impl DisplayFallback for QueueForecastOutput {
+    fn display(&self) -> String {
+        let mut lines = vec![format!("Previsão de playout {} → {}", self.start, self.end)];
+        if self.slots.is_empty() {
+            lines.push("  - Nada previsto para o período".to_string());
+        }
+        for slot in &self.slots {
+            let id = slot
+                .queue_id
+                .map(|id| format!("#{id}"))
+                .unwrap_or_else(|| "-".to_string());
+            let mut extras = vec![format!("source={}", slot.source)];
+            if let Some(kind) = &slot.content_kind {
+                extras.push(format!("kind={kind}"));
+            }
+            if let Some(anchor) = &slot.anchor {
+                extras.push(format!("anchor={anchor}"));
+            }
+            if slot.estimated {
+                extras.push("estimated".to_string());
+            }
+            lines.push(format!(
+                "  {} → {} {id} plan={} {}",
+                slot.starts_at,
+                slot.ends_at,
+                slot.plan_id,
+                extras.join(" ")
+            ));
+        }
         lines.join("\n")
     }
 }
@@ -2718,6 +3393,106 @@ pub struct QueueList {
     pub rows: Vec<QueueDisplayEntry>,
 }
 
+#[derive(Debug, Serialize)]
+pub struct QueueOrderOutput {
+    pub message: Option<String>,
+    pub pinned: Vec<QueueDisplayEntry>,
+}
+
+impl QueueOrderOutput {
+    fn new(message: Option<String>, pinned: Vec<QueueStoreEntry>) -> Self {
+        Self {
+            message,
+            pinned: pinned.into_iter().map(QueueDisplayEntry::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueBreakInOutput {
+    pub message: Option<String>,
+    pub break_ins: Vec<QueueBreakInView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueBreakInView {
+    pub id: i64,
+    pub queue_id: i64,
+    pub policy: String,
+    pub reason: Option<String>,
+    pub requested_at: Option<String>,
+    pub claimed_at: Option<String>,
+    pub interrupted_id: Option<i64>,
+}
+
+impl From<BreakIn> for QueueBreakInView {
+    fn from(break_in: BreakIn) -> Self {
+        Self {
+            id: break_in.id,
+            queue_id: break_in.queue_id,
+            policy: break_in.policy.to_string(),
+            reason: break_in.reason,
+            requested_at: format_datetime(Some(break_in.requested_at)),
+            claimed_at: format_datetime(break_in.claimed_at),
+            interrupted_id: break_in.interrupted_id,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueAsRunOutput {
+    pub path: String,
+    pub format: String,
+    pub start: String,
+    pub end: String,
+    pub rows: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueForecastOutput {
+    pub start: String,
+    pub end: String,
+    pub slots: Vec<QueueForecastSlotView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueForecastSlotView {
+    pub starts_at: String,
+    pub ends_at: String,
+    pub queue_id: Option<i64>,
+    pub plan_id: String,
+    pub asset_path: String,
+    pub content_kind: Option<String>,
+    pub anchor: Option<String>,
+    pub source: String,
+    pub estimated: bool,
+}
+
+impl From<Forecast> for QueueForecastOutput {
+    fn from(forecast: Forecast) -> Self {
+        let timestamp = |at: DateTime<Utc>| at.to_rfc3339_opts(SecondsFormat::Secs, true);
+        Self {
+            start: timestamp(forecast.start),
+            end: timestamp(forecast.end),
+            slots: forecast
+                .slots
+                .into_iter()
+                .map(|slot| QueueForecastSlotView {
+                    starts_at: timestamp(slot.starts_at),
+                    ends_at: timestamp(slot.ends_at),
+                    queue_id: slot.queue_id,
+                    plan_id: slot.plan_id,
+                    asset_path: slot.asset_path,
+                    content_kind: slot.content_kind,
+                    anchor: slot.anchor.map(|anchor| anchor.to_string()),
+                    source: slot.source.to_string(),
+                    estimated: slot.estimated,
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct QueueDisplayEntry {
     pub id: i64,
@@ -2730,6 +3505,15 @@ pub struct QueueDisplayEntry {
     pub updated_at: Option<String>,
     pub node_origin: Option<String>,
     pub content_kind: Option<String>,
+    pub scheduled_start: Option<String>,
+    pub anchor: Option<String>,
+    pub tags: Vec<String>,
+    pub source_domain: Option<String>,
+    pub retry_count: u32,
+    pub next_attempt_at: Option<String>,
+    pub failure_reason: Option<String>,
+    pub queue_position: Option<i64>,
+    pub resume_offset_s: Option<f64>,
 }
 
 impl From<QueueStoreEntry> for QueueDisplayEntry {
@@ -2745,6 +3529,57 @@ impl From<QueueStoreEntry> for QueueDisplayEntry {
             updated_at: format_datetime(entry.updated_at),
             node_origin: entry.node_origin,
             content_kind: entry.content_kind,
+            scheduled_start: format_datetime(entry.schedule.map(|schedule| schedule.start)),
+            anchor: entry.schedule.map(|schedule| schedule.anchor.to_string()),
+            tags: entry.tags,
+            source_domain: entry.source_domain,
+            retry_count: entry.retry_count,
+            next_attempt_at: format_datetime(entry.next_attempt_at),
+            failure_reason: entry.failure_reason,
+            queue_position: entry.queue_position,
+            resume_offset_s: entry.resume_offset_s,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueRestoreOutput {
+    pub mode: String,
+    pub dry_run: bool,
+    pub archived: usize,
+    pub restored: usize,
+    pub skipped: usize,
+    pub removed: usize,
+    pub conflicts: Vec<QueueRestoreConflictView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueueRestoreConflictView {
+    pub kind: String,
+    pub plan_id: String,
+    pub archived_id: Option<i64>,
+    pub existing_id: Option<i64>,
+}
+
+impl From<RestoreReport> for QueueRestoreOutput {
+    fn from(report: RestoreReport) -> Self {
+        Self {
+            mode: report.mode.to_string(),
+            dry_run: report.dry_run,
+            archived: report.archived,
+            restored: report.restored,
+            skipped: report.skipped,
+            removed: report.removed,
+            conflicts: report
+                .conflicts
+                .into_iter()
+                .map(|conflict| QueueRestoreConflictView {
+                    kind: conflict.kind.to_string(),
+                    plan_id: conflict.plan_id,
+                    archived_id: conflict.archived_id,
+                    existing_id: conflict.existing_id,
+                })
+                .collect(),
         }
     }
 }
@@ -2755,6 +3590,17 @@ pub struct QueueSummaryOutput {
     pub counts: HashMap<String, i64>,
     pub played_last_hour: i64,
     pub failures_last_hour: i64,
+    pub daypart: Option<DaypartSummaryView>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DaypartSummaryView {
+    pub name: String,
+    pub timezone: String,
+    pub window: String,
+    pub started_at: Option<String>,
+    pub targets: HashMap<String, f64>,
+    pub aired: HashMap<String, i64>,
 }
 
 #[derive(Debug, Serialize)]
@@ -3080,6 +3926,70 @@ impl DisplayFallback for IncidentReportResultView {
     }
 }
 
+impl DisplayFallback for FailoverStatusView {
+    fn display(&self) -> String {
+        let mut lines = Vec::new();
+        if !self.enabled {
+            lines.push("Coordenação primário/standby desativada".to_string());
+        }
+        match &self.lease {
+            Some(lease) => {
+                let state = if lease.released {
+                    " (liberada)"
+                } else if lease.expired {
+                    " (expirada)"
+                } else {
+                    ""
+                };
+                lines.push(format!(
+                    "Lease: {} época {} até {}{state}",
+                    lease.holder, lease.epoch, lease.expires_at
+                ));
+            }
+            None => lines.push("Lease: nenhum nó assumiu ainda".to_string()),
+        }
+        lines.push("Nós:".to_string());
+        if self.nodes.is_empty() {
+            lines.push("  nenhum nó registrado".to_string());
+        }
+        for node in &self.nodes {
+            let marker = if node.node_id == self.node_id {
+                "*"
+            } else {
+                " "
+            };
+            let epoch = node
+                .epoch
+                .map(|epoch| format!(" época {epoch}"))
+                .unwrap_or_default();
+            let detail = node
+                .detail
+                .as_deref()
+                .map(|detail| format!(" ({detail})"))
+                .unwrap_or_default();
+            lines.push(format!(
+                " {marker}{} [{}] {}{epoch}{detail} em {}",
+                node.node_id, node.role, node.state, node.updated_at
+            ));
+        }
+        lines.push("Trocas de comando:".to_string());
+        if self.handovers.is_empty() {
+            lines.push("  nenhuma troca registrada".to_string());
+        }
+        for handover in &self.handovers {
+            lines.push(format!(
+                "  {} {} -> {} época {} ({})",
+                handover.at,
+                handover.from_node.as_deref().unwrap_or("-"),
+                handover.to_node,
+                handover.epoch,
+                handover.reason
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
 impl DisplayFallback for DrmScanReport {
     fn display(&self) -> String {
         let mut lines = vec![format!("Arquivos analisados: {}", self.files_scanned)];
@@ -3228,6 +4138,9 @@ mod tests {
         conn_queue
             .execute_batch(&fs::read_to_string("../sql/queue.sql").unwrap())
             .unwrap();
+        conn_queue
+            .execute_batch(&fs::read_to_string("../sql/as_run.sql").unwrap())
+            .unwrap();
         conn_queue.execute(
             "INSERT INTO playout_queue(plan_id, asset_path, duration_s, status, curation_score) VALUES (?1, ?2, ?3, ?4, ?5)",
             params!["plan-1", "asset.mp4", 3600, "queued", 0.9],
@@ -3373,6 +4286,36 @@ mod tests {
         assert!(Path::new(json_path).exists());
     }
 
+    #[test]
+    fn failover_status_lists_lease_nodes_and_handovers() {
+        let (temp, mut context) = prepare_test_context().unwrap();
+        let store_path = temp.path().join("data/leadership.sqlite");
+        context.bundle.broadcaster.failover.leadership.store_path = store_path.clone();
+        assert!(matches!(
+            context.failover_status(&FailoverStatusArgs { limit: 5 }),
+            Err(AppError::MissingResource(_))
+        ));
+
+        let store = SqliteLeaseStore::new(&store_path).unwrap();
+        let start = Utc::now() - Duration::minutes(1);
+        store
+            .acquire("vvtv-primary", Duration::seconds(15), start)
+            .unwrap();
+        store
+            .acquire("vvtv-standby", Duration::seconds(15), Utc::now())
+            .unwrap();
+
+        let status = context
+            .failover_status(&FailoverStatusArgs { limit: 5 })
+            .unwrap();
+        let lease = status.lease.as_ref().unwrap();
+        assert_eq!((lease.holder.as_str(), lease.epoch), ("vvtv-standby", 2));
+        assert!(!lease.expired);
+        assert_eq!(status.handovers.len(), 2);
+        assert_eq!(status.handovers[0].reason, "expired");
+        assert!(status.display().contains("vvtv-primary -> vvtv-standby"));
+    }
+
     #[test]
     fn status_report_collects_metrics() {
         let (_temp, context) = prepare_test_context().unwrap();
@@ -3383,6 +4326,147 @@ mod tests {
         assert!(status.metrics.is_some());
     }
 
+    #[test]
+    fn queue_summary_reports_active_daypart() {
+        let (_temp, context) = prepare_test_context().unwrap();
+        let summary = context.queue_summary().unwrap();
+        let daypart = summary
+            .daypart
+            .as_ref()
+            .expect("fixture config defines dayparts");
+        assert_eq!(daypart.timezone, "America/Sao_Paulo");
+        let total: f64 = daypart.targets.values().sum();
+        assert!((total - 1.0).abs() < 1e-9);
+        assert!(summary.display().contains("Daypart ativo"));
+    }
+
+    #[test]
+    fn queue_restore_dry_run_reports_conflicts() {
+        let (temp, context) = prepare_test_context().unwrap();
+        let archive = temp.path().join("queue.sql.gz");
+        context
+            .queue_backup(&QueueBackupArgs {
+                output: archive.clone(),
+            })
+            .unwrap();
+        let result = context
+            .queue_restore(&QueueRestoreArgs {
+                input: archive,
+                mode: "merge".to_string(),
+                dry_run: true,
+            })
+            .unwrap();
+        assert!(result.dry_run);
+        assert_eq!(result.restored, 0);
+        assert_eq!(result.skipped, result.archived);
+        assert!(result
+            .conflicts
+            .iter()
+            .all(|conflict| conflict.kind == "same_entry"));
+        assert!(result.display().contains("Simulação de restauração"));
+    }
+
+    #[test]
+    fn queue_forecast_projects_queue_without_mutating_it() {
+        let (temp, context) = prepare_test_context().unwrap();
+        let result = context
+            .queue_forecast(&QueueForecastArgs {
+                hours: 2,
+                start: Some("2030-01-01T00:00:00Z".to_string()),
+                archive_dir: Some(temp.path().join("archive")),
+            })
+            .unwrap();
+        assert_eq!(result.slots[0].plan_id, "plan-1");
+        assert_eq!(result.slots[0].ends_at, "2030-01-01T01:00:00Z");
+        assert!(result.display().contains("Previsão de playout"));
+        let queued = context
+            .queue_show(&QueueShowArgs {
+                status: Some("queued".to_string()),
+                limit: 10,
+            })
+            .unwrap();
+        assert_eq!(queued.rows.len(), 1);
+        assert!(matches!(
+            context.queue_forecast(&QueueForecastArgs {
+                hours: i64::MAX,
+                start: None,
+                archive_dir: Some(temp.path().join("archive")),
+            }),
+            Err(AppError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn queue_as_run_exports_requested_format() {
+        let (temp, context) = prepare_test_context().unwrap();
+        let output = temp.path().join("as_run.json");
+        let result = context
+            .queue_as_run(&QueueAsRunArgs {
+                start: Some("2030-01-01T00:00:00Z".to_string()),
+                end: Some("2030-01-02T00:00:00Z".to_string()),
+                format: "json".to_string(),
+                output: Some(output.clone()),
+            })
+            .unwrap();
+        assert_eq!(result.rows, 0);
+        assert_eq!(fs::read_to_string(&output).unwrap().trim(), "[]");
+        assert!(result.display().contains("As-run exportado (json)"));
+        assert!(context
+            .queue_as_run(&QueueAsRunArgs {
+                start: None,
+                end: None,
+                format: "xml".to_string(),
+                output: Some(output),
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn queue_next_renders_manual_order() {
+        let (_temp, context) = prepare_test_context().unwrap();
+        assert!(context.queue_order().unwrap().pinned.is_empty());
+        let result = context.queue_next(&QueueNextArgs { id: 1 }).unwrap();
+        assert_eq!(result.pinned.len(), 1);
+        assert_eq!(result.pinned[0].queue_position, Some(1));
+        let text = result.display();
+        assert!(text.contains("Item 1 toca em seguida"));
+        assert!(text.contains("1. #1 plan=plan-1"));
+    }
+
+    #[test]
+    fn queue_break_in_is_listed_until_cancelled() {
+        let (_temp, context) = prepare_test_context().unwrap();
+        let result = context
+            .queue_break_in(&QueueBreakInArgs {
+                id: 1,
+                policy: Some("requeue".to_string()),
+                reason: Some("takedown".to_string()),
+            })
+            .unwrap();
+        let break_in_id = result.break_ins[0].id;
+        assert_eq!(result.break_ins[0].policy, "requeue");
+        let listed = context
+            .queue_break_ins(&QueueBreakInsArgs { limit: 5 })
+            .unwrap();
+        let text = listed.display();
+        assert!(text.contains("#1 política=requeue pendente motivo=takedown"));
+        assert!(context
+            .queue_break_in(&QueueBreakInArgs {
+                id: 1,
+                policy: Some("later".to_string()),
+                reason: None,
+            })
+            .is_err());
+        context
+            .queue_cancel_break_in(&QueueCancelBreakInArgs { id: break_in_id })
+            .unwrap();
+        assert!(context
+            .queue_break_ins(&QueueBreakInsArgs { limit: 5 })
+            .unwrap()
+            .break_ins
+            .is_empty());
+    }
+
     #[test]
     fn plan_listing_returns_entries() {
         let (_temp, context) = prepare_test_context().unwrap();